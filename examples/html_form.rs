@@ -26,7 +26,7 @@
 use safina::executor::Executor;
 use serde::Deserialize;
 use servlin::log::log_request_and_response;
-use servlin::{Error, HttpServerBuilder, Request, Response, socket_addr_127_0_0_1};
+use servlin::{Csrf, Error, HttpServerBuilder, Request, Response, socket_addr_127_0_0_1};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -54,7 +54,8 @@ impl State {
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn index(state: Arc<State>) -> Response {
+fn index(state: Arc<State>, csrf: &Csrf, req: &Request) -> Response {
+    let csrf_field = csrf.form_field(req);
     Response::html(
         200,
         format!(
@@ -64,9 +65,11 @@ fn index(state: Arc<State>) -> Response {
   <h1>Example</h1>
   <p>Count: {}</p>
   <form action='/increment' method='post'>
+    {csrf_field}
     <input type='submit' value='Increment'/><br/>
   </form>
   <form action='/add' method='post'>
+    {csrf_field}
     <label>Num <input type='number' autofocus name='num' /></label>
     <input type='submit' name='add' value='Add'/>
   </form>
@@ -99,10 +102,10 @@ fn add(state: Arc<State>, req: Request) -> Result<Response, Error> {
     Ok(Response::redirect_303("/"))
 }
 
-fn handle_req(state: Arc<State>, req: Request) -> Result<Response, Error> {
+fn handle_req(state: Arc<State>, csrf: &Csrf, req: Request) -> Result<Response, Error> {
     match (req.method(), req.url().path.as_str()) {
         ("GET", "/health") => Ok(Response::text(200, "ok")),
-        ("GET", "/") => Ok(index(state)),
+        ("GET", "/") => Ok(index(state, csrf, &req)),
         ("POST", "/increment") => Ok(increment(state)),
         ("POST", "/add") => add(state, req),
         _ => Ok(Response::text(404, "Not found")),
@@ -114,8 +117,13 @@ pub fn main() {
     safina::timer::start_timer_thread();
     let executor: Arc<Executor> = Arc::default();
     let state = Arc::new(State::new());
-    let request_handler =
-        move |req: Request| log_request_and_response(req, |req| handle_req(state, req)).unwrap();
+    // Rejects form posts from other sites.
+    let csrf = Csrf::new();
+    let request_handler = move |req: Request| {
+        csrf.handle(req, |req| {
+            log_request_and_response(req, |req| handle_req(state, &csrf, req)).unwrap()
+        })
+    };
     executor
         .block_on(
             HttpServerBuilder::new()
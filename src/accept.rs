@@ -1,10 +1,16 @@
 #![allow(dead_code)]
+use crate::conn_stream::ConnStream;
 use crate::log::{add_thread_local_log_tag, error};
+use crate::remote_addr::RemoteAddr;
 use crate::token_set::{Token, TokenSet};
 use async_net::TcpListener;
+use core::fmt::{Display, Formatter};
 use futures_lite::FutureExt;
 use permit::Permit;
+use std::future::poll_fn;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::task::Poll;
 use std::time::Duration;
 
 #[must_use]
@@ -17,11 +23,22 @@ pub fn socket_addr_127_0_0_1(port: u16) -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
 }
 
+/// Returns `[::]:port`.
+///
+/// On most systems, a socket bound to this address accepts both IPv6 and IPv4 connections.
+/// When the server also listens on an IPv4 address with the same port,
+/// it binds this address as IPv6-only.
 #[must_use]
 pub fn socket_addr_all_interfaces(port: u16) -> SocketAddr {
     SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port)
 }
 
+/// Returns `0.0.0.0:port`.
+#[must_use]
+pub fn socket_addr_all_ipv4_interfaces(port: u16) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)
+}
+
 /// Reads and parses the `PORT` environment variable.
 ///
 /// # Panics
@@ -61,16 +78,297 @@ pub async fn listen_all_interfaces(port: u16) -> Result<async_net::TcpListener,
     TcpListener::bind(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port)).await
 }
 
+/// Binds a listening socket to `addr`.
+///
+/// When `v6only` is true and `addr` is an IPv6 address,
+/// the socket accepts only IPv6 connections.
+/// This lets another socket bind to the same port on an IPv4 address.
+///
+/// # Errors
+/// Returns an error when we fail to bind to the address.
+pub async fn listen(addr: SocketAddr, v6only: bool) -> Result<TcpListener, std::io::Error> {
+    if v6only && addr.is_ipv6() {
+        TcpListener::try_from(bind_v6only(addr)?)
+    } else {
+        TcpListener::bind(addr).await
+    }
+}
+
+#[cfg(unix)]
+fn bind_v6only(addr: SocketAddr) -> Result<std::net::TcpListener, std::io::Error> {
+    use rustix::io::{FdFlags, fcntl_setfd};
+    use rustix::net::{AddressFamily, SocketType, ipproto, sockopt};
+    let fd = rustix::net::socket(AddressFamily::INET6, SocketType::STREAM, Some(ipproto::TCP))?;
+    fcntl_setfd(&fd, FdFlags::CLOEXEC)?;
+    sockopt::set_ipv6_v6only(&fd, true)?;
+    // Like `std::net::TcpListener::bind`.
+    sockopt::set_socket_reuseaddr(&fd, true)?;
+    rustix::net::bind(&fd, &addr)?;
+    rustix::net::listen(&fd, 1024)?;
+    Ok(std::net::TcpListener::from(fd))
+}
+
+/// Windows IPv6 sockets are IPv6-only by default.
+#[cfg(not(unix))]
+fn bind_v6only(addr: SocketAddr) -> Result<std::net::TcpListener, std::io::Error> {
+    std::net::TcpListener::bind(addr)
+}
+
+/// An address where the server accepts connections.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    /// A Unix socket file.
+    ///
+    /// Before binding, the server deletes a socket file that no process is listening on.
+    /// When the server stops, it deletes the socket file.
+    ///
+    /// When `mode` is set, the server sets the file's permissions to it, like `0o660`.
+    /// Clients need write permission to connect.
+    UnixPath {
+        path: PathBuf,
+        mode: Option<u32>,
+    },
+    /// A Linux abstract Unix socket.
+    /// It has no file, so file permissions do not apply.
+    /// Any process in the same network namespace can connect.
+    UnixAbstract(Vec<u8>),
+}
+impl ListenAddr {
+    #[must_use]
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self::UnixPath {
+            path: path.into(),
+            mode: None,
+        }
+    }
+
+    #[must_use]
+    pub fn unix_with_mode(path: impl Into<PathBuf>, mode: u32) -> Self {
+        Self::UnixPath {
+            path: path.into(),
+            mode: Some(mode),
+        }
+    }
+
+    #[must_use]
+    pub fn unix_abstract(name: impl Into<Vec<u8>>) -> Self {
+        Self::UnixAbstract(name.into())
+    }
+
+    /// Returns the address of a TCP listener.
+    #[must_use]
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(addr) => Some(*addr),
+            Self::UnixPath { .. } | Self::UnixAbstract(..) => None,
+        }
+    }
+}
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::UnixPath { path, .. } => write!(f, "unix:{}", path.display()),
+            Self::UnixAbstract(name) => write!(f, "unix:@{}", name.escape_ascii()),
+        }
+    }
+}
+
+/// A socket that accepts connections.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(async_net::unix::UnixListener, Option<UnixSocketFile>),
+}
+impl Listener {
+    /// Binds a listening socket to `addr`.
+    ///
+    /// See [`listen`] for `v6only`.
+    ///
+    /// # Errors
+    /// Returns an error when:
+    /// - we fail to bind to the address
+    /// - another process is listening on the Unix socket file
+    /// - the Unix socket path exists and is not a socket
+    /// - we fail to set the Unix socket file's permissions
+    /// - `addr` is a Unix socket and this system does not support it
+    pub async fn bind(addr: &ListenAddr, v6only: bool) -> Result<Self, std::io::Error> {
+        match addr {
+            ListenAddr::Tcp(socket_addr) => Ok(Self::Tcp(listen(*socket_addr, v6only).await?)),
+            ListenAddr::UnixPath { path, mode } => bind_unix_path(path.clone(), *mode),
+            ListenAddr::UnixAbstract(name) => bind_unix_abstract(name),
+        }
+    }
+
+    /// Returns the address that the socket is bound to.
+    ///
+    /// # Errors
+    /// Returns an error when the socket is closed.
+    pub fn local_addr(&self) -> Result<ListenAddr, std::io::Error> {
+        match self {
+            Self::Tcp(listener) => Ok(ListenAddr::Tcp(listener.local_addr()?)),
+            #[cfg(unix)]
+            Self::Unix(listener, opt_file) => {
+                let addr = listener.local_addr()?;
+                if let Some(path) = addr.as_pathname() {
+                    return Ok(ListenAddr::UnixPath {
+                        path: path.to_path_buf(),
+                        mode: opt_file.as_ref().and_then(|file| file.mode),
+                    });
+                }
+                #[cfg(any(target_os = "android", target_os = "linux"))]
+                {
+                    #[cfg(target_os = "android")]
+                    use std::os::android::net::SocketAddrExt;
+                    #[cfg(target_os = "linux")]
+                    use std::os::linux::net::SocketAddrExt;
+                    if let Some(name) = addr.as_abstract_name() {
+                        return Ok(ListenAddr::UnixAbstract(name.to_vec()));
+                    }
+                }
+                Err(std::io::Error::other("unix socket has no address"))
+            }
+        }
+    }
+
+    /// Waits for a client to connect.
+    ///
+    /// # Errors
+    /// Returns an error when we fail to accept a connection.
+    pub async fn accept(&self) -> Result<(ConnStream, RemoteAddr), std::io::Error> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((ConnStream::Tcp(stream), RemoteAddr::Tcp(addr)))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, ..) => {
+                let (stream, addr) = listener.accept().await?;
+                let peer = crate::remote_addr::UnixPeer {
+                    path: addr.as_pathname().map(std::path::Path::to_path_buf),
+                    credentials: peer_credentials(&stream),
+                };
+                Ok((ConnStream::Unix(stream), RemoteAddr::Unix(peer)))
+            }
+        }
+    }
+}
+
+/// Deletes the Unix socket file when dropped.
+#[derive(Debug)]
+pub struct UnixSocketFile {
+    path: PathBuf,
+    mode: Option<u32>,
+}
+impl Drop for UnixSocketFile {
+    fn drop(&mut self) {
+        let _ignored = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Deletes the socket file at `path` when no process is listening on it.
+/// A server that crashed or got killed leaves its socket file behind,
+/// and then binding to the path fails.
+#[cfg(unix)]
+fn remove_stale_socket_file(path: &std::path::Path) -> Result<(), std::io::Error> {
+    use std::io::ErrorKind;
+    use std::os::unix::fs::FileTypeExt;
+    match std::fs::symlink_metadata(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+        Ok(metadata) if !metadata.file_type().is_socket() => Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Ok(..) => match std::os::unix::net::UnixStream::connect(path) {
+            Ok(..) => Err(std::io::Error::new(
+                ErrorKind::AddrInUse,
+                format!("another process is listening on {}", path.display()),
+            )),
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => std::fs::remove_file(path),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix_path(path: PathBuf, mode: Option<u32>) -> Result<Listener, std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    remove_stale_socket_file(&path)?;
+    let listener = async_net::unix::UnixListener::bind(&path)?;
+    let file = UnixSocketFile { path, mode };
+    if let Some(mode) = mode {
+        std::fs::set_permissions(&file.path, std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok(Listener::Unix(listener, Some(file)))
+}
+
+#[cfg(not(unix))]
+fn bind_unix_path(_path: PathBuf, _mode: Option<u32>) -> Result<Listener, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "unix sockets are not supported on this system",
+    ))
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn bind_unix_abstract(name: &[u8]) -> Result<Listener, std::io::Error> {
+    #[cfg(target_os = "android")]
+    use std::os::android::net::SocketAddrExt;
+    #[cfg(target_os = "linux")]
+    use std::os::linux::net::SocketAddrExt;
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    let listener = std::os::unix::net::UnixListener::bind_addr(&addr)?;
+    Ok(Listener::Unix(
+        async_net::unix::UnixListener::try_from(listener)?,
+        None,
+    ))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn bind_unix_abstract(_name: &[u8]) -> Result<Listener, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract unix sockets are only supported on Linux",
+    ))
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn peer_credentials(
+    stream: &async_net::unix::UnixStream,
+) -> Option<crate::remote_addr::PeerCredentials> {
+    let ucred = rustix::net::sockopt::socket_peercred(stream).ok()?;
+    Some(crate::remote_addr::PeerCredentials {
+        pid: u32::try_from(ucred.pid.as_raw_nonzero().get()).ok(),
+        uid: ucred.uid.as_raw(),
+        gid: ucred.gid.as_raw(),
+    })
+}
+
+#[cfg(all(unix, not(any(target_os = "android", target_os = "linux"))))]
+fn peer_credentials(
+    _stream: &async_net::unix::UnixStream,
+) -> Option<crate::remote_addr::PeerCredentials> {
+    None
+}
+
 #[derive(Debug)]
 #[allow(clippy::module_name_repetitions)]
 pub enum AcceptResult {
-    Ok(async_net::TcpStream, SocketAddr),
+    Ok(ConnStream, RemoteAddr),
     TooManyOpenFiles,
     Err(std::io::Error),
 }
 impl AcceptResult {
     #[must_use]
-    pub fn new(res: Result<(async_net::TcpStream, SocketAddr), std::io::Error>) -> Self {
+    pub fn new(res: Result<(ConnStream, RemoteAddr), std::io::Error>) -> Self {
         match res {
             Ok((stream, addr)) => AcceptResult::Ok(stream, addr),
             // On Unix, std translates errno EMFILE (Too many open files) into
@@ -83,7 +381,10 @@ impl AcceptResult {
     }
 }
 
-/// Start a task to accept connections and pass them to `stream_handler`.
+/// Start a task to accept connections from `listeners` and pass them to the listener's
+/// `conn_handler`.
+///
+/// The listeners share `token_set`, so it limits the total number of connections.
 ///
 /// The task stops then `permit` is revoked.
 ///
@@ -94,35 +395,48 @@ impl AcceptResult {
 #[allow(clippy::module_name_repetitions)]
 pub async fn accept_loop<F>(
     mut permit: Permit,
-    listener: async_net::TcpListener,
+    listeners: Vec<(Listener, F)>,
     mut token_set: TokenSet,
-    conn_handler: F,
 ) where
-    F: FnOnce(Permit, Token, async_net::TcpStream, SocketAddr) + 'static + Send + Clone,
+    F: FnOnce(Permit, Token, ConnStream, RemoteAddr) + 'static + Send + Clone,
 {
     add_thread_local_log_tag("thread_name", "accept_loop");
+    let mut accepts: Vec<_> = listeners
+        .iter()
+        .map(|(listener, _)| Box::pin(listener.accept()))
+        .collect();
+    // Check listeners in turn, so a busy listener cannot starve the others.
+    let mut next = 0;
     loop {
         let token = token_set.async_wait_token().await;
         if permit.is_revoked() {
             return;
         }
-        match FutureExt::or(
-            async { Some(AcceptResult::new(listener.accept().await)) },
-            async {
-                (&mut permit).await;
-                None
-            },
-        )
+        let accept_any = poll_fn(|cx| {
+            for k in 0..accepts.len() {
+                let n = (next + k) % accepts.len();
+                if let Poll::Ready(result) = accepts[n].as_mut().poll(cx) {
+                    accepts[n] = Box::pin(listeners[n].0.accept());
+                    next = n + 1;
+                    return Poll::Ready((n, AcceptResult::new(result)));
+                }
+            }
+            Poll::Pending
+        });
+        match FutureExt::or(async { Some(accept_any.await) }, async {
+            (&mut permit).await;
+            None
+        })
         .await
         {
-            Some(AcceptResult::Ok(stream, addr)) => {
-                conn_handler.clone()(permit.new_sub(), token, stream, addr);
+            Some((n, AcceptResult::Ok(stream, addr))) => {
+                listeners[n].1.clone()(permit.new_sub(), token, stream, addr);
             }
-            Some(AcceptResult::TooManyOpenFiles) => {
+            Some((_, AcceptResult::TooManyOpenFiles)) => {
                 error("too many open files, unable to accept connection", ()).unwrap();
                 safina::timer::sleep_for(Duration::from_millis(500)).await;
             }
-            Some(AcceptResult::Err(e)) => {
+            Some((_, AcceptResult::Err(e))) => {
                 let _ = error(format!("error accepting connection: {e}"), ());
                 safina::timer::sleep_for(Duration::from_millis(500)).await;
             }
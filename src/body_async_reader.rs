@@ -1,4 +1,6 @@
+use crate::BodyStream;
 use crate::event::EventReceiver;
+use futures_io::AsyncRead;
 use std::io::{Cursor, Read};
 use std::path::Path;
 use std::pin::Pin;
@@ -10,6 +12,10 @@ pub enum BodyAsyncReader<'x> {
     Cursor(Cursor<&'x [u8]>),
     EventReceiver(&'x Mutex<EventReceiver>),
     File(async_fs::File),
+    Stream(&'x BodyStream),
+    AsyncReader(&'x Mutex<Pin<Box<dyn AsyncRead + Send>>>),
+    /// Bytes from a blocking reader running on another thread.
+    Background(BodyStream),
 }
 impl<'x> BodyAsyncReader<'x> {
     #[must_use]
@@ -38,6 +44,11 @@ impl futures_io::AsyncRead for BodyAsyncReader<'_> {
                 Pin::new(&mut *mutex_event_receiver.lock().unwrap()).poll_read(cx, buf)
             }
             BodyAsyncReader::File(async_fs_file) => Pin::new(async_fs_file).poll_read(cx, buf),
+            BodyAsyncReader::Stream(stream) => Pin::new(stream).poll_read(cx, buf),
+            BodyAsyncReader::AsyncReader(mutex_reader) => {
+                mutex_reader.lock().unwrap().as_mut().poll_read(cx, buf)
+            }
+            BodyAsyncReader::Background(stream) => Pin::new(&mut &*stream).poll_read(cx, buf),
         }
     }
 }
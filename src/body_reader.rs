@@ -1,6 +1,9 @@
+use crate::BodyStream;
 use crate::event::EventReceiver;
-use std::io::{Cursor, ErrorKind, SeekFrom};
+use futures_io::AsyncRead;
+use std::io::{Cursor, ErrorKind, Read, SeekFrom};
 use std::path::Path;
+use std::pin::Pin;
 use std::sync::Mutex;
 
 /// Struct returned by `RequestBody::reader` and `ResponseBody::reader`.
@@ -8,6 +11,10 @@ pub enum BodyReader<'x> {
     Cursor(Cursor<&'x [u8]>),
     EventReceiver(&'x Mutex<EventReceiver>),
     File(std::fs::File),
+    Stream(&'x BodyStream),
+    Reader(&'x Mutex<Box<dyn Read + Send>>),
+    /// Blocks the thread while reading.
+    AsyncReader(&'x Mutex<Pin<Box<dyn AsyncRead + Send>>>),
 }
 impl<'x> BodyReader<'x> {
     #[must_use]
@@ -31,6 +38,11 @@ impl std::io::Read for BodyReader<'_> {
                 mutex_event_receiver.lock().unwrap().read(buf)
             }
             BodyReader::File(file) => file.read(buf),
+            BodyReader::Stream(stream) => stream.read(buf),
+            BodyReader::Reader(mutex_reader) => mutex_reader.lock().unwrap().read(buf),
+            BodyReader::AsyncReader(mutex_reader) => futures_lite::future::block_on(
+                futures_lite::AsyncReadExt::read(&mut *mutex_reader.lock().unwrap(), buf),
+            ),
         }
     }
 }
@@ -43,6 +55,18 @@ impl std::io::Seek for BodyReader<'_> {
                 "BodyReader::EventReceiver cannot seek",
             )),
             BodyReader::File(file) => file.seek(pos),
+            BodyReader::Stream(..) => Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "BodyReader::Stream cannot seek",
+            )),
+            BodyReader::Reader(..) => Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "BodyReader::Reader cannot seek",
+            )),
+            BodyReader::AsyncReader(..) => Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "BodyReader::AsyncReader cannot seek",
+            )),
         }
     }
 }
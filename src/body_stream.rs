@@ -0,0 +1,261 @@
+use crate::http_error::HttpError;
+use futures_io::AsyncRead;
+use futures_lite::AsyncReadExt;
+use safina::sync::{Receiver, SyncSender, sync_channel};
+use std::fmt::Debug;
+use std::future::Future;
+use std::io::{ErrorKind, Read};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Makes a connected sender and stream.
+///
+/// The channel holds up to `bound` chunks.
+/// When it is full, the sender waits for the reader to consume a chunk.
+///
+/// `len` is the length of the body, if it is known.
+#[must_use]
+pub fn body_stream(bound: usize, len: Option<u64>) -> (BodyStreamSender, BodyStream) {
+    let (sender, receiver) = sync_channel(bound);
+    (
+        BodyStreamSender(sender),
+        BodyStream {
+            len,
+            inner: Arc::new(Mutex::new(BodyStreamInner {
+                receiver,
+                chunk: Vec::new(),
+                pos: 0,
+            })),
+        },
+    )
+}
+
+/// Reads `reader` on a blocking thread and sends the bytes to the returned stream.
+///
+/// # Panics
+/// Panics when called outside of a `safina` executor.
+#[must_use]
+pub fn read_in_background(mut reader: Box<dyn Read + Send>) -> BodyStream {
+    let (sender, stream) = body_stream(4, None);
+    safina::executor::schedule_blocking(move || {
+        loop {
+            let mut chunk = vec![0_u8; 65536];
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    if sender.send_blocking(chunk).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    sender.send_error_blocking(e.kind());
+                    break;
+                }
+            }
+        }
+    });
+    stream
+}
+
+/// Reads a request body from `reader` and sends it to `sender` in chunks.
+/// When the receiver does not keep up, this waits for it.
+///
+/// Reads `opt_len` bytes, or until end-of-stream when the length is unknown.
+///
+/// Returns `false` when the receiver dropped the stream before the end of the body.
+///
+/// # Errors
+/// Returns an error when:
+/// - the body is larger than `max_len`
+/// - `reader` fails or ends before `opt_len` bytes
+pub async fn copy_to_body_stream(
+    reader: impl AsyncRead + Unpin,
+    sender: &BodyStreamSender,
+    opt_len: Option<u64>,
+    max_len: u64,
+) -> Result<bool, HttpError> {
+    let mut reader = AsyncReadExt::take(reader, opt_len.unwrap_or(max_len.saturating_add(1)));
+    let mut num_read: u64 = 0;
+    loop {
+        let mut chunk = vec![0_u8; 65536];
+        let n = match reader.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(..) => return Err(HttpError::Truncated),
+        };
+        num_read += n as u64;
+        if num_read > max_len {
+            return Err(HttpError::BodyTooLong);
+        }
+        chunk.truncate(n);
+        if sender.send(chunk).await.is_err() {
+            return Ok(false);
+        }
+    }
+    match opt_len {
+        Some(len) if num_read < len => Err(HttpError::Truncated),
+        _ => Ok(true),
+    }
+}
+
+/// The sending half of a [`BodyStream`].
+///
+/// Drop this to end the stream.
+#[allow(clippy::module_name_repetitions)]
+pub struct BodyStreamSender(SyncSender<Result<Vec<u8>, ErrorKind>>);
+impl BodyStreamSender {
+    /// Sends a chunk of bytes, waiting while the channel is full.
+    ///
+    /// # Errors
+    /// Returns an error when the reader dropped the stream.
+    pub async fn send(&self, chunk: Vec<u8>) -> Result<(), ()> {
+        self.0.async_send(Ok(chunk)).await.map_err(|_| ())
+    }
+
+    /// Sends a chunk of bytes, blocking the thread while the channel is full.
+    ///
+    /// # Errors
+    /// Returns an error when the reader dropped the stream.
+    #[allow(clippy::result_unit_err)]
+    pub fn send_blocking(&self, chunk: Vec<u8>) -> Result<(), ()> {
+        self.0.send(Ok(chunk)).map_err(|_| ())
+    }
+
+    /// Makes the reader get an error with `kind` instead of end-of-stream.
+    pub async fn send_error(&self, kind: ErrorKind) {
+        let _ignored = self.0.async_send(Err(kind)).await;
+    }
+
+    /// Makes the reader get an error with `kind` instead of end-of-stream.
+    /// Blocks the thread while the channel is full.
+    pub fn send_error_blocking(&self, kind: ErrorKind) {
+        let _ignored = self.0.send(Err(kind));
+    }
+}
+impl Debug for BodyStreamSender {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(f, "BodyStreamSender")
+    }
+}
+
+struct BodyStreamInner {
+    receiver: Receiver<Result<Vec<u8>, ErrorKind>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+impl BodyStreamInner {
+    /// Copies buffered bytes into `buf`.
+    /// Returns `None` when there are no buffered bytes.
+    fn read_buffered(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let available = &self.chunk[self.pos..];
+        if available.is_empty() {
+            return None;
+        }
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos += n;
+        Some(n)
+    }
+
+    /// Stores a received item.
+    /// Returns `Some` when the read should return.
+    fn receive(
+        &mut self,
+        result: Result<Result<Vec<u8>, ErrorKind>, std::sync::mpsc::RecvError>,
+    ) -> Option<Result<usize, std::io::Error>> {
+        match result {
+            Ok(Ok(chunk)) => {
+                self.chunk = chunk;
+                self.pos = 0;
+                None
+            }
+            Ok(Err(kind)) => Some(Err(std::io::Error::new(
+                kind,
+                "error receiving request body",
+            ))),
+            Err(_) => Some(Ok(0)),
+        }
+    }
+}
+
+/// A body that is still arriving.
+///
+/// As a request body, it holds bytes that the server is still receiving from the client.
+/// Reads wait for the client to send more bytes.
+/// The server stops receiving when the reader does not keep up.
+///
+/// As a response body, it holds bytes from a [`BodyStreamSender`].
+///
+/// Clones share the same stream.
+#[derive(Clone)]
+pub struct BodyStream {
+    len: Option<u64>,
+    inner: Arc<Mutex<BodyStreamInner>>,
+}
+impl BodyStream {
+    /// Returns the body length, if it is known.
+    #[must_use]
+    pub fn len(&self) -> Option<u64> {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len.map(|len| len == 0)
+    }
+}
+impl Debug for BodyStream {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(f, "BodyStream(len={:?})", self.len)
+    }
+}
+impl Eq for BodyStream {}
+impl PartialEq for BodyStream {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+impl std::io::Read for &BodyStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            if let Some(n) = inner.read_buffered(buf) {
+                return Ok(n);
+            }
+            let result = inner.receiver.recv();
+            if let Some(result) = inner.receive(result) {
+                return result;
+            }
+        }
+    }
+}
+impl futures_io::AsyncRead for &BodyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            if let Some(n) = inner.read_buffered(buf) {
+                return Poll::Ready(Ok(n));
+            }
+            let result = match Pin::new(&mut inner.receiver).poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => result,
+            };
+            if let Some(result) = inner.receive(result) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use crate::util::{is_field_value, is_tchar};
+use crate::{AsciiString, Request, Response};
+use std::ffi::OsString;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Makes the CGI meta-variables for `req`.
+///
+/// `script_name` is the URL path prefix that identifies the script, like `/cgi-bin/app`.
+/// The rest of the path becomes `PATH_INFO`.
+///
+/// Returns `None` when the request path does not start with `script_name`.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc3875#section-4.1>
+#[must_use]
+pub fn cgi_variables(req: &Request, script_name: &str) -> Option<Vec<(String, String)>> {
+    let script_name = script_name.trim_end_matches('/');
+    let path_info = req.url.path.strip_prefix(script_name)?;
+    if !path_info.is_empty() && !path_info.starts_with('/') {
+        return None;
+    }
+    let mut vars: Vec<(String, String)> = vec![
+        ("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string()),
+        (
+            "SERVER_SOFTWARE".to_string(),
+            concat!("servlin/", env!("CARGO_PKG_VERSION")).to_string(),
+        ),
+        (
+            "SERVER_PROTOCOL".to_string(),
+            req.version.as_str().to_string(),
+        ),
+        ("REQUEST_METHOD".to_string(), req.method.clone()),
+        ("SCRIPT_NAME".to_string(), script_name.to_string()),
+        ("QUERY_STRING".to_string(), req.url.query.clone()),
+    ];
+    if !path_info.is_empty() {
+        vars.push(("PATH_INFO".to_string(), path_info.to_string()));
+    }
+    let server_name = match req.url.ip {
+        Some(ip) => ip.to_string(),
+        None if !req.url.host.is_empty() => req.url.host.clone(),
+        None => req
+            .local_addr
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default(),
+    };
+    vars.push(("SERVER_NAME".to_string(), server_name));
+    let default_port = if req.scheme() == "https" { 443 } else { 80 };
+    let server_port = match req.url.port {
+        Some(port) => port,
+        None if req.url.ip.is_some() || !req.url.host.is_empty() => default_port,
+        None => req.local_addr.map_or(default_port, |addr| addr.port()),
+    };
+    vars.push(("SERVER_PORT".to_string(), server_port.to_string()));
+    if let Some(ip) = req.client_addr() {
+        vars.push(("REMOTE_ADDR".to_string(), ip.to_string()));
+        vars.push(("REMOTE_HOST".to_string(), ip.to_string()));
+    }
+    if req.scheme() == "https" {
+        vars.push(("HTTPS".to_string(), "on".to_string()));
+    }
+    if let Some(len) = req.body.len().filter(|len| *len > 0) {
+        vars.push(("CONTENT_LENGTH".to_string(), len.to_string()));
+    }
+    if let Some(content_type) = req.headers.get_only("content-type") {
+        vars.push(("CONTENT_TYPE".to_string(), content_type.to_string()));
+    }
+    // https://datatracker.ietf.org/doc/html/rfc3875#section-4.1.18
+    for header in &req.headers {
+        let name = header.name.to_ascii_uppercase().replace('-', "_");
+        // Scripts read `HTTP_PROXY` as their outgoing proxy setting.
+        // https://httpoxy.org/
+        if matches!(name.as_str(), "CONTENT_LENGTH" | "CONTENT_TYPE" | "PROXY") {
+            continue;
+        }
+        let name = format!("HTTP_{name}");
+        let separator = if name == "HTTP_COOKIE" { "; " } else { ", " };
+        if let Some((_, value)) = vars.iter_mut().find(|(n, _)| *n == name) {
+            value.push_str(separator);
+            value.push_str(header.value.as_str());
+        } else {
+            vars.push((name, header.value.to_string()));
+        }
+    }
+    Some(vars)
+}
+
+/// Makes a response from the output of a CGI script.
+///
+/// The output has header fields, a blank line, and the body.
+/// The `Status` header sets the response code.
+/// A `Location` header without `Status` makes a `302 Found` redirect.
+///
+/// Returns `None` when the output is malformed.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc3875#section-6>
+#[must_use]
+pub fn parse_cgi_response(output: &[u8]) -> Option<Response> {
+    let mut response = Response::new(200);
+    let mut opt_status = None;
+    let mut location = false;
+    let mut rest = output;
+    loop {
+        let n = rest.iter().position(|b| *b == b'\n')?;
+        let mut line = &rest[..n];
+        rest = &rest[n + 1..];
+        if line.last() == Some(&b'\r') {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = std::str::from_utf8(line).ok()?.split_once(':')?;
+        if name.is_empty() || !name.bytes().all(is_tchar) {
+            return None;
+        }
+        let value = value.trim_matches([' ', '\t']);
+        if !is_field_value(value) {
+            return None;
+        }
+        if name.eq_ignore_ascii_case("status") {
+            // Status = "Status:" status-code SP reason-phrase NL
+            let code = value.split(' ').next().unwrap_or_default();
+            if code.len() != 3 {
+                return None;
+            }
+            let code: u16 = code.parse().ok()?;
+            if !(200..600).contains(&code) || opt_status.is_some() {
+                return None;
+            }
+            opt_status = Some(code);
+        } else if name.eq_ignore_ascii_case("content-length")
+            || name.eq_ignore_ascii_case("connection")
+            || name.eq_ignore_ascii_case("transfer-encoding")
+        {
+            // The server frames the body.
+        } else {
+            location |= name.eq_ignore_ascii_case("location");
+            let value = AsciiString::try_from(value).ok()?;
+            response.headers.add(name.to_ascii_lowercase(), value);
+        }
+    }
+    response.code = opt_status.unwrap_or(if location { 302 } else { 200 });
+    if !rest.is_empty() {
+        response.body = rest.to_vec().into();
+    }
+    Some(response)
+}
+
+/// Kills the program and, on Unix, the processes it started in its process group.
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    use rustix::process::{Pid, Signal, kill_process_group};
+    let _ignored = kill_process_group(Pid::from_child(child), Signal::KILL);
+}
+
+/// Kills the program.
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ignored = child.kill();
+}
+
+/// Returns the error response for a failed gateway exchange.
+pub(crate) fn gateway_error_response(e: &std::io::Error) -> Response {
+    if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) {
+        Response::gateway_timeout_504()
+    } else {
+        Response::bad_gateway_502()
+    }
+}
+
+/// Runs a CGI program for each request.
+///
+/// The program gets the request as
+/// [RFC 3875](https://datatracker.ietf.org/doc/html/rfc3875) environment variables,
+/// with the body on its stdin.
+/// It writes the response to its stdout.
+/// Its stderr goes to the server's stderr.
+///
+/// The handler returns `502 Bad Gateway` when the program fails to start,
+/// writes malformed or too much output,
+/// and `504 Gateway Timeout` when it runs too long.
+///
+/// Example:
+/// ```
+/// use servlin::{Cgi, Request};
+///
+/// let cgi = Cgi::new("/usr/lib/cgi-bin/app.pl").script_name("/app");
+/// let handler = move |req: Request| cgi.handle(&req);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cgi {
+    program: PathBuf,
+    args: Vec<OsString>,
+    env: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+    script_name: String,
+    timeout: Duration,
+    max_body_len: u64,
+    max_output_len: u64,
+}
+impl Cgi {
+    #[must_use]
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            env: Vec::new(),
+            current_dir: None,
+            script_name: String::new(),
+            timeout: Duration::from_secs(30),
+            max_body_len: 64 * 1024 * 1024,
+            max_output_len: 64 * 1024 * 1024,
+        }
+    }
+
+    /// Adds a command-line argument for the program.
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets an environment variable for the program.
+    ///
+    /// The program does not inherit the server's environment.
+    #[must_use]
+    pub fn env(mut self, name: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        self.env.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the program's working directory.
+    ///
+    /// Default: the server's working directory
+    #[must_use]
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the URL path of the script, like `/cgi-bin/app`.
+    /// The program gets the rest of the request path in `PATH_INFO`.
+    ///
+    /// The handler returns `404 Not Found` for requests with other paths.
+    ///
+    /// Default: `""`
+    #[must_use]
+    pub fn script_name(mut self, path: impl Into<String>) -> Self {
+        self.script_name = path.into();
+        self
+    }
+
+    /// Sets how long the program may run.
+    /// The handler kills the program, and on Unix the processes it started, when it runs longer.
+    ///
+    /// Default: 30 seconds
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum length of request bodies, in bytes.
+    /// See [`Response::get_body_and_reprocess`].
+    ///
+    /// Default: 64 MiB
+    #[must_use]
+    pub fn max_body_len(mut self, n: u64) -> Self {
+        self.max_body_len = n;
+        self
+    }
+
+    /// Sets the maximum length of the program's output, in bytes.
+    ///
+    /// Default: 64 MiB
+    #[must_use]
+    pub fn max_output_len(mut self, n: u64) -> Self {
+        self.max_output_len = n;
+        self
+    }
+
+    /// Runs the program for `req` and returns its response.
+    ///
+    /// When the request body is pending, returns [`Response::get_body_and_reprocess`],
+    /// so the server calls the handler again with the whole body.
+    /// CGI programs need the body length before they start.
+    #[must_use]
+    pub fn handle(&self, req: &Request) -> Response {
+        if req.body.is_pending() {
+            return Response::get_body_and_reprocess(self.max_body_len);
+        }
+        let Some(vars) = cgi_variables(req, &self.script_name) else {
+            return Response::not_found_404();
+        };
+        match self.run(req, vars) {
+            Ok(output) => parse_cgi_response(&output).unwrap_or_else(Response::bad_gateway_502),
+            Err(e) => gateway_error_response(&e),
+        }
+    }
+
+    fn run(&self, req: &Request, vars: Vec<(String, String)>) -> Result<Vec<u8>, std::io::Error> {
+        let deadline = Instant::now() + self.timeout;
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .env_clear()
+            .envs(vars)
+            .envs(self.env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command.spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        // We write and read on separate threads so a program that writes
+        // before reading all of its input cannot deadlock with us.
+        // We do not join them, since a background process can keep the pipes open.
+        let body = req.body.clone();
+        std::thread::spawn(move || {
+            if let Ok(mut reader) = body.reader() {
+                // The program may exit without reading its input.
+                let _ignored = std::io::copy(&mut reader, &mut stdin);
+            }
+        });
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let max_output_len = self.max_output_len;
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            let result = stdout
+                .take(max_output_len.saturating_add(1))
+                .read_to_end(&mut output)
+                .map(|_| output);
+            let _ignored = sender.send(result);
+        });
+        let result = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            Ok(Ok(output)) if output.len() as u64 > max_output_len => Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "CGI output is too long",
+            )),
+            Ok(result) => result,
+            Err(..) => Err(ErrorKind::TimedOut.into()),
+        };
+        if result.is_err() {
+            // Killing the program closes its pipes and stops the threads.
+            kill(&mut child);
+            child.wait()?;
+            return result;
+        }
+        // The program closed its output but may still be running.
+        while child.try_wait()?.is_none() {
+            if Instant::now() >= deadline {
+                kill(&mut child);
+                child.wait()?;
+                return Err(ErrorKind::TimedOut.into());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        result
+    }
+}
@@ -0,0 +1,91 @@
+use futures_io::{AsyncRead, AsyncWrite};
+use std::net::{Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A connection from a client.
+///
+/// Clones share the same connection.
+#[derive(Clone, Debug)]
+pub enum ConnStream {
+    Tcp(async_net::TcpStream),
+    #[cfg(unix)]
+    Unix(async_net::unix::UnixStream),
+}
+impl ConnStream {
+    /// Shuts down the read half, write half, or both halves of the connection.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is already closed.
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), std::io::Error> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.shutdown(how),
+        }
+    }
+}
+impl ConnStream {
+    /// Returns the server's address of a TCP connection.
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(stream) => stream.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Unix(..) => None,
+        }
+    }
+}
+impl From<async_net::TcpStream> for ConnStream {
+    fn from(stream: async_net::TcpStream) -> Self {
+        Self::Tcp(stream)
+    }
+}
+#[cfg(unix)]
+impl From<async_net::unix::UnixStream> for ConnStream {
+    fn from(stream: async_net::unix::UnixStream) -> Self {
+        Self::Unix(stream)
+    }
+}
+impl AsyncRead for ConnStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+impl AsyncWrite for ConnStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_close(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
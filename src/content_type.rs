@@ -47,6 +47,40 @@ impl ContentType {
         }
     }
 
+    /// Picks the content type for a file extension, like `"html"`.
+    ///
+    /// Returns [`ContentType::None`] for unknown extensions.
+    #[must_use]
+    pub fn from_extension(extension: &str) -> Self {
+        match extension {
+            "css" => ContentType::Css,
+            "csv" => ContentType::Csv,
+            "gif" => ContentType::Gif,
+            "htm" | "html" => ContentType::Html,
+            "js" => ContentType::JavaScript,
+            "jpg" | "jpeg" => ContentType::Jpeg,
+            "json" => ContentType::Json,
+            "md" => ContentType::Markdown,
+            "pdf" => ContentType::Pdf,
+            "txt" => ContentType::PlainText,
+            "png" => ContentType::Png,
+            "svg" => ContentType::Svg,
+            _ => ContentType::None,
+        }
+    }
+
+    /// Picks the content type from the extension of the file at `path`.
+    ///
+    /// Returns [`ContentType::None`] when the path has no extension or an unknown extension.
+    #[must_use]
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Self {
+        Self::from_extension(
+            path.as_ref()
+                .extension()
+                .map_or("", |os_str| os_str.to_str().unwrap_or("")),
+        )
+    }
+
     #[must_use]
     pub fn as_str(&self) -> &str {
         match self {
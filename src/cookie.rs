@@ -1,5 +1,5 @@
-use crate::AsciiString;
 use crate::time::FormatTime;
+use crate::{AsciiString, KeyRing};
 use core::fmt::{Display, Formatter};
 use core::time::Duration;
 use std::time::SystemTime;
@@ -100,6 +100,43 @@ impl Cookie {
         self.secure = b;
         self
     }
+
+    /// Returns when the cookie expires, or `None` for a session cookie.
+    fn expiration(&self) -> Option<SystemTime> {
+        if self.max_age > Duration::ZERO {
+            Some(SystemTime::now() + self.max_age)
+        } else if self.expires != SystemTime::UNIX_EPOCH {
+            Some(self.expires)
+        } else {
+            None
+        }
+    }
+
+    /// Signs the cookie's value with [`KeyRing::sign`].
+    /// Read it with [`Request::signed_cookie`](crate::Request::signed_cookie).
+    ///
+    /// Call this after setting `max_age` or `expires`,
+    /// so the signed value expires with the cookie.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn signed(mut self, keys: &KeyRing) -> Self {
+        let value = keys.sign(self.name.as_str(), self.value.as_str(), self.expiration());
+        self.value = value.try_into().unwrap();
+        self
+    }
+
+    /// Encrypts the cookie's value with [`KeyRing::encrypt`].
+    /// Read it with [`Request::encrypted_cookie`](crate::Request::encrypted_cookie).
+    ///
+    /// Call this after setting `max_age` or `expires`,
+    /// so the encrypted value expires with the cookie.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn encrypted(mut self, keys: &KeyRing) -> Self {
+        let value = keys.encrypt(self.name.as_str(), self.value.as_str(), self.expiration());
+        self.value = value.try_into().unwrap();
+        self
+    }
 }
 impl Display for Cookie {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
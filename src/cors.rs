@@ -0,0 +1,343 @@
+use crate::util::is_tchar;
+use crate::{AsciiString, Request, Response};
+use std::time::Duration;
+
+/// Adds `value` to the response's `Vary` headers, when it is not already there.
+fn add_vary(response: &mut Response, value: &str) {
+    // https://datatracker.ietf.org/doc/html/rfc9110#section-12.5.5
+    let present = response.headers.get_all("vary").iter().any(|header| {
+        header
+            .split(',')
+            .any(|s| s.trim().eq_ignore_ascii_case(value) || s.trim() == "*")
+    });
+    if !present {
+        response
+            .headers
+            .add("vary", AsciiString::try_from(value).unwrap());
+    }
+}
+
+/// Returns `value` as a header field token.
+///
+/// # Panics
+/// Panics when `value` is empty or contains non-token characters.
+fn token(value: &str, kind: &str) -> String {
+    // https://datatracker.ietf.org/doc/html/rfc9110#section-5.6.2
+    assert!(
+        !value.is_empty() && value.bytes().all(is_tchar),
+        "invalid {kind} {value:?}"
+    );
+    value.to_string()
+}
+
+fn join(values: &[String]) -> AsciiString {
+    values.join(", ").try_into().unwrap()
+}
+
+fn is_subdomain_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '.'
+}
+
+/// A [CORS](https://fetch.spec.whatwg.org/#http-cors-protocol) policy.
+///
+/// It answers preflight requests and adds `Access-Control-*` headers to responses,
+/// so scripts on other origins can call your handlers.
+///
+/// Example:
+/// ```
+/// use servlin::{Cors, Request, Response};
+/// use std::time::Duration;
+///
+/// let cors = Cors::new()
+///     .allow_origin("https://app.example.com")
+///     .allow_origin("https://*.preview.example.com")
+///     .allow_methods(["GET", "POST", "DELETE"])
+///     .allow_headers(["content-type", "x-api-key"])
+///     .allow_credentials()
+///     .max_age(Duration::from_mins(10));
+/// let handler = move |req: Request| cors.handle(req, |_req| Response::text(200, "ok"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<String>,
+    /// Origin patterns like `https://*.example.com`, as (`https://`, `.example.com`).
+    origin_patterns: Vec<(String, String)>,
+    methods: Vec<String>,
+    any_header: bool,
+    headers: Vec<String>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+impl Cors {
+    /// Makes a policy that allows no origins.
+    /// It allows the methods `GET`, `HEAD`, and `POST`.
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            any_origin: false,
+            origins: Vec::new(),
+            origin_patterns: Vec::new(),
+            methods: vec!["GET".to_string(), "HEAD".to_string(), "POST".to_string()],
+            any_header: false,
+            headers: Vec::new(),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allows requests from `origin`, like `https://app.example.com`
+    /// or `http://localhost:8080`.
+    ///
+    /// A pattern like `https://*.example.com` allows subdomains of `example.com`,
+    /// like `https://www.example.com`, but not `https://example.com`.
+    ///
+    /// # Panics
+    /// Panics when `origin` is not an origin or pattern.
+    #[must_use]
+    pub fn allow_origin(mut self, origin: impl AsRef<str>) -> Self {
+        let origin = origin.as_ref().to_ascii_lowercase();
+        let valid = |s: &str| !s.is_empty() && s.is_ascii() && !s.contains(['/', '*', ' ', ',']);
+        if let Some((scheme, rest)) = origin.split_once("://*.") {
+            assert!(
+                valid(scheme) && valid(rest),
+                "invalid origin pattern {origin:?}"
+            );
+            self.origin_patterns
+                .push((format!("{scheme}://"), format!(".{rest}")));
+        } else {
+            let (scheme, rest) = origin.split_once("://").unwrap_or(("", &origin));
+            assert!(
+                origin == "null" || (valid(scheme) && valid(rest)),
+                "invalid origin {origin:?}"
+            );
+            self.origins.push(origin);
+        }
+        self
+    }
+
+    /// Allows requests from any origin.
+    ///
+    /// # Panics
+    /// Panics when the policy allows credentials.
+    /// Browsers reject credentialed responses that allow any origin.
+    #[must_use]
+    pub fn allow_any_origin(mut self) -> Self {
+        assert!(
+            !self.credentials,
+            "Cors cannot allow credentials with any origin"
+        );
+        self.any_origin = true;
+        self
+    }
+
+    /// Sets the methods that scripts may use, like `PUT` and `DELETE`.
+    ///
+    /// Default: `GET`, `HEAD`, `POST`
+    ///
+    /// # Panics
+    /// Panics when a method is empty or contains non-token characters.
+    #[must_use]
+    pub fn allow_methods<S: AsRef<str>>(mut self, methods: impl IntoIterator<Item = S>) -> Self {
+        self.methods = methods
+            .into_iter()
+            .map(|method| token(method.as_ref(), "method"))
+            .collect();
+        self
+    }
+
+    /// Allows scripts to send request headers with these names.
+    ///
+    /// Browsers allow some headers, like `accept`, without this.
+    /// See <https://fetch.spec.whatwg.org/#cors-safelisted-request-header>.
+    ///
+    /// # Panics
+    /// Panics when a name is empty or contains non-token characters.
+    #[must_use]
+    pub fn allow_headers<S: AsRef<str>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.headers.extend(
+            names
+                .into_iter()
+                .map(|name| token(&name.as_ref().to_ascii_lowercase(), "header name")),
+        );
+        self
+    }
+
+    /// Allows scripts to send any request headers.
+    #[must_use]
+    pub fn allow_any_header(mut self) -> Self {
+        self.any_header = true;
+        self
+    }
+
+    /// Allows scripts to read response headers with these names.
+    ///
+    /// Browsers expose some headers, like `content-type`, without this.
+    /// See <https://fetch.spec.whatwg.org/#cors-safelisted-response-header-name>.
+    ///
+    /// # Panics
+    /// Panics when a name is empty or contains non-token characters.
+    #[must_use]
+    pub fn expose_headers<S: AsRef<str>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.expose_headers.extend(
+            names
+                .into_iter()
+                .map(|name| token(&name.as_ref().to_ascii_lowercase(), "header name")),
+        );
+        self
+    }
+
+    /// Allows scripts to send cookies and HTTP authentication,
+    /// and to read responses to those requests.
+    ///
+    /// # Panics
+    /// Panics when the policy allows any origin.
+    #[must_use]
+    pub fn allow_credentials(mut self) -> Self {
+        assert!(
+            !self.any_origin,
+            "Cors cannot allow credentials with any origin"
+        );
+        self.credentials = true;
+        self
+    }
+
+    /// Sets how long browsers may cache preflight responses.
+    ///
+    /// Default: the browser's default, which is 5 seconds
+    #[must_use]
+    pub fn max_age(mut self, duration: Duration) -> Self {
+        self.max_age = Some(duration);
+        self
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        self.any_origin
+            || self.origins.contains(&origin)
+            || self.origin_patterns.iter().any(|(scheme, suffix)| {
+                origin
+                    .strip_prefix(scheme.as_str())
+                    .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+                    .is_some_and(|sub| !sub.is_empty() && sub.chars().all(is_subdomain_char))
+            })
+    }
+
+    /// Adds `Access-Control-Allow-Origin` and `Access-Control-Allow-Credentials`.
+    fn add_allow_origin(&self, response: &mut Response, origin: &AsciiString) {
+        if self.any_origin {
+            response
+                .headers
+                .add("access-control-allow-origin", "*".try_into().unwrap());
+        } else {
+            response
+                .headers
+                .add("access-control-allow-origin", origin.clone());
+        }
+        if self.credentials {
+            response.headers.add(
+                "access-control-allow-credentials",
+                "true".try_into().unwrap(),
+            );
+        }
+    }
+
+    /// Answers a preflight request.
+    ///
+    /// <https://fetch.spec.whatwg.org/#cors-preflight-fetch>
+    fn preflight(&self, req: &Request, origin: &AsciiString, method: &str) -> Response {
+        let mut response = Response::no_content_204();
+        if !self.any_origin {
+            add_vary(&mut response, "origin");
+        }
+        add_vary(&mut response, "access-control-request-method");
+        add_vary(&mut response, "access-control-request-headers");
+        if !self.allows_origin(origin) || !self.methods.iter().any(|m| m == method) {
+            return response;
+        }
+        let requested_headers: Vec<String> = req
+            .headers
+            .get_all("access-control-request-headers")
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        if !self.any_header && !requested_headers.iter().all(|h| self.headers.contains(h)) {
+            return response;
+        }
+        self.add_allow_origin(&mut response, origin);
+        response
+            .headers
+            .add("access-control-allow-methods", join(&self.methods));
+        let allowed_headers = if self.any_header {
+            &requested_headers
+        } else {
+            &self.headers
+        };
+        if !allowed_headers.is_empty() {
+            response
+                .headers
+                .add("access-control-allow-headers", join(allowed_headers));
+        }
+        if let Some(max_age) = self.max_age {
+            response.headers.add(
+                "access-control-max-age",
+                max_age.as_secs().to_string().try_into().unwrap(),
+            );
+        }
+        response
+    }
+
+    /// Answers CORS preflight requests and calls `handler` for other requests,
+    /// adding CORS headers to its response.
+    ///
+    /// A preflight request is an `OPTIONS` request with `Origin` and
+    /// `Access-Control-Request-Method` headers.
+    /// The policy answers it with `204 No Content`.
+    /// When the policy does not allow the request, the response has no CORS headers,
+    /// so the browser does not send the request.
+    ///
+    /// For requests from origins that the policy does not allow,
+    /// `handler`'s response gets no CORS headers, so the browser hides it from the script.
+    /// When `handler`'s response already has `Access-Control-Allow-Origin`,
+    /// the policy leaves its CORS headers alone.
+    #[must_use]
+    pub fn handle<F>(&self, req: Request, handler: F) -> Response
+    where
+        F: FnOnce(Request) -> Response,
+    {
+        let opt_origin = req.headers.get_only("origin").cloned();
+        if req.method == "OPTIONS"
+            && let Some(origin) = &opt_origin
+            && let Some(method) = req.headers.get_only("access-control-request-method")
+        {
+            return self.preflight(&req, origin, method.as_str());
+        }
+        let mut response = handler(req);
+        if !response.is_normal() {
+            return response;
+        }
+        if !self.any_origin {
+            add_vary(&mut response, "origin");
+        }
+        if let Some(origin) = opt_origin
+            && self.allows_origin(&origin)
+            && response
+                .headers
+                .get_all("access-control-allow-origin")
+                .is_empty()
+        {
+            self.add_allow_origin(&mut response, &origin);
+            if !self.expose_headers.is_empty() {
+                response
+                    .headers
+                    .add("access-control-expose-headers", join(&self.expose_headers));
+            }
+        }
+        response
+    }
+}
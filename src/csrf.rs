@@ -0,0 +1,298 @@
+use crate::multipart::MultipartParser;
+use crate::rand::secure_random_bytes;
+use crate::url::percent_decode;
+use crate::util::{constant_time_eq, hex_encode};
+use crate::{ContentType, Cookie, Request, Response, SameSite};
+use std::io::Read;
+use std::path::PathBuf;
+
+const TOKEN_LEN: usize = 32;
+
+fn new_token() -> String {
+    hex_encode(&secure_random_bytes::<TOKEN_LEN>())
+}
+
+fn is_token(s: &str) -> bool {
+    s.len() == TOKEN_LEN * 2 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Returns the value of the field `name` in an `application/x-www-form-urlencoded` body.
+fn urlencoded_field(body: &[u8], name: &str) -> Option<String> {
+    // https://url.spec.whatwg.org/#urlencoded-parsing
+    let decode = |bytes: &[u8]| {
+        let bytes: Vec<u8> = bytes
+            .iter()
+            .map(|b| if *b == b'+' { b' ' } else { *b })
+            .collect();
+        percent_decode(bytes)
+    };
+    body.split(|b| *b == b'&').find_map(|pair| {
+        let (field_name, value) = match pair.iter().position(|b| *b == b'=') {
+            Some(n) => (&pair[..n], &pair[n + 1..]),
+            None => (pair, &[][..]),
+        };
+        (decode(field_name) == name).then(|| decode(value))
+    })
+}
+
+/// Protects form handlers from
+/// [cross-site request forgery](https://owasp.org/www-community/attacks/csrf).
+///
+/// [`Csrf::handle`] gives each client a random token in a cookie.
+/// Put the token in your forms with [`Csrf::form_field`].
+/// Scripts can send it in the `X-CSRF-Token` header instead.
+///
+/// For requests with methods other than `GET`, `HEAD`, `OPTIONS`, and `TRACE`,
+/// [`Csrf::handle`] checks that:
+/// - the `Sec-Fetch-Site` header is not `cross-site`,
+/// - the `Origin` header, when present, is the server's origin or a trusted origin, and
+/// - the request has the token from the cookie in the header or in
+///   an `application/x-www-form-urlencoded` or `multipart/form-data` body field.
+///
+/// It rejects other requests with `403 Forbidden`.
+///
+/// Example:
+/// ```
+/// use servlin::{Csrf, Request, Response};
+///
+/// let csrf = Csrf::new();
+/// let handler = move |req: Request| {
+///     csrf.handle(req, |req| match req.method.as_str() {
+///         "GET" => Response::html(
+///             200,
+///             format!(
+///                 "<form method='post'>{}<input type='submit'/></form>",
+///                 csrf.form_field(&req)
+///             ),
+///         ),
+///         _ => Response::redirect_303("/"),
+///     })
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct Csrf {
+    cookie_name: String,
+    field_name: String,
+    header_name: String,
+    trusted_origins: Vec<String>,
+    cache_dir: PathBuf,
+    max_body_len: u64,
+}
+impl Csrf {
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            cookie_name: "__Host-csrf".to_string(),
+            field_name: "csrf_token".to_string(),
+            header_name: "x-csrf-token".to_string(),
+            trusted_origins: Vec::new(),
+            cache_dir: std::env::temp_dir(),
+            max_body_len: 1024 * 1024,
+        }
+    }
+
+    /// Sets the name of the token cookie.
+    ///
+    /// Browsers accept a cookie with the `__Host-` prefix only from a secure origin
+    /// and do not let other subdomains set it,
+    /// so keep the prefix to stop attackers from planting a token in a client.
+    ///
+    /// Default: `__Host-csrf`
+    #[must_use]
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Sets the name of the form field with the token.
+    ///
+    /// Default: `csrf_token`
+    #[must_use]
+    pub fn field_name(mut self, name: impl Into<String>) -> Self {
+        self.field_name = name.into();
+        self
+    }
+
+    /// Sets the name of the request header with the token.
+    ///
+    /// Default: `x-csrf-token`
+    #[must_use]
+    pub fn header_name(mut self, name: impl Into<String>) -> Self {
+        self.header_name = name.into();
+        self
+    }
+
+    /// Accepts requests with this `Origin`, like `https://app.example.com`.
+    ///
+    /// The server's own origin is always allowed.
+    #[must_use]
+    pub fn trusted_origin(mut self, origin: impl AsRef<str>) -> Self {
+        self.trusted_origins
+            .push(origin.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Sets the directory for files from `multipart/form-data` bodies
+    /// while looking for the token.
+    ///
+    /// Default: [`std::env::temp_dir`]
+    #[must_use]
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = dir.into();
+        self
+    }
+
+    /// Sets the maximum length of form bodies, in bytes.
+    /// See [`Response::get_body_and_reprocess`].
+    ///
+    /// Default: 1 MiB
+    #[must_use]
+    pub fn max_body_len(mut self, n: u64) -> Self {
+        self.max_body_len = n;
+        self
+    }
+
+    /// Returns the request's CSRF token.
+    ///
+    /// # Panics
+    /// Panics when the request did not go through [`Csrf::handle`].
+    #[must_use]
+    pub fn token<'r>(&self, req: &'r Request) -> &'r str {
+        req.cookies
+            .get(&self.cookie_name)
+            .map(String::as_str)
+            .filter(|token| is_token(token))
+            .expect("request did not go through Csrf::handle")
+    }
+
+    /// Returns a hidden form field with the request's CSRF token.
+    ///
+    /// # Panics
+    /// Panics when the request did not go through [`Csrf::handle`].
+    #[must_use]
+    pub fn form_field(&self, req: &Request) -> String {
+        // The token is hex, so it needs no escaping.
+        format!(
+            "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+            self.field_name.replace('&', "&amp;").replace('"', "&quot;"),
+            self.token(req)
+        )
+    }
+
+    fn is_allowed_origin(&self, req: &Request, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        if self.trusted_origins.contains(&origin) {
+            return true;
+        }
+        let Some(host) = req.host() else {
+            return false;
+        };
+        let own_origin = format!("{}://{}", req.scheme(), host.to_ascii_lowercase());
+        let default_port = if req.scheme() == "https" {
+            ":443"
+        } else {
+            ":80"
+        };
+        let normalize = |s: &str| s.strip_suffix(default_port).unwrap_or(s).to_string();
+        normalize(&origin) == normalize(&own_origin)
+    }
+
+    /// Returns the token that the client submitted with the request.
+    fn submitted_token(&self, req: &Request) -> Result<Option<String>, Response> {
+        if let Some(value) = req.headers.get_only(&self.header_name) {
+            return Ok(Some(value.to_string()));
+        }
+        match req.content_type {
+            ContentType::FormUrlEncoded => {
+                if req.body.is_pending() {
+                    return Err(Response::get_body_and_reprocess(self.max_body_len));
+                }
+                let mut body = Vec::new();
+                req.body
+                    .reader()
+                    .and_then(|mut reader| reader.read_to_end(&mut body))
+                    .map_err(|_| Response::internal_server_error_500())?;
+                Ok(urlencoded_field(&body, &self.field_name))
+            }
+            ContentType::MultipartForm => {
+                if req.body.is_pending() {
+                    return Err(Response::get_body_and_reprocess(self.max_body_len));
+                }
+                let form = MultipartParser::new(&self.cache_dir)
+                    .max_total_len(self.max_body_len)
+                    .parse(req)?;
+                Ok(form.text(&self.field_name).map(str::to_string))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Checks a request with an unsafe method.
+    fn check(&self, req: &Request, opt_cookie_token: Option<&str>) -> Result<(), Response> {
+        // https://w3c.github.io/webappsec-fetch-metadata/#sec-fetch-site-header
+        if req
+            .headers
+            .get_only("sec-fetch-site")
+            .is_some_and(|value| value.eq_ignore_ascii_case("cross-site"))
+        {
+            return Err(Response::forbidden_403());
+        }
+        // https://datatracker.ietf.org/doc/html/rfc6454#section-7
+        if let Some(origin) = req.headers.get_only("origin")
+            && !self.is_allowed_origin(req, origin.as_str())
+        {
+            return Err(Response::forbidden_403());
+        }
+        let Some(cookie_token) = opt_cookie_token else {
+            return Err(Response::forbidden_403());
+        };
+        match self.submitted_token(req)? {
+            Some(token) if constant_time_eq(token.as_bytes(), cookie_token.as_bytes()) => Ok(()),
+            _ => Err(Response::forbidden_403()),
+        }
+    }
+
+    /// Checks the request and calls `handler`.
+    ///
+    /// When the client has no token, makes one and adds it to the request's cookies,
+    /// so `handler` can call [`Csrf::form_field`],
+    /// and sets the token cookie on the response.
+    ///
+    /// Returns `403 Forbidden` when the request fails the checks.
+    /// When it needs the body of a form request,
+    /// returns [`Response::get_body_and_reprocess`].
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn handle<F>(&self, mut req: Request, handler: F) -> Response
+    where
+        F: FnOnce(Request) -> Response,
+    {
+        let opt_cookie_token = req
+            .cookies
+            .get(&self.cookie_name)
+            .filter(|token| is_token(token))
+            .cloned();
+        if !matches!(req.method.as_str(), "GET" | "HEAD" | "OPTIONS" | "TRACE")
+            && let Err(response) = self.check(&req, opt_cookie_token.as_deref())
+        {
+            return response;
+        }
+        let opt_new_token = if opt_cookie_token.is_none() {
+            let token = new_token();
+            req.cookies.insert(self.cookie_name.clone(), token.clone());
+            Some(token)
+        } else {
+            None
+        };
+        let response = handler(req);
+        match opt_new_token {
+            Some(token) if response.is_normal() => response.with_set_cookie(
+                Cookie::new(&self.cookie_name, token.try_into().unwrap())
+                    .with_path("/")
+                    .with_same_site(SameSite::Lax),
+            ),
+            _ => response,
+        }
+    }
+}
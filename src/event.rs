@@ -73,10 +73,10 @@ impl EventSender {
     }
 
     pub fn send(&mut self, event: Event) {
-        if let Some(sender) = &self.0 {
-            if sender.try_send(event).is_err() {
-                self.0.take();
-            }
+        if let Some(sender) = &self.0
+            && sender.try_send(event).is_err()
+        {
+            self.0.take();
         }
     }
 
@@ -0,0 +1,312 @@
+use crate::cgi::{cgi_variables, gateway_error_response, parse_cgi_response};
+use crate::{Request, Response};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// https://fastcgi-archives.github.io/FastCGI_Specification.html#S8
+const FCGI_VERSION_1: u8 = 1;
+const FCGI_BEGIN_REQUEST: u8 = 1;
+const FCGI_END_REQUEST: u8 = 3;
+const FCGI_PARAMS: u8 = 4;
+const FCGI_STDIN: u8 = 5;
+const FCGI_STDOUT: u8 = 6;
+const FCGI_STDERR: u8 = 7;
+const FCGI_RESPONDER: u16 = 1;
+const FCGI_REQUEST_COMPLETE: u8 = 0;
+const REQUEST_ID: u16 = 1;
+const MAX_RECORD_CONTENT_LEN: usize = 65535;
+
+fn malformed(msg: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, format!("FastCGI responder {msg}"))
+}
+
+/// Appends a record to `buf`.
+fn write_record(buf: &mut Vec<u8>, record_type: u8, content: &[u8]) {
+    // https://fastcgi-archives.github.io/FastCGI_Specification.html#S3.3
+    assert!(content.len() <= MAX_RECORD_CONTENT_LEN);
+    let [id1, id0] = REQUEST_ID.to_be_bytes();
+    let [len1, len0] = u16::try_from(content.len()).unwrap().to_be_bytes();
+    buf.extend([FCGI_VERSION_1, record_type, id1, id0, len1, len0, 0, 0]);
+    buf.extend(content);
+}
+
+/// Appends the length of a name or value in a name-value pair.
+fn write_len(buf: &mut Vec<u8>, len: usize) {
+    // https://fastcgi-archives.github.io/FastCGI_Specification.html#S3.4
+    if len < 128 {
+        buf.push(u8::try_from(len).unwrap());
+    } else {
+        buf.extend((u32::try_from(len).unwrap() | 0x8000_0000).to_be_bytes());
+    }
+}
+
+trait FastCgiStream: Read + Write {
+    fn set_timeouts(&self, timeout: Duration) -> Result<(), std::io::Error>;
+}
+impl FastCgiStream for TcpStream {
+    fn set_timeouts(&self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+#[cfg(unix)]
+impl FastCgiStream for UnixStream {
+    fn set_timeouts(&self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+/// Makes the stream's timeouts expire at `deadline`.
+fn set_deadline(stream: &impl FastCgiStream, deadline: Instant) -> Result<(), std::io::Error> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(ErrorKind::TimedOut.into());
+    }
+    stream.set_timeouts(remaining)
+}
+
+#[derive(Clone, Debug)]
+enum FastCgiAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Sends requests to a FastCGI responder, like `php-fpm`.
+///
+/// The handler connects for each request and sends the request's
+/// [RFC 3875](https://datatracker.ietf.org/doc/html/rfc3875) meta-variables as params.
+/// Use [`FastCgi::param`] to add params that the responder needs, like `SCRIPT_FILENAME`.
+///
+/// The handler returns `502 Bad Gateway` when it cannot connect,
+/// the responder sends malformed or too much output,
+/// and `504 Gateway Timeout` when the responder takes too long.
+///
+/// <https://fastcgi-archives.github.io/FastCGI_Specification.html>
+///
+/// Example:
+/// ```
+/// use servlin::{FastCgi, Request};
+///
+/// let fastcgi = FastCgi::tcp("127.0.0.1:9000".parse().unwrap())
+///     .param("SCRIPT_FILENAME", "/var/www/index.php");
+/// let handler = move |req: Request| fastcgi.handle(&req);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FastCgi {
+    addr: FastCgiAddr,
+    params: Vec<(String, String)>,
+    script_name: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+    max_body_len: u64,
+    max_output_len: u64,
+}
+impl FastCgi {
+    fn new(addr: FastCgiAddr) -> Self {
+        Self {
+            addr,
+            params: Vec::new(),
+            script_name: String::new(),
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(30),
+            max_body_len: 64 * 1024 * 1024,
+            max_output_len: 64 * 1024 * 1024,
+        }
+    }
+
+    /// Makes a handler that connects to the responder at a TCP address.
+    #[must_use]
+    pub fn tcp(addr: SocketAddr) -> Self {
+        Self::new(FastCgiAddr::Tcp(addr))
+    }
+
+    /// Makes a handler that connects to the responder at a Unix socket path.
+    #[cfg(unix)]
+    #[must_use]
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self::new(FastCgiAddr::Unix(path.into()))
+    }
+
+    /// Adds a param to send with every request.
+    /// It replaces any meta-variable with the same name.
+    #[must_use]
+    pub fn param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the URL path of the script, like `/app`.
+    /// The responder gets the rest of the request path in `PATH_INFO`.
+    ///
+    /// The handler returns `404 Not Found` for requests with other paths.
+    ///
+    /// Default: `""`
+    #[must_use]
+    pub fn script_name(mut self, path: impl Into<String>) -> Self {
+        self.script_name = path.into();
+        self
+    }
+
+    /// Sets how long to wait to connect to the responder.
+    /// This applies only to TCP addresses.
+    ///
+    /// Default: 5 seconds
+    #[must_use]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets how long the responder may take to receive the request and send its response.
+    ///
+    /// Default: 30 seconds
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum length of request bodies, in bytes.
+    /// See [`Response::get_body_and_reprocess`].
+    ///
+    /// Default: 64 MiB
+    #[must_use]
+    pub fn max_body_len(mut self, n: u64) -> Self {
+        self.max_body_len = n;
+        self
+    }
+
+    /// Sets the maximum length of the responder's output, in bytes.
+    ///
+    /// Default: 64 MiB
+    #[must_use]
+    pub fn max_output_len(mut self, n: u64) -> Self {
+        self.max_output_len = n;
+        self
+    }
+
+    /// Sends `req` to the responder and returns its response.
+    ///
+    /// When the request body is pending, returns [`Response::get_body_and_reprocess`],
+    /// so the server calls the handler again with the whole body.
+    /// Responders need the body length before they start.
+    #[must_use]
+    pub fn handle(&self, req: &Request) -> Response {
+        if req.body.is_pending() {
+            return Response::get_body_and_reprocess(self.max_body_len);
+        }
+        let Some(mut vars) = cgi_variables(req, &self.script_name) else {
+            return Response::not_found_404();
+        };
+        for (name, value) in &self.params {
+            vars.retain(|(n, _)| n != name);
+            vars.push((name.clone(), value.clone()));
+        }
+        let deadline = Instant::now() + self.timeout;
+        let result = match &self.addr {
+            FastCgiAddr::Tcp(addr) => TcpStream::connect_timeout(addr, self.connect_timeout)
+                .and_then(|stream| self.exchange(stream, deadline, req, &vars)),
+            #[cfg(unix)]
+            FastCgiAddr::Unix(path) => UnixStream::connect(path)
+                .and_then(|stream| self.exchange(stream, deadline, req, &vars)),
+        };
+        match result {
+            Ok(output) => parse_cgi_response(&output).unwrap_or_else(Response::bad_gateway_502),
+            Err(e) => gateway_error_response(&e),
+        }
+    }
+
+    fn exchange(
+        &self,
+        mut stream: impl FastCgiStream,
+        deadline: Instant,
+        req: &Request,
+        vars: &[(String, String)],
+    ) -> Result<Vec<u8>, std::io::Error> {
+        // https://fastcgi-archives.github.io/FastCGI_Specification.html#S5.1
+        let mut buf = Vec::new();
+        let [role1, role0] = FCGI_RESPONDER.to_be_bytes();
+        // Flags are zero, so the responder closes the connection when it finishes.
+        write_record(
+            &mut buf,
+            FCGI_BEGIN_REQUEST,
+            &[role1, role0, 0, 0, 0, 0, 0, 0],
+        );
+        let mut params = Vec::new();
+        for (name, value) in vars {
+            write_len(&mut params, name.len());
+            write_len(&mut params, value.len());
+            params.extend(name.as_bytes());
+            params.extend(value.as_bytes());
+        }
+        for chunk in params.chunks(MAX_RECORD_CONTENT_LEN) {
+            write_record(&mut buf, FCGI_PARAMS, chunk);
+        }
+        write_record(&mut buf, FCGI_PARAMS, &[]);
+        set_deadline(&stream, deadline)?;
+        stream.write_all(&buf)?;
+        let mut reader = req.body.reader()?;
+        let mut chunk = vec![0_u8; MAX_RECORD_CONTENT_LEN];
+        loop {
+            let n = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            buf.clear();
+            write_record(&mut buf, FCGI_STDIN, &chunk[..n]);
+            set_deadline(&stream, deadline)?;
+            stream.write_all(&buf)?;
+        }
+        buf.clear();
+        write_record(&mut buf, FCGI_STDIN, &[]);
+        set_deadline(&stream, deadline)?;
+        stream.write_all(&buf)?;
+        let mut output = Vec::new();
+        loop {
+            set_deadline(&stream, deadline)?;
+            let mut header = [0_u8; 8];
+            stream.read_exact(&mut header)?;
+            if header[0] != FCGI_VERSION_1 {
+                return Err(malformed("sent a record with an unsupported version"));
+            }
+            let content_len = usize::from(u16::from_be_bytes([header[4], header[5]]));
+            let padding_len = usize::from(header[6]);
+            let mut content = vec![0_u8; content_len + padding_len];
+            set_deadline(&stream, deadline)?;
+            stream.read_exact(&mut content)?;
+            content.truncate(content_len);
+            if u16::from_be_bytes([header[2], header[3]]) != REQUEST_ID {
+                // Management records use request id 0.
+                continue;
+            }
+            match header[1] {
+                FCGI_STDOUT => {
+                    if (output.len() + content.len()) as u64 > self.max_output_len {
+                        return Err(malformed("output is too long"));
+                    }
+                    output.extend(content);
+                }
+                FCGI_STDERR => {
+                    let _ignored = std::io::stderr().write_all(&content);
+                }
+                FCGI_END_REQUEST => {
+                    // https://fastcgi-archives.github.io/FastCGI_Specification.html#S5.5
+                    if content.get(4) != Some(&FCGI_REQUEST_COMPLETE) {
+                        return Err(malformed("did not complete the request"));
+                    }
+                    return Ok(output);
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::headers::HeaderList;
+use crate::ip_cidr::IpCidr;
+use crate::remote_addr::RemoteAddr;
+use crate::util::is_tchar;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// What trusted proxies reported about the original request.
+///
+/// See [`HttpServerBuilder::trusted_proxies`](crate::HttpServerBuilder::trusted_proxies).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Forwarded {
+    /// The client's IP address.
+    /// `None` when the proxy hid it, with `for=unknown` or an obfuscated identifier.
+    pub client: Option<IpAddr>,
+    /// The scheme that the client used, `http` or `https`.
+    pub proto: Option<String>,
+    /// The `Host` header that the client sent.
+    pub host: Option<String>,
+}
+
+/// One element of a `Forwarded` header.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ForwardedElement {
+    pub for_: Option<String>,
+    pub proto: Option<String>,
+    pub host: Option<String>,
+}
+
+/// Parses the elements of `Forwarded` header values.
+///
+/// Returns `None` when a value is malformed.
+#[must_use]
+#[allow(clippy::missing_panics_doc)]
+pub fn parse_forwarded_header<'x>(
+    values: impl IntoIterator<Item = &'x str>,
+) -> Option<Vec<ForwardedElement>> {
+    // https://datatracker.ietf.org/doc/html/rfc7239#section-4
+    //     Forwarded   = 1#forwarded-element
+    //     forwarded-element = [ forwarded-pair ] *( ";" [ forwarded-pair ] )
+    //     forwarded-pair = token "=" value
+    //     value          = token / quoted-string
+    // https://datatracker.ietf.org/doc/html/rfc9110#section-5.6.1
+    //     A recipient MUST accept and ignore empty list elements.
+    let mut elements = Vec::new();
+    for value in values {
+        let mut bytes = value.as_bytes();
+        let mut element = ForwardedElement::default();
+        let mut empty = true;
+        loop {
+            while let [b' ' | b'\t', rest @ ..] = bytes {
+                bytes = rest;
+            }
+            match bytes {
+                [] => {
+                    if !empty {
+                        elements.push(element);
+                    }
+                    break;
+                }
+                [b',', rest @ ..] => {
+                    if !empty {
+                        elements.push(std::mem::take(&mut element));
+                    }
+                    empty = true;
+                    bytes = rest;
+                    continue;
+                }
+                [b';', rest @ ..] => {
+                    empty = false;
+                    bytes = rest;
+                    continue;
+                }
+                _ => empty = false,
+            }
+            let name_len = bytes
+                .iter()
+                .position(|b| !is_tchar(*b))
+                .unwrap_or(bytes.len());
+            if name_len == 0 || bytes.get(name_len) != Some(&b'=') {
+                return None;
+            }
+            let name = std::str::from_utf8(&bytes[..name_len])
+                .unwrap()
+                .to_ascii_lowercase();
+            bytes = &bytes[name_len + 1..];
+            let pair_value = if let [b'"', rest @ ..] = bytes {
+                let mut pair_value = String::new();
+                let mut n = 0;
+                loop {
+                    match rest.get(n) {
+                        None => return None,
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            pair_value.push(char::from(*rest.get(n + 1)?));
+                            n += 2;
+                        }
+                        Some(b) => {
+                            pair_value.push(char::from(*b));
+                            n += 1;
+                        }
+                    }
+                }
+                bytes = &rest[n + 1..];
+                pair_value
+            } else {
+                let len = bytes
+                    .iter()
+                    .position(|b| !is_tchar(*b))
+                    .unwrap_or(bytes.len());
+                if len == 0 {
+                    return None;
+                }
+                let pair_value = std::str::from_utf8(&bytes[..len]).unwrap().to_string();
+                bytes = &bytes[len..];
+                pair_value
+            };
+            while let [b' ' | b'\t', rest @ ..] = bytes {
+                bytes = rest;
+            }
+            if !matches!(bytes, [] | [b',' | b';', ..]) {
+                return None;
+            }
+            let field = match name.as_str() {
+                "for" => &mut element.for_,
+                "proto" => &mut element.proto,
+                "host" => &mut element.host,
+                _ => continue,
+            };
+            // Each parameter must not occur more than once per element.
+            if field.replace(pair_value).is_some() {
+                return None;
+            }
+        }
+    }
+    Some(elements)
+}
+
+/// Parses a node from `Forwarded: for=` or `X-Forwarded-For`.
+/// Returns `None` for `unknown`, obfuscated identifiers, and malformed values.
+#[must_use]
+pub fn parse_forwarded_node(node: &str) -> Option<IpAddr> {
+    // https://datatracker.ietf.org/doc/html/rfc7239#section-6
+    //     node     = nodename [ ":" node-port ]
+    //     nodename = IPv4address / "[" IPv6address "]" / "unknown" / obfnode
+    let node = node.trim();
+    if let Some(rest) = node.strip_prefix('[') {
+        let (ip, _port) = rest.split_once(']')?;
+        return ip.parse::<Ipv6Addr>().ok().map(IpAddr::V6);
+    }
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        // X-Forwarded-For may have bare IPv6 addresses.
+        return Some(ip);
+    }
+    let (ip, _port) = node.split_once(':')?;
+    ip.parse().ok().filter(IpAddr::is_ipv4)
+}
+
+fn parse_proto(proto: &str) -> Option<String> {
+    let proto = proto.trim().to_ascii_lowercase();
+    matches!(proto.as_str(), "http" | "https").then_some(proto)
+}
+
+fn parse_host(host: &str) -> Option<String> {
+    let host = host.trim();
+    (!host.is_empty() && host.bytes().all(|b| b.is_ascii_graphic() && b != b','))
+        .then(|| host.to_string())
+}
+
+fn is_trusted(trusted: &[IpCidr], ip: IpAddr) -> bool {
+    trusted.iter().any(|cidr| cidr.contains(ip))
+}
+
+/// Finds the original client address, scheme, and host of a request that came through proxies.
+///
+/// Returns `None` when the connection is not from a trusted proxy.
+/// Connections on Unix sockets are from trusted proxies.
+///
+/// Uses the `Forwarded` header when the request has one.
+/// Otherwise, it uses `X-Forwarded-For`, `X-Forwarded-Proto`, and `X-Forwarded-Host`.
+/// Each proxy adds an address to the end of the list.
+/// This walks the list from right to left, skipping trusted proxies,
+/// and stops at the first address that is not a trusted proxy.
+/// That address is the client.
+/// Addresses further left came from the client, so this ignores them.
+///
+/// When the headers are missing or malformed, the client is the connected proxy.
+#[must_use]
+pub fn resolve_forwarded(
+    remote_addr: &RemoteAddr,
+    headers: &HeaderList,
+    trusted: &[IpCidr],
+) -> Option<Forwarded> {
+    let peer = match remote_addr {
+        RemoteAddr::Tcp(addr) if is_trusted(trusted, addr.ip()) => Some(addr.ip()),
+        RemoteAddr::Tcp(..) => return None,
+        RemoteAddr::Unix(..) => None,
+    };
+    let mut forwarded = Forwarded {
+        client: peer,
+        proto: None,
+        host: None,
+    };
+    let forwarded_values = headers.get_all("forwarded");
+    if !forwarded_values.is_empty() {
+        let Some(elements) = parse_forwarded_header(forwarded_values.iter().map(|s| s.as_str()))
+        else {
+            return Some(forwarded);
+        };
+        for element in elements.iter().rev() {
+            forwarded.client = element.for_.as_deref().and_then(parse_forwarded_node);
+            forwarded.proto = element.proto.as_deref().and_then(parse_proto);
+            forwarded.host = element.host.as_deref().and_then(parse_host);
+            if !forwarded.client.is_some_and(|ip| is_trusted(trusted, ip)) {
+                break;
+            }
+        }
+        return Some(forwarded);
+    }
+    // These are not standard, so proxies use them differently.
+    // Most proxies send one `X-Forwarded-Proto` and `X-Forwarded-Host` value.
+    // We take the last one, which the nearest proxy added.
+    let last_value = |name: &str| -> Option<String> {
+        let values = headers.get_all(name);
+        let last = values.last()?.as_str().rsplit(',').next()?;
+        Some(last.to_string())
+    };
+    forwarded.proto = last_value("x-forwarded-proto")
+        .as_deref()
+        .and_then(parse_proto);
+    forwarded.host = last_value("x-forwarded-host")
+        .as_deref()
+        .and_then(parse_host);
+    for node in headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .flat_map(|s| s.as_str().split(','))
+        .filter(|node| !node.trim().is_empty())
+        .rev()
+    {
+        forwarded.client = parse_forwarded_node(node);
+        if !forwarded.client.is_some_and(|ip| is_trusted(trusted, ip)) {
+            break;
+        }
+    }
+    Some(forwarded)
+}
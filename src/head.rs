@@ -1,4 +1,5 @@
 use crate::http_error::HttpError;
+use crate::http_version::HttpVersion;
 use crate::util::find_slice;
 use crate::{AsciiString, Header, HeaderList, Url};
 use fixed_buffer::FixedBuf;
@@ -16,17 +17,17 @@ fn trim_trailing_cr(bytes: &[u8]) -> &[u8] {
 
 fn trim_whitespace(mut bytes: &[u8]) -> &[u8] {
     loop {
-        if let Some(&byte) = bytes.first() {
-            if byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n' {
-                bytes = bytes.split_first().unwrap().1;
-                continue;
-            }
+        if let Some(&byte) = bytes.first()
+            && (byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n')
+        {
+            bytes = bytes.split_first().unwrap().1;
+            continue;
         }
-        if let Some(&byte) = bytes.last() {
-            if byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n' {
-                bytes = bytes.split_last().unwrap().1;
-                continue;
-            }
+        if let Some(&byte) = bytes.last()
+            && (byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n')
+        {
+            bytes = bytes.split_last().unwrap().1;
+            continue;
         }
         break;
     }
@@ -42,25 +43,68 @@ pub enum HeadError {
     MalformedPath,
     UnsupportedProtocol,
     MalformedHeader,
+    AmbiguousBodyLength,
+    InvalidContentLength,
+    InvalidHost,
+    TooLong,
+    UriTooLong,
+    TooManyHeaders,
+    HeaderTooLong,
+}
+
+/// Settings for parsing request heads.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HeadOptions {
+    /// The maximum length of the request line and headers.
+    pub max_head_len: usize,
+    /// The maximum number of header lines.
+    pub max_header_count: usize,
+    /// The maximum length of one header line.
+    pub max_header_len: usize,
+    /// The maximum length of the request target, the URL in the request line.
+    pub max_url_len: usize,
+    /// Reject requests that do not strictly follow
+    /// [RFC 9112](https://datatracker.ietf.org/doc/html/rfc9112).
+    ///
+    /// Servers behind proxies use this to refuse the requests that proxies and servers
+    /// can disagree about, which attackers use to smuggle requests.
+    pub strict: bool,
+}
+impl Default for HeadOptions {
+    fn default() -> Self {
+        Self {
+            max_head_len: 8 * 1024,
+            max_header_count: 100,
+            max_header_len: 8 * 1024,
+            max_url_len: 8 * 1024,
+            strict: false,
+        }
+    }
 }
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct Head {
     pub method: String,
+    /// The request target exactly as the client sent it, like `/a%2Fb?c=d`.
+    pub target: String,
     pub url: Url,
+    pub version: HttpVersion,
     pub headers: HeaderList,
 }
 impl Head {
-    fn read_head_bytes<const BUF_SIZE: usize>(
-        buf: &mut FixedBuf<BUF_SIZE>,
-    ) -> Result<&[u8], HeadError> {
-        let head_len = find_slice(b"\r\n\r\n", buf.readable()).ok_or(HeadError::Truncated)?;
-        let head_bytes_with_delim = buf.try_read_exact(head_len + 4).unwrap();
-        let head_bytes = &head_bytes_with_delim[0..head_len];
-        Ok(head_bytes)
+    /// Returns the error for a head, or the start of a head, that is longer than allowed.
+    fn too_long(bytes: &[u8], options: &HeadOptions) -> HeadError {
+        let request_line = match find_slice(b"\n", bytes) {
+            Some(n) => &bytes[..n],
+            None => bytes,
+        };
+        match request_line.split(|&b| b == b' ').nth(1) {
+            Some(target) if target.len() > options.max_url_len => HeadError::UriTooLong,
+            _ => HeadError::TooLong,
+        }
     }
 
-    fn parse_request_line(line: &[u8]) -> Result<(String, Url), HeadError> {
+    fn parse_request_line(line: &[u8]) -> Result<(String, String, Url, HttpVersion), HeadError> {
         // https://datatracker.ietf.org/doc/html/rfc7230#section-3.1.1
         // https://datatracker.ietf.org/doc/html/rfc7230#section-5.3
         //     request-line   = method SP request-target SP HTTP-version CRLF
@@ -84,21 +128,115 @@ impl Head {
             .ok_or(HeadError::MalformedRequestLine)?;
         let method = std::str::from_utf8(method_bytes).unwrap().to_string();
         let url_string = std::str::from_utf8(path_bytes).map_err(|_| HeadError::MalformedPath)?;
-        if url_string != "*" && !url_string.starts_with('/') {
+        let url = if method == "CONNECT" {
+            Self::parse_authority_form(url_string)?
+        } else if url_string == "*" || url_string.starts_with('/') {
+            Url::parse_relative(url_string).map_err(|_| HeadError::MalformedPath)?
+        } else {
             return Err(HeadError::MalformedPath);
+        };
+        let version =
+            HttpVersion::parse_http1(proto_bytes).ok_or(HeadError::UnsupportedProtocol)?;
+        Ok((method, url_string.to_string(), url, version))
+    }
+
+    fn parse_authority_form(target: &str) -> Result<Url, HeadError> {
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-3.2.3
+        //     authority-form = uri-host ":" port
+        let url = Url::parse_absolute(format!("x://{target}"))
+            .ok()
+            .filter(|url| {
+                url.user.is_empty()
+                    && url.port.is_some()
+                    && url.path.is_empty()
+                    && url.query.is_empty()
+                    && url.fragment.is_empty()
+                    && !target.contains(['?', '#', '@'])
+            })
+            .ok_or(HeadError::MalformedPath)?;
+        Ok(Url {
+            scheme: String::new(),
+            ..url
+        })
+    }
+
+    /// Splits a head into lines, rejecting bare CR and LF bytes.
+    fn strict_lines(head: &[u8]) -> Vec<&[u8]> {
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-2.2
+        let mut lines = Vec::new();
+        let mut rest = head;
+        while let Some(n) = find_slice(b"\r\n", rest) {
+            lines.push(&rest[..n]);
+            rest = &rest[n + 2..];
+        }
+        lines.push(rest);
+        lines
+    }
+
+    fn check_strict_request_line(line: &[u8], method: &str) -> Result<(), HeadError> {
+        if line.iter().any(|&b| b == b'\r' || b == b'\n') {
+            return Err(HeadError::MalformedRequestLine);
+        }
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-3.2
+        let target = line.split(|&b| b == b' ').nth(1).unwrap_or_default();
+        if !target.iter().all(|&b| (0x21..=0x7E).contains(&b)) || target.contains(&b'#') {
+            return Err(HeadError::MalformedPath);
+        }
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-3.2.4
+        if target == b"*" && method != "OPTIONS" {
+            return Err(HeadError::MalformedPath);
+        }
+        Ok(())
+    }
+
+    fn check_strict_header_line(line: &[u8]) -> Result<(), HeadError> {
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-5.2
+        //     obs-fold     = OWS CRLF RWS
+        // https://datatracker.ietf.org/doc/html/rfc9110#section-5.5
+        //     field-vchar  = VCHAR / obs-text
+        match line.first() {
+            Some(b' ' | b'\t') => Err(HeadError::MalformedHeader),
+            _ if line.iter().any(|&b| (b < 0x20 && b != b'\t') || b == 0x7F) => {
+                Err(HeadError::MalformedHeader)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the headers that determine the message length and target host.
+    fn check_strict_headers(&self) -> Result<(), HeadError> {
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-3.2
+        if self.version == HttpVersion::Http11 && self.headers.get_all("host").len() != 1 {
+            return Err(HeadError::InvalidHost);
+        }
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-6.3
+        let content_lengths = self.headers.get_all("content-length");
+        let transfer_encodings = self.headers.get_all("transfer-encoding");
+        if content_lengths.len() > 1
+            || transfer_encodings.len() > 1
+            || content_lengths.iter().any(|s| s.as_str().contains(','))
+        {
+            return Err(HeadError::AmbiguousBodyLength);
+        }
+        if let Some(content_length) = content_lengths.first()
+            && (content_length.is_empty() || !content_length.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(HeadError::InvalidContentLength);
         }
-        let url = Url::parse_relative(url_string).map_err(|_| HeadError::MalformedPath)?;
-        if proto_bytes != b"HTTP/1.1" {
-            return Err(HeadError::UnsupportedProtocol);
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-6.1
+        if !transfer_encodings.is_empty()
+            && (!content_lengths.is_empty() || self.version == HttpVersion::Http10)
+        {
+            return Err(HeadError::AmbiguousBodyLength);
         }
-        Ok((method, url))
+        Ok(())
     }
 
     fn latin1_bytes_to_utf8(bytes: &[u8]) -> String {
         bytes.iter().map(|&b| b as char).collect()
     }
 
-    fn parse_header_line(line: &[u8]) -> Result<Header, HeadError> {
+    pub(crate) fn parse_header_line(line: &[u8]) -> Result<Header, HeadError> {
         // https://datatracker.ietf.org/doc/html/rfc7230#section-3.2
         //     header-field   = field-name ":" OWS field-value OWS
         //     field-name     = token
@@ -139,28 +277,86 @@ impl Head {
     pub fn try_read<const BUF_SIZE: usize>(
         buf: &mut FixedBuf<BUF_SIZE>,
     ) -> Result<Self, HeadError> {
-        let head = Self::read_head_bytes(buf)?;
-        let mut lines = head.split(|b| *b == b'\n').map(trim_trailing_cr);
+        Self::try_read_with(buf, &HeadOptions::default())
+    }
+
+    /// # Errors
+    /// Returns an error when:
+    /// - the buffer does not contain a full request head, ending in `"\r\n"`
+    /// - we fail to parse the request head
+    /// - `options.strict` is set and the request head does not strictly follow RFC 9112
+    #[allow(clippy::missing_panics_doc)]
+    pub fn try_read_with<const BUF_SIZE: usize>(
+        buf: &mut FixedBuf<BUF_SIZE>,
+        options: &HeadOptions,
+    ) -> Result<Self, HeadError> {
+        let Some(head_len) = find_slice(b"\r\n\r\n", buf.readable()) else {
+            return if buf.len() > options.max_head_len {
+                Err(Self::too_long(buf.readable(), options))
+            } else {
+                Err(HeadError::Truncated)
+            };
+        };
+        let head_bytes_with_delim = buf.try_read_exact(head_len + 4).unwrap();
+        Self::parse(&head_bytes_with_delim[0..head_len], options)
+    }
+
+    /// Parses a request head, without the blank line at the end.
+    fn parse(head: &[u8], options: &HeadOptions) -> Result<Self, HeadError> {
+        if head.len() > options.max_head_len {
+            return Err(Self::too_long(head, options));
+        }
+        let lines: Vec<&[u8]> = if options.strict {
+            Self::strict_lines(head)
+        } else {
+            head.split(|b| *b == b'\n').map(trim_trailing_cr).collect()
+        };
+        let mut lines = lines.into_iter();
         let request_line = lines.next().ok_or(HeadError::MissingRequestLine)?;
-        let (method, url) = Self::parse_request_line(request_line)?;
+        if request_line
+            .split(|&b| b == b' ')
+            .nth(1)
+            .is_some_and(|target| target.len() > options.max_url_len)
+        {
+            return Err(HeadError::UriTooLong);
+        }
+        let (method, target, url, version) = Self::parse_request_line(request_line)?;
+        if options.strict {
+            Self::check_strict_request_line(request_line, &method)?;
+        }
+        if lines.len() > options.max_header_count {
+            return Err(HeadError::TooManyHeaders);
+        }
         let mut headers = HeaderList::new();
         for line in lines {
+            if line.len() > options.max_header_len {
+                return Err(HeadError::HeaderTooLong);
+            }
+            if options.strict {
+                Self::check_strict_header_line(line)?;
+            }
             let header = Self::parse_header_line(line)?;
             headers.push(header);
         }
-        Ok(Self {
+        let head = Self {
             method,
+            target,
             url,
+            version,
             headers,
-        })
+        };
+        if options.strict {
+            head.check_strict_headers()?;
+        }
+        Ok(head)
     }
 }
 impl core::fmt::Debug for Head {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
         write!(
             f,
-            "Head{{method={:?}, url={}, headers={:?}}}",
-            self.method, self.url, self.headers
+            "Head{{method={:?}, url={}, version={}, headers={:?}}}",
+            self.method, self.url, self.version, self.headers
         )
     }
 }
@@ -169,25 +365,46 @@ impl core::fmt::Debug for Head {
 /// Returns an error when:
 /// - the connection is closed
 /// - we fail to read a request head
-/// - the request head is too long
+/// - the request head is longer than `options` allow
 /// - we fail to parse the request head
+///
+/// Reads heads that do not fit in `buf` into a temporary `Vec`.
+#[allow(clippy::missing_panics_doc)]
 #[allow(clippy::module_name_repetitions)]
 pub async fn read_http_head<const BUF_SIZE: usize>(
     buf: &mut FixedBuf<BUF_SIZE>,
     mut stream: impl AsyncRead + Unpin,
+    options: &HeadOptions,
 ) -> Result<Head, HttpError> {
+    // The start of a head that is larger than `buf`.
+    let mut spill: Vec<u8> = Vec::new();
     loop {
         //dbg!(&buf);
-        match Head::try_read(buf) {
-            Ok(head) => return Ok(head),
-            Err(HeadError::Truncated) => {}
-            Err(e) => return Err(e.into()),
+        if spill.is_empty() {
+            match Head::try_read_with(buf, options) {
+                Ok(head) => return Ok(head),
+                Err(HeadError::Truncated) => {}
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            let search_start = spill.len().saturating_sub(3);
+            spill.extend(buf.read_all());
+            if let Some(n) = find_slice(b"\r\n\r\n", &spill[search_start..]) {
+                let head_len = search_start + n;
+                buf.write_bytes(&spill[head_len + 4..]).unwrap();
+                return Ok(Head::parse(&spill[..head_len], options)?);
+            }
+            if spill.len() > options.max_head_len {
+                return Err(Head::too_long(&spill, options).into());
+            }
         }
         if buf.writable().is_empty() {
-            return Err(HttpError::HeadTooLong);
+            spill.extend(buf.read_all());
         }
         match stream.read(buf.writable()).await {
-            Err(..) | Ok(0) if buf.is_empty() => return Err(HttpError::Disconnected),
+            Err(..) | Ok(0) if buf.is_empty() && spill.is_empty() => {
+                return Err(HttpError::Disconnected);
+            }
             Err(..) | Ok(0) => return Err(HttpError::Truncated),
             Ok(n) => buf.wrote(n),
         }
@@ -0,0 +1,677 @@
+// HPACK header compression for HTTP/2.
+// https://datatracker.ietf.org/doc/html/rfc7541
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::sync::OnceLock;
+
+/// <https://datatracker.ietf.org/doc/html/rfc7541#appendix-A>
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// `(code, bit_len)` for each byte value and EOS (256).
+///
+/// The code is canonical: codes of the same length are consecutive, in symbol order.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc7541#appendix-B>
+// The codes match the hex column of the RFC's table, so they have no digit separators.
+#[allow(clippy::unreadable_literal)]
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+const HUFFMAN_EOS: u16 = 256;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum HpackError {
+    /// The header block is malformed.
+    Malformed,
+    /// The decoded header list is larger than the limit.
+    /// The decoder processed the whole block, so it can decode later blocks.
+    HeaderListTooLong,
+}
+impl Display for HpackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HpackError::Malformed => write!(f, "malformed header block"),
+            HpackError::HeaderListTooLong => write!(f, "header list is too long"),
+        }
+    }
+}
+impl std::error::Error for HpackError {}
+
+struct HuffmanDecodeTable {
+    /// Symbols ordered by `(bit_len, symbol)`.
+    symbols: Vec<u16>,
+    /// For each bit length, `(first_code, first_index, count)`.
+    lens: [(u32, usize, usize); 31],
+}
+impl HuffmanDecodeTable {
+    fn get() -> &'static Self {
+        static TABLE: OnceLock<HuffmanDecodeTable> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            let mut symbols: Vec<u16> = (0..=HUFFMAN_EOS).collect();
+            symbols.sort_by_key(|s| (HUFFMAN_CODES[usize::from(*s)].1, *s));
+            let mut lens = [(0, 0, 0); 31];
+            for (index, symbol) in symbols.iter().enumerate() {
+                let (code, len) = HUFFMAN_CODES[usize::from(*symbol)];
+                let entry = &mut lens[usize::from(len)];
+                if entry.2 == 0 {
+                    *entry = (code, index, 0);
+                }
+                entry.2 += 1;
+            }
+            Self { symbols, lens }
+        })
+    }
+}
+
+/// # Errors
+/// Returns an error when `bytes` contains EOS, an incomplete code, or invalid padding.
+#[allow(clippy::missing_panics_doc)]
+pub fn huffman_decode(bytes: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let table = HuffmanDecodeTable::get();
+    let mut result = Vec::with_capacity(bytes.len() * 8 / 5);
+    let mut code: u32 = 0;
+    let mut len: usize = 0;
+    for byte in bytes {
+        for shift in (0..8).rev() {
+            code = (code << 1) | u32::from((byte >> shift) & 1);
+            len += 1;
+            let (first_code, first_index, count) = table.lens[len];
+            if count != 0 && code >= first_code && ((code - first_code) as usize) < count {
+                let symbol = table.symbols[first_index + (code - first_code) as usize];
+                if symbol == HUFFMAN_EOS {
+                    return Err(HpackError::Malformed);
+                }
+                result.push(u8::try_from(symbol).unwrap());
+                code = 0;
+                len = 0;
+            } else if len == 30 {
+                return Err(HpackError::Malformed);
+            }
+        }
+    }
+    // Padding is the most-significant bits of EOS, all ones, shorter than 8 bits.
+    if len > 7 || code != (1 << len) - 1 {
+        return Err(HpackError::Malformed);
+    }
+    Ok(result)
+}
+
+#[must_use]
+#[allow(clippy::missing_panics_doc)]
+pub fn huffman_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(huffman_encoded_len(bytes));
+    let mut acc: u64 = 0;
+    let mut acc_len: u32 = 0;
+    for byte in bytes {
+        let (code, len) = HUFFMAN_CODES[usize::from(*byte)];
+        acc = (acc << len) | u64::from(code);
+        acc_len += u32::from(len);
+        while acc_len >= 8 {
+            acc_len -= 8;
+            result.push(u8::try_from((acc >> acc_len) & 0xFF).unwrap());
+        }
+    }
+    if acc_len > 0 {
+        let pad = 8 - acc_len;
+        result.push(u8::try_from(((acc << pad) | ((1 << pad) - 1)) & 0xFF).unwrap());
+    }
+    result
+}
+
+fn huffman_encoded_len(bytes: &[u8]) -> usize {
+    let bits: usize = bytes
+        .iter()
+        .map(|b| usize::from(HUFFMAN_CODES[usize::from(*b)].1))
+        .sum();
+    bits.div_ceil(8)
+}
+
+/// Decodes an integer with an `n`-bit prefix.
+/// Returns the value and the number of bytes used.
+fn decode_int(bytes: &[u8], n: u8) -> Result<(usize, usize), HpackError> {
+    // https://datatracker.ietf.org/doc/html/rfc7541#section-5.1
+    let first = bytes.first().ok_or(HpackError::Malformed)?;
+    let max_prefix = (1_usize << n) - 1;
+    let mut value = usize::from(*first) & max_prefix;
+    if value < max_prefix {
+        return Ok((value, 1));
+    }
+    let mut shift = 0;
+    for (n, byte) in bytes.iter().enumerate().skip(1) {
+        if shift > 21 {
+            // Reject values over 2^28, like other implementations do.
+            return Err(HpackError::Malformed);
+        }
+        value += usize::from(byte & 0x7F) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok((value, n + 1));
+        }
+    }
+    Err(HpackError::Malformed)
+}
+
+fn encode_int(buf: &mut Vec<u8>, first_bits: u8, n: u8, value: usize) {
+    let max_prefix = (1_usize << n) - 1;
+    if value < max_prefix {
+        buf.push(first_bits | u8::try_from(value).unwrap());
+        return;
+    }
+    buf.push(first_bits | u8::try_from(max_prefix).unwrap());
+    let mut rest = value - max_prefix;
+    while rest >= 0x80 {
+        buf.push(u8::try_from(rest & 0x7F).unwrap() | 0x80);
+        rest >>= 7;
+    }
+    buf.push(u8::try_from(rest).unwrap());
+}
+
+fn decode_string(bytes: &[u8]) -> Result<(Vec<u8>, usize), HpackError> {
+    // https://datatracker.ietf.org/doc/html/rfc7541#section-5.2
+    let huffman = bytes.first().ok_or(HpackError::Malformed)? & 0x80 != 0;
+    let (len, int_len) = decode_int(bytes, 7)?;
+    let end = int_len.checked_add(len).ok_or(HpackError::Malformed)?;
+    let string_bytes = bytes.get(int_len..end).ok_or(HpackError::Malformed)?;
+    let string = if huffman {
+        huffman_decode(string_bytes)?
+    } else {
+        string_bytes.to_vec()
+    };
+    Ok((string, end))
+}
+
+fn encode_string(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let huffman_len = huffman_encoded_len(bytes);
+    if huffman_len < bytes.len() {
+        encode_int(buf, 0x80, 7, huffman_len);
+        buf.extend(huffman_encode(bytes));
+    } else {
+        encode_int(buf, 0, 7, bytes.len());
+        buf.extend(bytes);
+    }
+}
+
+/// Size of an entry in the dynamic table.
+fn entry_size(name: &[u8], value: &[u8]) -> usize {
+    name.len() + value.len() + 32
+}
+
+/// A decoded `(name, value)` pair.
+pub type HeaderField = (Vec<u8>, Vec<u8>);
+
+/// Decodes header blocks from one side of a connection.
+///
+/// Use one decoder per connection and decode the blocks in the order they arrive.
+pub struct HpackDecoder {
+    dynamic_table: VecDeque<HeaderField>,
+    table_size: usize,
+    max_table_size: usize,
+    /// The limit from our `SETTINGS_HEADER_TABLE_SIZE`.
+    table_size_limit: usize,
+}
+impl HpackDecoder {
+    /// Makes a decoder that lets the peer use `table_size_limit` bytes for the dynamic table.
+    #[must_use]
+    pub fn new(table_size_limit: usize) -> Self {
+        Self {
+            dynamic_table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: table_size_limit,
+            table_size_limit,
+        }
+    }
+
+    fn get(&self, index: usize) -> Result<(&[u8], &[u8]), HpackError> {
+        match index {
+            0 => Err(HpackError::Malformed),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.as_bytes(), value.as_bytes()))
+            }
+            _ => self
+                .dynamic_table
+                .get(index - 62)
+                .map(|(name, value)| (name.as_slice(), value.as_slice()))
+                .ok_or(HpackError::Malformed),
+        }
+    }
+
+    fn evict_to(&mut self, size: usize) {
+        while self.table_size > size {
+            let (name, value) = self.dynamic_table.pop_back().unwrap();
+            self.table_size -= entry_size(&name, &value);
+        }
+    }
+
+    fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) {
+        let size = entry_size(&name, &value);
+        if size > self.max_table_size {
+            self.evict_to(0);
+            return;
+        }
+        self.evict_to(self.max_table_size - size);
+        self.table_size += size;
+        self.dynamic_table.push_front((name, value));
+    }
+
+    /// Decodes a complete header block into `(name, value)` pairs.
+    ///
+    /// # Errors
+    /// Returns [`HpackError::Malformed`] when the block is invalid.
+    /// The decoder is then unusable and the connection must close.
+    ///
+    /// Returns [`HpackError::HeaderListTooLong`] when the decoded headers are larger than
+    /// `max_list_size`, as defined by `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    pub fn decode(
+        &mut self,
+        mut block: &[u8],
+        max_list_size: usize,
+    ) -> Result<Vec<HeaderField>, HpackError> {
+        // https://datatracker.ietf.org/doc/html/rfc7541#section-6
+        let mut headers = Vec::new();
+        let mut list_size: usize = 0;
+        let mut allow_size_update = true;
+        while let Some(first) = block.first() {
+            let (name, value, used) = if first & 0x80 != 0 {
+                // Indexed Header Field
+                let (index, used) = decode_int(block, 7)?;
+                let (name, value) = self.get(index)?;
+                (name.to_vec(), value.to_vec(), used)
+            } else if first & 0xE0 == 0x20 {
+                // Dynamic Table Size Update
+                if !allow_size_update {
+                    return Err(HpackError::Malformed);
+                }
+                let (size, used) = decode_int(block, 5)?;
+                if size > self.table_size_limit {
+                    return Err(HpackError::Malformed);
+                }
+                self.max_table_size = size;
+                self.evict_to(size);
+                block = &block[used..];
+                continue;
+            } else {
+                // Literal Header Field with Incremental Indexing, without Indexing, or Never Indexed
+                let indexing = first & 0xC0 == 0x40;
+                let prefix_len = if indexing { 6 } else { 4 };
+                let (index, mut used) = decode_int(block, prefix_len)?;
+                let name = if index == 0 {
+                    let (name, name_len) = decode_string(&block[used..])?;
+                    used += name_len;
+                    name
+                } else {
+                    self.get(index)?.0.to_vec()
+                };
+                let (value, value_len) = decode_string(&block[used..])?;
+                used += value_len;
+                if indexing {
+                    self.insert(name.clone(), value.clone());
+                }
+                (name, value, used)
+            };
+            allow_size_update = false;
+            block = &block[used..];
+            list_size = list_size.saturating_add(entry_size(&name, &value));
+            if list_size <= max_list_size {
+                headers.push((name, value));
+            }
+        }
+        if list_size > max_list_size {
+            return Err(HpackError::HeaderListTooLong);
+        }
+        Ok(headers)
+    }
+}
+
+/// Encodes header blocks without using the dynamic table.
+///
+/// The encoder keeps no state, so blocks may be sent in any order.
+#[must_use]
+pub fn hpack_encode<'a>(headers: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (name, value) in headers {
+        let mut name_index = 0;
+        let mut full_index = 0;
+        for (n, (static_name, static_value)) in STATIC_TABLE.iter().enumerate() {
+            if *static_name == name {
+                if name_index == 0 {
+                    name_index = n + 1;
+                }
+                if static_value.as_bytes() == value {
+                    full_index = n + 1;
+                    break;
+                }
+            }
+        }
+        if full_index != 0 {
+            encode_int(&mut buf, 0x80, 7, full_index);
+            continue;
+        }
+        // Literal Header Field without Indexing
+        encode_int(&mut buf, 0x00, 4, name_index);
+        if name_index == 0 {
+            encode_string(&mut buf, name.as_bytes());
+        }
+        encode_string(&mut buf, value);
+    }
+    buf
+}
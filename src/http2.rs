@@ -0,0 +1,1565 @@
+// HTTP/2 over cleartext TCP (h2c).
+// https://datatracker.ietf.org/doc/html/rfc9113
+use crate::body_stream::{body_stream, copy_to_body_stream};
+use crate::conn_stream::ConnStream;
+use crate::head::Head;
+use crate::hpack::{HeaderField, HpackDecoder, HpackError, hpack_encode};
+use crate::http_error::HttpError;
+use crate::http_version::HttpVersion;
+use crate::request::request_from_head;
+use crate::request_body::{
+    read_http_body_to_file, read_http_body_to_vec, read_http_unsized_body_to_file,
+};
+use crate::response::ResponseKind;
+use crate::token_set::Token;
+use crate::upgrade::UpgradedConn;
+use crate::util::{base64url_decode, is_tchar};
+use crate::{AsciiString, ContentType, HeaderList, Request, RequestBody, Response, Url};
+use futures_io::AsyncRead;
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use permit::Permit;
+use safina::sync::{OneSender, oneshot};
+use std::collections::{HashMap, VecDeque};
+use std::future::{Future, poll_fn};
+use std::io::ErrorKind;
+use std::net::Shutdown;
+use std::path::PathBuf;
+use std::pin::{Pin, pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// The bytes that an HTTP/2 client sends first.
+pub const HTTP2_PREFACE: &[u8; 24] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_DATA: u8 = 0x0;
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_PRIORITY: u8 = 0x2;
+const FRAME_RST_STREAM: u8 = 0x3;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_PUSH_PROMISE: u8 = 0x5;
+const FRAME_PING: u8 = 0x6;
+const FRAME_GOAWAY: u8 = 0x7;
+const FRAME_WINDOW_UPDATE: u8 = 0x8;
+const FRAME_CONTINUATION: u8 = 0x9;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_ACK: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+/// HTTP/2 error codes.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc9113#section-7>
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Http2ErrorCode {
+    NoError = 0x0,
+    ProtocolError = 0x1,
+    InternalError = 0x2,
+    FlowControlError = 0x3,
+    StreamClosed = 0x5,
+    FrameSizeError = 0x6,
+    RefusedStream = 0x7,
+    Cancel = 0x8,
+    CompressionError = 0x9,
+    EnhanceYourCalm = 0xb,
+}
+
+const DEFAULT_WINDOW_SIZE: u32 = 65_535;
+const MAX_WINDOW_SIZE: i64 = 0x7FFF_FFFF;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+const HEADER_TABLE_SIZE: usize = 4096;
+const MAX_CONCURRENT_STREAMS: usize = 100;
+const MAX_HEADER_LIST_SIZE: usize = 16 * 1024;
+/// Limit on a header block split over HEADERS and CONTINUATION frames.
+const MAX_HEADER_BLOCK_LEN: usize = 64 * 1024;
+/// Limit on commands waiting for the writer task.
+/// A client that sends frames that need replies, like PING and SETTINGS, faster than it reads
+/// the replies gets GOAWAY with `ENHANCE_YOUR_CALM`.
+const MAX_QUEUED_CMDS: usize = 1000;
+
+fn frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(9 + payload.len());
+    push_frame(&mut bytes, frame_type, flags, stream_id, payload);
+    bytes
+}
+
+fn push_frame(buf: &mut Vec<u8>, frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+    // https://datatracker.ietf.org/doc/html/rfc9113#section-4.1
+    let len = u32::try_from(payload.len()).unwrap();
+    buf.extend(&len.to_be_bytes()[1..]);
+    buf.push(frame_type);
+    buf.push(flags);
+    buf.extend((stream_id & 0x7FFF_FFFF).to_be_bytes());
+    buf.extend(payload);
+}
+
+fn rst_stream_frame(stream_id: u32, code: Http2ErrorCode) -> Vec<u8> {
+    frame(FRAME_RST_STREAM, 0, stream_id, &(code as u32).to_be_bytes())
+}
+
+fn window_update_frame(stream_id: u32, increment: u32) -> Vec<u8> {
+    frame(FRAME_WINDOW_UPDATE, 0, stream_id, &increment.to_be_bytes())
+}
+
+fn settings_frame(settings: &[(u16, u32)]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for (id, value) in settings {
+        payload.extend(id.to_be_bytes());
+        payload.extend(value.to_be_bytes());
+    }
+    frame(FRAME_SETTINGS, 0, 0, &payload)
+}
+
+/// Parses the payload of a SETTINGS frame into `(id, value)` pairs.
+fn parse_settings(payload: &[u8]) -> Result<Vec<(u16, u32)>, Http2ErrorCode> {
+    if !payload.len().is_multiple_of(6) {
+        return Err(Http2ErrorCode::FrameSizeError);
+    }
+    Ok(payload
+        .chunks(6)
+        .map(|b| {
+            (
+                u16::from_be_bytes([b[0], b[1]]),
+                u32::from_be_bytes([b[2], b[3], b[4], b[5]]),
+            )
+        })
+        .collect())
+}
+
+/// Settings from the client that affect how we send.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct PeerSettings {
+    initial_window_size: Option<u32>,
+    max_frame_size: Option<u32>,
+}
+impl PeerSettings {
+    fn parse(settings: &[(u16, u32)]) -> Result<Self, Http2ErrorCode> {
+        // https://datatracker.ietf.org/doc/html/rfc9113#section-6.5.2
+        let mut result = Self::default();
+        for (id, value) in settings {
+            match *id {
+                SETTINGS_ENABLE_PUSH if *value > 1 => return Err(Http2ErrorCode::ProtocolError),
+                SETTINGS_INITIAL_WINDOW_SIZE if i64::from(*value) > MAX_WINDOW_SIZE => {
+                    return Err(Http2ErrorCode::FlowControlError);
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE => result.initial_window_size = Some(*value),
+                SETTINGS_MAX_FRAME_SIZE if !(16_384..=16_777_215).contains(value) => {
+                    return Err(Http2ErrorCode::ProtocolError);
+                }
+                SETTINGS_MAX_FRAME_SIZE => result.max_frame_size = Some(*value),
+                // We do not push and our encoder does not use the dynamic table.
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+enum Cmd {
+    /// Send these bytes.
+    Frame(Vec<u8>),
+    /// Apply client settings and then acknowledge them when `ack` is true.
+    Settings { settings: PeerSettings, ack: bool },
+    Headers {
+        stream_id: u32,
+        block: Vec<u8>,
+        end_stream: bool,
+    },
+    /// Send the data when flow control allows and then notify `sent`.
+    Data {
+        stream_id: u32,
+        data: Vec<u8>,
+        end_stream: bool,
+        sent: OneSender<()>,
+    },
+    /// The client increased a send window.
+    WindowUpdate { stream_id: u32, increment: u32 },
+    /// Send `RST_STREAM` and drop pending data.
+    Reset {
+        stream_id: u32,
+        code: Http2ErrorCode,
+    },
+    /// The client reset the stream.  Drop pending data.
+    ClientReset { stream_id: u32 },
+    /// The stream task finished.
+    StreamDone { stream_id: u32 },
+    /// Send `GOAWAY` and close the connection.
+    GoAway {
+        last_stream_id: u32,
+        code: Http2ErrorCode,
+    },
+}
+
+struct QueueInner {
+    cmds: VecDeque<Cmd>,
+    waker: Option<Waker>,
+    senders: usize,
+    closed: bool,
+}
+
+/// Commands for the writer task.  Sending never waits.
+struct CmdSender(Arc<Mutex<QueueInner>>);
+impl CmdSender {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(QueueInner {
+            cmds: VecDeque::new(),
+            waker: None,
+            senders: 1,
+            closed: false,
+        })))
+    }
+
+    fn send(&self, cmd: Cmd) {
+        let mut inner = self.0.lock().unwrap();
+        if inner.closed {
+            return;
+        }
+        if inner.cmds.len() >= MAX_QUEUED_CMDS {
+            // Drop the backlog, send GOAWAY, and close the connection.
+            inner.cmds.clear();
+            inner.cmds.push_back(Cmd::GoAway {
+                last_stream_id: 0,
+                code: Http2ErrorCode::EnhanceYourCalm,
+            });
+            inner.closed = true;
+        } else {
+            inner.cmds.push_back(cmd);
+        }
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns true after `close` or after too many commands queued up.
+    fn is_closed(&self) -> bool {
+        self.0.lock().unwrap().closed
+    }
+
+    /// Stops the writer and makes `send` drop commands.
+    fn close(&self) {
+        let mut inner = self.0.lock().unwrap();
+        inner.closed = true;
+        inner.cmds.clear();
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Waits for commands.  Returns `None` when all other senders are dropped.
+    async fn recv_all(&self) -> Option<VecDeque<Cmd>> {
+        poll_fn(|cx| {
+            let mut inner = self.0.lock().unwrap();
+            if !inner.cmds.is_empty() {
+                return Poll::Ready(Some(std::mem::take(&mut inner.cmds)));
+            }
+            if inner.senders <= 1 || inner.closed {
+                return Poll::Ready(None);
+            }
+            inner.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+impl Clone for CmdSender {
+    fn clone(&self) -> Self {
+        self.0.lock().unwrap().senders += 1;
+        Self(self.0.clone())
+    }
+}
+impl Drop for CmdSender {
+    fn drop(&mut self) {
+        let mut inner = self.0.lock().unwrap();
+        inner.senders -= 1;
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct PendingData {
+    data: Vec<u8>,
+    pos: usize,
+    end_stream: bool,
+    sent: OneSender<()>,
+}
+
+struct SendStream {
+    window: i64,
+    pending: VecDeque<PendingData>,
+    reset: bool,
+}
+
+/// Sends frames and enforces the client's flow control windows.
+struct Writer {
+    conn_window: i64,
+    initial_window: i64,
+    max_frame_size: usize,
+    streams: HashMap<u32, SendStream>,
+    /// `(last_stream_id, code)` after we send GOAWAY for an error.
+    go_away: Option<(u32, Http2ErrorCode)>,
+}
+impl Writer {
+    fn new() -> Self {
+        Self {
+            conn_window: i64::from(DEFAULT_WINDOW_SIZE),
+            initial_window: i64::from(DEFAULT_WINDOW_SIZE),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            streams: HashMap::new(),
+            go_away: None,
+        }
+    }
+
+    fn stream(&mut self, stream_id: u32) -> &mut SendStream {
+        let initial_window = self.initial_window;
+        self.streams.entry(stream_id).or_insert_with(|| SendStream {
+            window: initial_window,
+            pending: VecDeque::new(),
+            reset: false,
+        })
+    }
+
+    fn apply_settings(&mut self, settings: &PeerSettings) {
+        if let Some(size) = settings.initial_window_size {
+            let delta = i64::from(size) - self.initial_window;
+            self.initial_window = i64::from(size);
+            for stream in self.streams.values_mut() {
+                stream.window += delta;
+                if stream.window > MAX_WINDOW_SIZE {
+                    self.go_away = Some((0, Http2ErrorCode::FlowControlError));
+                }
+            }
+        }
+        if let Some(size) = settings.max_frame_size {
+            self.max_frame_size = usize::try_from(size).unwrap();
+        }
+    }
+
+    fn apply(&mut self, cmd: Cmd, buf: &mut Vec<u8>) {
+        match cmd {
+            Cmd::Frame(bytes) => buf.extend(bytes),
+            Cmd::Settings { settings, ack } => {
+                self.apply_settings(&settings);
+                if ack {
+                    push_frame(buf, FRAME_SETTINGS, FLAG_ACK, 0, &[]);
+                }
+            }
+            Cmd::Headers {
+                stream_id,
+                block,
+                end_stream,
+            } => {
+                let max_frame_size = self.max_frame_size;
+                if self.stream(stream_id).reset {
+                    return;
+                }
+                push_header_block(buf, stream_id, &block, end_stream, max_frame_size);
+            }
+            Cmd::Data {
+                stream_id,
+                data,
+                end_stream,
+                sent,
+            } => {
+                let stream = self.stream(stream_id);
+                if !stream.reset {
+                    stream.pending.push_back(PendingData {
+                        data,
+                        pos: 0,
+                        end_stream,
+                        sent,
+                    });
+                }
+            }
+            Cmd::WindowUpdate {
+                stream_id: 0,
+                increment,
+            } => {
+                self.conn_window += i64::from(increment);
+                if self.conn_window > MAX_WINDOW_SIZE {
+                    self.go_away = Some((0, Http2ErrorCode::FlowControlError));
+                }
+            }
+            Cmd::WindowUpdate {
+                stream_id,
+                increment,
+            } => {
+                let stream = self.stream(stream_id);
+                stream.window += i64::from(increment);
+                if stream.window > MAX_WINDOW_SIZE && !stream.reset {
+                    stream.reset = true;
+                    stream.pending.clear();
+                    buf.extend(rst_stream_frame(
+                        stream_id,
+                        Http2ErrorCode::FlowControlError,
+                    ));
+                }
+            }
+            Cmd::Reset { stream_id, code } => {
+                // Entries belong to streams with running tasks, which send `StreamDone` to
+                // remove them.  Resetting any other stream must not add an entry.
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    if stream.reset {
+                        return;
+                    }
+                    stream.reset = true;
+                    stream.pending.clear();
+                }
+                buf.extend(rst_stream_frame(stream_id, code));
+            }
+            Cmd::ClientReset { stream_id } => {
+                let stream = self.stream(stream_id);
+                stream.reset = true;
+                stream.pending.clear();
+            }
+            Cmd::StreamDone { stream_id } => {
+                self.streams.remove(&stream_id);
+            }
+            Cmd::GoAway {
+                last_stream_id,
+                code,
+            } => {
+                if self.go_away.is_none() {
+                    self.go_away = Some((last_stream_id, code));
+                }
+            }
+        }
+    }
+
+    /// Adds DATA frames that fit in the flow control windows.
+    /// Returns the senders to notify after the frames are written.
+    fn send_data(&mut self, buf: &mut Vec<u8>) -> Vec<OneSender<()>> {
+        let mut sent = Vec::new();
+        let mut stream_ids: Vec<u32> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| !stream.pending.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        stream_ids.sort_unstable();
+        for stream_id in stream_ids {
+            let stream = self.streams.get_mut(&stream_id).unwrap();
+            while let Some(pending) = stream.pending.front_mut() {
+                let remaining = pending.data.len() - pending.pos;
+                let window = stream.window.min(self.conn_window).max(0);
+                let n = remaining
+                    .min(usize::try_from(window).unwrap())
+                    .min(self.max_frame_size);
+                if n == 0 && remaining != 0 {
+                    break;
+                }
+                let done = n == remaining;
+                let flags = if done && pending.end_stream {
+                    FLAG_END_STREAM
+                } else {
+                    0
+                };
+                push_frame(
+                    buf,
+                    FRAME_DATA,
+                    flags,
+                    stream_id,
+                    &pending.data[pending.pos..pending.pos + n],
+                );
+                pending.pos += n;
+                let n64 = i64::try_from(n).unwrap();
+                stream.window -= n64;
+                self.conn_window -= n64;
+                if done {
+                    sent.push(stream.pending.pop_front().unwrap().sent);
+                }
+            }
+        }
+        sent
+    }
+}
+
+async fn write_loop(
+    queue: CmdSender,
+    mut stream: ConnStream,
+    last_stream_id: Arc<Mutex<u32>>,
+    _token: Option<Token>,
+) {
+    let mut writer = Writer::new();
+    while let Some(cmds) = queue.recv_all().await {
+        let mut buf = Vec::new();
+        for cmd in cmds {
+            writer.apply(cmd, &mut buf);
+        }
+        if let Some((mut last_id, code)) = writer.go_away {
+            if last_id == 0 {
+                last_id = *last_stream_id.lock().unwrap();
+            }
+            buf.extend(go_away_frame(last_id, code));
+            let _ignored = stream.write_all(&buf).await;
+            break;
+        }
+        let sent = writer.send_data(&mut buf);
+        if !buf.is_empty() && stream.write_all(&buf).await.is_err() {
+            break;
+        }
+        for sender in sent {
+            let _ignored = sender.send(());
+        }
+    }
+    queue.close();
+    let _ignored = stream.shutdown(Shutdown::Both);
+}
+
+struct InboxState {
+    chunks: VecDeque<Vec<u8>>,
+    /// `Some(Err(..))` when the stream ended abnormally.
+    end: Option<Result<(), ErrorKind>>,
+    waker: Option<Waker>,
+    /// How many more bytes the client may send.
+    window: u32,
+    expected_len: Option<u64>,
+    received: u64,
+    /// True when the client reset the stream or we reset it.
+    reset: bool,
+}
+
+/// Request body bytes that the connection task received for one stream.
+struct Inbox(Mutex<InboxState>);
+impl Inbox {
+    fn new(expected_len: Option<u64>, end_stream: bool) -> Self {
+        Self(Mutex::new(InboxState {
+            chunks: VecDeque::new(),
+            end: if end_stream { Some(Ok(())) } else { None },
+            waker: None,
+            window: DEFAULT_WINDOW_SIZE,
+            expected_len,
+            received: 0,
+            reset: false,
+        }))
+    }
+
+    fn is_ended(&self) -> bool {
+        self.0.lock().unwrap().end.is_some()
+    }
+
+    fn is_reset(&self) -> bool {
+        self.0.lock().unwrap().reset
+    }
+
+    fn end(&self, result: Result<(), ErrorKind>) {
+        let mut state = self.0.lock().unwrap();
+        if state.end.is_none() {
+            state.end = Some(result);
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Saves a DATA payload.
+    ///
+    /// # Errors
+    /// Returns an error code when the stream must be reset.
+    fn receive(&self, data: &[u8], flow_len: u32, end_stream: bool) -> Result<(), Http2ErrorCode> {
+        let mut state = self.0.lock().unwrap();
+        if flow_len > state.window {
+            return Err(Http2ErrorCode::FlowControlError);
+        }
+        state.window -= flow_len;
+        state.received += data.len() as u64;
+        let expected = state.expected_len;
+        if expected.is_some_and(|len| state.received > len)
+            || (end_stream && expected.is_some_and(|len| state.received != len))
+        {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        if !data.is_empty() {
+            state.chunks.push_back(data.to_vec());
+        }
+        if end_stream {
+            state.end = Some(Ok(()));
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+/// Reads a request body and opens the flow control window as the handler consumes it.
+struct BodyReader {
+    stream_id: u32,
+    inbox: Arc<Inbox>,
+    queue: CmdSender,
+}
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let mut state = self.inbox.0.lock().unwrap();
+        if let Some(chunk) = state.chunks.front_mut() {
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            chunk.drain(..n);
+            if chunk.is_empty() {
+                state.chunks.pop_front();
+            }
+            if state.end.is_none() && n > 0 {
+                let increment = u32::try_from(n).unwrap();
+                state.window += increment;
+                self.queue
+                    .send(Cmd::Frame(window_update_frame(self.stream_id, increment)));
+            }
+            return Poll::Ready(Ok(n));
+        }
+        match state.end {
+            Some(Ok(())) => Poll::Ready(Ok(0)),
+            Some(Err(kind)) => Poll::Ready(Err(std::io::Error::new(
+                kind,
+                "error receiving request body",
+            ))),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+struct Shared {
+    queue: CmdSender,
+    streams: Mutex<HashMap<u32, Arc<Inbox>>>,
+}
+
+/// Sends one response.
+struct StreamWriter {
+    stream_id: u32,
+    inbox: Arc<Inbox>,
+    queue: CmdSender,
+    headers_sent: bool,
+    /// True for a HEAD request, so the response gets no body.
+    head: bool,
+}
+impl StreamWriter {
+    fn send_headers(&mut self, block: Vec<u8>, end_stream: bool) -> Result<(), HttpError> {
+        if self.inbox.is_reset() {
+            return Err(HttpError::Disconnected);
+        }
+        self.headers_sent = true;
+        self.queue.send(Cmd::Headers {
+            stream_id: self.stream_id,
+            block,
+            end_stream,
+        });
+        Ok(())
+    }
+
+    async fn send_data(&self, data: Vec<u8>, end_stream: bool) -> Result<(), HttpError> {
+        let (sender, mut receiver) = oneshot();
+        self.queue.send(Cmd::Data {
+            stream_id: self.stream_id,
+            data,
+            end_stream,
+            sent: sender,
+        });
+        receiver
+            .async_recv()
+            .await
+            .map_err(|_| HttpError::Disconnected)
+    }
+
+    fn send_continue(&mut self) -> Result<(), HttpError> {
+        self.send_headers(hpack_encode([(":status", b"100".as_slice())]), false)
+    }
+
+    fn reset(&self, code: Http2ErrorCode) {
+        if !self.inbox.is_reset() {
+            self.queue.send(Cmd::Reset {
+                stream_id: self.stream_id,
+                code,
+            });
+        }
+    }
+
+    async fn write_response(
+        &mut self,
+        response: &Response,
+        send_trailers: bool,
+    ) -> Result<(), HttpError> {
+        if !response.is_normal() || response.code < 200 {
+            return Err(HttpError::UnwritableResponse);
+        }
+        let code = response.code.to_string();
+        let mut fields: Vec<(String, Vec<u8>)> = vec![(":status".to_string(), code.into_bytes())];
+        if response.content_type != ContentType::None {
+            if response.headers.get_only("content-type").is_some() {
+                return Err(HttpError::DuplicateContentTypeHeader);
+            }
+            fields.push((
+                "content-type".to_string(),
+                response.content_type.as_str().as_bytes().to_vec(),
+            ));
+        }
+        let body_len = response.body.len();
+        if let Some(len) = body_len {
+            if response.headers.get_only("content-length").is_some() {
+                return Err(HttpError::DuplicateContentLengthHeader);
+            }
+            fields.push(("content-length".to_string(), len.to_string().into_bytes()));
+        }
+        let opt_trailers = response.trailers.as_ref().filter(|trailers| {
+            send_trailers && !self.head && body_len.is_none() && !trailers.names().is_empty()
+        });
+        if let Some(trailers) = opt_trailers {
+            if response.headers.get_only("trailer").is_some() {
+                return Err(HttpError::DuplicateTrailerHeader);
+            }
+            fields.push((
+                "trailer".to_string(),
+                trailers.names().join(", ").into_bytes(),
+            ));
+        }
+        for header in &response.headers.0 {
+            let name = header.name.as_str().to_ascii_lowercase();
+            if !is_connection_specific(&name) {
+                fields.push((name, header.value.as_bytes().to_vec()));
+            }
+        }
+        // https://datatracker.ietf.org/doc/html/rfc9110#section-9.3.2
+        let end_stream = self.head || (body_len == Some(0) && opt_trailers.is_none());
+        self.send_headers(encode_fields(&fields), end_stream)?;
+        if end_stream {
+            return Ok(());
+        }
+        let mut reader = response
+            .body
+            .async_reader()
+            .await
+            .map_err(HttpError::error_reading_file)?;
+        let mut remaining = body_len;
+        loop {
+            let mut chunk = vec![0_u8; DEFAULT_MAX_FRAME_SIZE];
+            let n = reader
+                .read(&mut chunk)
+                .await
+                .map_err(HttpError::error_reading_response_body)?;
+            chunk.truncate(n);
+            if let Some(remaining) = &mut remaining {
+                if n == 0 {
+                    return Err(HttpError::ErrorReadingResponseBody(
+                        ErrorKind::UnexpectedEof,
+                        "body is smaller than expected".to_string(),
+                    ));
+                }
+                chunk.truncate(usize::try_from(*remaining).unwrap_or(usize::MAX).min(n));
+                *remaining -= chunk.len() as u64;
+                let done = *remaining == 0;
+                self.send_data(chunk, done && opt_trailers.is_none())
+                    .await?;
+                if done {
+                    break;
+                }
+            } else if n == 0 {
+                break;
+            } else {
+                self.send_data(chunk, false).await?;
+            }
+        }
+        if let Some(trailers) = opt_trailers {
+            let fields: Vec<(String, Vec<u8>)> = trailers
+                .values()
+                .0
+                .iter()
+                .map(|header| (header.name.to_string(), header.value.as_bytes().to_vec()))
+                .collect();
+            self.send_headers(encode_fields(&fields), true)?;
+        } else if body_len.is_none() {
+            self.send_data(Vec::new(), true).await?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_fields(fields: &[(String, Vec<u8>)]) -> Vec<u8> {
+    hpack_encode(
+        fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_slice())),
+    )
+}
+
+/// Fields that HTTP/2 does not allow.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc9113#section-8.2.2>
+fn is_connection_specific(name: &str) -> bool {
+    matches!(
+        name,
+        "connection" | "keep-alive" | "proxy-connection" | "transfer-encoding" | "upgrade"
+    )
+}
+
+/// Makes a request from decoded HEADERS fields.
+///
+/// Returns `Err(None)` when the request is malformed and the stream must be reset.
+fn parse_request(
+    conn: &UpgradedConn,
+    fields: Vec<HeaderField>,
+    end_stream: bool,
+) -> Result<Request, Option<HttpError>> {
+    // https://datatracker.ietf.org/doc/html/rfc9113#section-8.3.1
+    let mut method = None;
+    let mut scheme = None;
+    let mut authority = None;
+    let mut path = None;
+    let mut headers = HeaderList::new();
+    let mut cookies: Vec<String> = Vec::new();
+    for (name, value) in fields {
+        let name = String::from_utf8(name).map_err(|_| None)?;
+        let value = String::from_utf8(value).map_err(|_| None)?;
+        if value
+            .bytes()
+            .any(|b| b == 0 || b == b'\r' || b == b'\n' || !b.is_ascii())
+            || value.starts_with([' ', '\t'])
+            || value.ends_with([' ', '\t'])
+        {
+            return Err(None);
+        }
+        if let Some(pseudo) = name.strip_prefix(':') {
+            if !headers.is_empty() || !cookies.is_empty() {
+                return Err(None);
+            }
+            let slot = match pseudo {
+                "method" => &mut method,
+                "scheme" => &mut scheme,
+                "authority" => &mut authority,
+                "path" => &mut path,
+                _ => return Err(None),
+            };
+            if slot.replace(value).is_some() {
+                return Err(None);
+            }
+            continue;
+        }
+        if name.is_empty()
+            || !name.bytes().all(|b| is_tchar(b) && !b.is_ascii_uppercase())
+            || is_connection_specific(&name)
+            || (name == "te" && value != "trailers")
+        {
+            return Err(None);
+        }
+        if name == "cookie" {
+            cookies.push(value);
+        } else {
+            headers.add(name, AsciiString::try_from(value).unwrap());
+        }
+    }
+    if !cookies.is_empty() {
+        // https://datatracker.ietf.org/doc/html/rfc9113#section-8.2.3
+        headers.add("cookie", AsciiString::try_from(cookies.join("; ")).unwrap());
+    }
+    let (Some(method), Some(_scheme), Some(path)) = (method, scheme, path) else {
+        return Err(None);
+    };
+    if let Some(authority) = authority
+        && headers.get_only("host").is_none()
+    {
+        headers.add("host", AsciiString::try_from(authority).unwrap());
+    }
+    if path != "*" && !path.starts_with('/') {
+        return Err(None);
+    }
+    let url = Url::parse_relative(&path).map_err(|_| Some(HttpError::MalformedPath))?;
+    let head = Head {
+        method,
+        target: path,
+        url,
+        version: HttpVersion::Http2,
+        headers,
+    };
+    let mut req = request_from_head(conn.remote_addr.clone(), head).map_err(Some)?;
+    req.local_addr = conn.local_addr;
+    req.body = match req.content_length {
+        _ if end_stream => {
+            if req.content_length.is_some_and(|len| len != 0) {
+                return Err(None);
+            }
+            RequestBody::empty()
+        }
+        Some(len) => RequestBody::PendingKnown(len),
+        None => RequestBody::PendingUnknown,
+    };
+    Ok(req)
+}
+
+/// Settings from the `HTTP2-Settings` header of an h2c upgrade request.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc7540#section-3.2.1>
+fn upgrade_settings(req: &Request) -> Option<PeerSettings> {
+    let value = req.headers.get_only("http2-settings")?;
+    let payload = base64url_decode(value.as_str().trim_end_matches('='))?;
+    PeerSettings::parse(&parse_settings(&payload).ok()?).ok()
+}
+
+/// Returns true when `req` asks to switch to HTTP/2 over cleartext
+/// and the server can do that.
+///
+/// The server switches only for requests without bodies.
+#[must_use]
+pub fn is_h2c_upgrade(req: &Request) -> bool {
+    // https://datatracker.ietf.org/doc/html/rfc7540#section-3.2
+    req.version == HttpVersion::Http11
+        && req.has_header_token("upgrade", "h2c")
+        && req.has_header_token("connection", "upgrade")
+        && req.has_header_token("connection", "http2-settings")
+        && req.body.is_empty() == Some(true)
+        && upgrade_settings(req).is_some()
+}
+
+/// Handles a request stream, like `handle_http_conn_once` does for HTTP/1.1.
+async fn handle_stream_request<F, Fut>(
+    writer: &mut StreamWriter,
+    mut reader: BodyReader,
+    mut req: Request,
+    opt_cache_dir: Option<PathBuf>,
+    small_body_len: usize,
+    request_handler: F,
+) -> Result<(), HttpError>
+where
+    Fut: Future<Output = Response> + Send + 'static,
+    F: FnOnce(Request) -> Fut + 'static + Send + Clone,
+{
+    let send_trailers = req.accepts_trailers();
+    writer.head = req.method == "HEAD";
+    let mut expect_continue = req.expect_continue;
+    let mut send_continue = |writer: &mut StreamWriter| {
+        if std::mem::take(&mut expect_continue) {
+            writer.send_continue()
+        } else {
+            Ok(())
+        }
+    };
+    match req.body {
+        RequestBody::PendingKnown(len)
+            if len <= (small_body_len as u64) && !req.expect_continue =>
+        {
+            let len = usize::try_from(len).map_err(|_| HttpError::InvalidContentLength)?;
+            req.body = read_http_body_to_vec(&mut reader, len).await?;
+        }
+        RequestBody::PendingKnown(..) | RequestBody::PendingUnknown => {
+            let response = request_handler.clone()(req.clone()).await;
+            match response.kind {
+                ResponseKind::Normal => {
+                    return writer.write_response(&response, send_trailers).await;
+                }
+                ResponseKind::DropConnection => return Err(HttpError::Disconnected),
+                ResponseKind::Upgrade(..) => return Err(HttpError::UnwritableResponse),
+                ResponseKind::GetBodyAndReprocess(max_len) => {
+                    req.body = match req.body.len() {
+                        Some(len) if len > max_len => return Err(HttpError::BodyTooLong),
+                        Some(len) if len <= (small_body_len as u64) => {
+                            send_continue(writer)?;
+                            let len = usize::try_from(len)
+                                .map_err(|_| HttpError::InvalidContentLength)?;
+                            read_http_body_to_vec(&mut reader, len).await?
+                        }
+                        Some(len) => {
+                            let cache_dir =
+                                opt_cache_dir.ok_or(HttpError::CacheDirNotConfigured)?;
+                            send_continue(writer)?;
+                            read_http_body_to_file(&mut reader, len, &cache_dir).await?
+                        }
+                        None => {
+                            let cache_dir =
+                                opt_cache_dir.ok_or(HttpError::CacheDirNotConfigured)?;
+                            send_continue(writer)?;
+                            read_http_unsized_body_to_file(&mut reader, &cache_dir, max_len).await?
+                        }
+                    };
+                }
+                ResponseKind::StreamBodyAndReprocess(max_len) => {
+                    let opt_len = req.body.len();
+                    if opt_len.is_some_and(|len| len > max_len) {
+                        return Err(HttpError::BodyTooLong);
+                    }
+                    send_continue(writer)?;
+                    let (sender, stream) = body_stream(4, opt_len);
+                    req.body = RequestBody::Stream(stream);
+                    let mut opt_read_result = None;
+                    let response = {
+                        let mut read = pin!(async {
+                            let result =
+                                copy_to_body_stream(&mut reader, &sender, opt_len, max_len).await;
+                            match &result {
+                                Ok(..) => {}
+                                Err(HttpError::BodyTooLong) => {
+                                    sender.send_error(ErrorKind::InvalidData).await;
+                                }
+                                Err(..) => sender.send_error(ErrorKind::UnexpectedEof).await,
+                            }
+                            drop(sender);
+                            result
+                        });
+                        let mut handler = pin!(request_handler(req));
+                        poll_fn(|cx| {
+                            if opt_read_result.is_none()
+                                && let Poll::Ready(result) = read.as_mut().poll(cx)
+                            {
+                                opt_read_result = Some(result);
+                            }
+                            handler.as_mut().poll(cx)
+                        })
+                        .await
+                    };
+                    if let Some(read_result) = opt_read_result {
+                        read_result?;
+                    }
+                    return write_handler_response(writer, &response, send_trailers).await;
+                }
+            }
+        }
+        _ => {}
+    }
+    let response = request_handler(req).await;
+    write_handler_response(writer, &response, send_trailers).await
+}
+
+async fn write_handler_response(
+    writer: &mut StreamWriter,
+    response: &Response,
+    send_trailers: bool,
+) -> Result<(), HttpError> {
+    match &response.kind {
+        ResponseKind::Normal => writer.write_response(response, send_trailers).await,
+        ResponseKind::DropConnection => Err(HttpError::Disconnected),
+        ResponseKind::GetBodyAndReprocess(..) | ResponseKind::StreamBodyAndReprocess(..) => {
+            Err(HttpError::AlreadyGotBody)
+        }
+        ResponseKind::Upgrade(..) => Err(HttpError::UnwritableResponse),
+    }
+}
+
+async fn handle_stream<F, Fut>(
+    shared: Arc<Shared>,
+    stream_id: u32,
+    inbox: Arc<Inbox>,
+    req_result: Result<Request, HttpError>,
+    opt_cache_dir: Option<PathBuf>,
+    small_body_len: usize,
+    request_handler: F,
+) where
+    Fut: Future<Output = Response> + Send + 'static,
+    F: FnOnce(Request) -> Fut + 'static + Send + Clone,
+{
+    let mut writer = StreamWriter {
+        stream_id,
+        inbox: inbox.clone(),
+        queue: shared.queue.clone(),
+        headers_sent: false,
+        head: false,
+    };
+    let reader = BodyReader {
+        stream_id,
+        inbox: inbox.clone(),
+        queue: shared.queue.clone(),
+    };
+    let result = match req_result {
+        Ok(req) => {
+            handle_stream_request(
+                &mut writer,
+                reader,
+                req,
+                opt_cache_dir,
+                small_body_len,
+                request_handler,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => {}
+        Err(HttpError::Disconnected) => writer.reset(Http2ErrorCode::Cancel),
+        Err(e) if writer.headers_sent => {
+            println!("ERROR {}", e.description());
+            writer.reset(Http2ErrorCode::InternalError);
+        }
+        Err(e) => {
+            println!("ERROR {}", e.description());
+            if writer.write_response(&e.into(), false).await.is_err() {
+                writer.reset(Http2ErrorCode::InternalError);
+            }
+        }
+    }
+    if !inbox.is_ended() {
+        // We sent the response before the client sent the whole body.
+        // https://datatracker.ietf.org/doc/html/rfc9113#section-8.1
+        writer.reset(Http2ErrorCode::NoError);
+        inbox.end(Err(ErrorKind::ConnectionAborted));
+    }
+    let mut streams = shared.streams.lock().unwrap();
+    streams.remove(&stream_id);
+    shared.queue.send(Cmd::StreamDone { stream_id });
+}
+
+/// A header block that is still arriving in CONTINUATION frames.
+struct PendingHeaders {
+    stream_id: u32,
+    end_stream: bool,
+    block: Vec<u8>,
+}
+
+/// Reads frames and dispatches each request stream to `request_handler`.
+struct ConnReader<F> {
+    shared: Arc<Shared>,
+    decoder: HpackDecoder,
+    last_stream_id: Arc<Mutex<u32>>,
+    opt_cache_dir: Option<PathBuf>,
+    small_body_len: usize,
+    request_handler: F,
+    /// True after we sent GOAWAY for a graceful shutdown.
+    refuse_streams: bool,
+}
+impl<F, Fut> ConnReader<F>
+where
+    Fut: Future<Output = Response> + Send + 'static,
+    F: FnOnce(Request) -> Fut + 'static + Send + Clone,
+{
+    fn last_stream_id(&self) -> u32 {
+        *self.last_stream_id.lock().unwrap()
+    }
+
+    fn start_stream(
+        &self,
+        stream_id: u32,
+        req_result: Result<Request, HttpError>,
+        end_stream: bool,
+    ) {
+        let content_length = req_result.as_ref().ok().and_then(|req| req.content_length);
+        let inbox = Arc::new(Inbox::new(content_length, end_stream));
+        self.shared
+            .streams
+            .lock()
+            .unwrap()
+            .insert(stream_id, inbox.clone());
+        safina::executor::spawn(handle_stream(
+            self.shared.clone(),
+            stream_id,
+            inbox,
+            req_result,
+            self.opt_cache_dir.clone(),
+            self.small_body_len,
+            self.request_handler.clone(),
+        ));
+    }
+
+    fn reset(&self, stream_id: u32, code: Http2ErrorCode) {
+        self.shared.queue.send(Cmd::Reset { stream_id, code });
+    }
+
+    /// Resets a stream that has a task, so the task sends nothing more.
+    fn reset_task(&self, stream_id: u32, inbox: &Inbox, code: Http2ErrorCode) {
+        inbox.0.lock().unwrap().reset = true;
+        self.reset(stream_id, code);
+    }
+
+    fn on_headers(
+        &mut self,
+        conn: &UpgradedConn,
+        headers: &PendingHeaders,
+    ) -> Result<(), Http2ErrorCode> {
+        let stream_id = headers.stream_id;
+        let decode_result = match self.decoder.decode(&headers.block, MAX_HEADER_LIST_SIZE) {
+            Err(HpackError::Malformed) => return Err(Http2ErrorCode::CompressionError),
+            Err(HpackError::HeaderListTooLong) => Err(HttpError::HeadTooLong),
+            Ok(fields) => Ok(fields),
+        };
+        let opt_inbox = self.shared.streams.lock().unwrap().get(&stream_id).cloned();
+        if let Some(inbox) = opt_inbox {
+            // Trailer fields.  We ignore them.
+            if !headers.end_stream {
+                return Err(Http2ErrorCode::ProtocolError);
+            }
+            if inbox.receive(&[], 0, true).is_err() {
+                inbox.end(Err(ErrorKind::InvalidData));
+                self.reset_task(stream_id, &inbox, Http2ErrorCode::ProtocolError);
+            }
+            return Ok(());
+        }
+        if stream_id <= self.last_stream_id() {
+            // The stream is closed.
+            return Ok(());
+        }
+        *self.last_stream_id.lock().unwrap() = stream_id;
+        if self.refuse_streams
+            || self.shared.streams.lock().unwrap().len() >= MAX_CONCURRENT_STREAMS
+        {
+            self.reset(stream_id, Http2ErrorCode::RefusedStream);
+            return Ok(());
+        }
+        let req_result = match decode_result {
+            Ok(fields) => match parse_request(conn, fields, headers.end_stream) {
+                Ok(req) => Ok(req),
+                Err(Some(e)) => Err(e),
+                Err(None) => {
+                    self.reset(stream_id, Http2ErrorCode::ProtocolError);
+                    return Ok(());
+                }
+            },
+            Err(e) => Err(e),
+        };
+        self.start_stream(stream_id, req_result, headers.end_stream);
+        Ok(())
+    }
+
+    fn on_data(&self, stream_id: u32, flags: u8, payload: &[u8]) -> Result<(), Http2ErrorCode> {
+        if stream_id == 0 || stream_id > self.last_stream_id() {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        let flow_len = u32::try_from(payload.len()).unwrap();
+        if flow_len > 0 {
+            // We limit memory with the stream windows, so we return connection credit right away.
+            self.shared
+                .queue
+                .send(Cmd::Frame(window_update_frame(0, flow_len)));
+        }
+        let data = strip_padding(flags, payload)?;
+        let opt_inbox = self.shared.streams.lock().unwrap().get(&stream_id).cloned();
+        let Some(inbox) = opt_inbox else {
+            // We reset or finished the stream.
+            return Ok(());
+        };
+        if inbox.is_ended() {
+            self.reset_task(stream_id, &inbox, Http2ErrorCode::StreamClosed);
+            return Ok(());
+        }
+        if let Err(code) = inbox.receive(data, flow_len, flags & FLAG_END_STREAM != 0) {
+            inbox.end(Err(ErrorKind::InvalidData));
+            self.reset_task(stream_id, &inbox, code);
+            return Ok(());
+        }
+        let padding_len = flow_len - u32::try_from(data.len()).unwrap();
+        if padding_len > 0 && !inbox.is_ended() {
+            inbox.0.lock().unwrap().window += padding_len;
+            self.shared
+                .queue
+                .send(Cmd::Frame(window_update_frame(stream_id, padding_len)));
+        }
+        Ok(())
+    }
+
+    fn on_rst_stream(&self, stream_id: u32, payload: &[u8]) -> Result<(), Http2ErrorCode> {
+        if payload.len() != 4 {
+            return Err(Http2ErrorCode::FrameSizeError);
+        }
+        if stream_id == 0 || stream_id > self.last_stream_id() {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        let streams = self.shared.streams.lock().unwrap();
+        if let Some(inbox) = streams.get(&stream_id) {
+            inbox.0.lock().unwrap().reset = true;
+            inbox.end(Err(ErrorKind::ConnectionReset));
+            self.shared.queue.send(Cmd::ClientReset { stream_id });
+        }
+        Ok(())
+    }
+
+    fn on_window_update(&self, stream_id: u32, payload: &[u8]) -> Result<(), Http2ErrorCode> {
+        let bytes: [u8; 4] = payload
+            .try_into()
+            .map_err(|_| Http2ErrorCode::FrameSizeError)?;
+        let increment = u32::from_be_bytes(bytes) & 0x7FFF_FFFF;
+        if stream_id == 0 {
+            if increment == 0 {
+                return Err(Http2ErrorCode::ProtocolError);
+            }
+            self.shared.queue.send(Cmd::WindowUpdate {
+                stream_id,
+                increment,
+            });
+            return Ok(());
+        }
+        if stream_id > self.last_stream_id() {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        // Hold the lock so the stream task cannot send `StreamDone` before this.
+        let streams = self.shared.streams.lock().unwrap();
+        if let Some(inbox) = streams.get(&stream_id) {
+            if increment == 0 {
+                self.reset_task(stream_id, inbox, Http2ErrorCode::ProtocolError);
+            } else {
+                self.shared.queue.send(Cmd::WindowUpdate {
+                    stream_id,
+                    increment,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn on_settings(&self, stream_id: u32, flags: u8, payload: &[u8]) -> Result<(), Http2ErrorCode> {
+        if stream_id != 0 {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        if flags & FLAG_ACK != 0 {
+            if !payload.is_empty() {
+                return Err(Http2ErrorCode::FrameSizeError);
+            }
+            return Ok(());
+        }
+        let settings = PeerSettings::parse(&parse_settings(payload)?)?;
+        self.shared.queue.send(Cmd::Settings {
+            settings,
+            ack: true,
+        });
+        Ok(())
+    }
+
+    fn on_ping(&self, stream_id: u32, flags: u8, payload: &[u8]) -> Result<(), Http2ErrorCode> {
+        if stream_id != 0 {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        if payload.len() != 8 {
+            return Err(Http2ErrorCode::FrameSizeError);
+        }
+        if flags & FLAG_ACK == 0 {
+            self.shared
+                .queue
+                .send(Cmd::Frame(frame(FRAME_PING, FLAG_ACK, 0, payload)));
+        }
+        Ok(())
+    }
+
+    /// Reads frames until the connection closes or fails.
+    async fn read_frames(
+        &mut self,
+        permit: &Permit,
+        conn: &mut UpgradedConn,
+    ) -> Result<(), Http2ErrorCode> {
+        let mut preface = [0_u8; 24];
+        conn.read_exact(&mut preface)
+            .await
+            .map_err(|_| Http2ErrorCode::NoError)?;
+        if &preface != HTTP2_PREFACE {
+            return Err(Http2ErrorCode::ProtocolError);
+        }
+        let mut got_settings = false;
+        let mut opt_pending_headers: Option<PendingHeaders> = None;
+        loop {
+            if self.shared.queue.is_closed() {
+                // The writer stopped, or `send` queued GOAWAY because the client sends frames
+                // faster than it reads our replies.
+                return Err(Http2ErrorCode::EnhanceYourCalm);
+            }
+            if !self.refuse_streams && permit.is_revoked() {
+                self.refuse_streams = true;
+                self.shared.queue.send(Cmd::Frame(go_away_frame(
+                    self.last_stream_id(),
+                    Http2ErrorCode::NoError,
+                )));
+            }
+            let mut head = [0_u8; 9];
+            if conn.read_exact(&mut head).await.is_err() {
+                return Ok(());
+            }
+            let len = usize::try_from(u32::from_be_bytes([0, head[0], head[1], head[2]])).unwrap();
+            let frame_type = head[3];
+            let flags = head[4];
+            let stream_id = u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7FFF_FFFF;
+            if len > DEFAULT_MAX_FRAME_SIZE {
+                return Err(Http2ErrorCode::FrameSizeError);
+            }
+            let mut payload = vec![0_u8; len];
+            if conn.read_exact(&mut payload).await.is_err() {
+                return Ok(());
+            }
+            if !got_settings {
+                if frame_type != FRAME_SETTINGS || flags & FLAG_ACK != 0 {
+                    return Err(Http2ErrorCode::ProtocolError);
+                }
+                got_settings = true;
+            }
+            if let Some(pending) = &mut opt_pending_headers {
+                if frame_type != FRAME_CONTINUATION || stream_id != pending.stream_id {
+                    return Err(Http2ErrorCode::ProtocolError);
+                }
+                if pending.block.len() + payload.len() > MAX_HEADER_BLOCK_LEN {
+                    return Err(Http2ErrorCode::EnhanceYourCalm);
+                }
+                pending.block.extend(&payload);
+                if flags & FLAG_END_HEADERS != 0 {
+                    let pending = opt_pending_headers.take().unwrap();
+                    self.on_headers(conn, &pending)?;
+                }
+                continue;
+            }
+            match frame_type {
+                FRAME_DATA => self.on_data(stream_id, flags, &payload)?,
+                FRAME_HEADERS => {
+                    if stream_id == 0 || stream_id % 2 == 0 {
+                        return Err(Http2ErrorCode::ProtocolError);
+                    }
+                    let mut fragment = strip_padding(flags, &payload)?;
+                    if flags & FLAG_PRIORITY != 0 {
+                        fragment = fragment.get(5..).ok_or(Http2ErrorCode::FrameSizeError)?;
+                    }
+                    let pending = PendingHeaders {
+                        stream_id,
+                        end_stream: flags & FLAG_END_STREAM != 0,
+                        block: fragment.to_vec(),
+                    };
+                    if flags & FLAG_END_HEADERS == 0 {
+                        opt_pending_headers = Some(pending);
+                    } else {
+                        self.on_headers(conn, &pending)?;
+                    }
+                }
+                FRAME_PRIORITY => {
+                    if stream_id == 0 {
+                        return Err(Http2ErrorCode::ProtocolError);
+                    }
+                    if payload.len() != 5 {
+                        return Err(Http2ErrorCode::FrameSizeError);
+                    }
+                }
+                FRAME_RST_STREAM => self.on_rst_stream(stream_id, &payload)?,
+                FRAME_SETTINGS => self.on_settings(stream_id, flags, &payload)?,
+                FRAME_PUSH_PROMISE | FRAME_CONTINUATION => {
+                    return Err(Http2ErrorCode::ProtocolError);
+                }
+                FRAME_PING => self.on_ping(stream_id, flags, &payload)?,
+                FRAME_GOAWAY if stream_id != 0 => return Err(Http2ErrorCode::ProtocolError),
+                FRAME_WINDOW_UPDATE => self.on_window_update(stream_id, &payload)?,
+                // After GOAWAY, the client will not start new streams.
+                // We keep reading frames for the open streams.
+                // Ignore unknown frame types.
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Appends a HEADERS frame and the CONTINUATION frames that carry the rest of `block`.
+fn push_header_block(
+    buf: &mut Vec<u8>,
+    stream_id: u32,
+    block: &[u8],
+    end_stream: bool,
+    max_frame_size: usize,
+) {
+    let mut chunks = block.chunks(max_frame_size).peekable();
+    let first = chunks.next().unwrap_or_default();
+    let mut flags = if end_stream { FLAG_END_STREAM } else { 0 };
+    if chunks.peek().is_none() {
+        flags |= FLAG_END_HEADERS;
+    }
+    push_frame(buf, FRAME_HEADERS, flags, stream_id, first);
+    while let Some(chunk) = chunks.next() {
+        let flags = if chunks.peek().is_none() {
+            FLAG_END_HEADERS
+        } else {
+            0
+        };
+        push_frame(buf, FRAME_CONTINUATION, flags, stream_id, chunk);
+    }
+}
+
+fn go_away_frame(last_stream_id: u32, code: Http2ErrorCode) -> Vec<u8> {
+    let mut payload = last_stream_id.to_be_bytes().to_vec();
+    payload.extend((code as u32).to_be_bytes());
+    frame(FRAME_GOAWAY, 0, 0, &payload)
+}
+
+/// Removes the padding from a DATA or HEADERS frame payload.
+fn strip_padding(flags: u8, payload: &[u8]) -> Result<&[u8], Http2ErrorCode> {
+    if flags & FLAG_PADDED == 0 {
+        return Ok(payload);
+    }
+    let (pad_len, rest) = payload
+        .split_first()
+        .ok_or(Http2ErrorCode::FrameSizeError)?;
+    let pad_len = usize::from(*pad_len);
+    if pad_len > rest.len() {
+        return Err(Http2ErrorCode::ProtocolError);
+    }
+    Ok(&rest[..rest.len() - pad_len])
+}
+
+/// Serves HTTP/2 on `conn`.
+///
+/// When `opt_upgrade_req` is set, the client upgraded from HTTP/1.1 with that request
+/// and the server already sent `101 Switching Protocols`.
+/// The request becomes stream 1.
+///
+/// Calls `request_handler` for each request, concurrently.
+#[allow(clippy::missing_panics_doc)]
+pub async fn handle_http2_conn<F, Fut>(
+    permit: Permit,
+    mut conn: UpgradedConn,
+    opt_upgrade_req: Option<Request>,
+    opt_cache_dir: Option<PathBuf>,
+    small_body_len: usize,
+    request_handler: F,
+) where
+    Fut: Future<Output = Response> + Send + 'static,
+    F: FnOnce(Request) -> Fut + 'static + Send + Clone,
+{
+    let queue = CmdSender::new();
+    let last_stream_id = Arc::new(Mutex::new(0));
+    let setting = |value: usize| u32::try_from(value).unwrap();
+    queue.send(Cmd::Frame(settings_frame(&[
+        (SETTINGS_HEADER_TABLE_SIZE, setting(HEADER_TABLE_SIZE)),
+        (SETTINGS_ENABLE_PUSH, 0),
+        (
+            SETTINGS_MAX_CONCURRENT_STREAMS,
+            setting(MAX_CONCURRENT_STREAMS),
+        ),
+        (SETTINGS_MAX_FRAME_SIZE, setting(DEFAULT_MAX_FRAME_SIZE)),
+        (SETTINGS_MAX_HEADER_LIST_SIZE, setting(MAX_HEADER_LIST_SIZE)),
+    ])));
+    safina::executor::spawn(write_loop(
+        queue.clone(),
+        conn.stream.clone(),
+        last_stream_id.clone(),
+        conn.token.take(),
+    ));
+    let mut reader = ConnReader {
+        shared: Arc::new(Shared {
+            queue: queue.clone(),
+            streams: Mutex::new(HashMap::new()),
+        }),
+        decoder: HpackDecoder::new(HEADER_TABLE_SIZE),
+        last_stream_id,
+        opt_cache_dir,
+        small_body_len,
+        request_handler,
+        refuse_streams: false,
+    };
+    drop(queue);
+    if let Some(req) = opt_upgrade_req {
+        if let Some(settings) = upgrade_settings(&req) {
+            reader.shared.queue.send(Cmd::Settings {
+                settings,
+                ack: false,
+            });
+        }
+        *reader.last_stream_id.lock().unwrap() = 1;
+        reader.start_stream(1, Ok(req), true);
+    }
+    match reader.read_frames(&permit, &mut conn).await {
+        // The client is gone.  Stop the writer so responses waiting for flow control end.
+        Ok(()) => reader.shared.queue.close(),
+        Err(code) => reader.shared.queue.send(Cmd::GoAway {
+            last_stream_id: 0,
+            code,
+        }),
+    }
+    for inbox in reader.shared.streams.lock().unwrap().values() {
+        inbox.end(Err(ErrorKind::UnexpectedEof));
+    }
+}
@@ -1,4 +1,12 @@
+use crate::body_stream::{BodyStreamSender, body_stream, copy_to_body_stream};
+use crate::conn_stream::ConnStream;
+use crate::head::HeadOptions;
 use crate::http_error::HttpError;
+use crate::http_version::HttpVersion;
+use crate::http2::{HTTP2_PREFACE, handle_http2_conn, is_h2c_upgrade};
+use crate::ip_cidr::IpCidr;
+use crate::proxy_protocol::{ProxyHeader, ProxyHeaderError};
+use crate::remote_addr::RemoteAddr;
 use crate::request::read_http_request;
 use crate::request_body::{
     read_http_body_to_file, read_http_body_to_vec, read_http_unsized_body_to_file,
@@ -6,15 +14,20 @@ use crate::request_body::{
 };
 use crate::response::{ResponseKind, write_http_response};
 use crate::token_set::Token;
+use crate::upgrade::{Upgrade, UpgradedConn};
 use crate::util::AsyncWriteCounter;
 use crate::{Request, RequestBody, Response};
 use fixed_buffer::FixedBuf;
 use futures_lite::AsyncReadExt;
 use permit::Permit;
 use std::convert::TryFrom;
-use std::future::Future;
+use std::future::{Future, poll_fn};
+use std::io::ErrorKind;
 use std::net::{Shutdown, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::Poll;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ReadState {
@@ -35,22 +48,66 @@ pub enum WriteState {
     Shutdown,
 }
 
+#[allow(clippy::struct_excessive_bools)]
 pub struct HttpConn {
-    pub remote_addr: SocketAddr,
+    pub remote_addr: RemoteAddr,
+    /// The server address that the client connected to.
+    pub local_addr: Option<SocketAddr>,
     pub buf: FixedBuf<8192>,
-    pub stream: async_net::TcpStream,
+    pub stream: ConnStream,
     pub read_state: ReadState,
     pub write_state: WriteState,
+    /// True when the current request accepts trailer fields.
+    pub send_trailers: bool,
+    /// Set after sending a response that takes over the connection.
+    pub upgrade: Option<Upgrade>,
+    /// True when the server accepts HTTP/2 over cleartext (h2c).
+    pub h2c: bool,
+    /// Set after switching to HTTP/2 with `Upgrade: h2c`.
+    /// The server must answer this request on HTTP/2 stream 1.
+    pub http2_request: Option<Request>,
+    /// The protocol version of the current request.
+    pub version: HttpVersion,
+    pub head_options: HeadOptions,
+    /// True when the current request is HEAD, so the response gets no body.
+    pub head: bool,
+    /// False when the client asked to close the connection after the current response.
+    pub keep_alive: bool,
+    /// When set, TCP clients with addresses in these ranges must start the connection with
+    /// a PROXY protocol header.
+    pub proxy_protocol: Option<Arc<[IpCidr]>>,
 }
 impl HttpConn {
     #[must_use]
-    pub fn new(remote_addr: SocketAddr, stream: async_net::TcpStream) -> Self {
+    pub fn new(remote_addr: RemoteAddr, stream: ConnStream) -> Self {
         Self {
             remote_addr,
+            local_addr: stream.local_addr(),
             buf: FixedBuf::new(),
             stream,
             read_state: ReadState::Head,
             write_state: WriteState::None,
+            send_trailers: false,
+            upgrade: None,
+            h2c: false,
+            http2_request: None,
+            version: HttpVersion::Http11,
+            head_options: HeadOptions::default(),
+            head: false,
+            keep_alive: true,
+            proxy_protocol: None,
+        }
+    }
+
+    /// Hands the connection to another protocol.
+    #[must_use]
+    pub fn into_upgraded(self, token: Option<Token>) -> UpgradedConn {
+        UpgradedConn {
+            remote_addr: self.remote_addr,
+            local_addr: self.local_addr,
+            stream: self.stream,
+            prefix: self.buf.readable().to_vec(),
+            token,
         }
     }
 
@@ -73,6 +130,73 @@ impl HttpConn {
         result
     }
 
+    /// Reads a PROXY protocol header from a trusted client
+    /// and replaces `remote_addr` and `local_addr` with the addresses in the header.
+    ///
+    /// Does nothing when `proxy_protocol` is `None` or does not contain the client's address.
+    ///
+    /// # Errors
+    /// Returns an error when the connection fails or the client sent no valid header.
+    /// Then the server must close the connection without responding.
+    #[allow(clippy::missing_panics_doc)]
+    pub async fn read_proxy_header(&mut self) -> Result<(), HttpError> {
+        let Some(trusted) = &self.proxy_protocol else {
+            return Ok(());
+        };
+        let Some(ip) = self.remote_addr.ip() else {
+            return Ok(());
+        };
+        if !trusted.iter().any(|cidr| cidr.contains(ip)) {
+            return Ok(());
+        }
+        loop {
+            match ProxyHeader::parse(self.buf.readable()) {
+                Ok((header, len)) => {
+                    self.buf.try_read_exact(len).unwrap();
+                    if let Some(source) = header.source {
+                        self.remote_addr = RemoteAddr::Tcp(source);
+                        self.local_addr = header.destination;
+                    }
+                    return Ok(());
+                }
+                Err(ProxyHeaderError::Truncated) => {}
+                Err(ProxyHeaderError::Malformed | ProxyHeaderError::TooLong) => {
+                    return Err(HttpError::MalformedProxyHeader);
+                }
+            }
+            match self.stream.read(self.buf.writable()).await {
+                Ok(0) | Err(..) => return Err(HttpError::Disconnected),
+                Ok(n) => self.buf.wrote(n),
+            }
+        }
+    }
+
+    /// Reads until the buffer holds the HTTP/2 connection preface or something else.
+    /// Leaves the bytes in the buffer.
+    ///
+    /// Returns true when the client sent the preface.
+    ///
+    /// # Errors
+    /// Returns an error when the connection fails.
+    pub async fn read_http2_preface(&mut self) -> Result<bool, HttpError> {
+        loop {
+            let readable = self.buf.readable();
+            let n = readable.len().min(HTTP2_PREFACE.len());
+            if readable[..n] != HTTP2_PREFACE[..n] {
+                return Ok(false);
+            }
+            if n == HTTP2_PREFACE.len() {
+                return Ok(true);
+            }
+            match self.stream.read(self.buf.writable()).await {
+                // Let `read_request` report the disconnect.
+                Ok(0) => return Ok(false),
+                Ok(n) => self.buf.wrote(n),
+                Err(..) => return Err(HttpError::Disconnected),
+            }
+        }
+    }
+
     /// # Errors
     /// Returns an error when:
     /// - we did not send a response to the previous request
@@ -93,7 +217,18 @@ impl HttpConn {
             ReadState::Shutdown => return Err(HttpError::Disconnected),
         }
         self.write_state = WriteState::Response;
-        let req = read_http_request(self.remote_addr, &mut self.buf, &mut self.stream).await?;
+        let mut req = read_http_request(
+            self.remote_addr.clone(),
+            &mut self.buf,
+            &mut self.stream,
+            &self.head_options,
+        )
+        .await?;
+        req.local_addr = self.local_addr;
+        self.send_trailers = req.accepts_trailers();
+        self.version = req.version;
+        self.keep_alive = req.keep_alive();
+        self.head = req.method == "HEAD";
         self.read_state = match &req.body {
             RequestBody::PendingKnown(len) => ReadState::Body {
                 len: Some(*len),
@@ -232,6 +367,78 @@ impl HttpConn {
         }
     }
 
+    /// Reads the request body from the client and sends it to `sender` in chunks.
+    /// When the receiver does not keep up, this waits for it.
+    ///
+    /// When the receiver drops the stream before reading the whole body,
+    /// this stops reading and marks the connection as unusable for more requests.
+    ///
+    /// When this fails, the receiver gets an error instead of end-of-stream.
+    ///
+    /// # Errors
+    /// Returns an error when:
+    /// - the client did not send a request body
+    /// - the request body was already read from the client
+    /// - the client used an unsupported transfer encoding
+    /// - the client sends a request body that is larger than `max_len`
+    /// - we fail to read the request body
+    pub async fn read_body_to_stream(
+        &mut self,
+        sender: BodyStreamSender,
+        max_len: u64,
+    ) -> Result<(), HttpError> {
+        //dbg!("read_body_to_stream", max_len);
+        let result = self.copy_body_to_stream(&sender, max_len).await;
+        match &result {
+            Ok(()) => {}
+            Err(HttpError::BodyTooLong) => sender.send_error(ErrorKind::InvalidData).await,
+            Err(..) => sender.send_error(ErrorKind::UnexpectedEof).await,
+        }
+        result
+    }
+
+    async fn copy_body_to_stream(
+        &mut self,
+        sender: &BodyStreamSender,
+        max_len: u64,
+    ) -> Result<(), HttpError> {
+        let (opt_len, expect_continue) = match self.read_state {
+            ReadState::Head => return Err(HttpError::BodyNotAvailable),
+            ReadState::Body { chunked: true, .. } | ReadState::Body { gzip: true, .. } => {
+                return Err(HttpError::UnsupportedTransferEncoding);
+            }
+            ReadState::Body { len: Some(len), .. } if len > max_len => {
+                return Err(HttpError::BodyTooLong);
+            }
+            ReadState::Body {
+                len,
+                expect_continue,
+                ..
+            } => (len, expect_continue),
+            ReadState::Shutdown => return Err(HttpError::Disconnected),
+        };
+        if expect_continue {
+            self.write_http_continue().await?;
+        }
+        self.read_state = if opt_len.is_some() {
+            ReadState::Head
+        } else {
+            ReadState::Shutdown
+        };
+        let complete = copy_to_body_stream(
+            (&mut self.buf).chain(&mut self.stream),
+            sender,
+            opt_len,
+            max_len,
+        )
+        .await?;
+        if !complete {
+            // The handler stopped reading.  We cannot find the next request.
+            self.read_state = ReadState::Shutdown;
+        }
+        Ok(())
+    }
+
     /// # Errors
     /// Returns an error when a response was already sent, the connection is closed,
     /// or it fails to send the response bytes over the network connection.
@@ -243,8 +450,30 @@ impl HttpConn {
             WriteState::Shutdown => return Err(HttpError::Disconnected),
         }
         let mut write_counter = AsyncWriteCounter::new(&mut self.stream);
-        let close = (500..=599).contains(&response.code);
-        let result = write_http_response(&mut write_counter, response, close).await;
+        let final_response = !response.is_1xx() && !response.is_upgrade();
+        // When we did not ask for the body, the client may still send it, so we close.
+        let close = (500..=599).contains(&response.code)
+            || (final_response
+                && (!self.keep_alive
+                    || matches!(
+                        self.read_state,
+                        ReadState::Body {
+                            expect_continue: true,
+                            ..
+                        }
+                    )
+                    || (self.version == HttpVersion::Http10
+                        && !self.head
+                        && response.body.len().is_none())));
+        let result = write_http_response(
+            &mut write_counter,
+            response,
+            self.version,
+            self.head,
+            close,
+            self.send_trailers,
+        )
+        .await;
         if result.is_ok() {
             if !response.is_1xx() {
                 self.write_state = WriteState::None;
@@ -278,8 +507,22 @@ where
     //dbg!("handle_http_conn_once");
     let mut req = http_conn.read_request().await?;
     //dbg!(&req);
+    if http_conn.h2c && is_h2c_upgrade(&req) {
+        // https://datatracker.ietf.org/doc/html/rfc7540#section-3.2
+        // `handle_http_conn` serves HTTP/2 on the connection, not the upgrade function.
+        http_conn
+            .write_response(&Response::upgrade("h2c", |_conn| {}))
+            .await?;
+        http_conn.read_state = ReadState::Shutdown;
+        http_conn.write_state = WriteState::Shutdown;
+        http_conn.http2_request = Some(req);
+        return Ok(());
+    }
     match &req.body {
-        RequestBody::PendingKnown(len) if *len <= (small_body_len as u64) => {
+        // When the client expects `100 Continue`, the handler decides whether to get the body.
+        RequestBody::PendingKnown(len)
+            if *len <= (small_body_len as u64) && !req.expect_continue =>
+        {
             req.body = http_conn.read_body_to_vec().await?;
         }
         RequestBody::PendingKnown(..) | RequestBody::PendingUnknown => {
@@ -287,13 +530,58 @@ where
             let response = request_handler.clone()(req.clone()).await;
             //dbg!(&response);
             match response.kind {
+                ResponseKind::Normal if req.expect_continue => {
+                    return write_handler_response(http_conn, response).await;
+                }
                 ResponseKind::Normal => {}
                 ResponseKind::DropConnection => return Err(HttpError::Disconnected),
+                ResponseKind::Upgrade(..) => {
+                    return write_handler_response(http_conn, response).await;
+                }
                 ResponseKind::GetBodyAndReprocess(max_len) => {
-                    let cache_dir = opt_cache_dir.ok_or(HttpError::CacheDirNotConfigured)?;
-                    req.body = http_conn.read_body_to_file(cache_dir, max_len).await?;
+                    req.body = match req.body.len() {
+                        Some(len) if len <= (small_body_len as u64) && len <= max_len => {
+                            http_conn.read_body_to_vec().await?
+                        }
+                        _ => {
+                            let cache_dir =
+                                opt_cache_dir.ok_or(HttpError::CacheDirNotConfigured)?;
+                            http_conn.read_body_to_file(cache_dir, max_len).await?
+                        }
+                    };
                     //dbg!(&req);
                 }
+                ResponseKind::StreamBodyAndReprocess(max_len) => {
+                    if let ReadState::Body { len: Some(len), .. } = http_conn.read_state
+                        && len > max_len
+                    {
+                        return Err(HttpError::BodyTooLong);
+                    }
+                    let (sender, stream) = body_stream(4, req.body.len());
+                    req.body = RequestBody::Stream(stream);
+                    //dbg!("request_handler");
+                    let mut opt_read_result = None;
+                    let response = {
+                        let mut read = pin!(http_conn.read_body_to_stream(sender, max_len));
+                        let mut handler = pin!(request_handler(req));
+                        poll_fn(|cx| {
+                            if opt_read_result.is_none()
+                                && let Poll::Ready(result) = read.as_mut().poll(cx)
+                            {
+                                opt_read_result = Some(result);
+                            }
+                            handler.as_mut().poll(cx)
+                        })
+                        .await
+                    };
+                    //dbg!(&opt_read_result, &response);
+                    match opt_read_result {
+                        Some(read_result) => read_result?,
+                        // The handler returned before the client sent the whole body.
+                        None => http_conn.read_state = ReadState::Shutdown,
+                    }
+                    return write_handler_response(http_conn, response).await;
+                }
             }
         }
         _ => {}
@@ -301,10 +589,27 @@ where
     //dbg!("request_handler");
     let response = request_handler(req).await;
     //dbg!(&response);
-    match response.kind {
+    write_handler_response(http_conn, response).await
+}
+
+async fn write_handler_response(
+    http_conn: &mut HttpConn,
+    response: Response,
+) -> Result<(), HttpError> {
+    match &response.kind {
         ResponseKind::Normal => {}
         ResponseKind::DropConnection => return Err(HttpError::Disconnected),
-        ResponseKind::GetBodyAndReprocess(..) => return Err(HttpError::AlreadyGotBody),
+        ResponseKind::GetBodyAndReprocess(..) | ResponseKind::StreamBodyAndReprocess(..) => {
+            return Err(HttpError::AlreadyGotBody);
+        }
+        ResponseKind::Upgrade(upgrade) => {
+            http_conn.write_response(&response).await?;
+            // Stop handling HTTP requests on the connection.
+            http_conn.read_state = ReadState::Shutdown;
+            http_conn.write_state = WriteState::Shutdown;
+            http_conn.upgrade = Some(upgrade.clone());
+            return Ok(());
+        }
     }
     if response.is_normal() && (response.is_4xx() || response.is_5xx()) {
         let _ignored = http_conn.write_response(&response).await;
@@ -317,16 +622,37 @@ where
 #[allow(clippy::module_name_repetitions)]
 pub async fn handle_http_conn<F, Fut>(
     permit: Permit,
-    _token: Token,
+    token: Token,
     mut http_conn: HttpConn,
     opt_cache_dir: Option<PathBuf>,
     small_body_len: usize,
     async_request_handler: F,
 ) where
-    Fut: Future<Output = Response>,
+    Fut: Future<Output = Response> + Send + 'static,
     F: FnOnce(Request) -> Fut + 'static + Send + Clone,
 {
     //dbg!("handle_http_conn");
+    if http_conn.read_proxy_header().await.is_err() {
+        return;
+    }
+    if http_conn.h2c {
+        match http_conn.read_http2_preface().await {
+            Ok(true) => {
+                handle_http2_conn(
+                    permit,
+                    http_conn.into_upgraded(Some(token)),
+                    None,
+                    opt_cache_dir,
+                    small_body_len,
+                    async_request_handler,
+                )
+                .await;
+                return;
+            }
+            Ok(false) => {}
+            Err(..) => return,
+        }
+    }
     while !permit.is_revoked() {
         if !http_conn.is_ready() {
             // Previous request did not download body.
@@ -341,7 +667,24 @@ pub async fn handle_http_conn<F, Fut>(
         .await;
         //dbg!(&result);
         match result {
-            Ok(()) => {}
+            Ok(()) => {
+                if let Some(upgrade) = http_conn.upgrade.take() {
+                    upgrade.start(http_conn.into_upgraded(Some(token)));
+                    return;
+                }
+                if let Some(req) = http_conn.http2_request.take() {
+                    handle_http2_conn(
+                        permit,
+                        http_conn.into_upgraded(Some(token)),
+                        Some(req),
+                        opt_cache_dir,
+                        small_body_len,
+                        async_request_handler,
+                    )
+                    .await;
+                    return;
+                }
+            }
             Err(HttpError::Disconnected) => return,
             Err(e) => {
                 println!("ERROR {}", e.description());
@@ -6,6 +6,7 @@ use std::io::ErrorKind;
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialOrd, PartialEq)]
 pub enum HttpError {
     AlreadyGotBody,
+    AmbiguousBodyLength,
     BodyNotAvailable,
     BodyNotRead,
     BodyNotUtf8,
@@ -14,25 +15,31 @@ pub enum HttpError {
     Disconnected,
     DuplicateContentLengthHeader,
     DuplicateContentTypeHeader,
+    DuplicateTrailerHeader,
     DuplicateTransferEncodingHeader,
     ErrorReadingFile(ErrorKind, String),
     ErrorReadingResponseBody(ErrorKind, String),
     ErrorSavingFile(ErrorKind, String),
     HandlerDeadlineExceeded,
     HeadTooLong,
+    HeaderTooLong,
     InvalidContentLength,
+    InvalidHostHeader,
     MalformedCookieHeader,
     MalformedHeaderLine,
     MalformedPath,
+    MalformedProxyHeader,
     MalformedRequestLine,
     MissingRequestLine,
     ResponseAlreadySent,
     ResponseNotSent,
     TimerThreadNotStarted,
+    TooManyHeaders,
     Truncated,
     UnsupportedProtocol,
     UnsupportedTransferEncoding,
     UnwritableResponse,
+    UriTooLong,
 }
 impl HttpError {
     #[must_use]
@@ -62,6 +69,7 @@ impl HttpError {
             | HttpError::CacheDirNotConfigured
             | HttpError::DuplicateContentLengthHeader
             | HttpError::DuplicateContentTypeHeader
+            | HttpError::DuplicateTrailerHeader
             | HttpError::DuplicateTransferEncodingHeader
             | HttpError::ErrorReadingFile(..)
             | HttpError::ErrorReadingResponseBody(..)
@@ -70,20 +78,26 @@ impl HttpError {
             | HttpError::ResponseAlreadySent
             | HttpError::ResponseNotSent
             | HttpError::UnwritableResponse => true,
-            HttpError::BodyNotUtf8
+            HttpError::AmbiguousBodyLength
+            | HttpError::BodyNotUtf8
             | HttpError::BodyTooLong
             | HttpError::Disconnected
             | HttpError::HeadTooLong
+            | HttpError::HeaderTooLong
             | HttpError::InvalidContentLength
+            | HttpError::InvalidHostHeader
             | HttpError::MalformedCookieHeader
             | HttpError::MalformedHeaderLine
             | HttpError::MalformedPath
+            | HttpError::MalformedProxyHeader
             | HttpError::MalformedRequestLine
             | HttpError::MissingRequestLine
             | HttpError::TimerThreadNotStarted
+            | HttpError::TooManyHeaders
             | HttpError::Truncated
             | HttpError::UnsupportedProtocol
-            | HttpError::UnsupportedTransferEncoding => false,
+            | HttpError::UnsupportedTransferEncoding
+            | HttpError::UriTooLong => false,
         }
     }
 
@@ -91,6 +105,7 @@ impl HttpError {
     pub fn description(&self) -> String {
         match self {
             HttpError::AlreadyGotBody => "HttpError::AlreadyGotBody".to_string(),
+            HttpError::AmbiguousBodyLength => "HttpError::AmbiguousBodyLength".to_string(),
             HttpError::BodyNotAvailable => "HttpError::BodyNotAvailable".to_string(),
             HttpError::BodyNotRead => "HttpError::BodyNotRead".to_string(),
             HttpError::BodyNotUtf8 => "HttpError::BodyNotUtf8".to_string(),
@@ -102,6 +117,7 @@ impl HttpError {
             HttpError::DuplicateContentTypeHeader => {
                 "HttpError::DuplicateContentTypeHeader".to_string()
             }
+            HttpError::DuplicateTrailerHeader => "HttpError::DuplicateTrailerHeader".to_string(),
             HttpError::DuplicateTransferEncodingHeader => {
                 "HttpError::DuplicateTransferEncodingHeader".to_string()
             }
@@ -117,21 +133,26 @@ impl HttpError {
             }
             HttpError::HandlerDeadlineExceeded => "HttpError::HandlerDeadlineExceeded".to_string(),
             HttpError::HeadTooLong => "HttpError::HeadTooLong".to_string(),
+            HttpError::HeaderTooLong => "HttpError::HeaderTooLong".to_string(),
             HttpError::InvalidContentLength => "HttpError::InvalidContentLength".to_string(),
+            HttpError::InvalidHostHeader => "HttpError::InvalidHostHeader".to_string(),
             HttpError::MalformedCookieHeader => "HttpError::MalformedCookieHeader".to_string(),
             HttpError::MalformedHeaderLine => "HttpError::MalformedHeaderLine".to_string(),
             HttpError::MalformedPath => "HttpError::MalformedPath".to_string(),
+            HttpError::MalformedProxyHeader => "HttpError::MalformedProxyHeader".to_string(),
             HttpError::MalformedRequestLine => "HttpError::MalformedRequestLine".to_string(),
             HttpError::MissingRequestLine => "HttpError::MissingRequestLine".to_string(),
             HttpError::ResponseAlreadySent => "HttpError::ResponseAlreadySent".to_string(),
             HttpError::ResponseNotSent => "HttpError::ResponseNotSent".to_string(),
             HttpError::TimerThreadNotStarted => "HttpError::TimerThreadNotStarted".to_string(),
+            HttpError::TooManyHeaders => "HttpError::TooManyHeaders".to_string(),
             HttpError::Truncated => "HttpError::Truncated".to_string(),
             HttpError::UnsupportedProtocol => "HttpError::UnsupportedProtocol".to_string(),
             HttpError::UnsupportedTransferEncoding => {
                 "HttpError::UnsupportedTransferEncoding".to_string()
             }
             HttpError::UnwritableResponse => "HttpError::UnwritableResponse".to_string(),
+            HttpError::UriTooLong => "HttpError::UriTooLong".to_string(),
         }
     }
 }
@@ -144,14 +165,23 @@ impl From<HeadError> for HttpError {
             HeadError::MalformedPath => HttpError::MalformedPath,
             HeadError::UnsupportedProtocol => HttpError::UnsupportedProtocol,
             HeadError::MalformedHeader => HttpError::MalformedHeaderLine,
+            HeadError::AmbiguousBodyLength => HttpError::AmbiguousBodyLength,
+            HeadError::InvalidContentLength => HttpError::InvalidContentLength,
+            HeadError::InvalidHost => HttpError::InvalidHostHeader,
+            HeadError::TooLong => HttpError::HeadTooLong,
+            HeadError::UriTooLong => HttpError::UriTooLong,
+            HeadError::TooManyHeaders => HttpError::TooManyHeaders,
+            HeadError::HeaderTooLong => HttpError::HeaderTooLong,
         }
     }
 }
 impl From<HttpError> for Response {
     fn from(e: HttpError) -> Self {
         match e {
-            HttpError::BodyNotUtf8
+            HttpError::AmbiguousBodyLength
+            | HttpError::BodyNotUtf8
             | HttpError::InvalidContentLength
+            | HttpError::InvalidHostHeader
             | HttpError::MalformedCookieHeader
             | HttpError::MalformedHeaderLine
             | HttpError::MalformedPath
@@ -159,9 +189,15 @@ impl From<HttpError> for Response {
             | HttpError::MissingRequestLine
             | HttpError::Truncated
             | HttpError::UnsupportedTransferEncoding => Response::text(400, e.description()),
-            HttpError::Disconnected => Response::drop_connection(),
+            // The PROXY protocol spec says to close the connection without responding.
+            HttpError::Disconnected | HttpError::MalformedProxyHeader => {
+                Response::drop_connection()
+            }
             HttpError::BodyTooLong => Response::text(413, "Uploaded data is too big."),
-            HttpError::HeadTooLong => Response::text(431, e.description()),
+            HttpError::HeadTooLong | HttpError::HeaderTooLong | HttpError::TooManyHeaders => {
+                Response::text(431, e.description())
+            }
+            HttpError::UriTooLong => Response::text(414, e.description()),
             HttpError::UnsupportedProtocol => Response::text(505, e.description()),
             HttpError::AlreadyGotBody
             | HttpError::BodyNotAvailable
@@ -169,6 +205,7 @@ impl From<HttpError> for Response {
             | HttpError::CacheDirNotConfigured
             | HttpError::DuplicateContentLengthHeader
             | HttpError::DuplicateContentTypeHeader
+            | HttpError::DuplicateTrailerHeader
             | HttpError::DuplicateTransferEncodingHeader
             | HttpError::ErrorReadingFile(..)
             | HttpError::ErrorReadingResponseBody(..)
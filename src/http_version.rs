@@ -0,0 +1,41 @@
+use std::fmt::{Display, Formatter};
+
+/// The HTTP protocol version of a request.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum HttpVersion {
+    /// `HTTP/1.0`
+    Http10,
+    /// `HTTP/1.1`
+    Http11,
+    /// `HTTP/2`
+    Http2,
+}
+impl HttpVersion {
+    /// Parses the protocol from an HTTP/1 request line.
+    /// Returns `None` for other versions.
+    #[must_use]
+    pub fn parse_http1(bytes: &[u8]) -> Option<Self> {
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-2.3
+        //     HTTP-version  = HTTP-name "/" DIGIT "." DIGIT
+        //     HTTP-name     = %s"HTTP"
+        match bytes {
+            b"HTTP/1.0" => Some(Self::Http10),
+            b"HTTP/1.1" => Some(Self::Http11),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http10 => "HTTP/1.0",
+            Self::Http11 => "HTTP/1.1",
+            Self::Http2 => "HTTP/2",
+        }
+    }
+}
+impl Display for HttpVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
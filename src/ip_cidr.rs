@@ -0,0 +1,114 @@
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A range of IP addresses, like `10.0.0.0/8` or `fd00::/8`.
+///
+/// An IPv4 range also contains the IPv4-mapped IPv6 addresses of its members,
+/// like `::ffff:10.1.2.3`.
+/// A server listening on `[::]` sees IPv4 clients with these addresses.
+/// An IPv4-mapped range, like `::ffff:10.0.0.0/104`, becomes the IPv4 range `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+impl IpCidr {
+    /// Makes a range of the addresses that start with the first `prefix_len` bits of `addr`.
+    ///
+    /// # Errors
+    /// Returns an error when `prefix_len` is longer than `addr`.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, String> {
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(format!(
+                "prefix length {prefix_len} is too long for address {addr}"
+            ));
+        }
+        if let IpAddr::V6(v6) = addr
+            && prefix_len >= 96
+            && let Some(v4) = v6.to_ipv4_mapped()
+        {
+            return Self::new(IpAddr::V4(v4), prefix_len - 96);
+        }
+        Ok(Self {
+            addr: mask(addr, prefix_len),
+            prefix_len,
+        })
+    }
+
+    /// Makes a range that contains only `addr`.
+    #[must_use]
+    pub fn single(addr: IpAddr) -> Self {
+        let addr = addr.to_canonical();
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix_len }
+    }
+
+    #[must_use]
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    #[must_use]
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    #[must_use]
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        addr.is_ipv4() == self.addr.is_ipv4() && mask(addr, self.prefix_len) == self.addr
+    }
+}
+impl From<IpAddr> for IpCidr {
+    fn from(addr: IpAddr) -> Self {
+        Self::single(addr)
+    }
+}
+impl FromStr for IpCidr {
+    type Err = String;
+
+    /// Parses `ADDR/LEN` or `ADDR`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr_str, opt_len_str) = match s.split_once('/') {
+            Some((addr_str, len_str)) => (addr_str, Some(len_str)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr_str
+            .parse()
+            .map_err(|_| format!("invalid IP address in CIDR {s:?}"))?;
+        match opt_len_str {
+            Some(len_str) if !len_str.is_empty() && len_str.bytes().all(|b| b.is_ascii_digit()) => {
+                let prefix_len: u8 = len_str
+                    .parse()
+                    .map_err(|_| format!("invalid prefix length in CIDR {s:?}"))?;
+                Self::new(addr, prefix_len)
+            }
+            Some(..) => Err(format!("invalid prefix length in CIDR {s:?}")),
+            None => Ok(Self::single(addr)),
+        }
+    }
+}
+impl Display for IpCidr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+fn mask(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(addr) & mask).into())
+        }
+        IpAddr::V6(addr) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(addr) & mask).into())
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::rand::secure_random_bytes;
+use crate::util::{base64url_decode, base64url_encode};
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const MIN_KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+fn hmac_sha256(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap()
+}
+
+/// Keys derived from one secret, so each use has its own key.
+#[derive(Clone)]
+struct DerivedKeys {
+    sign: [u8; 32],
+    encrypt: [u8; 32],
+}
+impl DerivedKeys {
+    fn new(secret: &[u8]) -> Self {
+        let derive = |label: &[u8]| -> [u8; 32] {
+            let mut mac = hmac_sha256(secret);
+            mac.update(label);
+            mac.finalize().into_bytes().into()
+        };
+        Self {
+            sign: derive(b"servlin cookie signing"),
+            encrypt: derive(b"servlin cookie encryption"),
+        }
+    }
+
+    fn sign_mac(&self, name: &str, payload: &str) -> Hmac<Sha256> {
+        let mut mac = hmac_sha256(&self.sign);
+        mac.update(format!("{name}={payload}").as_bytes());
+        mac
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&self.encrypt.into())
+    }
+}
+
+fn unix_secs(opt_time: Option<SystemTime>) -> u64 {
+    opt_time
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs().max(1))
+}
+
+/// Returns false when `expires` is in the past.
+/// Zero means no expiration.
+fn is_current(expires: u64) -> bool {
+    expires == 0 || SystemTime::UNIX_EPOCH + Duration::from_secs(expires) > SystemTime::now()
+}
+
+/// Secret keys for signing and encrypting cookie values.
+///
+/// The first key signs and encrypts new values.
+/// All keys verify and decrypt values.
+/// To rotate keys, add a new first key and keep the old keys until their cookies expire.
+///
+/// Sealed values include the cookie name, so a client cannot move a value to another cookie.
+/// They also include an expiration time, so a client cannot keep using a value
+/// after its cookie expires.
+///
+/// Signing uses HMAC-SHA256.
+/// Encryption uses ChaCha20-Poly1305 with a random nonce.
+///
+/// Example:
+/// ```
+/// use servlin::{Cookie, KeyRing, Request, Response};
+///
+/// # let new_secret = KeyRing::generate_secret();
+/// # let old_secret = KeyRing::generate_secret();
+/// let keys = KeyRing::new(new_secret).with_old_key(old_secret);
+/// let handler = move |req: Request| {
+///     match req.signed_cookie("user", &keys) {
+///         Some(user) => Response::text(200, format!("Hello, {user}.")),
+///         None => Response::text(200, "Hello.").with_set_cookie(
+///             Cookie::new("user", "alice".try_into().unwrap()).signed(&keys),
+///         ),
+///     }
+/// };
+/// ```
+#[derive(Clone)]
+pub struct KeyRing {
+    keys: Arc<Vec<DerivedKeys>>,
+}
+impl KeyRing {
+    /// Makes a key ring that signs and encrypts with `secret`.
+    ///
+    /// Get a secret from [`KeyRing::generate_secret`] and store it with your other secrets.
+    ///
+    /// # Panics
+    /// Panics when `secret` is shorter than 32 bytes.
+    #[must_use]
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            keys: Arc::new(Vec::new()),
+        }
+        .with_old_key(secret)
+    }
+
+    /// Returns a new random secret.
+    #[must_use]
+    pub fn generate_secret() -> [u8; 32] {
+        secure_random_bytes()
+    }
+
+    /// Adds a key that verifies and decrypts values, but does not make new ones.
+    ///
+    /// # Panics
+    /// Panics when `secret` is shorter than 32 bytes.
+    #[must_use]
+    pub fn with_old_key(mut self, secret: impl AsRef<[u8]>) -> Self {
+        let secret = secret.as_ref();
+        assert!(
+            secret.len() >= MIN_KEY_LEN,
+            "KeyRing secret must have at least {MIN_KEY_LEN} bytes"
+        );
+        Arc::make_mut(&mut self.keys).push(DerivedKeys::new(secret));
+        self
+    }
+
+    fn primary(&self) -> &DerivedKeys {
+        &self.keys[0]
+    }
+
+    /// Returns `value` with a signature.
+    /// Clients can read the value, but cannot change it.
+    ///
+    /// [`KeyRing::verify`] rejects the result after `opt_expires`.
+    #[must_use]
+    pub fn sign(&self, name: &str, value: &str, opt_expires: Option<SystemTime>) -> String {
+        let payload = format!(
+            "{}.{}",
+            base64url_encode(value.as_bytes()),
+            unix_secs(opt_expires)
+        );
+        let mac = self.primary().sign_mac(name, &payload).finalize();
+        format!("{payload}.{}", base64url_encode(&mac.into_bytes()))
+    }
+
+    /// Checks the signature of a value from [`KeyRing::sign`] and returns the original value.
+    ///
+    /// Returns `None` when the value is malformed, has a bad signature, or expired.
+    #[must_use]
+    pub fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        let (payload, mac_b64) = signed.rsplit_once('.')?;
+        let mac = base64url_decode(mac_b64)?;
+        if !self
+            .keys
+            .iter()
+            .any(|keys| keys.sign_mac(name, payload).verify_slice(&mac).is_ok())
+        {
+            return None;
+        }
+        let (value_b64, expires) = payload.split_once('.')?;
+        if !is_current(expires.parse().ok()?) {
+            return None;
+        }
+        String::from_utf8(base64url_decode(value_b64)?).ok()
+    }
+
+    /// Returns `value` encrypted.
+    /// Clients cannot read or change the value.
+    ///
+    /// [`KeyRing::decrypt`] rejects the result after `opt_expires`.
+    ///
+    /// # Panics
+    /// Panics when encryption fails, which happens only for values larger than 256 GiB.
+    #[must_use]
+    pub fn encrypt(&self, name: &str, value: &str, opt_expires: Option<SystemTime>) -> String {
+        let nonce: [u8; NONCE_LEN] = secure_random_bytes();
+        let mut plaintext = unix_secs(opt_expires).to_be_bytes().to_vec();
+        plaintext.extend(value.as_bytes());
+        let ciphertext = self
+            .primary()
+            .cipher()
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .unwrap();
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        base64url_encode(&sealed)
+    }
+
+    /// Decrypts a value from [`KeyRing::encrypt`].
+    ///
+    /// Returns `None` when the value is malformed, was changed, or expired.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn decrypt(&self, name: &str, encrypted: &str) -> Option<String> {
+        let sealed = base64url_decode(encrypted)?;
+        if sealed.len() < NONCE_LEN + 8 + TAG_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = || Payload {
+            msg: ciphertext,
+            aad: name.as_bytes(),
+        };
+        let plaintext = self
+            .keys
+            .iter()
+            .find_map(|keys| keys.cipher().decrypt(nonce.into(), payload()).ok())?;
+        let (expires, value) = plaintext.split_at(8);
+        if !is_current(u64::from_be_bytes(expires.try_into().unwrap())) {
+            return None;
+        }
+        String::from_utf8(value.to_vec()).ok()
+    }
+}
+impl Debug for KeyRing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "KeyRing{{{} keys}}", self.keys.len())
+    }
+}
@@ -15,7 +15,15 @@
 //! - JSON
 //! - Server-Sent Events (SSE)
 //! - Saves large request bodies to temp files
-//! - Sends 100-Continue
+//! - Sends 100-Continue, after the handler approves the request head
+//! - HTTP/1.0, HTTP/1.1, and HTTP/2 cleartext (h2c)
+//! - WebSocket, connection upgrades, and `CONNECT` tunnels
+//! - Streaming request and response bodies, response trailers
+//! - `multipart/form-data` parsing
+//! - TCP, IPv6 dual-stack, and Unix domain socket listeners
+//! - PROXY protocol and `Forwarded` headers from trusted proxies
+//! - Virtual hosts, reverse proxy, CGI, and FastCGI handlers
+//! - CORS, CSRF protection, sessions, signed and encrypted cookies, security headers
 //! - Limits number of threads and connections
 //! - Modular: roll your own logging, write custom versions of internal methods, etc.
 //! - No macros or complicated type params
@@ -101,6 +109,35 @@
 //! See [rust-webserver-comparison.md](https://github.com/mleonhard/servlin/blob/main/rust-webserver-comparison.md).
 //!
 //! # Changelog
+//! - v0.9.0 2026-10-18
+//!   - Breaking changes:
+//!     - Add public [`Request`] fields `version`, `target`, `local_addr`, `forwarded`,
+//!       and `listener`.
+//!       Code that builds a `Request` with a struct literal must set them.
+//!     - [`Request::remote_addr`] is a [`RemoteAddr`] instead of a `SocketAddr`,
+//!       because clients can connect over Unix sockets.
+//!       Use `req.remote_addr.ip()` or [`Request::client_addr`] to get the client's IP address.
+//!     - `internal::HttpConn::new` and `internal::request_from_head` take a `RemoteAddr`.
+//!       `HttpConn::new` takes a `ConnStream` instead of a `TcpStream`.
+//!     - `internal::accept_loop` takes a list of listeners, each with its connection handler.
+//!       Handlers receive a `ConnStream` and a `RemoteAddr`.
+//!       `internal::AcceptResult::Ok` holds the same types.
+//!     - When the client sends `Expect: 100-continue`, the server calls the handler
+//!       before sending `100 Continue`, even for small bodies.
+//!       The handler must return [`Response::get_body_and_reprocess`] to get the body.
+//!     - Fill [`Url`] host, IP, and port from the `Host` header.
+//!       A URL with a host and no scheme displays as `//host:port/path`.
+//!   - Accept HTTP/1.0 requests and honor the `Connection` header.
+//!   - Omit response bodies for `HEAD` requests.  Add `HttpServerBuilder::head_as_get`.
+//!   - Add HTTP/2 cleartext (h2c), WebSocket, upgrade, and `CONNECT` tunnel support.
+//!   - Add streaming request bodies, reader and stream response bodies, and [`Trailers`].
+//!   - Add [`MultipartForm`] and [`MultipartParser`].
+//!   - Serve precompressed siblings of static files.
+//!   - Add strict parsing mode and configurable request head limits.
+//!   - Add multiple tagged listeners, IPv6 dual-stack, and Unix domain sockets.
+//!   - Read PROXY protocol headers and [`Forwarded`] headers from trusted proxies.
+//!   - Add [`VirtualHosts`], [`ReverseProxy`], [`Cgi`], and [`FastCgi`].
+//!   - Add [`Cors`], [`Csrf`], [`KeyRing`], [`Sessions`], and [`SecurityHeaders`].
 //! - v0.8.0 2025-07-06 - Use own [Url] struct.
 //! - v0.7.0 2025-06-30
 //!    - Require Rust 2024 edition.
@@ -151,8 +188,6 @@
 //!
 //! # TO DO
 //! - Fix limitations above
-//! - Support [HEAD](https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/HEAD)
-//!   responses that have Content-Length set and no body.
 //! - Add a server-wide limit on upload body size.
 //! - Limit disk usage for caching uploads.
 //! - Update `rust-webserver-comparison.md`
@@ -165,42 +200,90 @@ mod accept;
 mod ascii_string;
 mod body_async_reader;
 mod body_reader;
+mod body_stream;
+mod cgi;
+mod conn_stream;
 mod content_type;
 mod cookie;
+mod cors;
+mod csrf;
 mod error;
 mod event;
+mod fastcgi;
+mod forwarded;
 mod head;
 mod headers;
+mod hpack;
+mod http2;
 mod http_conn;
 mod http_error;
+mod http_version;
+mod ip_cidr;
+mod key_ring;
 pub mod log;
+mod multipart;
+mod proxy_protocol;
 mod rand;
+mod remote_addr;
 mod request;
 mod request_body;
 mod response;
 mod response_body;
+mod reverse_proxy;
+mod security_headers;
+mod session;
 mod time;
 mod token_set;
+mod trailers;
+mod upgrade;
 mod url;
 mod util;
+mod virtual_hosts;
+mod websocket;
 
 pub use crate::accept::{
-    PORT_env, socket_addr_127_0_0_1, socket_addr_127_0_0_1_any_port, socket_addr_all_interfaces,
+    ListenAddr, PORT_env, socket_addr_127_0_0_1, socket_addr_127_0_0_1_any_port,
+    socket_addr_all_interfaces, socket_addr_all_ipv4_interfaces,
 };
 pub use crate::ascii_string::AsciiString;
 pub use crate::body_async_reader::BodyAsyncReader;
 pub use crate::body_reader::BodyReader;
+pub use crate::body_stream::{BodyStream, BodyStreamSender};
+pub use crate::cgi::Cgi;
 pub use crate::content_type::ContentType;
 pub use crate::cookie::{Cookie, SameSite};
+pub use crate::cors::Cors;
+pub use crate::csrf::Csrf;
 pub use crate::error::Error;
 pub use crate::event::{Event, EventSender};
+pub use crate::fastcgi::FastCgi;
+pub use crate::forwarded::Forwarded;
 pub use crate::headers::{Header, HeaderList};
 pub use crate::http_conn::HttpConn;
+pub use crate::http_version::HttpVersion;
+pub use crate::ip_cidr::IpCidr;
+pub use crate::key_ring::KeyRing;
+pub use crate::multipart::{MultipartForm, MultipartParser, MultipartPart};
+pub use crate::remote_addr::{PeerCredentials, RemoteAddr, UnixPeer};
 pub use crate::request::Request;
 pub use crate::request_body::RequestBody;
 pub use crate::response::Response;
 pub use crate::response_body::ResponseBody;
+pub use crate::reverse_proxy::ReverseProxy;
+pub use crate::security_headers::{
+    CrossOriginEmbedderPolicy, CrossOriginOpenerPolicy, CrossOriginResourcePolicy, ReferrerPolicy,
+    SecurityHeaders,
+};
+pub use crate::session::{
+    FileSessionStore, MemorySessionStore, Session, SessionRecord, SessionStore, Sessions,
+};
+pub use crate::trailers::Trailers;
+pub use crate::upgrade::{Upgrade, UpgradedConn};
 pub use crate::url::{PercentEncodePurpose, Url, UrlParseError, percent_decode, percent_encode};
+pub use crate::virtual_hosts::VirtualHosts;
+pub use crate::websocket::{
+    CLOSE_NO_STATUS, CLOSE_NORMAL, WebSocket, WebSocketAcceptor, WebSocketError, WebSocketMessage,
+};
 
 /// This part of the library is not covered by the semver guarantees.
 /// If you use these in your program, a minor version upgrade could break your build.
@@ -211,60 +294,148 @@ pub mod internal {
     pub use crate::accept::*;
     pub use crate::body_async_reader::*;
     pub use crate::body_reader::*;
+    pub use crate::body_stream::*;
+    pub use crate::cgi::*;
+    pub use crate::conn_stream::*;
     pub use crate::content_type::*;
     pub use crate::cookie::*;
+    pub use crate::cors::*;
+    pub use crate::csrf::*;
     pub use crate::event::*;
+    pub use crate::fastcgi::*;
+    pub use crate::forwarded::*;
     pub use crate::head::*;
     pub use crate::headers::*;
+    pub use crate::hpack::*;
     pub use crate::http_conn::*;
     pub use crate::http_error::*;
+    pub use crate::http_version::*;
+    pub use crate::http2::*;
+    pub use crate::ip_cidr::*;
+    pub use crate::key_ring::*;
+    pub use crate::multipart::*;
+    pub use crate::proxy_protocol::*;
+    pub use crate::remote_addr::*;
     pub use crate::request::*;
     pub use crate::request_body::*;
     pub use crate::response::*;
     pub use crate::response_body::*;
+    pub use crate::reverse_proxy::*;
+    pub use crate::security_headers::*;
+    pub use crate::session::*;
     pub use crate::time::*;
     pub use crate::token_set::*;
+    pub use crate::trailers::*;
+    pub use crate::upgrade::*;
     pub use crate::util::*;
+    pub use crate::virtual_hosts::*;
+    pub use crate::websocket::*;
 }
 
-use crate::accept::accept_loop;
+use crate::accept::{Listener, accept_loop};
+use crate::forwarded::resolve_forwarded;
+use crate::head::HeadOptions;
 use crate::http_conn::handle_http_conn;
 use crate::token_set::TokenSet;
-use async_net::TcpListener;
 use permit::Permit;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Builds an HTTP server.
 pub struct HttpServerBuilder {
     opt_cache_dir: Option<PathBuf>,
-    listen_addr: SocketAddr,
+    listen_addrs: Vec<(ListenAddr, Option<&'static str>)>,
     max_conns: usize,
     small_body_len: usize,
     permit: Permit,
+    h2c: bool,
+    head_as_get: bool,
+    head_options: HeadOptions,
+    proxy_protocol: Option<Arc<[IpCidr]>>,
+    trusted_proxies: Option<Arc<[IpCidr]>>,
 }
 impl HttpServerBuilder {
     /// Makes a new builder these default settings:
-    /// - Listens on 127.0.0.1
+    /// - Listens on 127.0.0.1, when you add no listen addresses
     /// - Picks a random port
     /// - 100 max connections
     /// - 64 KiB small body length
     /// - no cache dir, server rejects large request bodies
+    /// - HTTP/1.0 and HTTP/1.1 only
     #[allow(clippy::new_without_default)]
     #[must_use]
     pub fn new() -> Self {
         Self {
             opt_cache_dir: None,
-            listen_addr: socket_addr_127_0_0_1_any_port(),
+            listen_addrs: Vec::new(),
             max_conns: 100,
             small_body_len: 64 * 1024,
             permit: Permit::new(),
+            h2c: false,
+            head_as_get: false,
+            head_options: HeadOptions::default(),
+            proxy_protocol: None,
+            trusted_proxies: None,
         }
     }
 
+    /// Listen on `addr`, replacing any addresses added before.
+    ///
+    /// `addr` is a [`SocketAddr`] or a [`ListenAddr`].
+    /// Example:
+    /// ```
+    /// use servlin::{HttpServerBuilder, ListenAddr};
+    /// let builder = HttpServerBuilder::new()
+    ///     .listen_addr(ListenAddr::unix_with_mode("/run/app/http.sock", 0o660));
+    /// ```
+    #[must_use]
+    pub fn listen_addr(mut self, addr: impl Into<ListenAddr>) -> Self {
+        self.listen_addrs = vec![(addr.into(), None)];
+        self
+    }
+
+    /// Listen on `addr`, in addition to the other listen addresses.
+    ///
+    /// All listeners share the request handler, connection limit, and shutdown permit.
+    ///
+    /// When you add an IPv4 address and an IPv6 address with the same port,
+    /// the server binds the IPv6 address as IPv6-only, so both binds succeed.
+    /// Example:
+    /// ```
+    /// use servlin::{socket_addr_all_interfaces, socket_addr_all_ipv4_interfaces, HttpServerBuilder};
+    /// let builder = HttpServerBuilder::new()
+    ///     .add_listen_addr(socket_addr_all_ipv4_interfaces(8000))
+    ///     .add_listen_addr(socket_addr_all_interfaces(8000));
+    /// ```
+    #[must_use]
+    pub fn add_listen_addr(mut self, addr: impl Into<ListenAddr>) -> Self {
+        self.listen_addrs.push((addr.into(), None));
+        self
+    }
+
+    /// Listen on `addr`, in addition to the other listen addresses.
+    ///
+    /// The server sets [`Request::listener`] to `tag` for requests received on this listener.
+    /// Handlers can use this to serve an admin port:
+    /// ```
+    /// use servlin::{socket_addr_127_0_0_1, HttpServerBuilder, Request, Response};
+    /// let builder = HttpServerBuilder::new()
+    ///     .add_listen_addr(socket_addr_127_0_0_1(8000))
+    ///     .add_tagged_listen_addr(socket_addr_127_0_0_1(8001), "admin");
+    /// let handler = |req: Request| match (req.listener, req.url.path.as_str()) {
+    ///     (Some("admin"), "/metrics") => Response::text(200, "requests=1"),
+    ///     (_, "/") => Response::text(200, "hello"),
+    ///     _ => Response::not_found_404(),
+    /// };
+    /// ```
     #[must_use]
-    pub fn listen_addr(mut self, addr: SocketAddr) -> Self {
-        self.listen_addr = addr;
+    pub fn add_tagged_listen_addr(
+        mut self,
+        addr: impl Into<ListenAddr>,
+        tag: &'static str,
+    ) -> Self {
+        self.listen_addrs.push((addr.into(), Some(tag)));
         self
     }
 
@@ -345,6 +516,158 @@ impl HttpServerBuilder {
         self
     }
 
+    /// Accept HTTP/2 over cleartext TCP (h2c).
+    ///
+    /// The server switches a connection to HTTP/2 when the client starts with the HTTP/2
+    /// connection preface ("prior knowledge") or sends an HTTP/1.1 request with
+    /// `Upgrade: h2c` and no body.
+    /// The server calls the request handler for each HTTP/2 stream, concurrently.
+    ///
+    /// Browsers use HTTP/2 only over TLS.
+    /// Enable this for clients like `curl --http2-prior-knowledge`
+    /// and for reverse proxies that speak h2c to their backends.
+    #[must_use]
+    pub fn enable_h2c(mut self) -> Self {
+        self.h2c = true;
+        self
+    }
+
+    /// Makes the server call the request handler with method `GET` for `HEAD` requests.
+    ///
+    /// The server sends the handler's response headers, including `content-length`,
+    /// and omits the body.
+    /// It does not open `File` bodies.
+    ///
+    /// Without this setting, the handler sees method `HEAD`.
+    /// The server still omits the body of every response to a `HEAD` request.
+    #[must_use]
+    pub fn head_as_get(mut self) -> Self {
+        self.head_as_get = true;
+        self
+    }
+
+    /// Read a [PROXY protocol](https://www.haproxy.org/download/3.0/doc/proxy-protocol.txt)
+    /// header at the start of connections from `trusted` addresses.
+    ///
+    /// Use this when the server is behind a TCP load balancer, like HAProxy or AWS NLB.
+    /// The balancer sends the header with the client's address.
+    /// The server puts the client's address in [`Request::remote_addr`]
+    /// and the address that the client connected to in [`Request::local_addr`].
+    ///
+    /// The server accepts version 1 (text) and version 2 (binary) headers.
+    /// It closes connections from `trusted` addresses that do not start with a valid header.
+    /// Connections from other addresses must not send a header.
+    /// The server handles them normally, using their real addresses.
+    ///
+    /// Example:
+    /// ```
+    /// use servlin::{HttpServerBuilder, IpCidr};
+    /// let builder = HttpServerBuilder::new()
+    ///     .proxy_protocol(["10.0.0.0/8".parse::<IpCidr>().unwrap()]);
+    /// ```
+    #[must_use]
+    pub fn proxy_protocol(mut self, trusted: impl IntoIterator<Item = IpCidr>) -> Self {
+        self.proxy_protocol = Some(trusted.into_iter().collect());
+        self
+    }
+
+    /// Read `Forwarded` and `X-Forwarded-*` headers on requests from `trusted` addresses.
+    ///
+    /// Use this when the server is behind an HTTP reverse proxy, like nginx or AWS ALB.
+    /// The server puts what the proxies reported in [`Request::forwarded`].
+    /// Use [`Request::client_addr`], [`Request::scheme`], and [`Request::host`] to read it.
+    ///
+    /// Add the addresses of every proxy in front of the server.
+    /// The server walks the proxy chain from the nearest proxy
+    /// and stops at the first address that is not in `trusted`.
+    /// That is the client.
+    /// This way, a client cannot forge its address by sending its own headers.
+    ///
+    /// The server ignores the headers on requests from other addresses.
+    /// It treats requests from Unix socket connections as coming from a trusted proxy.
+    ///
+    /// Example:
+    /// ```
+    /// use servlin::{HttpServerBuilder, IpCidr};
+    /// let builder = HttpServerBuilder::new()
+    ///     .trusted_proxies(["10.0.0.0/8".parse::<IpCidr>().unwrap()]);
+    /// ```
+    #[must_use]
+    pub fn trusted_proxies(mut self, trusted: impl IntoIterator<Item = IpCidr>) -> Self {
+        self.trusted_proxies = Some(trusted.into_iter().collect());
+        self
+    }
+
+    /// Reject requests that do not strictly follow
+    /// [RFC 9112](https://datatracker.ietf.org/doc/html/rfc9112) with `400 Bad Request`.
+    ///
+    /// Use this when the server is behind a proxy or load balancer.
+    /// It refuses requests that the proxy and the server could parse differently,
+    /// which attackers use to smuggle a second request past the proxy:
+    /// - both `Content-Length` and `Transfer-Encoding`
+    /// - more than one `Content-Length` or `Transfer-Encoding`, or a `Content-Length` list
+    /// - `Transfer-Encoding` in HTTP/1.0 requests
+    /// - HTTP/1.1 requests without exactly one `Host` header
+    /// - lines ending in LF without CR, and CR without LF
+    /// - header lines continued on the next line (obs-fold) or with control characters
+    /// - request targets with fragments or non-ASCII bytes, and `*` for methods other than `OPTIONS`
+    #[must_use]
+    pub fn strict_parsing(mut self) -> Self {
+        self.head_options.strict = true;
+        self
+    }
+
+    /// Sets the maximum length of a request head: the request line and the headers.
+    ///
+    /// The default value is 8 KiB.
+    ///
+    /// The server rejects longer requests with `431 Request Header Fields Too Large`,
+    /// or `414 URI Too Long` when the URL is longer than [`max_url_len`](Self::max_url_len).
+    ///
+    /// Raise this when clients send large cookies.
+    /// The server keeps heads up to 8 KiB in each connection's buffer
+    /// and allocates memory for longer ones.
+    #[must_use]
+    pub fn max_head_len(mut self, n: usize) -> Self {
+        self.head_options.max_head_len = n;
+        self
+    }
+
+    /// Sets the maximum number of headers in a request.
+    ///
+    /// The default value is 100.
+    ///
+    /// The server rejects requests with more headers with
+    /// `431 Request Header Fields Too Large`.
+    #[must_use]
+    pub fn max_header_count(mut self, n: usize) -> Self {
+        self.head_options.max_header_count = n;
+        self
+    }
+
+    /// Sets the maximum length of one request header line.
+    ///
+    /// The default value is 8 KiB.
+    ///
+    /// The server rejects requests with longer headers with
+    /// `431 Request Header Fields Too Large`.
+    #[must_use]
+    pub fn max_header_len(mut self, n: usize) -> Self {
+        self.head_options.max_header_len = n;
+        self
+    }
+
+    /// Sets the maximum length of the URL in a request line.
+    ///
+    /// The default value is 8 KiB.
+    ///
+    /// The server rejects requests with longer URLs with `414 URI Too Long`.
+    #[must_use]
+    pub fn max_url_len(mut self, n: usize) -> Self {
+        self.head_options.max_url_len = n;
+        self
+    }
+
     /// Sets the permit used by the server.
     ///
     /// Revoke the permit to make the server gracefully shut down.
@@ -380,10 +703,15 @@ impl HttpServerBuilder {
     ///
     /// Returns `(addr, stopped_receiver)`.
     /// The server is listening on `addr`.
+    /// When the server has multiple listen addresses, `addr` is the first TCP one.
     /// After the server gracefully shuts down, it sends a message on `stopped_receiver`.
     ///
     /// # Errors
-    /// Returns an error when it fails to bind to the [`listen_addr`](ServerBuilder::listen_addr).
+    /// Returns an error when:
+    /// - it fails to bind to a [`listen_addr`](HttpServerBuilder::listen_addr)
+    /// - all listen addresses are Unix sockets.
+    ///   Use [`spawn_listeners`](HttpServerBuilder::spawn_listeners) instead.
+    #[allow(clippy::missing_panics_doc)]
     pub async fn spawn<F>(
         self,
         request_handler: F,
@@ -391,7 +719,47 @@ impl HttpServerBuilder {
     where
         F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
     {
-        let async_request_handler = |req: Request| async move {
+        if !self.listen_addrs.is_empty()
+            && self
+                .listen_addrs
+                .iter()
+                .all(|(addr, _)| addr.socket_addr().is_none())
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "spawn needs a TCP listen address, use spawn_listeners",
+            ));
+        }
+        let (addrs, receiver) = self.spawn_listeners(request_handler).await?;
+        let addr = addrs.iter().find_map(ListenAddr::socket_addr).unwrap();
+        Ok((addr, receiver))
+    }
+
+    /// Spawns the server task.
+    ///
+    /// Returns `(addrs, stopped_receiver)`.
+    /// The server is listening on `addrs`, in the order you added them.
+    /// After the server gracefully shuts down, it sends a message on `stopped_receiver`.
+    ///
+    /// # Errors
+    /// Returns an error when it fails to bind to a [`listen_addr`](HttpServerBuilder::listen_addr).
+    pub async fn spawn_listeners<F>(
+        self,
+        request_handler: F,
+    ) -> Result<(Vec<ListenAddr>, safina::sync::Receiver<()>), std::io::Error>
+    where
+        F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
+    {
+        let head_as_get = self.head_as_get;
+        let trusted_proxies = self.trusted_proxies.clone();
+        let async_request_handler = move |listener, mut req: Request| async move {
+            req.listener = listener;
+            if let Some(trusted) = &trusted_proxies {
+                req.forwarded = resolve_forwarded(&req.remote_addr, &req.headers, trusted);
+            }
+            if head_as_get && req.method == "HEAD" {
+                req.method = "GET".to_string();
+            }
             let request_handler_clone = request_handler.clone();
             // TODO: Handle threadpool backpressure.
             //   - Keep set of pending requests, worker threads pull from it.
@@ -404,35 +772,62 @@ impl HttpServerBuilder {
                 .await
                 .unwrap_or_else(|_| Response::text(500, "Server error"))
         };
-        let conn_handler = move |permit, token, stream: async_net::TcpStream, addr| {
-            let http_conn = HttpConn::new(addr, stream);
-            safina::executor::spawn(handle_http_conn(
-                permit,
-                token,
-                http_conn,
-                self.opt_cache_dir,
-                self.small_body_len,
-                async_request_handler,
-            ));
+        let listen_addrs = if self.listen_addrs.is_empty() {
+            vec![(socket_addr_127_0_0_1_any_port().into(), None)]
+        } else {
+            self.listen_addrs
         };
-        let listener = TcpListener::bind(self.listen_addr).await?;
-        let addr = listener.local_addr()?;
+        let mut listeners = Vec::with_capacity(listen_addrs.len());
+        let mut addrs = Vec::with_capacity(listen_addrs.len());
+        for (listen_addr, listener_tag) in &listen_addrs {
+            // An IPv6 socket usually accepts IPv4 connections, too.
+            // Then binding the same port on IPv4 fails.
+            let port = listen_addr.socket_addr().map(|a| a.port());
+            let v6only = listen_addrs.iter().any(|(a, _)| {
+                a.socket_addr()
+                    .is_some_and(|a| a.is_ipv4() && Some(a.port()) == port && a.port() != 0)
+            });
+            let listener = Listener::bind(listen_addr, v6only).await?;
+            addrs.push(listener.local_addr()?);
+            let listener_tag = *listener_tag;
+            let h2c = self.h2c;
+            let head_options = self.head_options;
+            let proxy_protocol = self.proxy_protocol.clone();
+            let opt_cache_dir = self.opt_cache_dir.clone();
+            let small_body_len = self.small_body_len;
+            let async_request_handler = async_request_handler.clone();
+            let conn_handler = move |permit, token, stream, addr| {
+                let mut http_conn = HttpConn::new(addr, stream);
+                http_conn.h2c = h2c;
+                http_conn.head_options = head_options;
+                http_conn.proxy_protocol = proxy_protocol;
+                safina::executor::spawn(handle_http_conn(
+                    permit,
+                    token,
+                    http_conn,
+                    opt_cache_dir,
+                    small_body_len,
+                    move |req| async_request_handler(listener_tag, req),
+                ));
+            };
+            listeners.push((listener, conn_handler));
+        }
         let token_set = TokenSet::new(self.max_conns);
         let (sender, receiver) = safina::sync::oneshot();
         safina::executor::spawn(async move {
             // Let's not make spawn accept_loop tasks, since that reduces throughput.
             // To speed this up, we could use a separate accepter thread, or multiple threads.
-            accept_loop(self.permit, listener, token_set, conn_handler).await;
+            accept_loop(self.permit, listeners, token_set).await;
             // TODO: Wait for connection tokens to return.
             let _ignored = sender.send(());
         });
-        Ok((addr, receiver))
+        Ok((addrs, receiver))
     }
 
     /// Spawns the server task and waits for it to shutdown gracefully.
     ///
     /// # Errors
-    /// Returns an error when it fails to bind to the [`listen_addr`](ServerBuilder::listen_addr).
+    /// Returns an error when it fails to bind to the [`listen_addr`](HttpServerBuilder::listen_addr).
     pub async fn spawn_and_join<F>(self, request_handler: F) -> Result<(), std::io::Error>
     where
         F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
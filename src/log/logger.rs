@@ -200,6 +200,12 @@ pub fn add_thread_local_log_tags_from_request(req: &Request) {
     add_thread_local_log_tag("http_method", req.method());
     add_thread_local_log_tag("path", req.url().path.clone());
     add_thread_local_log_tag("request_id", req.id);
+    if let Some(ip) = req.client_addr() {
+        add_thread_local_log_tag("client_addr", ip.to_string());
+    }
+    if req.forwarded.is_some() {
+        add_thread_local_log_tag("scheme", req.scheme());
+    }
     if let Some(len) = req.body.len() {
         add_thread_local_log_tag("request_body_len", len);
     } else {
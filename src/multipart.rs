@@ -0,0 +1,491 @@
+// Parser for `multipart/form-data` request bodies.
+// - <https://datatracker.ietf.org/doc/html/rfc7578>
+// - <https://datatracker.ietf.org/doc/html/rfc2046#section-5.1>
+use crate::head::Head;
+use crate::util::find_slice;
+use crate::{ContentType, HeaderList, Request, RequestBody, Response};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use temp_file::TempFile;
+
+const MAX_PART_HEAD_LEN: usize = 8 * 1024;
+
+fn malformed(msg: &str) -> Response {
+    Response::text(400, format!("malformed multipart/form-data body: {msg}"))
+}
+
+/// Parses a header parameter list like `form-data; name="field1"; filename="a.txt"`.
+///
+/// Returns the parameters with lowercase names.
+/// Un-escapes quoted values.
+#[must_use]
+pub fn parse_header_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = value.chars().peekable();
+    // Skip the first item, like `form-data`.
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+    loop {
+        while chars
+            .next_if(|c| *c == ' ' || *c == '\t' || *c == ';')
+            .is_some()
+        {}
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ';') {
+            name.push(c.to_ascii_lowercase());
+        }
+        if name.is_empty() {
+            return params;
+        }
+        let mut param_value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => param_value.extend(chars.next()),
+                        c => param_value.push(c),
+                    }
+                }
+                while chars.next_if(|c| *c != ';').is_some() {}
+            } else {
+                while let Some(c) = chars.next_if(|c| *c != ';') {
+                    param_value.push(c);
+                }
+            }
+        }
+        params.push((name.trim().to_string(), param_value.trim().to_string()));
+    }
+}
+
+/// One part of a `multipart/form-data` request body.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MultipartPart {
+    /// The `name` parameter of the part's `Content-Disposition` header.
+    pub name: String,
+    /// The `filename` parameter of the part's `Content-Disposition` header.
+    /// The client sends this for file uploads.
+    pub filename: Option<String>,
+    pub content_type: ContentType,
+    pub headers: HeaderList,
+    /// A [`RequestBody::Vec`] for text fields,
+    /// or a [`RequestBody::TempFile`] for file uploads.
+    pub body: RequestBody,
+}
+impl MultipartPart {
+    #[must_use]
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// Returns the value of a text field.
+    ///
+    /// Returns `None` when the part is a file upload or the value is not UTF-8.
+    #[must_use]
+    pub fn text(&self) -> Option<&str> {
+        match &self.body {
+            RequestBody::Vec(v) if self.filename.is_none() => std::str::from_utf8(v).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed `multipart/form-data` request body.
+///
+/// Make this with [`Request::multipart`] or [`MultipartParser::parse`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MultipartForm {
+    pub parts: Vec<MultipartPart>,
+}
+impl MultipartForm {
+    /// Returns the first part named `name`.
+    #[must_use]
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&MultipartPart> {
+        self.parts.iter().find(|part| part.name == name.as_ref())
+    }
+
+    /// Returns the value of the first text field named `name`.
+    #[must_use]
+    pub fn text(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.parts
+            .iter()
+            .filter(|part| part.name == name.as_ref())
+            .find_map(MultipartPart::text)
+    }
+
+    /// Returns the parts that are file uploads.
+    pub fn files(&self) -> impl Iterator<Item = &MultipartPart> {
+        self.parts.iter().filter(|part| part.is_file())
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, MultipartPart> {
+        self.parts.iter()
+    }
+
+    /// Deserializes the text fields into type `T`.
+    ///
+    /// This supports the same types as [`Request::urlencoded`].
+    /// It ignores file uploads.
+    ///
+    /// # Errors
+    /// Returns a 400 Bad Request response when we fail to deserialize the fields into a `T`.
+    #[cfg(feature = "urlencoded")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(&self) -> Result<T, Response> {
+        use crate::util::escape_and_elide;
+        let pairs: Vec<(&str, &str)> = self
+            .parts
+            .iter()
+            .filter_map(|part| Some((part.name.as_str(), part.text()?)))
+            .collect();
+        let encoded = serde_urlencoded::to_string(pairs)
+            .map_err(|e| Response::text(400, format!("error processing form data: {e}")))?;
+        serde_urlencoded::from_str(&encoded).map_err(|e| {
+            Response::text(
+                400,
+                format!(
+                    "error processing form data: {}",
+                    escape_and_elide(e.to_string().as_bytes(), 100)
+                ),
+            )
+        })
+    }
+}
+impl<'x> IntoIterator for &'x MultipartForm {
+    type Item = &'x MultipartPart;
+    type IntoIter = core::slice::Iter<'x, MultipartPart>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.parts.iter()
+    }
+}
+
+/// Parses `multipart/form-data` request bodies.
+///
+/// Keeps text fields in memory and saves file uploads to temporary files in `cache_dir`.
+///
+/// # Example
+/// ```
+/// use servlin::{MultipartParser, Request, Response};
+/// # fn f(req: Request, cache_dir: &std::path::Path) -> Result<Response, Response> {
+/// let form = MultipartParser::new(cache_dir)
+///     .max_file_len(100 * 1024 * 1024)
+///     .parse(&req)?;
+/// for file in form.files() {
+///     println!("got file {:?} len={:?}", file.filename, file.body.len());
+/// }
+/// # Ok(Response::ok_200())
+/// # }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MultipartParser {
+    cache_dir: PathBuf,
+    max_parts: usize,
+    max_text_len: usize,
+    max_file_len: u64,
+    max_total_len: u64,
+}
+impl MultipartParser {
+    /// Makes a new parser with these default settings:
+    /// - 100 max parts
+    /// - 64 KiB max text field length
+    /// - 10 MiB max file length
+    /// - 10 MiB max total length of all parts
+    #[must_use]
+    pub fn new(cache_dir: impl AsRef<Path>) -> Self {
+        Self {
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            max_parts: 100,
+            max_text_len: 64 * 1024,
+            max_file_len: 10 * 1024 * 1024,
+            max_total_len: 10 * 1024 * 1024,
+        }
+    }
+
+    /// Sets the maximum number of parts.
+    #[must_use]
+    pub fn max_parts(mut self, n: usize) -> Self {
+        self.max_parts = n;
+        self
+    }
+
+    /// Sets the maximum length of a text field, in bytes.
+    /// The parser keeps text fields in memory.
+    #[must_use]
+    pub fn max_text_len(mut self, n: usize) -> Self {
+        self.max_text_len = n;
+        self
+    }
+
+    /// Sets the maximum length of a single uploaded file, in bytes.
+    #[must_use]
+    pub fn max_file_len(mut self, n: u64) -> Self {
+        self.max_file_len = n;
+        self
+    }
+
+    /// Sets the maximum total length of all parts, in bytes.
+    #[must_use]
+    pub fn max_total_len(mut self, n: u64) -> Self {
+        self.max_total_len = n;
+        self
+    }
+
+    /// Checks that the request body has type `multipart/form-data` and parses it.
+    ///
+    /// # Errors
+    /// Returns an error response when:
+    /// - the request content type is not `multipart/form-data` or has no `boundary` parameter
+    /// - the request body was not received
+    /// - the body is malformed
+    /// - a part or the whole body is larger than the configured limits
+    /// - we fail to read the request body or write a temporary file
+    pub fn parse(&self, req: &Request) -> Result<MultipartForm, Response> {
+        if req.content_type != ContentType::MultipartForm {
+            return Err(Response::text(
+                400,
+                "expected multipart/form-data request body",
+            ));
+        }
+        let boundary = req
+            .headers
+            .get_only("content-type")
+            .and_then(|value| {
+                parse_header_params(value.as_str())
+                    .into_iter()
+                    .find(|(name, _)| name == "boundary")
+            })
+            .map(|(_, value)| value)
+            .filter(|value| (1..=70).contains(&value.len()))
+            .ok_or_else(|| malformed("missing boundary"))?;
+        if req.body.is_pending() {
+            return Err(if req.body.len().is_some() {
+                Response::payload_too_large_413()
+            } else {
+                Response::length_required_411()
+            });
+        }
+        let reader = req
+            .body
+            .reader()
+            .map_err(|e| Response::text(500, format!("error reading request body: {e}")))?;
+        self.parse_reader(reader, boundary.as_bytes())
+    }
+
+    /// Parses a `multipart/form-data` body from `reader`.
+    ///
+    /// # Errors
+    /// Returns an error response when:
+    /// - the body is malformed
+    /// - a part or the whole body is larger than the configured limits
+    /// - we fail to read the body or write a temporary file
+    pub fn parse_reader(
+        &self,
+        reader: impl Read,
+        boundary: &[u8],
+    ) -> Result<MultipartForm, Response> {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary);
+        let mut buf = BufReader::new(reader);
+        // Pretend the body starts with CRLF, so the first delimiter looks like the others.
+        buf.data.extend_from_slice(b"\r\n");
+        // Skip the preamble.
+        buf.copy_until(&delimiter, &mut std::io::sink(), u64::MAX)?;
+        let mut form = MultipartForm::default();
+        let mut total_len: u64 = 0;
+        loop {
+            // After the delimiter comes "--" for the end, or optional whitespace and CRLF.
+            if !buf.fill_to(2)? {
+                return Err(malformed("truncated"));
+            }
+            if buf.data.starts_with(b"--") {
+                return Ok(form);
+            }
+            while buf.fill_to(1)? && (buf.data[0] == b' ' || buf.data[0] == b'\t') {
+                buf.data.remove(0);
+            }
+            if form.parts.len() >= self.max_parts {
+                return Err(Response::text(
+                    413,
+                    "too many parts in multipart/form-data body",
+                ));
+            }
+            let mut part = Self::read_part_head(&mut buf)?;
+            let remaining_total = self.max_total_len - total_len;
+            let len = if part.filename.is_some() {
+                let max_len = self.max_file_len.min(remaining_total);
+                let temp_file = TempFile::in_dir(&self.cache_dir)
+                    .map_err(|e| Response::text(500, format!("error creating temp file: {e}")))?;
+                let mut file = std::fs::File::create(temp_file.path())
+                    .map_err(|e| Response::text(500, format!("error creating temp file: {e}")))?;
+                let len = buf.copy_until(&delimiter, &mut file, max_len)?;
+                file.flush()
+                    .map_err(|e| Response::text(500, format!("error writing temp file: {e}")))?;
+                part.body = RequestBody::TempFile(temp_file, len);
+                len
+            } else {
+                let max_len = (self.max_text_len as u64).min(remaining_total);
+                let mut value = Vec::new();
+                let len = buf.copy_until(&delimiter, &mut value, max_len)?;
+                part.body = RequestBody::Vec(value);
+                len
+            };
+            total_len += len;
+            form.parts.push(part);
+        }
+    }
+
+    fn read_part_head(buf: &mut BufReader<impl Read>) -> Result<MultipartPart, Response> {
+        // The part head starts with the CRLF at the end of the delimiter line
+        // and ends with an empty line.
+        let head_len = loop {
+            if let Some(n) = find_slice(b"\r\n\r\n", &buf.data) {
+                break n;
+            }
+            if buf.data.len() > MAX_PART_HEAD_LEN {
+                return Err(Response::text(
+                    431,
+                    "multipart/form-data part head is too long",
+                ));
+            }
+            if !buf.fill_more()? {
+                return Err(malformed("truncated"));
+            }
+        };
+        if !buf.data.starts_with(b"\r\n") {
+            return Err(malformed("expected CRLF after boundary"));
+        }
+        let head_bytes: Vec<u8> = buf.data.drain(..head_len + 4).collect();
+        let mut headers = HeaderList::new();
+        // A part with no headers has the empty line right after the delimiter line.
+        let header_bytes = head_bytes.get(2..head_len).unwrap_or_default();
+        for line in header_bytes
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let header = Head::parse_header_line(line).map_err(|_| malformed("bad part header"))?;
+            headers.push(header);
+        }
+        let disposition_params = headers
+            .get_only("content-disposition")
+            .filter(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .is_some_and(|s| s.trim().eq_ignore_ascii_case("form-data"))
+            })
+            .map(|value| parse_header_params(value.as_str()))
+            .ok_or_else(|| malformed("part is missing content-disposition: form-data header"))?;
+        let mut name = None;
+        let mut filename = None;
+        for (param_name, value) in disposition_params {
+            match param_name.as_str() {
+                "name" => name = Some(value),
+                "filename" => filename = Some(value),
+                _ => {}
+            }
+        }
+        let name = name.ok_or_else(|| malformed("part is missing name"))?;
+        let content_type = headers
+            .get_only("content-type")
+            .map_or(ContentType::None, |value| {
+                ContentType::parse(value.as_str())
+            });
+        Ok(MultipartPart {
+            name,
+            filename,
+            content_type,
+            headers,
+            body: RequestBody::empty(),
+        })
+    }
+}
+
+struct BufReader<R: Read> {
+    reader: R,
+    data: Vec<u8>,
+    eof: bool,
+}
+impl<R: Read> BufReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            data: Vec::new(),
+            eof: false,
+        }
+    }
+
+    /// Reads more data.  Returns false at end of stream.
+    fn fill_more(&mut self) -> Result<bool, Response> {
+        if self.eof {
+            return Ok(false);
+        }
+        let mut chunk = [0_u8; 8192];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(false);
+                }
+                Ok(n) => {
+                    self.data.extend_from_slice(&chunk[..n]);
+                    return Ok(true);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    return Err(Response::text(
+                        500,
+                        format!("error reading request body: {e}"),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Reads until there are at least `n` bytes in `data`.
+    /// Returns false when the stream ends first.
+    fn fill_to(&mut self, n: usize) -> Result<bool, Response> {
+        while self.data.len() < n {
+            if !self.fill_more()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Copies bytes to `writer` until it finds `delimiter`.
+    /// Consumes the delimiter.
+    /// Returns the number of bytes copied.
+    fn copy_until(
+        &mut self,
+        delimiter: &[u8],
+        writer: &mut impl Write,
+        max_len: u64,
+    ) -> Result<u64, Response> {
+        let mut num_copied: u64 = 0;
+        loop {
+            let (copy_len, found) = match find_slice(delimiter, &self.data) {
+                Some(n) => (n, true),
+                None => (self.data.len().saturating_sub(delimiter.len() - 1), false),
+            };
+            num_copied += copy_len as u64;
+            if num_copied > max_len {
+                return Err(Response::payload_too_large_413());
+            }
+            writer
+                .write_all(&self.data[..copy_len])
+                .map_err(|e| Response::text(500, format!("error writing temp file: {e}")))?;
+            if found {
+                self.data.drain(..copy_len + delimiter.len());
+                return Ok(num_copied);
+            }
+            self.data.drain(..copy_len);
+            if !self.fill_more()? {
+                return Err(malformed("truncated"));
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// https://www.haproxy.org/download/3.0/doc/proxy-protocol.txt
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+/// Version 2 headers can carry extensions (TLVs).  We accept up to this many bytes of them.
+pub const MAX_PROXY_HEADER_LEN: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProxyHeaderError {
+    /// The bytes are the start of a header.  Read more bytes and try again.
+    Truncated,
+    Malformed,
+    TooLong,
+}
+
+/// A PROXY protocol header.
+///
+/// A load balancer sends this at the start of a connection
+/// to tell the server the client's address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProxyHeader {
+    /// The client's address.
+    /// `None` when the balancer connected on its own behalf, like for a health check,
+    /// or when it did not send a TCP address.
+    pub source: Option<SocketAddr>,
+    /// The address that the client connected to.
+    pub destination: Option<SocketAddr>,
+}
+impl ProxyHeader {
+    /// Parses a version 1 or version 2 header from the start of `bytes`.
+    ///
+    /// Returns the header and its length.
+    ///
+    /// # Errors
+    /// Returns an error when:
+    /// - `bytes` holds part of a header
+    /// - the header is malformed
+    /// - the header is longer than [`MAX_PROXY_HEADER_LEN`]
+    pub fn parse(bytes: &[u8]) -> Result<(Self, usize), ProxyHeaderError> {
+        if bytes.starts_with(V2_SIGNATURE) {
+            parse_v2(bytes)
+        } else if bytes.starts_with(V1_PREFIX) {
+            parse_v1(bytes)
+        } else if V2_SIGNATURE.starts_with(bytes) || V1_PREFIX.starts_with(bytes) {
+            Err(ProxyHeaderError::Truncated)
+        } else {
+            Err(ProxyHeaderError::Malformed)
+        }
+    }
+}
+
+fn parse_v1(bytes: &[u8]) -> Result<(ProxyHeader, usize), ProxyHeaderError> {
+    let search = &bytes[..bytes.len().min(V1_MAX_LEN)];
+    let Some(lf) = search.iter().position(|b| *b == b'\n') else {
+        return Err(if bytes.len() < V1_MAX_LEN {
+            ProxyHeaderError::Truncated
+        } else {
+            ProxyHeaderError::TooLong
+        });
+    };
+    if bytes[lf - 1] != b'\r' {
+        return Err(ProxyHeaderError::Malformed);
+    }
+    let end = lf - 1;
+    let line = std::str::from_utf8(&bytes[V1_PREFIX.len()..end])
+        .map_err(|_| ProxyHeaderError::Malformed)?;
+    let mut parts = line.split(' ');
+    let header = match parts.next() {
+        // The receiver must ignore anything after `UNKNOWN`.
+        Some("UNKNOWN") => ProxyHeader {
+            source: None,
+            destination: None,
+        },
+        Some(family @ ("TCP4" | "TCP6")) => {
+            let parse_ip = |s: Option<&str>| -> Result<IpAddr, ProxyHeaderError> {
+                let s = s.ok_or(ProxyHeaderError::Malformed)?;
+                let ip = if family == "TCP4" {
+                    IpAddr::V4(s.parse().map_err(|_| ProxyHeaderError::Malformed)?)
+                } else {
+                    IpAddr::V6(s.parse().map_err(|_| ProxyHeaderError::Malformed)?)
+                };
+                Ok(ip)
+            };
+            let source_ip = parse_ip(parts.next())?;
+            let destination_ip = parse_ip(parts.next())?;
+            let source_port = parse_v1_port(parts.next())?;
+            let destination_port = parse_v1_port(parts.next())?;
+            if parts.next().is_some() {
+                return Err(ProxyHeaderError::Malformed);
+            }
+            ProxyHeader {
+                source: Some(SocketAddr::new(source_ip, source_port)),
+                destination: Some(SocketAddr::new(destination_ip, destination_port)),
+            }
+        }
+        _ => return Err(ProxyHeaderError::Malformed),
+    };
+    Ok((header, end + 2))
+}
+
+fn parse_v1_port(s: Option<&str>) -> Result<u16, ProxyHeaderError> {
+    match s {
+        Some(s)
+            if !s.is_empty()
+                && s.len() <= 5
+                && s.bytes().all(|b| b.is_ascii_digit())
+                && (s == "0" || !s.starts_with('0')) =>
+        {
+            s.parse().map_err(|_| ProxyHeaderError::Malformed)
+        }
+        _ => Err(ProxyHeaderError::Malformed),
+    }
+}
+
+#[allow(clippy::match_same_arms)]
+fn parse_v2(bytes: &[u8]) -> Result<(ProxyHeader, usize), ProxyHeaderError> {
+    if bytes.len() < V2_HEADER_LEN {
+        return Err(ProxyHeaderError::Truncated);
+    }
+    let version_and_command = bytes[12];
+    let family_and_protocol = bytes[13];
+    let len = V2_HEADER_LEN + usize::from(u16::from_be_bytes([bytes[14], bytes[15]]));
+    if version_and_command >> 4 != 2 {
+        return Err(ProxyHeaderError::Malformed);
+    }
+    if len > MAX_PROXY_HEADER_LEN {
+        return Err(ProxyHeaderError::TooLong);
+    }
+    if bytes.len() < len {
+        return Err(ProxyHeaderError::Truncated);
+    }
+    let addrs = &bytes[V2_HEADER_LEN..len];
+    let (source, destination) = match (version_and_command & 0x0F, family_and_protocol) {
+        // LOCAL
+        (0x0, _) => (None, None),
+        // PROXY, TCP over IPv4
+        (0x1, 0x11) => {
+            if addrs.len() < 12 {
+                return Err(ProxyHeaderError::Malformed);
+            }
+            let ip = |b: &[u8]| IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3]));
+            let port = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+            (
+                Some(SocketAddr::new(ip(&addrs[0..4]), port(&addrs[8..10]))),
+                Some(SocketAddr::new(ip(&addrs[4..8]), port(&addrs[10..12]))),
+            )
+        }
+        // PROXY, TCP over IPv6
+        (0x1, 0x21) => {
+            if addrs.len() < 36 {
+                return Err(ProxyHeaderError::Malformed);
+            }
+            let ip = |b: &[u8]| IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(b).unwrap()));
+            let port = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+            (
+                Some(SocketAddr::new(ip(&addrs[0..16]), port(&addrs[32..34]))),
+                Some(SocketAddr::new(ip(&addrs[16..32]), port(&addrs[34..36]))),
+            )
+        }
+        // PROXY with another family or protocol.
+        // The receiver must accept the header and use the connection's real addresses.
+        (0x1, _) => (None, None),
+        _ => return Err(ProxyHeaderError::Malformed),
+    };
+    Ok((
+        ProxyHeader {
+            source,
+            destination,
+        },
+        len,
+    ))
+}
@@ -1,4 +1,4 @@
-use rand::rngs::SmallRng;
+use rand::rngs::{OsRng, SmallRng};
 use rand::{RngCore, SeedableRng};
 use std::cell::RefCell;
 
@@ -9,3 +9,15 @@ thread_local! {
 pub fn next_insecure_rand_u64() -> u64 {
     THREAD_LOCAL_SMALL_RNG.with(|cell| cell.borrow_mut().next_u64())
 }
+
+/// Returns random bytes from the operating system,
+/// for secrets like tokens and keys.
+///
+/// # Panics
+/// Panics when the operating system fails to provide random bytes.
+#[must_use]
+pub fn secure_random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0_u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
@@ -0,0 +1,98 @@
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+/// The client end of a connection.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RemoteAddr {
+    Tcp(SocketAddr),
+    Unix(UnixPeer),
+}
+impl RemoteAddr {
+    /// Returns the address of a TCP client.
+    #[must_use]
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(addr) => Some(*addr),
+            Self::Unix(..) => None,
+        }
+    }
+
+    /// Returns the IP address of a TCP client.
+    #[must_use]
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.socket_addr().map(|addr| addr.ip())
+    }
+
+    /// Returns the client of a Unix socket connection.
+    #[must_use]
+    pub fn unix(&self) -> Option<&UnixPeer> {
+        match self {
+            Self::Tcp(..) => None,
+            Self::Unix(peer) => Some(peer),
+        }
+    }
+}
+impl From<SocketAddr> for RemoteAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+impl From<UnixPeer> for RemoteAddr {
+    fn from(peer: UnixPeer) -> Self {
+        Self::Unix(peer)
+    }
+}
+impl PartialEq<SocketAddr> for RemoteAddr {
+    fn eq(&self, other: &SocketAddr) -> bool {
+        self.socket_addr().as_ref() == Some(other)
+    }
+}
+impl Display for RemoteAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(peer) => write!(f, "{peer}"),
+        }
+    }
+}
+
+/// The client of a Unix socket connection.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UnixPeer {
+    /// The path that the client bound its socket to.
+    /// Clients usually connect with unnamed sockets, so this is usually `None`.
+    pub path: Option<PathBuf>,
+    /// The client process's credentials.
+    /// The server gets these on Linux and Android.
+    pub credentials: Option<PeerCredentials>,
+}
+impl Display for UnixPeer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unix:")?;
+        if let Some(path) = &self.path {
+            write!(f, "{}", path.display())?;
+        }
+        if let Some(credentials) = &self.credentials {
+            write!(f, "({credentials})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The credentials of the process that opened a Unix socket connection.
+/// The kernel records them when the client connects.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PeerCredentials {
+    pub pid: Option<u32>,
+    pub uid: u32,
+    pub gid: u32,
+}
+impl Display for PeerCredentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(pid) = self.pid {
+            write!(f, "pid={pid} ")?;
+        }
+        write!(f, "uid={} gid={}", self.uid, self.gid)
+    }
+}
@@ -1,19 +1,41 @@
-use crate::head::read_http_head;
+use crate::forwarded::Forwarded;
+use crate::head::{Head, HeadOptions, read_http_head};
 use crate::http_error::HttpError;
+use crate::http_version::HttpVersion;
 use crate::rand::next_insecure_rand_u64;
-use crate::{AsciiString, ContentType, HeaderList, RequestBody, Response, Url};
+use crate::remote_addr::RemoteAddr;
+use crate::{
+    AsciiString, ContentType, HeaderList, KeyRing, MultipartForm, MultipartParser, RequestBody,
+    Response, Url,
+};
 use fixed_buffer::FixedBuf;
 use futures_io::AsyncRead;
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct Request {
     pub id: u64,
-    pub remote_addr: SocketAddr,
+    pub remote_addr: RemoteAddr,
+    /// The tag of the listener that accepted the connection.
+    /// See [`HttpServerBuilder::add_tagged_listen_addr`](crate::HttpServerBuilder::add_tagged_listen_addr).
+    pub listener: Option<&'static str>,
+    /// The server address that the client connected to.
+    /// `None` for Unix socket connections.
+    pub local_addr: Option<SocketAddr>,
+    /// What trusted proxies reported about the client.
+    /// `None` when the request did not come from a trusted proxy.
+    /// See [`HttpServerBuilder::trusted_proxies`](crate::HttpServerBuilder::trusted_proxies).
+    pub forwarded: Option<Forwarded>,
     pub method: String,
+    /// The request target exactly as the client sent it, like `/a%2Fb?c=d`.
+    ///
+    /// [`url`](Self::url) holds the parsed and percent-decoded form.
+    pub target: String,
     pub url: Url,
+    pub version: HttpVersion,
     pub headers: HeaderList,
     pub cookies: HashMap<String, String>,
     pub content_type: ContentType,
@@ -44,6 +66,146 @@ impl Request {
         &self.url
     }
 
+    /// Returns the client's IP address.
+    ///
+    /// When the request came through trusted proxies, this is the address that they reported.
+    /// Otherwise, it is the address of the connection.
+    ///
+    /// Returns `None` for Unix socket connections
+    /// and when a proxy hid the client's address.
+    #[must_use]
+    pub fn client_addr(&self) -> Option<IpAddr> {
+        match &self.forwarded {
+            Some(forwarded) => forwarded.client,
+            None => self.remote_addr.ip(),
+        }
+    }
+
+    /// Returns the scheme that the client used, `"http"` or `"https"`.
+    ///
+    /// The server does not do TLS, so this is `"http"`
+    /// unless a trusted proxy reported otherwise.
+    #[must_use]
+    pub fn scheme(&self) -> &str {
+        self.forwarded
+            .as_ref()
+            .and_then(|forwarded| forwarded.proto.as_deref())
+            .unwrap_or("http")
+    }
+
+    /// Returns the host that the client requested.
+    ///
+    /// This is the host that a trusted proxy reported, or else the request's `Host` header.
+    #[must_use]
+    pub fn host(&self) -> Option<&str> {
+        self.forwarded
+            .as_ref()
+            .and_then(|forwarded| forwarded.host.as_deref())
+            .or_else(|| self.headers.get_only("host").map(AsciiString::as_str))
+    }
+
+    /// Returns the value of a cookie set with [`Cookie::signed`](crate::Cookie::signed).
+    ///
+    /// Returns `None` when the request has no such cookie,
+    /// or its signature does not match any key in `keys`,
+    /// or it expired.
+    #[must_use]
+    pub fn signed_cookie(&self, name: &str, keys: &KeyRing) -> Option<String> {
+        keys.verify(name, self.cookies.get(name)?)
+    }
+
+    /// Returns the value of a cookie set with [`Cookie::encrypted`](crate::Cookie::encrypted).
+    ///
+    /// Returns `None` when the request has no such cookie,
+    /// or it does not decrypt with any key in `keys`,
+    /// or it expired.
+    #[must_use]
+    pub fn encrypted_cookie(&self, name: &str, keys: &KeyRing) -> Option<String> {
+        keys.decrypt(name, self.cookies.get(name)?)
+    }
+
+    /// Returns true when the request's `Accept-Encoding` header allows the server to
+    /// send a body with content-coding `coding`, like `"gzip"` or `"br"`.
+    ///
+    /// Uses a case-insensitive comparison.
+    /// Honors `q=0` and the `*` wildcard.
+    ///
+    /// <https://datatracker.ietf.org/doc/html/rfc9110#section-12.5.3>
+    #[must_use]
+    pub fn accepts_encoding(&self, coding: &str) -> bool {
+        // Accept-Encoding  = #( codings [ weight ] )
+        // codings          = content-coding / "identity" / "*"
+        // weight           = OWS ";" OWS "q=" qvalue
+        let mut opt_wildcard = None;
+        for header_value in self.headers.get_all("accept-encoding") {
+            for item in header_value.split(',') {
+                let mut parts = item.split(';').map(str::trim);
+                let name = parts.next().unwrap_or_default();
+                if name.is_empty() {
+                    continue;
+                }
+                let acceptable = parts
+                    .filter_map(|param| param.strip_prefix("q=").or(param.strip_prefix("Q=")))
+                    .map(|q| q.parse::<f32>().unwrap_or(0.0) > 0.0)
+                    .next()
+                    .unwrap_or(true);
+                if name.eq_ignore_ascii_case(coding) {
+                    return acceptable;
+                } else if name == "*" {
+                    opt_wildcard = Some(acceptable);
+                }
+            }
+        }
+        opt_wildcard.unwrap_or(false)
+    }
+
+    /// Returns true when a `header_name` header contains `token` in its comma-separated list.
+    /// Uses a case-insensitive comparison.
+    pub(crate) fn has_header_token(&self, header_name: &str, token: &str) -> bool {
+        self.headers.get_all(header_name).iter().any(|value| {
+            value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(token))
+        })
+    }
+
+    /// Returns true when the client wants to send more requests on the connection
+    /// after it receives the response.
+    ///
+    /// HTTP/1.1 connections stay open unless the client sends `Connection: close`.
+    /// HTTP/1.0 connections close unless the client sends `Connection: keep-alive`.
+    ///
+    /// <https://datatracker.ietf.org/doc/html/rfc9112#section-9.3>
+    #[must_use]
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            _ if self.has_header_token("connection", "close") => false,
+            HttpVersion::Http10 => self.has_header_token("connection", "keep-alive"),
+            HttpVersion::Http11 | HttpVersion::Http2 => true,
+        }
+    }
+
+    /// Returns true when the request has a `TE` header with `trailers`,
+    /// meaning the client accepts trailer fields in a chunked response.
+    ///
+    /// <https://datatracker.ietf.org/doc/html/rfc9110#section-10.1.4>
+    #[must_use]
+    pub fn accepts_trailers(&self) -> bool {
+        self.headers.get_all("te").iter().any(|header_value| {
+            header_value
+                .split(',')
+                .map(|item| item.split(';').next().unwrap_or_default().trim())
+                .any(|name| name.eq_ignore_ascii_case("trailers"))
+        })
+    }
+
+    /// Returns true when the request asks to upgrade the connection to a WebSocket.
+    /// See [`Response::websocket`](crate::Response::websocket).
+    #[must_use]
+    pub fn is_websocket_upgrade(&self) -> bool {
+        crate::websocket::is_websocket_upgrade(self)
+    }
+
     /// # Errors
     /// Returns an error when the request body length is known and it is larger than `max_len`.
     ///
@@ -156,6 +318,23 @@ impl Request {
         }
     }
 
+    /// Checks that the request body has type `multipart/form-data` and parses it.
+    ///
+    /// Keeps text fields in memory and saves uploaded files to temporary files in `cache_dir`.
+    /// Uses the default limits of [`MultipartParser::new`].
+    /// To change the limits, use [`MultipartParser`].
+    ///
+    /// # Errors
+    /// Returns an error when:
+    /// - the request content type is not `multipart/form-data`
+    /// - the request body was not received
+    /// - we fail to parse the body
+    /// - the body is larger than the limits
+    /// - we fail to write a temporary file
+    pub fn multipart(&self, cache_dir: impl AsRef<Path>) -> Result<MultipartForm, Response> {
+        MultipartParser::new(cache_dir).parse(self)
+    }
+
     /// Parses the request URL and deserializes it into type `T`.
     ///
     /// Treats a missing URL query string (`/foo`) as an empty query string (`/foo?`).
@@ -188,10 +367,16 @@ impl Debug for Request {
         cookie_strings.sort();
         write!(
             f,
-            "Request{{{}, method={}, path={:?}, headers={:?}, cookies={:?}, {:?}{}{}{}{}, {:?}}}",
+            "Request{{{}{}, method={}, path={:?}, {}, headers={:?}, cookies={:?}, {:?}{}{}{}{}, {:?}}}",
             self.remote_addr,
+            if let Some(tag) = self.listener {
+                format!(", listener={tag}")
+            } else {
+                String::new()
+            },
             self.method(),
             self.url().path,
+            self.version,
             self.headers,
             cookie_strings,
             self.content_type(),
@@ -217,22 +402,52 @@ impl Debug for Request {
 /// - the request content-length is too long to fit in `u64`
 #[allow(clippy::module_name_repetitions)]
 pub async fn read_http_request<const BUF_SIZE: usize>(
-    remote_addr: SocketAddr,
+    remote_addr: RemoteAddr,
     buf: &mut FixedBuf<BUF_SIZE>,
     reader: impl AsyncRead + Unpin,
+    head_options: &HeadOptions,
 ) -> Result<Request, HttpError> {
     //dbg!("read_http_request", &buf);
     buf.shift();
-    let mut head = read_http_head(buf, reader).await?;
+    let head = read_http_head(buf, reader, head_options).await?;
     //dbg!(&head);
+    request_from_head(remote_addr, head)
+}
+
+/// Makes a request from a parsed head.
+/// The request body is pending or empty, depending on the headers.
+///
+/// # Errors
+/// Returns an error when:
+/// - the request uses an unsupported transfer encoding
+/// - the request has a malformed cookie header
+/// - the request content-length is too long to fit in `u64`
+pub fn request_from_head(remote_addr: RemoteAddr, mut head: Head) -> Result<Request, HttpError> {
+    // An origin-form target has no host, so handlers get it from the `Host` header.
+    // https://datatracker.ietf.org/doc/html/rfc9112#section-3.3
+    if head.method != "CONNECT"
+        && head.url.host.is_empty()
+        && head.url.ip.is_none()
+        && let Some(host) = head.headers.get_only("host")
+        && !host.contains(['/', '?', '#', '@'])
+        && let Ok(url) = Url::parse_absolute(format!("x://{host}"))
+    {
+        head.url.host = url.host.to_ascii_lowercase();
+        head.url.ip = url.ip;
+        head.url.port = url.port;
+    }
+    // Keep the header, since some content types have parameters, like `boundary`.
     let content_type = head
         .headers
-        .remove_only("content-type")
+        .get_only("content-type")
         .map_or(ContentType::None, |s| ContentType::parse(s.as_str()));
+    // Servers must ignore `Expect: 100-continue` from HTTP/1.0 clients.
+    // https://datatracker.ietf.org/doc/html/rfc9110#section-10.1.1
     let expect_continue = head
         .headers
         .remove_only("expect")
-        .is_some_and(|s| s.as_str() == "100-continue");
+        .is_some_and(|s| s.as_str() == "100-continue")
+        && head.version != HttpVersion::Http10;
     let (gzip, chunked) = {
         let opt_ascii_string = head.headers.remove_only("transfer-encoding");
         let mut iter = opt_ascii_string
@@ -284,8 +499,13 @@ pub async fn read_http_request<const BUF_SIZE: usize>(
     Ok(Request {
         id: next_insecure_rand_u64(),
         remote_addr,
+        listener: None,
+        local_addr: None,
+        forwarded: None,
         method: head.method,
+        target: head.target,
         url: head.url,
+        version: head.version,
         headers: head.headers,
         cookies,
         content_type,
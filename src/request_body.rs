@@ -1,11 +1,11 @@
 use crate::http_error::HttpError;
 use crate::util::{CopyResult, copy_async, escape_and_elide};
-use crate::{BodyAsyncReader, BodyReader};
+use crate::{BodyAsyncReader, BodyReader, BodyStream};
 use futures_io::AsyncRead;
 use futures_lite::{AsyncReadExt, AsyncWriteExt};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
-use std::io::{Cursor, ErrorKind};
+use std::io::{Cursor, ErrorKind, Read};
 use std::path::{Path, PathBuf};
 use temp_file::TempFile;
 
@@ -26,6 +26,9 @@ pub enum RequestBody {
     Vec(Vec<u8>),
     File(PathBuf, u64),
     TempFile(TempFile, u64),
+    /// A body that the server is receiving while the request handler reads it.
+    /// See [`Response::stream_body_and_reprocess`](crate::Response::stream_body_and_reprocess).
+    Stream(BodyStream),
 }
 impl RequestBody {
     #[must_use]
@@ -58,6 +61,7 @@ impl RequestBody {
             RequestBody::StaticStr(s) => Some(u64::try_from(s.len()).unwrap()),
             RequestBody::Vec(v) => Some(u64::try_from(v.len()).unwrap()),
             RequestBody::File(.., len) | RequestBody::TempFile(.., len) => Some(*len),
+            RequestBody::Stream(stream) => stream.len(),
         }
     }
 
@@ -75,6 +79,7 @@ impl RequestBody {
             RequestBody::TempFile(temp_file, ..) => {
                 std::fs::File::open(temp_file.path()).map(BodyReader::File)
             }
+            RequestBody::Stream(stream) => Ok(BodyReader::Stream(stream)),
         }
     }
 
@@ -94,6 +99,7 @@ impl RequestBody {
             RequestBody::TempFile(temp_file, ..) => Ok(BodyAsyncReader::File(
                 async_fs::File::open(temp_file.path()).await?,
             )),
+            RequestBody::Stream(stream) => Ok(BodyAsyncReader::Stream(stream)),
         }
     }
 }
@@ -138,6 +144,7 @@ impl Debug for RequestBody {
                 len,
                 temp_file.path().to_string_lossy(),
             ),
+            RequestBody::Stream(stream) => write!(f, "RequestBody::{stream:?}"),
         }
     }
 }
@@ -188,6 +195,11 @@ impl TryFrom<RequestBody> for Vec<u8> {
             RequestBody::Vec(v) => Ok(v),
             RequestBody::File(path, ..) => std::fs::read(path),
             RequestBody::TempFile(temp_file, ..) => std::fs::read(temp_file.path()),
+            RequestBody::Stream(stream) => {
+                let mut buf = Vec::new();
+                Read::read_to_end(&mut &stream, &mut buf)?;
+                Ok(buf)
+            }
         }
     }
 }
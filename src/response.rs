@@ -4,26 +4,50 @@ use std::convert::TryFrom;
 use std::io::ErrorKind;
 use std::io::Write;
 
-#[cfg(any(feature = "include_dir", feature = "json"))]
-use crate::Error;
-#[cfg(feature = "include_dir")]
-use crate::Request;
+use crate::body_stream::body_stream;
 use crate::event::EventReceiver;
 use crate::http_error::HttpError;
-use crate::util::{copy_async, copy_chunked_async};
-use crate::{AsciiString, ContentType, Cookie, EventSender, HeaderList, ResponseBody};
+use crate::http_version::HttpVersion;
+use crate::upgrade::{Upgrade, UpgradedConn};
+use crate::util::{copy_async, copy_chunked_with_trailers_async};
+use crate::websocket::{WebSocket, WebSocketAcceptor};
+use crate::{
+    AsciiString, BodyStreamSender, ContentType, Cookie, Error, EventSender, HeaderList,
+    PercentEncodePurpose, Request, ResponseBody, Trailers, percent_encode,
+};
 use safina::sync::sync_channel;
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
+/// Content-codings and file suffixes of precompressed static files, in order of preference.
+const PRECOMPRESSED_SUFFIXES: [(&str, &str); 2] = [("br", ".br"), ("gzip", ".gz")];
+
+/// Returns `None` when there is no regular file at `path`.
+fn file_body(path: PathBuf) -> Option<Result<ResponseBody, std::io::Error>> {
+    match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_file() => Some(Ok(ResponseBody::File(path, metadata.len()))),
+        Ok(..) => None,
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
-#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ResponseKind {
     DropConnection,
     /// `GetBodyAndReprocess(max_len: u64)`<br>
     /// Read the body from the client, but only up to the specified `u64` bytes.
     GetBodyAndReprocess(u64),
     Normal,
+    /// `StreamBodyAndReprocess(max_len: u64)`<br>
+    /// Call the request handler again with a [`RequestBody::Stream`](crate::RequestBody::Stream)
+    /// and read the body from the client while the handler runs,
+    /// but only up to the specified `u64` bytes.
+    StreamBodyAndReprocess(u64),
+    /// Send the response and then hand the connection to the [`Upgrade`] function.
+    Upgrade(Upgrade),
 }
 
 #[derive(Eq, PartialEq)]
@@ -33,6 +57,7 @@ pub struct Response {
     pub content_type: ContentType,
     pub headers: HeaderList,
     pub body: ResponseBody,
+    pub trailers: Option<Trailers>,
 }
 impl Response {
     #[must_use]
@@ -43,6 +68,7 @@ impl Response {
             content_type: ContentType::None,
             headers: HeaderList::new(),
             body: ResponseBody::empty(),
+            trailers: None,
         }
     }
 
@@ -55,12 +81,19 @@ impl Response {
             content_type: ContentType::None,
             headers: HeaderList::new(),
             body: ResponseBody::empty(),
+            trailers: None,
         }
     }
 
     /// Return this and the server will read the request body from the client
     /// and call the request handler again.
     ///
+    /// When the client sent `Expect: 100-continue`, the server calls the handler before
+    /// sending `100 Continue`, even for small bodies.
+    /// The handler can check the request head and return a final response like
+    /// 401 Unauthorized or 413 Payload Too Large, so the client never sends the body.
+    /// Or it can return this to make the server send `100 Continue` and read the body.
+    ///
     /// If the request body is larger than `max_len` bytes, it sends 413 Payload Too Large.
     #[must_use]
     pub fn get_body_and_reprocess(max_len: u64) -> Self {
@@ -70,6 +103,57 @@ impl Response {
             content_type: ContentType::None,
             headers: HeaderList::new(),
             body: ResponseBody::empty(),
+            trailers: None,
+        }
+    }
+
+    /// Return this and the server will call the request handler again
+    /// with a [`RequestBody::Stream`](crate::RequestBody::Stream).
+    /// The handler can read the body while the server receives it from the client.
+    /// The server does not save the body in memory or a file.
+    ///
+    /// When the handler stops reading, the server stops receiving.
+    ///
+    /// The server reads bodies smaller than
+    /// [`small_body_len`](crate::HttpServerBuilder::small_body_len) before calling the handler,
+    /// so those never arrive as a stream,
+    /// unless the client sent `Expect: 100-continue`.
+    ///
+    /// If the request body is larger than `max_len` bytes, it sends 413 Payload Too Large.
+    /// When the body length is unknown and the client sends more than `max_len` bytes,
+    /// the handler gets an error when reading the body.
+    ///
+    /// # Example
+    /// ```
+    /// use servlin::{Request, Response};
+    /// use std::io::Read;
+    ///
+    /// fn handle(req: Request) -> Response {
+    ///     if req.body.is_pending() {
+    ///         return Response::stream_body_and_reprocess(1024 * 1024 * 1024);
+    ///     }
+    ///     let mut reader = req.body.reader().unwrap();
+    ///     let mut buf = [0_u8; 65536];
+    ///     let mut total = 0;
+    ///     loop {
+    ///         match reader.read(&mut buf) {
+    ///             Ok(0) => break,
+    ///             Ok(n) => total += n,
+    ///             Err(e) => return Response::text(400, format!("{e}")),
+    ///         }
+    ///     }
+    ///     Response::text(200, format!("got {total} bytes"))
+    /// }
+    /// ```
+    #[must_use]
+    pub fn stream_body_and_reprocess(max_len: u64) -> Self {
+        Self {
+            kind: ResponseKind::StreamBodyAndReprocess(max_len),
+            code: 0,
+            content_type: ContentType::None,
+            headers: HeaderList::new(),
+            body: ResponseBody::empty(),
+            trailers: None,
         }
     }
 
@@ -80,11 +164,14 @@ impl Response {
     ///
     /// When the request path is `"/"`, tries to return the file `/index.html`.
     ///
+    /// When `dir` contains a precompressed version of the file, like `app.js.br` or `app.js.gz`,
+    /// and the request's `Accept-Encoding` header allows it, returns the precompressed file
+    /// with a `content-encoding` header.
+    ///
     /// # Errors
     /// Returns a 404 Not Found response if the file is not found in the included dir.
     #[cfg(feature = "include_dir")]
     // TODO: Change this to accept only GET and HEAD requests.
-    // TODO: Change this to handle HEAD requests properly.
     // TODO: Honor Accept request header.
     pub fn include_dir(req: &Request, dir: &'static include_dir::Dir) -> Result<Response, Error> {
         let path = &req.url.path;
@@ -102,27 +189,112 @@ impl Response {
             None
         }
         .ok_or_else(|| Error::client_error(Response::not_found_404()))?;
-        let extension = std::path::Path::new(path)
-            .extension()
-            .map_or("", |os_str| os_str.to_str().unwrap_or(""));
-        let content_type = match extension {
-            "css" => ContentType::Css,
-            "csv" => ContentType::Csv,
-            "gif" => ContentType::Gif,
-            "htm" | "html" => ContentType::Html,
-            "js" => ContentType::JavaScript,
-            "jpg" | "jpeg" => ContentType::Jpeg,
-            "json" => ContentType::Json,
-            "md" => ContentType::Markdown,
-            "pdf" => ContentType::Pdf,
-            "txt" => ContentType::PlainText,
-            "png" => ContentType::Png,
-            "svg" => ContentType::Svg,
-            _ => ContentType::None,
+        Ok(Self::precompressed_or(
+            req,
+            ContentType::from_path(file.path()),
+            ResponseBody::StaticBytes(file.contents()),
+            |suffix| {
+                let mut sibling_path = file.path().as_os_str().to_os_string();
+                sibling_path.push(suffix);
+                dir.get_file(sibling_path)
+                    .map(|sibling| Ok(ResponseBody::StaticBytes(sibling.contents())))
+            },
+        ))
+    }
+
+    /// Returns the file at `path`.
+    ///
+    /// Determines the content-type from the file extension.
+    /// For the list of supported content types, see [`ContentType`].
+    ///
+    /// When there is a precompressed version of the file next to it,
+    /// like `app.js.br` or `app.js.gz`,
+    /// and the request's `Accept-Encoding` header allows it, returns the precompressed file
+    /// with a `content-encoding` header.
+    ///
+    /// # Errors
+    /// Returns a 404 Not Found response if the file does not exist.
+    ///
+    /// Returns an error when it fails to read the file's metadata.
+    pub fn static_file(req: &Request, path: impl AsRef<Path>) -> Result<Response, Error> {
+        let path = path.as_ref();
+        let body = match file_body(path.to_path_buf()) {
+            Some(result) => result?,
+            None => return Err(Error::client_error(Response::not_found_404())),
         };
-        Ok(Response::new(200)
-            .with_type(content_type)
-            .with_body(ResponseBody::StaticBytes(file.contents())))
+        Ok(Self::precompressed_or(
+            req,
+            ContentType::from_path(path),
+            body,
+            |suffix| {
+                let mut sibling_path = path.as_os_str().to_os_string();
+                sibling_path.push(suffix);
+                file_body(PathBuf::from(sibling_path))
+            },
+        ))
+    }
+
+    /// Looks for the requested file in `dir` on disk.
+    ///
+    /// This works like [`Response::include_dir`], but reads the files at request time.
+    ///
+    /// Refuses request paths with `.` or `..` segments.
+    ///
+    /// # Errors
+    /// Returns a 404 Not Found response if the file is not found in `dir`.
+    ///
+    /// Returns an error when it fails to read file metadata.
+    pub fn static_dir(req: &Request, dir: impl AsRef<Path>) -> Result<Response, Error> {
+        let path = &req.url.path;
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let mut file_path = dir.as_ref().to_path_buf();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            if segment == "." || segment == ".." || segment.contains(['\\', '\0']) {
+                return Err(Error::client_error(Response::not_found_404()));
+            }
+            file_path.push(segment);
+        }
+        if file_path.is_dir() {
+            if !path.is_empty() && !path.ends_with('/') {
+                return Ok(Response::redirect_301(format!(
+                    "/{}/",
+                    percent_encode(path, PercentEncodePurpose::Path)
+                )));
+            }
+            file_path.push("index.html");
+        }
+        Self::static_file(req, file_path)
+    }
+
+    /// Calls `get_sibling` with each precompressed file suffix that the client accepts.
+    /// Returns a response with the first sibling body found,
+    /// or with `original` if there is no acceptable sibling.
+    fn precompressed_or(
+        req: &Request,
+        content_type: ContentType,
+        original: ResponseBody,
+        mut get_sibling: impl FnMut(&str) -> Option<Result<ResponseBody, std::io::Error>>,
+    ) -> Response {
+        let response = Response::new(200).with_type(content_type);
+        let mut has_sibling = false;
+        for (coding, suffix) in PRECOMPRESSED_SUFFIXES {
+            if let Some(Ok(body)) = get_sibling(suffix) {
+                if req.accepts_encoding(coding) {
+                    return response
+                        .with_header("content-encoding", coding.try_into().unwrap())
+                        .with_header("vary", "accept-encoding".try_into().unwrap())
+                        .with_body(body);
+                }
+                has_sibling = true;
+            }
+        }
+        if has_sibling {
+            response
+                .with_header("vary", "accept-encoding".try_into().unwrap())
+                .with_body(original)
+        } else {
+            response.with_body(original)
+        }
     }
 
     #[must_use]
@@ -154,6 +326,100 @@ impl Response {
         )
     }
 
+    /// Makes a 200 OK response with a body that the handler sends in chunks.
+    ///
+    /// The server sends the chunks to the client with chunked encoding.
+    /// When the client does not keep up, [`BodyStreamSender::send_blocking`] waits.
+    /// Drop the sender to end the body.
+    ///
+    /// # Example
+    /// ```
+    /// use servlin::Response;
+    ///
+    /// let (sender, response) = Response::byte_stream();
+    /// std::thread::spawn(move || {
+    ///     for n in 0..3 {
+    ///         if sender.send_blocking(format!("{n}\n").into_bytes()).is_err() {
+    ///             return;
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    #[must_use]
+    pub fn byte_stream() -> (BodyStreamSender, Response) {
+        let (sender, stream) = body_stream(4, None);
+        (
+            sender,
+            Self::new(200).with_body(ResponseBody::Stream(stream)),
+        )
+    }
+
+    /// Accepts a WebSocket upgrade request with the default [`WebSocketAcceptor`] settings.
+    ///
+    /// After the server sends the response,
+    /// it calls `handler` on a thread where it can block.
+    ///
+    /// # Errors
+    /// Returns an error response when `req` is not a valid WebSocket upgrade request.
+    /// See [`WebSocketAcceptor::accept`].
+    pub fn websocket<F>(req: &Request, handler: F) -> Result<Response, Response>
+    where
+        F: FnOnce(WebSocket) + Send + 'static,
+    {
+        WebSocketAcceptor::new().accept(req, handler)
+    }
+
+    /// Switches the connection to another protocol.
+    ///
+    /// The server sends `101 Switching Protocols` with `upgrade: {protocol}` and `connection: upgrade` headers.
+    /// Then it stops processing the connection as HTTP
+    /// and calls `f` on a thread where it can block.
+    /// The connection counts against the server's connection limit until `f` drops it.
+    ///
+    /// Check the request's `upgrade` header before calling this.
+    ///
+    /// # Panics
+    /// Panics when `protocol` is not a valid header value.
+    #[must_use]
+    pub fn upgrade<F>(protocol: impl AsRef<str>, f: F) -> Self
+    where
+        F: FnOnce(UpgradedConn) + Send + 'static,
+    {
+        let protocol = AsciiString::try_from(protocol.as_ref())
+            .unwrap_or_else(|_| panic!("invalid protocol {:?}", protocol.as_ref()));
+        Response::new(101)
+            .with_header("upgrade", protocol)
+            .with_header("connection", "upgrade".try_into().unwrap())
+            .with_upgrade(f)
+    }
+
+    /// Accepts a `CONNECT` request and turns the connection into a tunnel.
+    ///
+    /// The server sends `200 OK`.
+    /// Then it stops processing the connection as HTTP
+    /// and calls `f` on a thread where it can block.
+    /// The connection counts against the server's connection limit until `f` drops it.
+    #[must_use]
+    pub fn connect_tunnel<F>(f: F) -> Self
+    where
+        F: FnOnce(UpgradedConn) + Send + 'static,
+    {
+        Response::new(200).with_upgrade(f)
+    }
+
+    /// Makes the server hand the connection to `f` after it sends this response.
+    ///
+    /// The server sends the response head without a body.
+    /// It refuses to send an upgrade response with a status other than 101 or 2xx.
+    #[must_use]
+    pub fn with_upgrade<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(UpgradedConn) + Send + 'static,
+    {
+        self.kind = ResponseKind::Upgrade(Upgrade::new(f));
+        self
+    }
+
     #[must_use]
     pub fn text(code: u16, body: impl Into<ResponseBody>) -> Self {
         Self::new(code)
@@ -202,7 +468,7 @@ impl Response {
 
     #[must_use]
     pub fn forbidden_403() -> Self {
-        Response::new(401)
+        Response::new(403)
     }
 
     #[must_use]
@@ -227,6 +493,11 @@ impl Response {
         Response::text(413, "Uploaded data is too big.")
     }
 
+    #[must_use]
+    pub fn misdirected_request_421() -> Self {
+        Response::text(421, "Misdirected request.")
+    }
+
     #[must_use]
     pub fn unprocessable_entity_422(body: impl Into<String>) -> Self {
         let body: String = body.into();
@@ -248,11 +519,21 @@ impl Response {
         Response::new(501)
     }
 
+    #[must_use]
+    pub fn bad_gateway_502() -> Self {
+        Response::new(502)
+    }
+
     #[must_use]
     pub fn service_unavailable_503() -> Self {
         Response::new(503)
     }
 
+    #[must_use]
+    pub fn gateway_timeout_504() -> Self {
+        Response::new(504)
+    }
+
     #[must_use]
     pub fn with_body(mut self, b: impl Into<ResponseBody>) -> Self {
         self.body = b.into();
@@ -317,6 +598,32 @@ impl Response {
         self
     }
 
+    /// Declares trailer fields to send after the body.
+    /// Set their values with [`Trailers::set`] before the body ends.
+    ///
+    /// The server sends trailers only when the body has unknown length,
+    /// like [`Response::byte_stream`],
+    /// and the request has a `TE: trailers` header.
+    ///
+    /// # Example
+    /// ```
+    /// use servlin::{Response, Trailers};
+    ///
+    /// let trailers = Trailers::new(["x-checksum"]);
+    /// let (sender, response) = Response::byte_stream();
+    /// let response = response.with_trailers(&trailers);
+    /// std::thread::spawn(move || {
+    ///     let _ = sender.send_blocking(b"abc".to_vec());
+    ///     trailers.set("x-checksum", "123".try_into().unwrap());
+    ///     // Dropping `sender` ends the body.
+    /// });
+    /// ```
+    #[must_use]
+    pub fn with_trailers(mut self, trailers: &Trailers) -> Self {
+        self.trailers = Some(trailers.clone());
+        self
+    }
+
     #[must_use]
     pub fn with_status(mut self, c: u16) -> Self {
         self.code = c;
@@ -363,6 +670,16 @@ impl Response {
     pub fn is_get_body_and_reprocess(&self) -> bool {
         matches!(self.kind, ResponseKind::GetBodyAndReprocess(..))
     }
+
+    #[must_use]
+    pub fn is_stream_body_and_reprocess(&self) -> bool {
+        matches!(self.kind, ResponseKind::StreamBodyAndReprocess(..))
+    }
+
+    #[must_use]
+    pub fn is_upgrade(&self) -> bool {
+        matches!(self.kind, ResponseKind::Upgrade(..))
+    }
 }
 impl From<std::io::Error> for Response {
     fn from(e: std::io::Error) -> Self {
@@ -379,6 +696,18 @@ impl Debug for Response {
             ResponseKind::GetBodyAndReprocess(max_len) => {
                 write!(f, "Response(kind=GetBodyAndReprocess({max_len}))")
             }
+            ResponseKind::StreamBodyAndReprocess(max_len) => {
+                write!(f, "Response(kind=StreamBodyAndReprocess({max_len}))")
+            }
+            ResponseKind::Upgrade(..) => {
+                write!(
+                    f,
+                    "Response(kind=Upgrade, {} {}, {:?})",
+                    self.code,
+                    reason_phrase(self.code),
+                    self.headers,
+                )
+            }
             ResponseKind::Normal => {
                 write!(
                     f,
@@ -463,22 +792,27 @@ pub fn reason_phrase(code: u16) -> &'static str {
     }
 }
 
-/// # Errors
-/// Returns an error when:
-/// - `response` is not `Response::Normal`
-/// - the connection is closed
-/// - we fail to send the response on the connection
-/// - the response body is saved in a file and we fail to read the file
-#[allow(clippy::module_name_repetitions)]
-pub async fn write_http_response(
-    mut writer: impl AsyncWrite + Unpin,
+/// Appends header lines to `buf`.
+fn push_header_lines(buf: &mut Vec<u8>, headers: &HeaderList) {
+    for header in headers {
+        // Convert headers from UTF-8 back to ISO-8859-1, with 0xFF for a replacement byte.
+        write!(buf, "{}: ", header.name).unwrap();
+        buf.extend(header.value.chars().map(|c| u8::try_from(c).unwrap_or(255)));
+        buf.extend(b"\r\n");
+    }
+}
+
+/// Returns the status line and header lines for `response`, with the blank line at the end,
+/// and the trailers to send after the body.
+///
+/// See [`write_http_response`] for the meaning of the flags.
+fn response_head(
     response: &Response,
+    version: HttpVersion,
+    head: bool,
     close: bool,
-) -> Result<(), HttpError> {
-    //dbg!("write_http_response", &response);
-    if !response.is_normal() {
-        return Err(HttpError::UnwritableResponse);
-    }
+    send_trailers: bool,
+) -> Result<(Vec<u8>, Option<&Trailers>), HttpError> {
     // https://datatracker.ietf.org/doc/html/rfc7230#section-3.1.2
     //     status-line = HTTP-version SP status-code SP reason-phrase CRLF
     //     status-code    = 3DIGIT
@@ -500,27 +834,81 @@ pub async fn write_http_response(
         )
         .unwrap();
     }
+    let http10 = version == HttpVersion::Http10;
     if close {
         write!(head_bytes, "connection: close\r\n",).unwrap();
+    } else if http10 && !response.is_1xx() && !response.is_upgrade() {
+        // https://datatracker.ietf.org/doc/html/rfc9112#appendix-C.2.2
+        write!(head_bytes, "connection: keep-alive\r\n").unwrap();
     }
-    if let Some(body_len) = response.body.len() {
+    if response.is_upgrade() {
+        // After the head, the connection carries another protocol.
+    } else if let Some(body_len) = response.body.len() {
         if response.headers.get_only("content-length").is_some() {
             return Err(HttpError::DuplicateContentLengthHeader);
         }
         write!(head_bytes, "content-length: {body_len}\r\n").unwrap();
+    } else if http10 {
+        if !close && !head {
+            return Err(HttpError::UnwritableResponse);
+        }
     } else {
         if response.headers.get_only("transfer-encoding").is_some() {
             return Err(HttpError::DuplicateTransferEncodingHeader);
         }
         write!(head_bytes, "transfer-encoding: chunked\r\n").unwrap();
     }
-    for header in &response.headers {
-        // Convert headers from UTF-8 back to ISO-8859-1, with 0xFF for a replacement byte.
-        write!(head_bytes, "{}: ", header.name).unwrap();
-        head_bytes.extend(header.value.chars().map(|c| u8::try_from(c).unwrap_or(255)));
-        head_bytes.extend(b"\r\n");
+    let opt_trailers = response.trailers.as_ref().filter(|trailers| {
+        send_trailers
+            && !head
+            && !http10
+            && response.body.len().is_none()
+            && !trailers.names().is_empty()
+    });
+    if let Some(trailers) = opt_trailers {
+        if response.headers.get_only("trailer").is_some() {
+            return Err(HttpError::DuplicateTrailerHeader);
+        }
+        write!(head_bytes, "trailer: {}\r\n", trailers.names().join(", ")).unwrap();
     }
+    push_header_lines(&mut head_bytes, &response.headers);
     head_bytes.extend(b"\r\n");
+    Ok((head_bytes, opt_trailers))
+}
+
+/// Writes `response` to `writer`.
+///
+/// Sends the response's trailers when `send_trailers` is true and the body has unknown length.
+///
+/// HTTP/1.0 clients do not understand chunked encoding.
+/// For them, this writes a body with unknown length as-is and the caller must pass
+/// `close` and then close the connection to mark the end of the body.
+///
+/// When `head` is true, this writes the same header lines it would write for a GET request
+/// and omits the body.
+///
+/// # Errors
+/// Returns an error when:
+/// - `response` is not `Response::Normal`
+/// - the connection is closed
+/// - we fail to send the response on the connection
+/// - the response body is saved in a file and we fail to read the file
+#[allow(clippy::module_name_repetitions)]
+pub async fn write_http_response(
+    mut writer: impl AsyncWrite + Unpin,
+    response: &Response,
+    version: HttpVersion,
+    head: bool,
+    close: bool,
+    send_trailers: bool,
+) -> Result<(), HttpError> {
+    //dbg!("write_http_response", &response);
+    if !response.is_normal()
+        && !(response.is_upgrade() && (response.code == 101 || response.is_2xx()))
+    {
+        return Err(HttpError::UnwritableResponse);
+    }
+    let (head_bytes, opt_trailers) = response_head(response, version, head, close, send_trailers)?;
     //dbg!(escape_ascii(head_bytes.as_slice()));
     writer
         .write_all(head_bytes.as_slice())
@@ -528,6 +916,8 @@ pub async fn write_http_response(
         .map_err(|_| HttpError::Disconnected)?;
     drop(head_bytes);
     match response.body.len() {
+        // https://datatracker.ietf.org/doc/html/rfc9110#section-9.3.2
+        _ if head || response.is_upgrade() => {}
         Some(0) => {}
         Some(body_len) => {
             let mut reader = AsyncReadExt::take(
@@ -550,13 +940,32 @@ pub async fn write_http_response(
                 ));
             }
         }
+        None if version == HttpVersion::Http10 => {
+            let reader = response
+                .body
+                .async_reader()
+                .await
+                .map_err(HttpError::error_reading_response_body)?;
+            copy_async(reader, &mut writer, u64::MAX)
+                .await
+                .map_errs(HttpError::error_reading_response_body, |_| {
+                    HttpError::Disconnected
+                })?;
+        }
         None => {
             let mut reader = response
                 .body
                 .async_reader()
                 .await
                 .map_err(HttpError::error_reading_response_body)?;
-            copy_chunked_async(&mut reader, &mut writer)
+            let get_trailer_section = || {
+                let mut bytes = Vec::new();
+                if let Some(trailers) = opt_trailers {
+                    push_header_lines(&mut bytes, &trailers.values());
+                }
+                bytes
+            };
+            copy_chunked_with_trailers_async(&mut reader, &mut writer, get_trailer_section)
                 .await
                 .map_errs(HttpError::error_reading_response_body, |_| {
                     HttpError::Disconnected
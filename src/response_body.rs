@@ -1,15 +1,31 @@
+use crate::body_stream::read_in_background;
 use crate::event::EventReceiver;
 use crate::util::escape_and_elide;
-use crate::{BodyAsyncReader, BodyReader};
+use crate::{BodyAsyncReader, BodyReader, BodyStream};
+use futures_io::AsyncRead;
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
 use std::io::{ErrorKind, Read};
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Mutex;
 use temp_file::TempFile;
 
 pub enum ResponseBody {
     EventStream(Mutex<EventReceiver>),
+    /// Bytes from a blocking reader.
+    /// The server reads it on a blocking thread and sends the bytes with chunked encoding.
+    ///
+    /// Use [`ResponseBody::from_reader`] to make this variant.
+    Reader(Mutex<Box<dyn Read + Send>>),
+    /// Bytes from an async reader, sent with chunked encoding.
+    ///
+    /// Use [`ResponseBody::from_async_reader`] to make this variant.
+    AsyncReader(Mutex<Pin<Box<dyn AsyncRead + Send>>>),
+    /// Bytes from a [`BodyStreamSender`](crate::BodyStreamSender), sent with chunked encoding.
+    ///
+    /// Use [`Response::byte_stream`](crate::Response::byte_stream) to make this variant.
+    Stream(BodyStream),
     StaticBytes(&'static [u8]),
     StaticStr(&'static str),
     Vec(Vec<u8>),
@@ -22,6 +38,16 @@ impl ResponseBody {
         ResponseBody::StaticStr("")
     }
 
+    #[must_use]
+    pub fn from_reader(reader: impl Read + Send + 'static) -> Self {
+        ResponseBody::Reader(Mutex::new(Box::new(reader)))
+    }
+
+    #[must_use]
+    pub fn from_async_reader(reader: impl AsyncRead + Send + 'static) -> Self {
+        ResponseBody::AsyncReader(Mutex::new(Box::pin(reader)))
+    }
+
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
     pub fn is_empty(&self) -> bool {
@@ -37,7 +63,10 @@ impl ResponseBody {
     #[allow(clippy::missing_panics_doc)]
     pub fn len(&self) -> Option<u64> {
         match self {
-            ResponseBody::EventStream(..) => None,
+            ResponseBody::EventStream(..)
+            | ResponseBody::Reader(..)
+            | ResponseBody::AsyncReader(..) => None,
+            ResponseBody::Stream(stream) => stream.len(),
             ResponseBody::StaticBytes(b) => Some(u64::try_from(b.len()).unwrap()),
             ResponseBody::StaticStr(s) => Some(u64::try_from(s.len()).unwrap()),
             ResponseBody::Vec(v) => Some(u64::try_from(v.len()).unwrap()),
@@ -52,6 +81,9 @@ impl ResponseBody {
             ResponseBody::EventStream(mutex_receiver) => {
                 Ok(BodyReader::EventReceiver(mutex_receiver))
             }
+            ResponseBody::Reader(mutex_reader) => Ok(BodyReader::Reader(mutex_reader)),
+            ResponseBody::AsyncReader(mutex_reader) => Ok(BodyReader::AsyncReader(mutex_reader)),
+            ResponseBody::Stream(stream) => Ok(BodyReader::Stream(stream)),
             ResponseBody::StaticBytes(b) => Ok(BodyReader::bytes(b)),
             ResponseBody::StaticStr(s) => Ok(BodyReader::bytes(s.as_bytes())),
             ResponseBody::Vec(v) => Ok(BodyReader::bytes(v.as_slice())),
@@ -60,13 +92,30 @@ impl ResponseBody {
         }
     }
 
+    /// A [`ResponseBody::Reader`] body can be read only once.
+    /// This moves the reader to a blocking thread.
+    ///
     /// # Errors
     /// Returns an error when the body is cached in a file and we fail to open the file.
+    ///
+    /// # Panics
+    /// Panics when the body is a [`ResponseBody::Reader`] and this is called outside of a `safina` executor.
     pub async fn async_reader(&self) -> Result<BodyAsyncReader<'_>, std::io::Error> {
         match self {
             ResponseBody::EventStream(mutex_receiver) => {
                 Ok(BodyAsyncReader::EventReceiver(mutex_receiver))
             }
+            ResponseBody::Reader(mutex_reader) => {
+                let reader = std::mem::replace(
+                    &mut *mutex_reader.lock().unwrap(),
+                    Box::new(std::io::empty()),
+                );
+                Ok(BodyAsyncReader::Background(read_in_background(reader)))
+            }
+            ResponseBody::AsyncReader(mutex_reader) => {
+                Ok(BodyAsyncReader::AsyncReader(mutex_reader))
+            }
+            ResponseBody::Stream(stream) => Ok(BodyAsyncReader::Stream(stream)),
             ResponseBody::StaticBytes(b) => Ok(BodyAsyncReader::bytes(b)),
             ResponseBody::StaticStr(s) => Ok(BodyAsyncReader::bytes(s.as_bytes())),
             ResponseBody::Vec(v) => Ok(BodyAsyncReader::bytes(v.as_slice())),
@@ -79,6 +128,9 @@ impl Debug for ResponseBody {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
         match self {
             ResponseBody::EventStream(..) => write!(f, "ResponseBody::EventStream(..)"),
+            ResponseBody::Reader(..) => write!(f, "ResponseBody::Reader(..)"),
+            ResponseBody::AsyncReader(..) => write!(f, "ResponseBody::AsyncReader(..)"),
+            ResponseBody::Stream(stream) => write!(f, "ResponseBody::Stream({stream:?})"),
             ResponseBody::StaticBytes(b) => {
                 write!(
                     f,
@@ -153,6 +205,7 @@ impl PartialEq for ResponseBody {
         #[allow(clippy::match_same_arms)]
         match (self, other) {
             (ResponseBody::EventStream(..), ResponseBody::EventStream(..)) => false,
+            (ResponseBody::Stream(stream1), ResponseBody::Stream(stream2)) => stream1 == stream2,
             (ResponseBody::StaticBytes(b1), ResponseBody::StaticBytes(b2)) => b1 == b2,
             (ResponseBody::StaticStr(s1), ResponseBody::StaticStr(s2)) => s1 == s2,
             (ResponseBody::Vec(v1), ResponseBody::Vec(v2)) => v1 == v2,
@@ -186,6 +239,24 @@ impl TryFrom<ResponseBody> for Vec<u8> {
                 mutex_receiver.lock().unwrap().read_to_end(&mut buf)?;
                 Ok(buf)
             }
+            ResponseBody::Reader(mutex_reader) => {
+                let mut buf = Vec::new();
+                mutex_reader.into_inner().unwrap().read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+            ResponseBody::AsyncReader(mutex_reader) => {
+                let mut buf = Vec::new();
+                futures_lite::future::block_on(futures_lite::AsyncReadExt::read_to_end(
+                    &mut mutex_reader.into_inner().unwrap(),
+                    &mut buf,
+                ))?;
+                Ok(buf)
+            }
+            ResponseBody::Stream(stream) => {
+                let mut buf = Vec::new();
+                Read::read_to_end(&mut &stream, &mut buf)?;
+                Ok(buf)
+            }
             ResponseBody::StaticBytes(b) => Ok(b.to_vec()),
             ResponseBody::StaticStr(s) => Ok(s.as_bytes().to_vec()),
             ResponseBody::Vec(v) => Ok(v),
@@ -0,0 +1,546 @@
+use crate::body_stream::{BodyStreamSender, body_stream};
+use crate::headers::HeaderList;
+use crate::remote_addr::RemoteAddr;
+use crate::util::{is_field_value, is_tchar};
+use crate::{AsciiString, Request, RequestBody, Response, ResponseBody};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Header fields that apply to one connection.
+/// Proxies must not forward them.
+/// <https://datatracker.ietf.org/doc/html/rfc9110#section-7.6.1>
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+const MAX_RESPONSE_HEAD_LEN: u64 = 64 * 1024;
+const MAX_CHUNK_LINE_LEN: u64 = 1024;
+
+fn malformed(msg: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, format!("upstream {msg}"))
+}
+
+/// Returns the lowercase names listed in `Connection` headers.
+fn connection_options(headers: &HeaderList) -> Vec<String> {
+    headers
+        .get_all("connection")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|s| s.trim().to_ascii_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Returns true when a proxy must not forward the header `name`.
+fn is_hop_by_hop(name: &str, connection_options: &[String]) -> bool {
+    HOP_BY_HOP_HEADERS
+        .iter()
+        .any(|hop| name.eq_ignore_ascii_case(hop))
+        || connection_options
+            .iter()
+            .any(|option| name.eq_ignore_ascii_case(option))
+}
+
+/// Formats `value` as a `Forwarded` parameter value, quoting it when it is not a token.
+fn forwarded_value(value: &str) -> String {
+    // https://datatracker.ietf.org/doc/html/rfc7239#section-4
+    if !value.is_empty() && value.bytes().all(is_tchar) {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reads a line and removes its line ending.
+fn read_line(reader: &mut impl BufRead) -> Result<String, std::io::Error> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    if line.pop() != Some(b'\n') {
+        return Err(std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "upstream sent a truncated or too long line",
+        ));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| malformed("sent a line that is not UTF-8"))
+}
+
+/// Sends bytes from `reader` to `sender`.
+///
+/// Returns `false` when the receiver dropped the stream.
+///
+/// # Errors
+/// Returns an error when `reader` fails or returns fewer than `opt_len` bytes.
+fn send_body_chunks(
+    mut reader: impl Read,
+    sender: &BodyStreamSender,
+    opt_len: Option<u64>,
+) -> Result<bool, std::io::Error> {
+    let mut total = 0_u64;
+    loop {
+        let mut chunk = vec![0_u8; 65536];
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        chunk.truncate(n);
+        total += n as u64;
+        if sender.send_blocking(chunk).is_err() {
+            return Ok(false);
+        }
+    }
+    if opt_len.is_some_and(|len| total < len) {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(true)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum BodyFraming {
+    Empty,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+struct ResponseHead {
+    http11: bool,
+    code: u16,
+    headers: HeaderList,
+}
+
+/// Reads a response head, skipping interim responses like `100 Continue`.
+fn read_response_head(conn: &mut BufReader<TcpStream>) -> Result<ResponseHead, std::io::Error> {
+    loop {
+        let mut reader = conn.by_ref().take(MAX_RESPONSE_HEAD_LEN);
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-4
+        //     status-line = HTTP-version SP status-code SP [ reason-phrase ]
+        let status_line = read_line(&mut reader)?;
+        let http11 = match status_line.get(..9) {
+            Some("HTTP/1.1 ") => true,
+            Some("HTTP/1.0 ") => false,
+            _ => return Err(malformed("sent a malformed status line")),
+        };
+        let code: u16 = status_line
+            .get(9..12)
+            .filter(|s| s.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|s| s.parse().ok())
+            .filter(|code| (100..600).contains(code))
+            .ok_or_else(|| malformed("sent a malformed status code"))?;
+        if !matches!(status_line.as_bytes().get(12), None | Some(b' ')) {
+            return Err(malformed("sent a malformed status line"));
+        }
+        let mut headers = HeaderList::new();
+        loop {
+            let line = read_line(&mut reader)?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .filter(|(name, _)| !name.is_empty() && name.bytes().all(is_tchar))
+                .ok_or_else(|| malformed("sent a malformed header line"))?;
+            let value = Some(value.trim_matches([' ', '\t']))
+                .filter(|value| is_field_value(value))
+                .and_then(|value| AsciiString::try_from(value).ok())
+                .ok_or_else(|| malformed("sent a malformed header value"))?;
+            headers.add(name.to_ascii_lowercase(), value);
+        }
+        match code {
+            101 => return Err(malformed("tried to switch protocols")),
+            100..=199 => {}
+            _ => {
+                return Ok(ResponseHead {
+                    http11,
+                    code,
+                    headers,
+                });
+            }
+        }
+    }
+}
+
+/// Forwards requests to an upstream HTTP/1.1 server.
+///
+/// Clones share a pool of idle upstream connections.
+///
+/// The proxy:
+/// - removes hop-by-hop headers, like `Connection` and `Upgrade`
+/// - forwards the request target exactly as the client sent it
+/// - adds a [`Forwarded`](https://datatracker.ietf.org/doc/html/rfc7239) header
+///   with the client's address
+/// - sends the upstream response body to the client while receiving it
+/// - returns `502 Bad Gateway` when it cannot connect to the upstream server
+///   or the server sends a malformed response
+/// - returns `504 Gateway Timeout` when connecting or waiting for the response takes too long
+///
+/// It does not support protocol upgrades, like WebSocket.
+///
+/// Example:
+/// ```
+/// use servlin::{Request, ReverseProxy};
+/// use std::net::SocketAddr;
+///
+/// let upstream: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+/// let proxy = ReverseProxy::new(upstream);
+/// let handler = move |req: Request| proxy.forward(&req);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReverseProxy {
+    upstream: SocketAddr,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+    max_body_len: u64,
+    max_idle_conns: usize,
+    idle_conns: Arc<Mutex<Vec<BufReader<TcpStream>>>>,
+}
+impl ReverseProxy {
+    #[must_use]
+    pub fn new(upstream: SocketAddr) -> Self {
+        Self {
+            upstream,
+            connect_timeout: Duration::from_secs(5),
+            response_timeout: Duration::from_secs(30),
+            max_body_len: 1024 * 1024 * 1024,
+            max_idle_conns: 16,
+            idle_conns: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Sets how long to wait when connecting to the upstream server.
+    ///
+    /// Default: 5 seconds
+    #[must_use]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for each read from or write to the upstream server.
+    ///
+    /// Default: 30 seconds
+    #[must_use]
+    pub fn response_timeout(mut self, timeout: Duration) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum length of request bodies, in bytes.
+    /// See [`Response::stream_body_and_reprocess`].
+    ///
+    /// Default: 1 GiB
+    #[must_use]
+    pub fn max_body_len(mut self, n: u64) -> Self {
+        self.max_body_len = n;
+        self
+    }
+
+    /// Sets how many idle upstream connections to keep for reuse.
+    ///
+    /// Default: 16
+    #[must_use]
+    pub fn max_idle_conns(mut self, n: usize) -> Self {
+        self.max_idle_conns = n;
+        self
+    }
+
+    /// Sends `req` to the upstream server and returns its response.
+    ///
+    /// When the request body is pending,
+    /// returns [`Response::stream_body_and_reprocess`],
+    /// so the server calls the handler again and the proxy sends the body while receiving it.
+    #[must_use]
+    pub fn forward(&self, req: &Request) -> Response {
+        if req.body.is_pending() {
+            return Response::stream_body_and_reprocess(self.max_body_len);
+        }
+        match self.exchange(req) {
+            Ok(response) => response,
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                Response::gateway_timeout_504()
+            }
+            Err(..) => Response::bad_gateway_502(),
+        }
+    }
+
+    fn exchange(&self, req: &Request) -> Result<Response, std::io::Error> {
+        let head = self.request_head(req);
+        // A pooled connection can fail when the upstream server closed it.
+        // Then we send the request again on a new connection,
+        // when that is safe and we still have the body.
+        // https://datatracker.ietf.org/doc/html/rfc9110#section-9.2.2
+        let retry = matches!(
+            req.method.as_str(),
+            "GET" | "HEAD" | "OPTIONS" | "TRACE" | "PUT" | "DELETE"
+        ) && !matches!(req.body, RequestBody::Stream(..));
+        if let Some(mut conn) = self.take_idle_conn() {
+            match self
+                .send_request(&mut conn, req, &head)
+                .and_then(|()| read_response_head(&mut conn))
+            {
+                Ok(response_head) => return self.make_response(req, conn, response_head),
+                Err(e)
+                    if !retry
+                        || matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) =>
+                {
+                    return Err(e);
+                }
+                Err(..) => {}
+            }
+        }
+        let stream = TcpStream::connect_timeout(&self.upstream, self.connect_timeout)?;
+        stream.set_nodelay(true)?;
+        let mut conn = BufReader::new(stream);
+        self.send_request(&mut conn, req, &head)?;
+        let response_head = read_response_head(&mut conn)?;
+        self.make_response(req, conn, response_head)
+    }
+
+    fn take_idle_conn(&self) -> Option<BufReader<TcpStream>> {
+        loop {
+            let conn = self.idle_conns.lock().unwrap().pop()?;
+            // An idle connection has nothing to read.
+            // When it is readable, the upstream server closed it.
+            let stream = conn.get_ref();
+            let mut buf = [0_u8];
+            if stream.set_nonblocking(true).is_ok()
+                && matches!(stream.peek(&mut buf), Err(e) if e.kind() == ErrorKind::WouldBlock)
+                && stream.set_nonblocking(false).is_ok()
+            {
+                return Some(conn);
+            }
+        }
+    }
+
+    fn put_idle_conn(&self, conn: BufReader<TcpStream>) {
+        if !conn.buffer().is_empty() {
+            return;
+        }
+        let mut idle_conns = self.idle_conns.lock().unwrap();
+        if idle_conns.len() < self.max_idle_conns {
+            idle_conns.push(conn);
+        }
+    }
+
+    fn request_head(&self, req: &Request) -> Vec<u8> {
+        // Forward the target as the client sent it.  Decoding and re-encoding it would turn
+        // `/a%2Fb` into `/a/b` and `/x/%2e%2e/y` into `/x/../y`.
+        let target = req.target.split('#').next().unwrap_or_default();
+        let mut head = Vec::new();
+        write!(head, "{} {target} HTTP/1.1\r\n", req.method).unwrap();
+        let connection_options = connection_options(&req.headers);
+        for header in &req.headers {
+            if is_hop_by_hop(&header.name, &connection_options)
+                || header.name.eq_ignore_ascii_case("content-length")
+            {
+                continue;
+            }
+            write!(head, "{}: {}\r\n", header.name, header.value).unwrap();
+        }
+        if req.headers.get_all("host").is_empty() {
+            write!(head, "host: {}\r\n", self.upstream).unwrap();
+        }
+        // https://datatracker.ietf.org/doc/html/rfc7239#section-5.2
+        let node = match &req.remote_addr {
+            RemoteAddr::Tcp(addr) => match addr.ip().to_canonical() {
+                IpAddr::V4(ip) => ip.to_string(),
+                IpAddr::V6(ip) => format!("\"[{ip}]\""),
+            },
+            RemoteAddr::Unix(..) => "unknown".to_string(),
+        };
+        write!(head, "forwarded: for={node};proto={}", req.scheme()).unwrap();
+        if let Some(host) = req.headers.get_only("host") {
+            write!(head, ";host={}", forwarded_value(host)).unwrap();
+        }
+        head.extend(b"\r\n");
+        match req.body.len() {
+            Some(len) if len > 0 || req.content_length.is_some() => {
+                write!(head, "content-length: {len}\r\n").unwrap();
+            }
+            Some(..) => {}
+            None => head.extend(b"transfer-encoding: chunked\r\n"),
+        }
+        head.extend(b"\r\n");
+        head
+    }
+
+    fn send_request(
+        &self,
+        conn: &mut BufReader<TcpStream>,
+        req: &Request,
+        head: &[u8],
+    ) -> Result<(), std::io::Error> {
+        let stream = conn.get_mut();
+        stream.set_read_timeout(Some(self.response_timeout))?;
+        stream.set_write_timeout(Some(self.response_timeout))?;
+        stream.write_all(head)?;
+        let mut body_reader = req.body.reader()?;
+        match req.body.len() {
+            Some(0) => {}
+            Some(len) => {
+                let n = std::io::copy(&mut body_reader.by_ref().take(len), stream)?;
+                if n < len {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+            }
+            None => {
+                // https://datatracker.ietf.org/doc/html/rfc9112#section-7.1
+                let mut buf = vec![0_u8; 65536];
+                loop {
+                    let n = match body_reader.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e),
+                    };
+                    stream.write_all(format!("{n:x}\r\n").as_bytes())?;
+                    stream.write_all(&buf[..n])?;
+                    stream.write_all(b"\r\n")?;
+                }
+                stream.write_all(b"0\r\n\r\n")?;
+            }
+        }
+        stream.flush()
+    }
+
+    fn make_response(
+        &self,
+        req: &Request,
+        conn: BufReader<TcpStream>,
+        response_head: ResponseHead,
+    ) -> Result<Response, std::io::Error> {
+        let ResponseHead {
+            http11,
+            code,
+            headers: mut upstream_headers,
+        } = response_head;
+        let connection_options = connection_options(&upstream_headers);
+        let mut reusable = http11 && !connection_options.iter().any(|s| s == "close");
+        // https://datatracker.ietf.org/doc/html/rfc9112#section-6.3
+        let content_length = match upstream_headers
+            .remove_all("content-length")
+            .iter()
+            .map(|s| s.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|_| malformed("sent an invalid content-length"))?
+            .as_slice()
+        {
+            [] => None,
+            [len, rest @ ..] if rest.iter().all(|n| n == len) => Some(*len),
+            _ => return Err(malformed("sent conflicting content-length headers")),
+        };
+        let transfer_encoding = upstream_headers.get_all("transfer-encoding");
+        let framing = if req.method == "HEAD" || code == 204 || code == 304 {
+            BodyFraming::Empty
+        } else if !transfer_encoding.is_empty() {
+            let chunked = transfer_encoding.len() == 1
+                && transfer_encoding[0].trim().eq_ignore_ascii_case("chunked");
+            if !chunked {
+                return Err(malformed("sent an unsupported transfer-encoding"));
+            }
+            BodyFraming::Chunked
+        } else if let Some(len) = content_length {
+            BodyFraming::Length(len)
+        } else {
+            BodyFraming::UntilClose
+        };
+        let mut response = Response::new(code);
+        for header in &upstream_headers {
+            if !is_hop_by_hop(&header.name, &connection_options) {
+                response.headers.add(&header.name, header.value.clone());
+            }
+        }
+        match framing {
+            BodyFraming::Empty | BodyFraming::Length(0) => {
+                if req.method == "HEAD"
+                    && let Some(len) = content_length
+                {
+                    // The server sends the length and no body.
+                    let (_sender, stream) = body_stream(1, Some(len));
+                    response.body = ResponseBody::Stream(stream);
+                }
+                if reusable {
+                    self.put_idle_conn(conn);
+                }
+            }
+            BodyFraming::Length(..) | BodyFraming::Chunked | BodyFraming::UntilClose => {
+                let len = match framing {
+                    BodyFraming::Length(len) => Some(len),
+                    _ => None,
+                };
+                reusable &= framing != BodyFraming::UntilClose;
+                let (sender, stream) = body_stream(4, len);
+                response.body = ResponseBody::Stream(stream);
+                let proxy = self.clone();
+                let mut conn = conn;
+                safina::executor::schedule_blocking(move || {
+                    match copy_response_body(&mut conn, framing, &sender) {
+                        Ok(true) if reusable => proxy.put_idle_conn(conn),
+                        Ok(..) => {}
+                        Err(e) => sender.send_error_blocking(e.kind()),
+                    }
+                });
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// Sends the response body from `conn` to `sender`.
+///
+/// Returns `false` when the receiver dropped the stream before the end of the body.
+fn copy_response_body(
+    conn: &mut BufReader<TcpStream>,
+    framing: BodyFraming,
+    sender: &BodyStreamSender,
+) -> Result<bool, std::io::Error> {
+    match framing {
+        BodyFraming::Empty => Ok(true),
+        BodyFraming::Length(len) => send_body_chunks(conn.by_ref().take(len), sender, Some(len)),
+        BodyFraming::UntilClose => send_body_chunks(conn, sender, None),
+        BodyFraming::Chunked => loop {
+            // https://datatracker.ietf.org/doc/html/rfc9112#section-7.1
+            //     chunked-body   = *chunk last-chunk trailer-section CRLF
+            //     chunk          = chunk-size [ chunk-ext ] CRLF chunk-data CRLF
+            //     last-chunk     = 1*("0") [ chunk-ext ] CRLF
+            let line = read_line(&mut conn.by_ref().take(MAX_CHUNK_LINE_LEN))?;
+            let size_str = line.split(';').next().unwrap_or_default().trim();
+            let size = Some(size_str)
+                .filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|s| u64::from_str_radix(s, 16).ok())
+                .ok_or_else(|| malformed("sent a malformed chunk size"))?;
+            if size == 0 {
+                // The proxy does not forward trailers.
+                let mut reader = conn.by_ref().take(MAX_RESPONSE_HEAD_LEN);
+                while !read_line(&mut reader)?.is_empty() {}
+                return Ok(true);
+            }
+            if !send_body_chunks(conn.by_ref().take(size), sender, Some(size))? {
+                return Ok(false);
+            }
+            if !read_line(&mut conn.by_ref().take(2))?.is_empty() {
+                return Err(malformed("sent a malformed chunk"));
+            }
+        },
+    }
+}
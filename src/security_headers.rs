@@ -0,0 +1,359 @@
+use crate::rand::secure_random_bytes;
+use crate::util::base64url_encode;
+use crate::{AsciiString, Request, Response};
+use std::time::Duration;
+
+/// Values of the
+/// [`Referrer-Policy`](https://www.w3.org/TR/referrer-policy/#referrer-policies) header.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ReferrerPolicy {
+    NoReferrer,
+    NoReferrerWhenDowngrade,
+    Origin,
+    OriginWhenCrossOrigin,
+    SameOrigin,
+    StrictOrigin,
+    StrictOriginWhenCrossOrigin,
+    UnsafeUrl,
+}
+impl ReferrerPolicy {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferrerPolicy::NoReferrer => "no-referrer",
+            ReferrerPolicy::NoReferrerWhenDowngrade => "no-referrer-when-downgrade",
+            ReferrerPolicy::Origin => "origin",
+            ReferrerPolicy::OriginWhenCrossOrigin => "origin-when-cross-origin",
+            ReferrerPolicy::SameOrigin => "same-origin",
+            ReferrerPolicy::StrictOrigin => "strict-origin",
+            ReferrerPolicy::StrictOriginWhenCrossOrigin => "strict-origin-when-cross-origin",
+            ReferrerPolicy::UnsafeUrl => "unsafe-url",
+        }
+    }
+}
+
+/// Values of the
+/// [`Cross-Origin-Opener-Policy`](https://html.spec.whatwg.org/multipage/browsers.html#cross-origin-opener-policies)
+/// header.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CrossOriginOpenerPolicy {
+    UnsafeNone,
+    SameOriginAllowPopups,
+    SameOrigin,
+}
+impl CrossOriginOpenerPolicy {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrossOriginOpenerPolicy::UnsafeNone => "unsafe-none",
+            CrossOriginOpenerPolicy::SameOriginAllowPopups => "same-origin-allow-popups",
+            CrossOriginOpenerPolicy::SameOrigin => "same-origin",
+        }
+    }
+}
+
+/// Values of the
+/// [`Cross-Origin-Embedder-Policy`](https://html.spec.whatwg.org/multipage/browsers.html#embedder-policy-value)
+/// header.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CrossOriginEmbedderPolicy {
+    UnsafeNone,
+    RequireCorp,
+    Credentialless,
+}
+impl CrossOriginEmbedderPolicy {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrossOriginEmbedderPolicy::UnsafeNone => "unsafe-none",
+            CrossOriginEmbedderPolicy::RequireCorp => "require-corp",
+            CrossOriginEmbedderPolicy::Credentialless => "credentialless",
+        }
+    }
+}
+
+/// Values of the
+/// [`Cross-Origin-Resource-Policy`](https://fetch.spec.whatwg.org/#cross-origin-resource-policy-header)
+/// header.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CrossOriginResourcePolicy {
+    SameSite,
+    SameOrigin,
+    CrossOrigin,
+}
+impl CrossOriginResourcePolicy {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrossOriginResourcePolicy::SameSite => "same-site",
+            CrossOriginResourcePolicy::SameOrigin => "same-origin",
+            CrossOriginResourcePolicy::CrossOrigin => "cross-origin",
+        }
+    }
+}
+
+/// Sets `value` for `name` in a list of pairs, keeping the list's order.
+fn set_pair(pairs: &mut Vec<(String, String)>, name: &str, value: &str) {
+    let name = name.to_ascii_lowercase();
+    match pairs.iter_mut().find(|(n, _)| *n == name) {
+        Some((_, v)) => *v = value.to_string(),
+        None => pairs.push((name, value.to_string())),
+    }
+}
+
+/// Security headers for responses.
+///
+/// [`SecurityHeaders::handle`] adds these headers to responses from a handler.
+/// It does not replace headers that the handler set,
+/// so a handler can override a header for one response.
+///
+/// Defaults:
+/// - `Content-Security-Policy: default-src 'self'; script-src 'self' 'nonce-…';
+///   style-src 'self' 'nonce-…'; object-src 'none'; base-uri 'self';
+///   form-action 'self'; frame-ancestors 'none'`
+/// - `Cross-Origin-Opener-Policy: same-origin`
+/// - `Cross-Origin-Resource-Policy: same-origin`
+/// - `Permissions-Policy: camera=(), geolocation=(), microphone=(), payment=(), usb=()`
+/// - `Referrer-Policy: no-referrer`
+/// - `Strict-Transport-Security: max-age=63072000; includeSubDomains`, only for HTTPS requests
+/// - `X-Content-Type-Options: nosniff`
+///
+/// `Cross-Origin-Embedder-Policy` is not set by default,
+/// because it stops pages from loading cross-origin resources that do not opt in.
+///
+/// Each request gets a new random nonce.
+/// Put it in the `nonce` attribute of your inline `<script>` and `<style>` elements.
+///
+/// Example:
+/// ```
+/// use servlin::{Request, Response, SecurityHeaders};
+///
+/// let security_headers = SecurityHeaders::new()
+///     .csp_directive("img-src", "'self' https://images.example.com");
+/// let handler = move |req: Request| {
+///     security_headers.handle(req, |_req, nonce| {
+///         Response::html(
+///             200,
+///             format!("<script nonce=\"{nonce}\">console.log('hello');</script>"),
+///         )
+///     })
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    csp: Vec<(String, String)>,
+    csp_report_only: bool,
+    permissions: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+impl SecurityHeaders {
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            csp: Vec::new(),
+            csp_report_only: false,
+            permissions: Vec::new(),
+            headers: Vec::new(),
+        }
+        .csp_directive("default-src", "'self'")
+        .csp_directive("script-src", "'self' 'nonce'")
+        .csp_directive("style-src", "'self' 'nonce'")
+        .csp_directive("object-src", "'none'")
+        .csp_directive("base-uri", "'self'")
+        .csp_directive("form-action", "'self'")
+        .csp_directive("frame-ancestors", "'none'")
+        .cross_origin_opener_policy(CrossOriginOpenerPolicy::SameOrigin)
+        .cross_origin_resource_policy(CrossOriginResourcePolicy::SameOrigin)
+        .permission("camera", "()")
+        .permission("geolocation", "()")
+        .permission("microphone", "()")
+        .permission("payment", "()")
+        .permission("usb", "()")
+        .referrer_policy(ReferrerPolicy::NoReferrer)
+        .hsts(Duration::from_hours(2 * 365 * 24), true)
+        .header("x-content-type-options", "nosniff")
+    }
+
+    /// Sets a header, replacing any previous value.
+    ///
+    /// # Panics
+    /// Panics when `name` or `value` is not US-ASCII.
+    #[must_use]
+    pub fn header(mut self, name: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        let (name, value) = (name.as_ref(), value.as_ref());
+        assert!(
+            name.is_ascii() && value.is_ascii(),
+            "header is not US-ASCII: {name:?}: {value:?}"
+        );
+        set_pair(&mut self.headers, name, value);
+        self
+    }
+
+    /// Stops adding the header `name`.
+    #[must_use]
+    pub fn without(mut self, name: impl AsRef<str>) -> Self {
+        let name = name.as_ref().to_ascii_lowercase();
+        match name.as_str() {
+            "content-security-policy" | "content-security-policy-report-only" => self.csp.clear(),
+            "permissions-policy" => self.permissions.clear(),
+            _ => self.headers.retain(|(n, _)| *n != name),
+        }
+        self
+    }
+
+    /// Sets a `Content-Security-Policy` directive, replacing any previous value.
+    ///
+    /// The source `'nonce'` becomes the request's nonce, like `'nonce-c2VjcmV0'`.
+    ///
+    /// See <https://www.w3.org/TR/CSP3/#csp-directives>.
+    ///
+    /// # Panics
+    /// Panics when `name` or `sources` is not US-ASCII or contains `;` or `,`.
+    #[must_use]
+    pub fn csp_directive(mut self, name: impl AsRef<str>, sources: impl AsRef<str>) -> Self {
+        let (name, sources) = (name.as_ref(), sources.as_ref());
+        assert!(
+            !name.is_empty()
+                && format!("{name}{sources}")
+                    .chars()
+                    .all(|c| c.is_ascii() && c != ';' && c != ','),
+            "invalid CSP directive: {name:?} {sources:?}"
+        );
+        set_pair(&mut self.csp, name, sources);
+        self
+    }
+
+    /// Sends the policy in `Content-Security-Policy-Report-Only`,
+    /// so browsers report violations without blocking anything.
+    /// Use this to test a new policy.
+    #[must_use]
+    pub fn csp_report_only(mut self) -> Self {
+        self.csp_report_only = true;
+        self
+    }
+
+    /// Sets a `Permissions-Policy` feature, like `("geolocation", "(self)")`.
+    ///
+    /// See <https://www.w3.org/TR/permissions-policy/#structured-header-serialization>.
+    ///
+    /// # Panics
+    /// Panics when `feature` or `allowlist` is not US-ASCII.
+    #[must_use]
+    pub fn permission(mut self, feature: impl AsRef<str>, allowlist: impl AsRef<str>) -> Self {
+        let (feature, allowlist) = (feature.as_ref(), allowlist.as_ref());
+        assert!(
+            feature.is_ascii() && allowlist.is_ascii(),
+            "permission is not US-ASCII: {feature:?}={allowlist:?}"
+        );
+        set_pair(&mut self.permissions, feature, allowlist);
+        self
+    }
+
+    /// Sets `Strict-Transport-Security`, telling browsers to use only HTTPS for `max_age`.
+    ///
+    /// [`SecurityHeaders::handle`] adds it only to responses to HTTPS requests.
+    /// See [RFC 6797](https://datatracker.ietf.org/doc/html/rfc6797#section-7.2).
+    #[must_use]
+    pub fn hsts(self, max_age: Duration, include_subdomains: bool) -> Self {
+        let value = if include_subdomains {
+            format!("max-age={}; includeSubDomains", max_age.as_secs())
+        } else {
+            format!("max-age={}", max_age.as_secs())
+        };
+        self.header("strict-transport-security", value)
+    }
+
+    #[must_use]
+    pub fn referrer_policy(self, policy: ReferrerPolicy) -> Self {
+        self.header("referrer-policy", policy.as_str())
+    }
+
+    #[must_use]
+    pub fn cross_origin_opener_policy(self, policy: CrossOriginOpenerPolicy) -> Self {
+        self.header("cross-origin-opener-policy", policy.as_str())
+    }
+
+    #[must_use]
+    pub fn cross_origin_embedder_policy(self, policy: CrossOriginEmbedderPolicy) -> Self {
+        self.header("cross-origin-embedder-policy", policy.as_str())
+    }
+
+    #[must_use]
+    pub fn cross_origin_resource_policy(self, policy: CrossOriginResourcePolicy) -> Self {
+        self.header("cross-origin-resource-policy", policy.as_str())
+    }
+
+    fn csp_value(&self, nonce: &str) -> String {
+        let nonce_source = format!("'nonce-{nonce}'");
+        self.csp
+            .iter()
+            .map(|(name, sources)| {
+                let sources: Vec<&str> = sources
+                    .split_ascii_whitespace()
+                    .map(|source| {
+                        if source == "'nonce'" {
+                            nonce_source.as_str()
+                        } else {
+                            source
+                        }
+                    })
+                    .collect();
+                if sources.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{name} {}", sources.join(" "))
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    /// Makes a nonce, calls `handler`, and adds the headers to its response.
+    ///
+    /// Skips each header that the response already has.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn handle<F>(&self, req: Request, handler: F) -> Response
+    where
+        F: FnOnce(Request, &str) -> Response,
+    {
+        let nonce = base64url_encode(&secure_random_bytes::<16>());
+        let is_https = req.scheme() == "https";
+        let mut response = handler(req, &nonce);
+        if !response.is_normal() {
+            return response;
+        }
+        let mut add = |name: &str, value: String| {
+            if response.headers.get_all(name).is_empty() {
+                response
+                    .headers
+                    .add(name, AsciiString::try_from(value).unwrap());
+            }
+        };
+        if !self.csp.is_empty() {
+            let name = if self.csp_report_only {
+                "content-security-policy-report-only"
+            } else {
+                "content-security-policy"
+            };
+            add(name, self.csp_value(&nonce));
+        }
+        if !self.permissions.is_empty() {
+            let value = self
+                .permissions
+                .iter()
+                .map(|(feature, allowlist)| format!("{feature}={allowlist}"))
+                .collect::<Vec<String>>()
+                .join(", ");
+            add("permissions-policy", value);
+        }
+        for (name, value) in &self.headers {
+            if name == "strict-transport-security" && !is_https {
+                continue;
+            }
+            add(name, value.clone());
+        }
+        response
+    }
+}
@@ -0,0 +1,603 @@
+use crate::log::error;
+use crate::rand::secure_random_bytes;
+use crate::time::EpochTime;
+use crate::util::{base64url_decode, base64url_encode, hex_encode};
+use crate::{AsciiString, Cookie, Request, Response, SameSite};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+const ID_LEN: usize = 32;
+const CLEANUP_INTERVAL: Duration = Duration::from_mins(1);
+/// How long cleanup waits before deleting a temporary file left by a failed save.
+const TEMP_FILE_MAX_AGE: Duration = Duration::from_hours(1);
+
+fn is_session_id(s: &str) -> bool {
+    s.len() == ID_LEN * 2 && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Returns the store key for a session id.
+/// Stores never see session ids, so a leaked store does not reveal them.
+fn store_key(id: &str) -> String {
+    hex_encode(&Sha256::digest(id.as_bytes()))
+}
+
+/// A session's data, as kept in a [`SessionStore`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SessionRecord {
+    pub values: HashMap<String, String>,
+    pub created: SystemTime,
+    /// The store may delete the record after this time.
+    pub expires: SystemTime,
+}
+
+/// Keeps session records for [`Sessions`].
+///
+/// Keys are lowercase hex strings.
+pub trait SessionStore: Send + Sync {
+    /// Returns the record saved with `key`, or `None` when there is none.
+    ///
+    /// # Errors
+    /// Returns an error when it fails to read the record.
+    fn load(&self, key: &str) -> Result<Option<SessionRecord>, std::io::Error>;
+
+    /// Saves `record` with `key`, replacing any record with the same key.
+    ///
+    /// # Errors
+    /// Returns an error when it fails to save the record.
+    fn save(&self, key: &str, record: &SessionRecord) -> Result<(), std::io::Error>;
+
+    /// Deletes the record with `key`, if any.
+    ///
+    /// # Errors
+    /// Returns an error when it fails to delete the record.
+    fn remove(&self, key: &str) -> Result<(), std::io::Error>;
+
+    /// Deletes records that expired before `now`.
+    ///
+    /// # Errors
+    /// Returns an error when it fails to delete records.
+    fn remove_expired(&self, now: SystemTime) -> Result<(), std::io::Error>;
+}
+impl<T: SessionStore + ?Sized> SessionStore for Arc<T> {
+    fn load(&self, key: &str) -> Result<Option<SessionRecord>, std::io::Error> {
+        self.as_ref().load(key)
+    }
+
+    fn save(&self, key: &str, record: &SessionRecord) -> Result<(), std::io::Error> {
+        self.as_ref().save(key, record)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), std::io::Error> {
+        self.as_ref().remove(key)
+    }
+
+    fn remove_expired(&self, now: SystemTime) -> Result<(), std::io::Error> {
+        self.as_ref().remove_expired(now)
+    }
+}
+
+/// Keeps sessions in memory.
+/// The server loses them when it restarts.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    records: Mutex<HashMap<String, SessionRecord>>,
+}
+impl MemorySessionStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of records in the store.
+    ///
+    /// # Panics
+    /// Panics when the internal mutex is poisoned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    /// # Panics
+    /// Panics when the internal mutex is poisoned.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl SessionStore for MemorySessionStore {
+    fn load(&self, key: &str) -> Result<Option<SessionRecord>, std::io::Error> {
+        Ok(self.records.lock().unwrap().get(key).cloned())
+    }
+
+    fn save(&self, key: &str, record: &SessionRecord) -> Result<(), std::io::Error> {
+        self.records
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), record.clone());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), std::io::Error> {
+        self.records.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn remove_expired(&self, now: SystemTime) -> Result<(), std::io::Error> {
+        self.records
+            .lock()
+            .unwrap()
+            .retain(|_key, record| record.expires > now);
+        Ok(())
+    }
+}
+
+/// Keeps each session in a file in a directory.
+///
+/// The first line of a file has the creation and expiration times.
+/// Each other line has a base64url-encoded key and value.
+#[derive(Clone, Debug)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+impl FileSessionStore {
+    /// Makes a store that keeps files in `dir`.
+    /// It creates the directory when it saves the first session.
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, std::io::Error> {
+        if key.is_empty() || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid session store key {key:?}"),
+            ));
+        }
+        Ok(self.dir.join(key))
+    }
+
+    fn parse(contents: &str) -> Option<SessionRecord> {
+        let mut lines = contents.lines();
+        let (created, expires) = lines.next()?.split_once(' ')?;
+        let mut values = HashMap::new();
+        for line in lines {
+            let (key, value) = line.split_once(' ')?;
+            values.insert(
+                String::from_utf8(base64url_decode(key)?).ok()?,
+                String::from_utf8(base64url_decode(value)?).ok()?,
+            );
+        }
+        Some(SessionRecord {
+            values,
+            created: SystemTime::UNIX_EPOCH
+                .checked_add(Duration::from_secs(created.parse().ok()?))?,
+            expires: SystemTime::UNIX_EPOCH
+                .checked_add(Duration::from_secs(expires.parse().ok()?))?,
+        })
+    }
+
+    fn write_file(path: &PathBuf, contents: &str) -> Result<(), std::io::Error> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(path)?, contents.as_bytes())
+    }
+}
+impl SessionStore for FileSessionStore {
+    fn load(&self, key: &str) -> Result<Option<SessionRecord>, std::io::Error> {
+        let contents = match std::fs::read_to_string(self.path(key)?) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::parse(&contents).map(Some).ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("malformed session file {key:?}"),
+            )
+        })
+    }
+
+    fn save(&self, key: &str, record: &SessionRecord) -> Result<(), std::io::Error> {
+        let path = self.path(key)?;
+        let mut contents = format!(
+            "{} {}\n",
+            record.created.epoch_s(),
+            record.expires.epoch_s()
+        );
+        for (key, value) in &record.values {
+            contents.push_str(&base64url_encode(key.as_bytes()));
+            contents.push(' ');
+            contents.push_str(&base64url_encode(value.as_bytes()));
+            contents.push('\n');
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        // Write a temporary file and rename it, so readers never see a partial file.
+        let temp_path = self.dir.join(format!(
+            "{key}.{}.tmp",
+            hex_encode(&secure_random_bytes::<8>())
+        ));
+        Self::write_file(&temp_path, &contents)
+            .and_then(|()| std::fs::rename(&temp_path, &path))
+            .inspect_err(|_| {
+                let _ignored = std::fs::remove_file(&temp_path);
+            })
+    }
+
+    fn remove(&self, key: &str) -> Result<(), std::io::Error> {
+        match std::fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn remove_expired(&self, now: SystemTime) -> Result<(), std::io::Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        // Keep going after errors, so one bad file does not stop cleanup.
+        let mut result = Ok(());
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    result = result.and(Err(e));
+                    continue;
+                }
+            };
+            let Some(key) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if let Some((temp_key, _)) = key.strip_suffix(".tmp").and_then(|s| s.split_once('.'))
+                && self.path(temp_key).is_ok()
+            {
+                // A process that stops during `save` leaves its temporary file behind.
+                let stale = entry.metadata().and_then(|m| m.modified()).map(|modified| {
+                    modified
+                        .checked_add(TEMP_FILE_MAX_AGE)
+                        .is_some_and(|t| t <= now)
+                });
+                let removed = match stale {
+                    Ok(true) => std::fs::remove_file(entry.path()),
+                    Ok(false) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = removed
+                    && e.kind() != ErrorKind::NotFound
+                {
+                    result = result.and(Err(e));
+                }
+                continue;
+            }
+            if self.path(&key).is_err() {
+                continue;
+            }
+            let expired = match self.load(&key) {
+                Ok(opt_record) => opt_record.is_some_and(|record| record.expires <= now),
+                // Malformed records are useless, so delete them.
+                Err(e) if e.kind() == ErrorKind::InvalidData => true,
+                Err(e) => {
+                    result = result.and(Err(e));
+                    false
+                }
+            };
+            if expired && let Err(e) = self.remove(&key) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+/// A client's session, for a handler called by [`Sessions::handle`].
+///
+/// Changes are saved after the handler returns.
+#[derive(Clone, Debug)]
+pub struct Session {
+    opt_id: Option<String>,
+    opt_old_id: Option<String>,
+    values: HashMap<String, String>,
+    created: SystemTime,
+}
+impl Session {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            opt_id: None,
+            opt_old_id: None,
+            values: HashMap::new(),
+            created: now,
+        }
+    }
+
+    /// Returns true when the client had no session.
+    #[must_use]
+    pub fn is_new(&self) -> bool {
+        self.opt_id.is_none()
+    }
+
+    /// Returns the value of `key`.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Returns the value of `key` parsed as a `T`,
+    /// or `None` when there is no value or it does not parse.
+    #[must_use]
+    pub fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.parse().ok()
+    }
+
+    /// Sets the value of `key`.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn set(&mut self, key: impl Into<String>, value: impl ToString) {
+        self.values.insert(key.into(), value.to_string());
+    }
+
+    /// Returns the value of `key` deserialized from JSON,
+    /// or `None` when there is no value or it does not deserialize.
+    #[cfg(feature = "json")]
+    #[must_use]
+    pub fn get_json<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        serde_json::from_str(self.get(key)?).ok()
+    }
+
+    /// Sets the value of `key` to `value` serialized as JSON.
+    ///
+    /// # Errors
+    /// Returns an error when `serde_json` fails to serialize `value`.
+    #[cfg(feature = "json")]
+    pub fn set_json(
+        &mut self,
+        key: impl Into<String>,
+        value: &impl serde::Serialize,
+    ) -> Result<(), crate::Error> {
+        let json = serde_json::to_string(value).map_err(|e| {
+            crate::Error::server_error(format!("error serializing session value to json: {e}"))
+        })?;
+        self.values.insert(key.into(), json);
+        Ok(())
+    }
+
+    /// Removes `key` and returns its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.values.remove(key)
+    }
+
+    /// Removes all values.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Gives the session a new id, keeping its values.
+    ///
+    /// Call this when the user logs in or gains privileges,
+    /// so an attacker who planted a session id in the client cannot use the session.
+    /// See <https://owasp.org/www-community/attacks/Session_fixation>.
+    pub fn rotate_id(&mut self) {
+        if let Some(id) = self.opt_id.take() {
+            self.opt_old_id.get_or_insert(id);
+        }
+    }
+
+    /// Deletes the session and all of its values.
+    /// Call this when the user logs out.
+    pub fn destroy(&mut self) {
+        self.rotate_id();
+        self.values.clear();
+        self.created = SystemTime::now();
+    }
+}
+
+/// Server-side sessions.
+///
+/// [`Sessions::handle`] reads the session id from a cookie,
+/// loads the session from a [`SessionStore`],
+/// calls your handler with it,
+/// and then saves the session.
+///
+/// It creates a session when the handler sets a value.
+/// The session cookie is `HttpOnly`, `Secure`, `SameSite=Lax`, and has path `/`.
+///
+/// A session expires when the client makes no request for the idle timeout,
+/// and always expires after the absolute timeout.
+/// Once a minute, a background thread deletes expired sessions from the store
+/// and logs any error.
+///
+/// Example:
+/// ```
+/// use servlin::{MemorySessionStore, Request, Response, Sessions};
+///
+/// let sessions = Sessions::new(MemorySessionStore::new());
+/// let handler = move |req: Request| {
+///     sessions.handle(req, |req, session| match req.url.path.as_str() {
+///         "/login" => {
+///             session.rotate_id();
+///             session.set("user", "alice");
+///             Response::redirect_303("/")
+///         }
+///         "/logout" => {
+///             session.destroy();
+///             Response::redirect_303("/")
+///         }
+///         _ => match session.get("user") {
+///             Some(user) => Response::text(200, format!("Hello, {user}.")),
+///             None => Response::text(200, "Hello."),
+///         },
+///     })
+/// };
+/// ```
+#[derive(Clone)]
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    cookie_name: String,
+    idle_timeout: Duration,
+    absolute_timeout: Duration,
+    next_cleanup: Arc<Mutex<SystemTime>>,
+}
+impl Sessions {
+    #[must_use]
+    pub fn new(store: impl SessionStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            cookie_name: "session".to_string(),
+            idle_timeout: Duration::from_hours(1),
+            absolute_timeout: Duration::from_hours(24),
+            next_cleanup: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+        }
+    }
+
+    /// Sets the name of the session id cookie.
+    ///
+    /// Use a name with the `__Host-` prefix to stop other subdomains from setting the cookie.
+    ///
+    /// Default: `session`
+    #[must_use]
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Sessions expire when the client makes no requests for this long.
+    ///
+    /// Default: 1 hour
+    #[must_use]
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sessions expire this long after they are created.
+    ///
+    /// Default: 24 hours
+    #[must_use]
+    pub fn absolute_timeout(mut self, timeout: Duration) -> Self {
+        self.absolute_timeout = timeout;
+        self
+    }
+
+    fn session_cookie(&self, value: AsciiString) -> Cookie {
+        Cookie::new(&self.cookie_name, value)
+            .with_path("/")
+            .with_same_site(SameSite::Lax)
+    }
+
+    /// Deletes expired sessions on another thread, at most once per minute.
+    ///
+    /// Cleanup is best-effort, so it logs errors and does not delay or fail the request.
+    fn cleanup(&self, now: SystemTime) {
+        {
+            let mut next_cleanup = self.next_cleanup.lock().unwrap();
+            if now < *next_cleanup {
+                return;
+            }
+            *next_cleanup = now + CLEANUP_INTERVAL;
+        }
+        let store = self.store.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = store.remove_expired(now) {
+                let _ = error(format!("error deleting expired sessions: {e}"), ());
+            }
+        });
+    }
+
+    fn load(&self, req: &Request, now: SystemTime) -> Result<Session, std::io::Error> {
+        let mut session = Session::new(now);
+        let Some(id) = req
+            .cookies
+            .get(&self.cookie_name)
+            .filter(|id| is_session_id(id))
+        else {
+            return Ok(session);
+        };
+        let key = store_key(id);
+        match self.store.load(&key)? {
+            Some(record) if now < record.expires => {
+                session.opt_id = Some(id.clone());
+                session.values = record.values;
+                session.created = record.created;
+            }
+            Some(_) => self.store.remove(&key)?,
+            None => {}
+        }
+        Ok(session)
+    }
+
+    /// Saves the session and returns the cookie to set, if any.
+    fn save(
+        &self,
+        mut session: Session,
+        had_cookie: bool,
+        now: SystemTime,
+    ) -> Result<Option<Cookie>, std::io::Error> {
+        if let Some(old_id) = &session.opt_old_id {
+            self.store.remove(&store_key(old_id))?;
+        }
+        if session.opt_id.is_none() && session.values.is_empty() {
+            // Delete the client's cookie when it has one.
+            return Ok(had_cookie.then(|| {
+                self.session_cookie(AsciiString::new())
+                    .with_max_age(Duration::ZERO)
+                    .with_expires(SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+            }));
+        }
+        let opt_cookie = if session.opt_id.is_none() {
+            let id = hex_encode(&secure_random_bytes::<ID_LEN>());
+            // Round up, since `Max-Age=0` deletes the cookie.
+            let max_age = self.absolute_timeout.as_secs()
+                + u64::from(self.absolute_timeout.subsec_nanos() > 0);
+            let cookie = self
+                .session_cookie(id.clone().try_into().unwrap())
+                .with_max_age(Duration::from_secs(max_age));
+            session.opt_id = Some(id);
+            Some(cookie)
+        } else {
+            None
+        };
+        let record = SessionRecord {
+            values: session.values,
+            created: session.created,
+            expires: (now + self.idle_timeout).min(session.created + self.absolute_timeout),
+        };
+        self.store
+            .save(&store_key(session.opt_id.as_ref().unwrap()), &record)?;
+        Ok(opt_cookie)
+    }
+
+    /// Loads the client's session, calls `handler`, and saves the session.
+    ///
+    /// The session is not saved when `handler` returns a response that is not normal,
+    /// like [`Response::get_body_and_reprocess`].
+    ///
+    /// Returns `500 Internal Server Error` when the store fails.
+    #[must_use]
+    pub fn handle<F>(&self, req: Request, handler: F) -> Response
+    where
+        F: FnOnce(Request, &mut Session) -> Response,
+    {
+        let now = SystemTime::now();
+        self.cleanup(now);
+        let Ok(mut session) = self.load(&req, now) else {
+            return Response::internal_server_error_500();
+        };
+        let had_cookie = req.cookies.contains_key(&self.cookie_name);
+        let response = handler(req, &mut session);
+        if !response.is_normal() {
+            return response;
+        }
+        match self.save(session, had_cookie, now) {
+            Ok(Some(cookie)) => response.with_set_cookie(cookie),
+            Ok(None) => response,
+            Err(_) => Response::internal_server_error_500(),
+        }
+    }
+}
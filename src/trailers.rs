@@ -0,0 +1,108 @@
+use crate::util::is_tchar;
+use crate::{AsciiString, HeaderList};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Header fields to send after a chunked response body.
+///
+/// Make one with the names of the fields and pass it to [`Response::with_trailers`](crate::Response::with_trailers).
+/// Then set the values before the response body ends.
+/// The server reads the values after the body ends.
+///
+/// The server sends trailers only when the response body has unknown length
+/// and the request has a `TE: trailers` header.
+/// Otherwise, it drops them.
+///
+/// Clones share the same values.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc9110#section-6.5>
+#[derive(Clone)]
+pub struct Trailers(Arc<Inner>);
+struct Inner {
+    names: Vec<String>,
+    values: Mutex<HeaderList>,
+}
+impl Trailers {
+    /// # Panics
+    /// Panics when a name is empty, contains non-token characters,
+    /// or is a field that must not appear in a trailer, like `content-length`.
+    #[must_use]
+    pub fn new(names: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        let names: Vec<String> = names
+            .into_iter()
+            .map(|name| name.as_ref().to_ascii_lowercase())
+            .collect();
+        for name in &names {
+            assert!(
+                !name.is_empty() && name.bytes().all(is_tchar),
+                "invalid trailer name {name:?}"
+            );
+            assert!(
+                !FORBIDDEN_NAMES.contains(&name.as_str()),
+                "field {name:?} is not allowed in a trailer"
+            );
+        }
+        Self(Arc::new(Inner {
+            names,
+            values: Mutex::new(HeaderList::new()),
+        }))
+    }
+
+    /// The declared field names, in lowercase.
+    #[must_use]
+    pub fn names(&self) -> &[String] {
+        self.0.names.as_slice()
+    }
+
+    /// Sets the value of the trailer field `name`, replacing any previous value.
+    ///
+    /// # Panics
+    /// Panics when `name` was not passed to [`Trailers::new`].
+    pub fn set(&self, name: impl AsRef<str>, value: AsciiString) {
+        let name = name.as_ref().to_ascii_lowercase();
+        assert!(
+            self.0.names.contains(&name),
+            "trailer {name:?} was not declared"
+        );
+        let mut values = self.0.values.lock().unwrap();
+        values.remove_all(&name);
+        values.add(name, value);
+    }
+
+    /// Returns the values that were set.
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn values(&self) -> HeaderList {
+        self.0.values.lock().unwrap().clone()
+    }
+}
+impl Debug for Trailers {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(f, "Trailers{:?}", self.0.names)
+    }
+}
+impl Eq for Trailers {}
+impl PartialEq for Trailers {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Fields used for message framing, routing, authentication, and payload processing.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc9110#section-6.5.1>
+const FORBIDDEN_NAMES: [&str; 13] = [
+    "authorization",
+    "cache-control",
+    "content-encoding",
+    "content-length",
+    "content-range",
+    "content-type",
+    "expect",
+    "host",
+    "max-forwards",
+    "set-cookie",
+    "te",
+    "trailer",
+    "transfer-encoding",
+];
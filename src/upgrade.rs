@@ -0,0 +1,134 @@
+use crate::conn_stream::ConnStream;
+use crate::remote_addr::RemoteAddr;
+use crate::token_set::Token;
+use futures_io::{AsyncRead, AsyncWrite};
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// A connection that the server stopped processing as HTTP.
+///
+/// Reading returns the bytes in `prefix` and then bytes from `stream`.
+/// Writing writes to `stream`.
+/// The blocking [`Read`] and [`Write`] implementations block the current thread.
+///
+/// The connection counts against the server's connection limit until you drop this.
+pub struct UpgradedConn {
+    pub remote_addr: RemoteAddr,
+    /// The server address that the client connected to.
+    pub local_addr: Option<SocketAddr>,
+    pub stream: ConnStream,
+    /// Bytes that the server received after the request head.
+    /// Read these before reading from `stream`.
+    pub prefix: Vec<u8>,
+    /// Counts this connection against the server's connection limit.
+    pub token: Option<Token>,
+}
+impl UpgradedConn {
+    /// Shuts down the write half of the connection, sending EOF to the client.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is already closed.
+    pub fn shutdown_write(&self) -> Result<(), std::io::Error> {
+        self.stream.shutdown(Shutdown::Write)
+    }
+}
+impl AsyncRead for UpgradedConn {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        if self.prefix.is_empty() {
+            Pin::new(&mut self.stream).poll_read(cx, buf)
+        } else {
+            let n = self.prefix.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.prefix[..n]);
+            self.prefix.drain(..n);
+            Poll::Ready(Ok(n))
+        }
+    }
+}
+impl AsyncWrite for UpgradedConn {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+impl Read for UpgradedConn {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        futures_lite::future::block_on(futures_lite::AsyncReadExt::read(self, buf))
+    }
+}
+impl Write for UpgradedConn {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        futures_lite::future::block_on(futures_lite::AsyncWriteExt::write(self, buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        futures_lite::future::block_on(futures_lite::AsyncWriteExt::flush(self))
+    }
+}
+impl Debug for UpgradedConn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(
+            f,
+            "UpgradedConn({}, prefix_len={})",
+            self.remote_addr,
+            self.prefix.len()
+        )
+    }
+}
+
+type UpgradeFn = Box<dyn FnOnce(UpgradedConn) + Send>;
+
+/// A function that takes over the connection after the server sends the response.
+///
+/// The server calls the function on a thread where it can block.
+/// Use it with [`Response::upgrade`](crate::Response::upgrade),
+/// [`Response::connect_tunnel`](crate::Response::connect_tunnel),
+/// or [`Response::with_upgrade`](crate::Response::with_upgrade).
+#[derive(Clone)]
+pub struct Upgrade(Arc<Mutex<Option<UpgradeFn>>>);
+impl Upgrade {
+    #[must_use]
+    pub fn new(f: impl FnOnce(UpgradedConn) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Some(Box::new(f)))))
+    }
+
+    /// Calls the function on a blocking thread.
+    /// Does nothing when the function was already called.
+    ///
+    /// # Panics
+    /// Panics when called outside of a `safina` executor.
+    pub fn start(&self, conn: UpgradedConn) {
+        if let Some(f) = self.0.lock().unwrap().take() {
+            safina::executor::schedule_blocking(move || f(conn));
+        }
+    }
+}
+impl Debug for Upgrade {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(f, "Upgrade")
+    }
+}
+impl Eq for Upgrade {}
+impl PartialEq for Upgrade {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
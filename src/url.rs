@@ -288,6 +288,10 @@ impl Display for Url {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if !self.scheme.is_empty() {
             write!(f, "{}://", self.scheme)?;
+        } else if !self.host.is_empty() || self.ip.is_some() {
+            // A network-path reference, like a request URL with a `Host` header.
+            // https://datatracker.ietf.org/doc/html/rfc3986#section-4.2
+            write!(f, "//")?;
         }
         if !self.user.is_empty() {
             write!(f, "{}@", self.user)?;
@@ -1,3 +1,5 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use futures_io::{AsyncRead, AsyncWrite};
 use futures_lite::{AsyncReadExt, AsyncWriteExt};
 use std::pin::Pin;
@@ -78,10 +80,23 @@ fn trim_prefix(mut slice: &[u8], prefix: u8) -> &[u8] {
 }
 
 /// Reads blocks from `reader`, encodes them in HTTP chunked encoding, and writes them to `writer`.
-#[allow(clippy::missing_panics_doc)]
 pub async fn copy_chunked_async(
+    reader: impl AsyncRead + Unpin,
+    writer: impl AsyncWrite + Unpin,
+) -> CopyResult {
+    copy_chunked_with_trailers_async(reader, writer, Vec::new).await
+}
+
+/// Reads blocks from `reader`, encodes them in HTTP chunked encoding, and writes them to `writer`.
+///
+/// After `reader` ends, calls `get_trailer_section` and writes the bytes it returns
+/// after the last chunk.
+/// The bytes must be header lines, each ending with CRLF.
+#[allow(clippy::missing_panics_doc)]
+pub async fn copy_chunked_with_trailers_async(
     mut reader: impl AsyncRead + Unpin,
     mut writer: impl AsyncWrite + Unpin,
+    get_trailer_section: impl FnOnce() -> Vec<u8>,
 ) -> CopyResult {
     let mut num_copied = 0;
     loop {
@@ -107,7 +122,10 @@ pub async fn copy_chunked_async(
         }
         num_copied += len as u64;
     }
-    if let Err(e) = writer.write_all(b"0\r\n\r\n").await {
+    let mut last_chunk = b"0\r\n".to_vec();
+    last_chunk.extend(get_trailer_section());
+    last_chunk.extend(b"\r\n");
+    if let Err(e) = writer.write_all(&last_chunk).await {
         return CopyResult::WriterErr(e);
     }
     num_copied += 3;
@@ -202,3 +220,62 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncWriteCounter<W> {
         Pin::new(&mut self.0).poll_close(cx)
     }
 }
+
+/// Encodes `bytes` with the base64url alphabet and no padding.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc4648#section-5>
+#[must_use]
+pub(crate) fn base64url_encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes base64url without padding.
+///
+/// Returns `None` when `s` contains other characters or has an impossible length.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc4648#section-5>
+#[must_use]
+pub(crate) fn base64url_decode(s: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(s).ok()
+}
+
+/// Returns true when `a` and `b` are equal.
+///
+/// The time it takes depends only on the lengths,
+/// so it does not reveal how much of a secret an attacker guessed.
+#[must_use]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0_u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Returns `bytes` as lowercase hexadecimal.
+#[allow(clippy::missing_panics_doc)]
+#[must_use]
+pub fn hex_encode(bytes: &[u8]) -> String {
+    let mut hex = Vec::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        hex.push(hex_digit(b >> 4));
+        hex.push(hex_digit(b & 0x0f));
+    }
+    String::from_utf8(hex).unwrap()
+}
+
+/// Returns true when `b` may appear in a token, like a header field name.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc9110#section-5.6.2>
+#[must_use]
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Returns true when `value` has no control characters other than tab,
+/// so it is a valid header field value.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc9110#section-5.5>
+#[must_use]
+pub(crate) fn is_field_value(value: &str) -> bool {
+    value.bytes().all(|b| b == b'\t' || !b.is_ascii_control())
+}
@@ -0,0 +1,140 @@
+use crate::{Request, Response};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+type Handler = Arc<dyn Fn(Request) -> Response + Send + Sync>;
+
+fn to_handler<F>(handler: F) -> Handler
+where
+    F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
+{
+    Arc::new(move |req| handler.clone()(req))
+}
+
+/// Dispatches requests to handlers by host.
+///
+/// The host comes from the request target or `Host` header.
+/// See [`Url::host`](crate::Url::host).
+///
+/// Example:
+/// ```
+/// use servlin::{Request, Response, VirtualHosts};
+///
+/// let hosts = VirtualHosts::new()
+///     .host("example.com", |_req| Response::text(200, "main site"))
+///     .host("*.example.com", |_req| Response::text(200, "a subdomain"))
+///     .default(|_req| Response::not_found_404());
+/// let handler = move |req: Request| hosts.handle(req);
+/// ```
+#[derive(Clone)]
+pub struct VirtualHosts {
+    exact: Vec<(String, Handler)>,
+    /// Suffixes, like `.example.com`, sorted longest first.
+    wildcard: Vec<(String, Handler)>,
+    default: Option<Handler>,
+}
+impl VirtualHosts {
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            exact: Vec::new(),
+            wildcard: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Sends requests for hosts that match `pattern` to `handler`.
+    ///
+    /// `pattern` is a host name, like `example.com`, or an IP address, like `127.0.0.1` or `::1`.
+    /// A pattern like `*.example.com` matches subdomains of `example.com`,
+    /// like `www.example.com` and `a.b.example.com`, but not `example.com`.
+    /// Patterns do not include ports and are case-insensitive.
+    ///
+    /// A request for a host that matches an exact pattern goes to that pattern's handler.
+    /// Otherwise, it goes to the handler of the longest matching wildcard pattern.
+    ///
+    /// # Panics
+    /// Panics when `pattern` is empty or has a `*` that is not at the start.
+    #[must_use]
+    pub fn host<F>(mut self, pattern: impl AsRef<str>, handler: F) -> Self
+    where
+        F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
+    {
+        let pattern = pattern.as_ref().to_ascii_lowercase();
+        let pattern = pattern.trim_end_matches('.');
+        if let Some(suffix) = pattern.strip_prefix("*.") {
+            assert!(
+                !suffix.is_empty() && !suffix.contains('*'),
+                "invalid host pattern {pattern:?}"
+            );
+            self.wildcard
+                .push((format!(".{suffix}"), to_handler(handler)));
+            self.wildcard
+                .sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        } else {
+            assert!(
+                !pattern.is_empty() && !pattern.contains('*'),
+                "invalid host pattern {pattern:?}"
+            );
+            self.exact.push((pattern.to_string(), to_handler(handler)));
+        }
+        self
+    }
+
+    /// Sends requests for other hosts to `handler`.
+    ///
+    /// Without a default handler, those requests get `421 Misdirected Request`.
+    #[must_use]
+    pub fn default<F>(mut self, handler: F) -> Self
+    where
+        F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
+    {
+        self.default = Some(to_handler(handler));
+        self
+    }
+
+    /// Calls the handler for the request's host.
+    #[must_use]
+    pub fn handle(&self, req: Request) -> Response {
+        let host = match req.url.ip {
+            Some(ip) => ip.to_string(),
+            None => req.url.host.trim_end_matches('.').to_ascii_lowercase(),
+        };
+        let opt_handler = self
+            .exact
+            .iter()
+            .find(|(name, _)| *name == host)
+            .or_else(|| {
+                self.wildcard.iter().find(|(suffix, _)| {
+                    host.len() > suffix.len() && host.ends_with(suffix.as_str())
+                })
+            })
+            .map(|(_, handler)| handler)
+            .or(self.default.as_ref());
+        match opt_handler {
+            Some(handler) => handler(req),
+            // https://datatracker.ietf.org/doc/html/rfc9110#section-15.5.20
+            None => Response::misdirected_request_421(),
+        }
+    }
+}
+impl Debug for VirtualHosts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let patterns: Vec<String> = self
+            .exact
+            .iter()
+            .map(|(name, _)| name.clone())
+            .chain(self.wildcard.iter().map(|(suffix, _)| format!("*{suffix}")))
+            .collect();
+        write!(
+            f,
+            "VirtualHosts{{{patterns:?}{}}}",
+            if self.default.is_some() {
+                ", default"
+            } else {
+                ""
+            }
+        )
+    }
+}
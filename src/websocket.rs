@@ -0,0 +1,612 @@
+//! WebSocket server connections.
+//! - <https://datatracker.ietf.org/doc/html/rfc6455>
+//! - <https://developer.mozilla.org/en-US/docs/Web/API/WebSockets_API/Writing_WebSocket_servers>
+use crate::conn_stream::ConnStream;
+use crate::remote_addr::RemoteAddr;
+use crate::token_set::Token;
+use crate::upgrade::UpgradedConn;
+use crate::{AsciiString, Request, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use core::fmt::{Display, Formatter};
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use std::net::Shutdown;
+use std::sync::Arc;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Close code for a normal closure.
+pub const CLOSE_NORMAL: u16 = 1000;
+/// Close code that means the peer sent a close frame with no code.
+/// Never send this code.
+pub const CLOSE_NO_STATUS: u16 = 1005;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+/// Returns true when the request asks to upgrade the connection to a WebSocket.
+#[must_use]
+pub fn is_websocket_upgrade(req: &Request) -> bool {
+    req.has_header_token("connection", "upgrade") && req.has_header_token("upgrade", "websocket")
+}
+
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    /// `Close(code, reason)`
+    ///
+    /// When the peer sends a close frame with no code, `code` is [`CLOSE_NO_STATUS`].
+    Close(u16, String),
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum WebSocketError {
+    /// The connection is closing or closed.
+    Closed,
+    /// The connection failed or the peer disconnected without sending a close frame.
+    Disconnected,
+    /// The peer sent a message longer than the limit.
+    /// We sent a close frame with code 1009.
+    MessageTooLong,
+    /// The peer sent text that is not valid UTF-8.
+    /// We sent a close frame with code 1007.
+    NotUtf8,
+    /// The peer violated the protocol.
+    /// We sent a close frame with code 1002.
+    Protocol(String),
+}
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), core::fmt::Error> {
+        match self {
+            WebSocketError::Closed => write!(f, "WebSocket closed"),
+            WebSocketError::Disconnected => write!(f, "WebSocket disconnected"),
+            WebSocketError::MessageTooLong => write!(f, "WebSocket message too long"),
+            WebSocketError::NotUtf8 => write!(f, "WebSocket text is not UTF-8"),
+            WebSocketError::Protocol(msg) => write!(f, "WebSocket protocol error: {msg}"),
+        }
+    }
+}
+impl std::error::Error for WebSocketError {}
+
+/// Checks WebSocket upgrade requests and makes `101 Switching Protocols` responses.
+///
+/// # Example
+/// ```
+/// use servlin::{Request, Response, WebSocketAcceptor, WebSocketMessage};
+///
+/// fn handle(req: Request) -> Result<Response, Response> {
+///     WebSocketAcceptor::new()
+///         .max_message_len(64 * 1024)
+///         .protocol("chat")
+///         .accept(&req, |socket| {
+///             while let Ok(message) = socket.recv() {
+///                 if let WebSocketMessage::Text(text) = message {
+///                     if socket.send(WebSocketMessage::Text(text)).is_err() {
+///                         return;
+///                     }
+///                 }
+///             }
+///         })
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WebSocketAcceptor {
+    max_message_len: usize,
+    protocols: Vec<String>,
+}
+impl WebSocketAcceptor {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            max_message_len: 1024 * 1024,
+            protocols: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum length of a received message, in bytes.
+    ///
+    /// Default: 1 MiB
+    #[must_use]
+    pub fn max_message_len(mut self, n: usize) -> Self {
+        self.max_message_len = n;
+        self
+    }
+
+    /// Adds a subprotocol that the handler supports.
+    ///
+    /// When the client offers subprotocols,
+    /// the acceptor chooses the first one added with this method that the client offered.
+    /// See [`WebSocket::protocol`].
+    #[must_use]
+    pub fn protocol(mut self, name: impl AsRef<str>) -> Self {
+        self.protocols.push(name.as_ref().to_string());
+        self
+    }
+
+    /// Checks that `req` is a WebSocket upgrade request and
+    /// returns a `101 Switching Protocols` response.
+    ///
+    /// After the server sends the response,
+    /// it calls `handler` on a thread where it can block.
+    /// The handler can also move the [`WebSocket`] to an async task.
+    /// The server closes the connection when the handler drops the last clone of the [`WebSocket`].
+    ///
+    /// # Errors
+    /// Returns an error response when:
+    /// - the request method is not GET: 405 Method Not Allowed
+    /// - the request is not a WebSocket upgrade: 400 Bad Request
+    /// - the request has an unsupported WebSocket version: 426 Upgrade Required
+    /// - the request has a missing or malformed `Sec-WebSocket-Key` header: 400 Bad Request
+    #[allow(clippy::missing_panics_doc)]
+    pub fn accept<F>(&self, req: &Request, handler: F) -> Result<Response, Response>
+    where
+        F: FnOnce(WebSocket) + Send + 'static,
+    {
+        if req.method() != "GET" {
+            return Err(Response::method_not_allowed_405(&["GET"]));
+        }
+        if !is_websocket_upgrade(req) {
+            return Err(Response::text(400, "Not a WebSocket upgrade request."));
+        }
+        if req
+            .headers
+            .get_only("sec-websocket-version")
+            .map(|s| s.as_str().trim())
+            != Some("13")
+        {
+            return Err(Response::text(426, "Unsupported WebSocket version.")
+                .with_header("sec-websocket-version", "13".try_into().unwrap()));
+        }
+        let key = req
+            .headers
+            .get_only("sec-websocket-key")
+            .map(|s| s.as_str().trim())
+            .filter(|key| {
+                // Base64 of 16 bytes.
+                key.len() == 24
+                    && key.ends_with("==")
+                    && key[..22]
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+            })
+            .ok_or_else(|| Response::text(400, "Missing or malformed Sec-WebSocket-Key header."))?;
+        let accept = STANDARD.encode(
+            sha1_smol::Sha1::from(format!("{key}{ACCEPT_GUID}"))
+                .digest()
+                .bytes(),
+        );
+        let offered: Vec<&str> = req
+            .headers
+            .get_all("sec-websocket-protocol")
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        let protocol = self
+            .protocols
+            .iter()
+            .find(|name| offered.contains(&name.as_str()))
+            .cloned();
+        let mut response = Response::new(101)
+            .with_header("upgrade", "websocket".try_into().unwrap())
+            .with_header("connection", "upgrade".try_into().unwrap())
+            .with_header("sec-websocket-accept", accept.try_into().unwrap());
+        if let Some(name) = &protocol {
+            response = response.with_header(
+                "sec-websocket-protocol",
+                AsciiString::try_from(name.as_str()).unwrap(),
+            );
+        }
+        let max_message_len = self.max_message_len;
+        Ok(response.with_upgrade(move |conn| {
+            handler(WebSocket::new(conn, protocol, max_message_len));
+        }))
+    }
+}
+impl Default for WebSocketAcceptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+struct ReadHalf {
+    stream: ConnStream,
+    prefix: Vec<u8>,
+    prefix_pos: usize,
+    /// `(opcode, bytes)` of a fragmented message.
+    fragments: Option<(u8, Vec<u8>)>,
+    close_received: bool,
+}
+impl ReadHalf {
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), WebSocketError> {
+        let available = &self.prefix[self.prefix_pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.prefix_pos += n;
+        self.stream
+            .read_exact(&mut buf[n..])
+            .await
+            .map_err(|_| WebSocketError::Disconnected)
+    }
+
+    /// Reads a frame.
+    /// Data frame payloads may be up to `max_data_len` bytes.
+    async fn read_frame(&mut self, max_data_len: usize) -> Result<Frame, WebSocketError> {
+        // https://datatracker.ietf.org/doc/html/rfc6455#section-5.2
+        let mut head = [0_u8; 2];
+        self.read_exact(&mut head).await?;
+        let fin = head[0] & 0x80 != 0;
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol(
+                "reserved bits are set".to_string(),
+            ));
+        }
+        let opcode = head[0] & 0x0F;
+        if head[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol("frame is not masked".to_string()));
+        }
+        let len: u64 = match head[1] & 0x7F {
+            126 => {
+                let mut bytes = [0_u8; 2];
+                self.read_exact(&mut bytes).await?;
+                u64::from(u16::from_be_bytes(bytes))
+            }
+            127 => {
+                let mut bytes = [0_u8; 8];
+                self.read_exact(&mut bytes).await?;
+                let len = u64::from_be_bytes(bytes);
+                if len >> 63 != 0 {
+                    return Err(WebSocketError::Protocol("invalid frame length".to_string()));
+                }
+                len
+            }
+            n => u64::from(n),
+        };
+        if opcode & 0x8 != 0 {
+            if !fin || len > 125 {
+                return Err(WebSocketError::Protocol(
+                    "invalid control frame".to_string(),
+                ));
+            }
+        } else if len > max_data_len as u64 {
+            return Err(WebSocketError::MessageTooLong);
+        }
+        let mut mask = [0_u8; 4];
+        self.read_exact(&mut mask).await?;
+        let mut payload = vec![0_u8; usize::try_from(len).unwrap()];
+        self.read_exact(&mut payload).await?;
+        for (n, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[n % 4];
+        }
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+}
+
+struct WriteHalf {
+    stream: ConnStream,
+    close_sent: bool,
+}
+
+/// A WebSocket connection.
+///
+/// Clones share the same connection.
+/// You can receive on one thread or task while you send on another.
+///
+/// [`WebSocket::recv`] answers pings automatically.
+#[derive(Clone)]
+pub struct WebSocket {
+    remote_addr: RemoteAddr,
+    protocol: Option<String>,
+    max_message_len: usize,
+    reader: Arc<safina::sync::Mutex<ReadHalf>>,
+    writer: Arc<safina::sync::Mutex<WriteHalf>>,
+    _token: Arc<Option<Token>>,
+}
+impl WebSocket {
+    fn new(conn: UpgradedConn, protocol: Option<String>, max_message_len: usize) -> Self {
+        Self {
+            remote_addr: conn.remote_addr,
+            protocol,
+            max_message_len,
+            reader: Arc::new(safina::sync::Mutex::new(ReadHalf {
+                stream: conn.stream.clone(),
+                prefix: conn.prefix,
+                prefix_pos: 0,
+                fragments: None,
+                close_received: false,
+            })),
+            writer: Arc::new(safina::sync::Mutex::new(WriteHalf {
+                stream: conn.stream,
+                close_sent: false,
+            })),
+            _token: Arc::new(conn.token),
+        }
+    }
+
+    #[must_use]
+    pub fn remote_addr(&self) -> &RemoteAddr {
+        &self.remote_addr
+    }
+
+    /// The subprotocol that the server chose, if any.
+    #[must_use]
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    async fn send_frame(&self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        // https://datatracker.ietf.org/doc/html/rfc6455#section-5.2
+        let mut writer = self.writer.lock().await;
+        if writer.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        if opcode == OPCODE_CLOSE {
+            writer.close_sent = true;
+        }
+        let mut bytes = Vec::with_capacity(payload.len() + 10);
+        bytes.push(0x80 | opcode);
+        match payload.len() {
+            len @ 0..=125 => bytes.push(u8::try_from(len).unwrap()),
+            len @ 126..=0xFFFF => {
+                bytes.push(126);
+                bytes.extend(u16::try_from(len).unwrap().to_be_bytes());
+            }
+            len => {
+                bytes.push(127);
+                bytes.extend((len as u64).to_be_bytes());
+            }
+        }
+        bytes.extend(payload);
+        writer
+            .stream
+            .write_all(&bytes)
+            .await
+            .map_err(|_| WebSocketError::Disconnected)?;
+        writer
+            .stream
+            .flush()
+            .await
+            .map_err(|_| WebSocketError::Disconnected)
+    }
+
+    /// Sends a message.
+    ///
+    /// Sending [`WebSocketMessage::Close`] starts the close handshake.
+    /// See [`WebSocket::close_async`].
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closing or closed, or we fail to send.
+    pub async fn send_async(&self, message: WebSocketMessage) -> Result<(), WebSocketError> {
+        match message {
+            WebSocketMessage::Text(text) => self.send_frame(OPCODE_TEXT, text.as_bytes()).await,
+            WebSocketMessage::Binary(bytes) => self.send_frame(OPCODE_BINARY, &bytes).await,
+            WebSocketMessage::Close(code, reason) => self.close_async(code, reason).await,
+        }
+    }
+
+    /// Sends a message, blocking the thread.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closing or closed, or we fail to send.
+    pub fn send(&self, message: WebSocketMessage) -> Result<(), WebSocketError> {
+        futures_lite::future::block_on(self.send_async(message))
+    }
+
+    /// Sends a ping.  The peer answers with a pong, which [`WebSocket::recv`] ignores.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closing or closed, or we fail to send.
+    ///
+    /// # Panics
+    /// Panics when `payload` is longer than 125 bytes.
+    pub async fn ping_async(&self, payload: &[u8]) -> Result<(), WebSocketError> {
+        assert!(
+            payload.len() <= 125,
+            "ping payload is longer than 125 bytes"
+        );
+        self.send_frame(OPCODE_PING, payload).await
+    }
+
+    /// Sends a ping, blocking the thread.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closing or closed, or we fail to send.
+    ///
+    /// # Panics
+    /// Panics when `payload` is longer than 125 bytes.
+    pub fn ping(&self, payload: &[u8]) -> Result<(), WebSocketError> {
+        futures_lite::future::block_on(self.ping_async(payload))
+    }
+
+    /// Starts the close handshake.
+    /// Keep calling [`WebSocket::recv`] until it returns the peer's [`WebSocketMessage::Close`].
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closing or closed, or we fail to send.
+    ///
+    /// # Panics
+    /// Panics when `code` is not a valid close code or `reason` is longer than 123 bytes.
+    pub async fn close_async(
+        &self,
+        code: u16,
+        reason: impl AsRef<str>,
+    ) -> Result<(), WebSocketError> {
+        assert!(is_valid_close_code(code), "invalid close code {code}");
+        let reason = reason.as_ref().as_bytes();
+        assert!(reason.len() <= 123, "close reason is longer than 123 bytes");
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend(reason);
+        self.send_frame(OPCODE_CLOSE, &payload).await
+    }
+
+    /// Starts the close handshake, blocking the thread.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closing or closed, or we fail to send.
+    ///
+    /// # Panics
+    /// Panics when `code` is not a valid close code or `reason` is longer than 123 bytes.
+    pub fn close(&self, code: u16, reason: impl AsRef<str>) -> Result<(), WebSocketError> {
+        futures_lite::future::block_on(self.close_async(code, reason))
+    }
+
+    async fn shutdown(&self) {
+        let _ignored = self.writer.lock().await.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Waits for the next message.
+    ///
+    /// When the peer sends a close frame, this answers it, closes the connection,
+    /// and returns [`WebSocketMessage::Close`].
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closed, fails, or the peer violates the protocol.
+    /// When the peer violates the protocol, this sends a close frame with the matching code
+    /// and closes the connection.
+    pub async fn recv_async(&self) -> Result<WebSocketMessage, WebSocketError> {
+        let mut reader = self.reader.lock().await;
+        if reader.close_received {
+            return Err(WebSocketError::Closed);
+        }
+        let result = self.recv_locked(&mut reader).await;
+        let opt_close_code = match &result {
+            Err(WebSocketError::Protocol(..)) => Some(CLOSE_PROTOCOL_ERROR),
+            Err(WebSocketError::NotUtf8) => Some(CLOSE_INVALID_DATA),
+            Err(WebSocketError::MessageTooLong) => Some(CLOSE_MESSAGE_TOO_BIG),
+            _ => None,
+        };
+        if let Some(code) = opt_close_code {
+            reader.close_received = true;
+            let _ignored = self.send_frame(OPCODE_CLOSE, &code.to_be_bytes()).await;
+            self.shutdown().await;
+        } else if result.is_err() {
+            reader.close_received = true;
+        }
+        result
+    }
+
+    /// Waits for the next message, blocking the thread.
+    ///
+    /// # Errors
+    /// Returns an error when the connection is closed, fails, or the peer violates the protocol.
+    pub fn recv(&self) -> Result<WebSocketMessage, WebSocketError> {
+        futures_lite::future::block_on(self.recv_async())
+    }
+
+    async fn recv_locked(&self, reader: &mut ReadHalf) -> Result<WebSocketMessage, WebSocketError> {
+        loop {
+            let fragments_len = reader
+                .fragments
+                .as_ref()
+                .map_or(0, |(_, bytes)| bytes.len());
+            let frame = reader
+                .read_frame(self.max_message_len - fragments_len)
+                .await?;
+            match frame.opcode {
+                OPCODE_CONTINUATION => {
+                    let Some((opcode, mut bytes)) = reader.fragments.take() else {
+                        return Err(WebSocketError::Protocol(
+                            "unexpected continuation frame".to_string(),
+                        ));
+                    };
+                    bytes.extend(frame.payload);
+                    if frame.fin {
+                        return message(opcode, bytes);
+                    }
+                    reader.fragments = Some((opcode, bytes));
+                }
+                OPCODE_TEXT | OPCODE_BINARY => {
+                    if reader.fragments.is_some() {
+                        return Err(WebSocketError::Protocol(
+                            "expected continuation frame".to_string(),
+                        ));
+                    }
+                    if frame.fin {
+                        return message(frame.opcode, frame.payload);
+                    }
+                    reader.fragments = Some((frame.opcode, frame.payload));
+                }
+                OPCODE_CLOSE => {
+                    let (code, reason) = parse_close(&frame.payload)?;
+                    reader.close_received = true;
+                    // Echo the code.  When we already sent a close frame, this does nothing.
+                    let reply: &[u8] = if code == CLOSE_NO_STATUS {
+                        &[]
+                    } else {
+                        &frame.payload[..2]
+                    };
+                    let _ignored = self.send_frame(OPCODE_CLOSE, reply).await;
+                    self.shutdown().await;
+                    return Ok(WebSocketMessage::Close(code, reason));
+                }
+                OPCODE_PING => match self.send_frame(OPCODE_PONG, &frame.payload).await {
+                    Ok(()) | Err(WebSocketError::Closed) => {}
+                    Err(e) => return Err(e),
+                },
+                OPCODE_PONG => {}
+                opcode => {
+                    return Err(WebSocketError::Protocol(format!(
+                        "unknown opcode {opcode:#x}"
+                    )));
+                }
+            }
+        }
+    }
+}
+impl core::fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(f, "WebSocket({}, {:?})", self.remote_addr, self.protocol)
+    }
+}
+
+fn message(opcode: u8, bytes: Vec<u8>) -> Result<WebSocketMessage, WebSocketError> {
+    if opcode == OPCODE_TEXT {
+        String::from_utf8(bytes)
+            .map(WebSocketMessage::Text)
+            .map_err(|_| WebSocketError::NotUtf8)
+    } else {
+        Ok(WebSocketMessage::Binary(bytes))
+    }
+}
+
+fn parse_close(payload: &[u8]) -> Result<(u16, String), WebSocketError> {
+    // https://datatracker.ietf.org/doc/html/rfc6455#section-5.5.1
+    match payload {
+        [] => Ok((CLOSE_NO_STATUS, String::new())),
+        [_] => Err(WebSocketError::Protocol(
+            "close frame payload has 1 byte".to_string(),
+        )),
+        [b0, b1, reason @ ..] => {
+            let code = u16::from_be_bytes([*b0, *b1]);
+            if !is_valid_close_code(code) {
+                return Err(WebSocketError::Protocol(format!(
+                    "invalid close code {code}"
+                )));
+            }
+            let reason = String::from_utf8(reason.to_vec()).map_err(|_| WebSocketError::NotUtf8)?;
+            Ok((code, reason))
+        }
+    }
+}
@@ -0,0 +1,166 @@
+#![cfg(unix)]
+use crate::test_util::TestServer;
+use servlin::{Cgi, Request};
+use std::time::{Duration, Instant};
+
+mod test_util;
+
+fn sh(script: &str) -> Cgi {
+    Cgi::new("/bin/sh").arg("-c").arg(script)
+}
+
+#[test]
+fn variables() {
+    let cgi =
+        sh("printf 'Content-Type: text/plain\\n\\n'; env | sort | grep -v '^PWD=\\|^SHLVL=\\|^_='")
+            .script_name("/app/")
+            .env("EXTRA", "1");
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    let response = server
+        .exchange(
+            "GET /app/a%20b?c=d HTTP/1.1\r\nhost: example.com:8080\r\nx-custom: 1\r\n\
+            x-custom: 2\r\ncookie: a=1\r\ncookie: b=2\r\nproxy: evil\r\n\r\n",
+        )
+        .unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert_eq!(
+        head,
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\ncontent-type: text/plain",
+            body.len()
+        )
+    );
+    assert_eq!(
+        body,
+        format!(
+            "EXTRA=1\n\
+            GATEWAY_INTERFACE=CGI/1.1\n\
+            HTTP_COOKIE=a=1; b=2\n\
+            HTTP_HOST=example.com:8080\n\
+            HTTP_X_CUSTOM=1, 2\n\
+            PATH_INFO=/a b\n\
+            QUERY_STRING=c=d\n\
+            REMOTE_ADDR=127.0.0.1\n\
+            REMOTE_HOST=127.0.0.1\n\
+            REQUEST_METHOD=GET\n\
+            SCRIPT_NAME=/app\n\
+            SERVER_NAME=example.com\n\
+            SERVER_PORT=8080\n\
+            SERVER_PROTOCOL=HTTP/1.1\n\
+            SERVER_SOFTWARE=servlin/{}\n",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+    assert_eq!(
+        server.exchange("GET /other HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 404 Not Found\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 9\r\n\r\nnot found",
+    );
+    assert_eq!(
+        server
+            .exchange("GET /application HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 404 Not Found\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 9\r\n\r\nnot found",
+    );
+}
+
+#[test]
+fn request_body() {
+    let cgi = sh(
+        "printf 'Content-Type: text/plain\\r\\n\\r\\n'; echo \"$CONTENT_LENGTH $CONTENT_TYPE\"; cat",
+    );
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    assert_eq!(
+        server
+            .exchange("POST / HTTP/1.1\r\ncontent-type: text/csv\r\ncontent-length: 3\r\n\r\nabc")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 14\r\ncontent-type: text/plain\r\n\r\n3 text/csv\nabc",
+    );
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 2\r\ncontent-type: text/plain\r\n\r\n \n",
+    );
+    // The program may exit without reading its input.
+    let cgi = sh("printf 'Status: 204 No Content\\n\\n'");
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    let body = "a".repeat(1024 * 1024);
+    assert_eq!(
+        server
+            .exchange(format!(
+                "POST / HTTP/1.1\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            ))
+            .unwrap(),
+        "HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n",
+    );
+}
+
+#[test]
+fn status_and_location() {
+    let cgi =
+        sh("printf 'Status: 404 Not Found\\r\\nX-A: 1\\r\\nContent-Length: 99\\r\\n\\r\\nmissing'");
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 404 Not Found\r\ncontent-length: 7\r\nx-a: 1\r\n\r\nmissing",
+    );
+    let cgi = sh("printf 'Location: /other\\n\\n'");
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 302 Found\r\ncontent-length: 0\r\nlocation: /other\r\n\r\n",
+    );
+}
+
+#[test]
+fn errors() {
+    for cgi in [
+        Cgi::new("/nonexistent"),
+        sh("echo hello"),
+        sh("printf 'bad header\\n\\n'"),
+        sh("printf 'X-A: 1\\rX-B: 2\\n\\n'"),
+        sh("printf 'Status: 2000 OK\\n\\n'"),
+        sh("printf 'Status: 101 Switching Protocols\\n\\n'"),
+        sh("printf 'Content-Type: text/plain\\n\\n'; head -c 1000 /dev/zero").max_output_len(100),
+    ] {
+        let debug = format!("{cgi:?}");
+        let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+        assert_eq!(
+            server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+            "HTTP/1.1 502 Bad Gateway\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+            "{debug}"
+        );
+    }
+    let cgi = sh("exec sleep 5").timeout(Duration::from_millis(100));
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 504 Gateway Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+    // A background process that keeps the output pipe open does not delay the response.
+    let cgi = sh("sleep 5 & echo started").timeout(Duration::from_millis(100));
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    let before = Instant::now();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 504 Gateway Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+    assert!(
+        before.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        before.elapsed()
+    );
+    // A program that closes its output and keeps running gets killed at the deadline.
+    let cgi = sh("printf 'Content-Type: text/plain\\n\\n'; exec >&-; sleep 5")
+        .timeout(Duration::from_millis(100));
+    let server = TestServer::start(move |req: Request| cgi.handle(&req)).unwrap();
+    let before = Instant::now();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 504 Gateway Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+    assert!(
+        before.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        before.elapsed()
+    );
+}
@@ -0,0 +1,204 @@
+use crate::test_util::TestServer;
+use servlin::{Cors, Request, Response};
+use std::time::Duration;
+
+mod test_util;
+
+#[allow(clippy::needless_pass_by_value)]
+fn app(req: Request) -> Response {
+    if req.url.path == "/vary" {
+        Response::text(200, "ok").with_header("vary", "Accept-Encoding, Origin".try_into().unwrap())
+    } else if req.url.path == "/own" {
+        Response::text(200, "ok").with_header(
+            "access-control-allow-origin",
+            "https://own.example.com".try_into().unwrap(),
+        )
+    } else {
+        Response::text(200, "ok").with_header("x-total", "5".try_into().unwrap())
+    }
+}
+
+fn head(server: &TestServer, request: &str) -> String {
+    let response = server.exchange(request).unwrap();
+    response.split_once("\r\n\r\n").unwrap().0.to_string()
+}
+
+#[test]
+fn simple_requests() {
+    let cors = Cors::new()
+        .allow_origin("https://App.example.com")
+        .allow_origin("http://*.preview.example.com:8080")
+        .expose_headers(["X-Total"])
+        .allow_credentials();
+    let server = TestServer::start(move |req: Request| cors.handle(req, app)).unwrap();
+    let allowed = "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+        x-total: 5\r\nvary: origin\r\naccess-control-allow-origin: {}\r\n\
+        access-control-allow-credentials: true\r\naccess-control-expose-headers: x-total";
+    for origin in [
+        "https://app.example.com",
+        "http://a.preview.example.com:8080",
+        "http://a.b.preview.example.com:8080",
+    ] {
+        assert_eq!(
+            head(
+                &server,
+                &format!("GET / HTTP/1.1\r\norigin: {origin}\r\n\r\n")
+            ),
+            allowed.replace("{}", origin),
+        );
+    }
+    for request in [
+        "GET / HTTP/1.1\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: https://evil.com\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: null\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: http://app.example.com\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: https://app.example.com:8443\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: http://preview.example.com:8080\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: http://a.preview.example.com\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: http://evil.com/.preview.example.com:8080\r\n\r\n",
+        "GET / HTTP/1.1\r\norigin: https://app.example.com\r\norigin: https://evil.com\r\n\r\n",
+    ] {
+        assert_eq!(
+            head(&server, request),
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+            x-total: 5\r\nvary: origin",
+            "{request:?}"
+        );
+    }
+    // The policy does not repeat `Vary: Origin`.
+    assert_eq!(
+        head(&server, "GET /vary HTTP/1.1\r\n\r\n"),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+        vary: Accept-Encoding, Origin",
+    );
+}
+
+#[test]
+fn any_origin() {
+    let cors = Cors::new().allow_any_origin();
+    let server = TestServer::start(move |req: Request| cors.handle(req, app)).unwrap();
+    assert_eq!(
+        head(
+            &server,
+            "GET / HTTP/1.1\r\norigin: https://example.com\r\n\r\n"
+        ),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+        x-total: 5\r\naccess-control-allow-origin: *",
+    );
+    assert_eq!(
+        head(&server, "GET / HTTP/1.1\r\n\r\n"),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+        x-total: 5",
+    );
+}
+
+#[test]
+fn handler_allow_origin() {
+    let cors = Cors::new().allow_any_origin().expose_headers(["x-total"]);
+    let server = TestServer::start(move |req: Request| cors.handle(req, app)).unwrap();
+    assert_eq!(
+        head(
+            &server,
+            "GET /own HTTP/1.1\r\norigin: https://example.com\r\n\r\n"
+        ),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+        access-control-allow-origin: https://own.example.com",
+    );
+}
+
+#[test]
+fn preflight() {
+    let cors = Cors::new()
+        .allow_origin("https://app.example.com")
+        .allow_methods(["GET", "PUT"])
+        .allow_headers(["Content-Type", "x-api-key"])
+        .max_age(Duration::from_mins(10));
+    let server = TestServer::start(move |req: Request| cors.handle(req, app)).unwrap();
+    assert_eq!(
+        server
+            .exchange(
+                "OPTIONS /a HTTP/1.1\r\norigin: https://app.example.com\r\n\
+                access-control-request-method: PUT\r\n\
+                access-control-request-headers: x-api-key,content-type\r\n\r\n"
+            )
+            .unwrap(),
+        "HTTP/1.1 204 No Content\r\ncontent-length: 0\r\nvary: origin\r\n\
+        vary: access-control-request-method\r\nvary: access-control-request-headers\r\n\
+        access-control-allow-origin: https://app.example.com\r\n\
+        access-control-allow-methods: GET, PUT\r\n\
+        access-control-allow-headers: content-type, x-api-key\r\n\
+        access-control-max-age: 600\r\n\r\n",
+    );
+    let denied = "HTTP/1.1 204 No Content\r\ncontent-length: 0\r\nvary: origin\r\n\
+        vary: access-control-request-method\r\nvary: access-control-request-headers";
+    for request in [
+        "OPTIONS /a HTTP/1.1\r\norigin: https://evil.com\r\n\
+        access-control-request-method: PUT\r\n\r\n",
+        "OPTIONS /a HTTP/1.1\r\norigin: https://app.example.com\r\n\
+        access-control-request-method: DELETE\r\n\r\n",
+        "OPTIONS /a HTTP/1.1\r\norigin: https://app.example.com\r\n\
+        access-control-request-method: put\r\n\r\n",
+        "OPTIONS /a HTTP/1.1\r\norigin: https://app.example.com\r\n\
+        access-control-request-method: PUT\r\naccess-control-request-headers: x-other\r\n\r\n",
+    ] {
+        assert_eq!(head(&server, request), denied, "{request:?}");
+    }
+    // Other OPTIONS requests go to the handler.
+    assert_eq!(
+        head(
+            &server,
+            "OPTIONS /a HTTP/1.1\r\norigin: https://app.example.com\r\n\r\n"
+        ),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\
+        x-total: 5\r\nvary: origin\r\naccess-control-allow-origin: https://app.example.com",
+    );
+}
+
+#[test]
+fn preflight_any_header() {
+    let cors = Cors::new().allow_any_origin().allow_any_header();
+    let server = TestServer::start(move |req: Request| cors.handle(req, app)).unwrap();
+    assert_eq!(
+        head(
+            &server,
+            "OPTIONS /a HTTP/1.1\r\norigin: https://example.com\r\n\
+            access-control-request-method: POST\r\n\
+            access-control-request-headers: X-A, x-b\r\n\r\n"
+        ),
+        "HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\
+        vary: access-control-request-method\r\nvary: access-control-request-headers\r\n\
+        access-control-allow-origin: *\r\n\
+        access-control-allow-methods: GET, HEAD, POST\r\n\
+        access-control-allow-headers: x-a, x-b",
+    );
+}
+
+#[test]
+#[should_panic(expected = "Cors cannot allow credentials with any origin")]
+fn credentials_with_any_origin() {
+    let _cors = Cors::new().allow_credentials().allow_any_origin();
+}
+
+#[test]
+#[should_panic(expected = "invalid origin")]
+fn invalid_origin() {
+    let _cors = Cors::new().allow_origin("https://example.com/");
+}
+
+#[test]
+#[should_panic(expected = "invalid method")]
+fn invalid_method() {
+    let _cors = Cors::new().allow_methods(["GET", "DELETE "]);
+}
+
+#[test]
+#[should_panic(expected = "invalid header name")]
+fn invalid_allow_header() {
+    let _cors = Cors::new().allow_headers(["x-caf\u{e9}"]);
+}
+
+#[test]
+#[should_panic(expected = "invalid header name")]
+fn invalid_expose_header() {
+    let _cors = Cors::new().expose_headers(["x-a, x-b"]);
+}
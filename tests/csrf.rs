@@ -0,0 +1,208 @@
+use crate::test_util::TestServer;
+use servlin::{Csrf, Request, Response};
+
+mod test_util;
+
+#[allow(clippy::needless_pass_by_value)]
+fn app(csrf: &Csrf, req: Request) -> Response {
+    match req.method.as_str() {
+        "GET" => Response::html(200, csrf.form_field(&req)),
+        _ => Response::text(200, format!("ok {}", req.body.len().unwrap_or_default())),
+    }
+}
+
+fn status(server: &TestServer, request: impl AsRef<[u8]>) -> String {
+    let response = server.exchange(request).unwrap();
+    response.lines().next().unwrap().to_string()
+}
+
+fn get_token(server: &TestServer) -> String {
+    let response = server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap();
+    let (_, rest) = response.split_once("\r\nset-cookie: __Host-csrf=").unwrap();
+    let token = &rest[..64];
+    // The `__Host-` prefix requires `Secure`, `Path=/`, and no `Domain`.
+    assert!(
+        rest[64..].starts_with("; HttpOnly; Max-Age=2592000; Path=/; SameSite=Lax; Secure\r\n"),
+        "{response:?}"
+    );
+    assert!(
+        response.ends_with(&format!(
+            "\r\n\r\n<input type=\"hidden\" name=\"csrf_token\" value=\"{token}\">"
+        )),
+        "{response:?}"
+    );
+    token.to_string()
+}
+
+#[test]
+fn token() {
+    let csrf = Csrf::new();
+    let server =
+        TestServer::start(move |req: Request| csrf.handle(req, |req| app(&csrf, req))).unwrap();
+    let token = get_token(&server);
+    assert_ne!(token, get_token(&server));
+    // Clients with a token keep it.
+    let response = server
+        .exchange(format!(
+            "GET / HTTP/1.1\r\ncookie: __Host-csrf={token}\r\n\r\n"
+        ))
+        .unwrap();
+    assert!(!response.contains("set-cookie"), "{response:?}");
+    assert!(
+        response.contains(&format!("value=\"{token}\"")),
+        "{response:?}"
+    );
+    // Safe methods need no token.
+    assert_eq!(
+        status(&server, "HEAD / HTTP/1.1\r\n\r\n"),
+        "HTTP/1.1 200 OK"
+    );
+}
+
+#[test]
+fn form_posts() {
+    let csrf = Csrf::new();
+    let server =
+        TestServer::start(move |req: Request| csrf.handle(req, |req| app(&csrf, req))).unwrap();
+    let token = get_token(&server);
+    let post = |cookie: &str, body: &str| {
+        status(
+            &server,
+            format!(
+                "POST / HTTP/1.1\r\ncookie: {cookie}\r\n\
+                content-type: application/x-www-form-urlencoded\r\n\
+                content-length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+    };
+    let cookie = format!("__Host-csrf={token}");
+    assert_eq!(
+        post(&cookie, &format!("a=1&csrf_token={token}")),
+        "HTTP/1.1 200 OK"
+    );
+    assert_eq!(
+        post(&cookie, &format!("csrf%5Ftoken={token}&a=1")),
+        "HTTP/1.1 200 OK"
+    );
+    // The handler gets large bodies after the check.
+    let body = format!("csrf_token={token}&a={}", "b".repeat(100_000));
+    let response = server
+        .exchange(format!(
+            "POST / HTTP/1.1\r\ncookie: {cookie}\r\n\
+            content-type: application/x-www-form-urlencoded\r\n\
+            content-length: {}\r\n\r\n{body}",
+            body.len()
+        ))
+        .unwrap();
+    assert!(
+        response.ends_with(&format!("\r\n\r\nok {}", body.len())),
+        "{response:?}"
+    );
+    let other_token = "0".repeat(64);
+    for (cookie, body) in [
+        (cookie.as_str(), "a=1".to_string()),
+        (cookie.as_str(), format!("csrf_token={other_token}")),
+        (cookie.as_str(), format!("csrf_token={}", &token[..63])),
+        ("a=1", format!("csrf_token={token}")),
+        ("__Host-csrf=abc", "csrf_token=abc".to_string()),
+    ] {
+        assert_eq!(
+            post(cookie, &body),
+            "HTTP/1.1 403 Forbidden",
+            "{cookie} {body}"
+        );
+    }
+}
+
+#[test]
+fn multipart_posts() {
+    let csrf = Csrf::new();
+    let server =
+        TestServer::start(move |req: Request| csrf.handle(req, |req| app(&csrf, req))).unwrap();
+    let token = get_token(&server);
+    let post = |field: &str| {
+        let body = format!(
+            "--XYZ\r\ncontent-disposition: form-data; name=\"a\"\r\n\r\n1\r\n\
+            --XYZ\r\ncontent-disposition: form-data; name=\"{field}\"\r\n\r\n{token}\r\n--XYZ--\r\n"
+        );
+        status(
+            &server,
+            format!(
+                "POST / HTTP/1.1\r\ncookie: __Host-csrf={token}\r\n\
+                content-type: multipart/form-data; boundary=XYZ\r\n\
+                content-length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+    };
+    assert_eq!(post("csrf_token"), "HTTP/1.1 200 OK");
+    assert_eq!(post("other"), "HTTP/1.1 403 Forbidden");
+}
+
+#[test]
+fn header_token() {
+    let csrf = Csrf::new().header_name("x-token");
+    let server =
+        TestServer::start(move |req: Request| csrf.handle(req, |req| app(&csrf, req))).unwrap();
+    let token = get_token(&server);
+    assert_eq!(
+        status(
+            &server,
+            format!("DELETE / HTTP/1.1\r\ncookie: __Host-csrf={token}\r\nx-token: {token}\r\n\r\n")
+        ),
+        "HTTP/1.1 200 OK"
+    );
+    assert_eq!(
+        status(
+            &server,
+            format!(
+                "DELETE / HTTP/1.1\r\ncookie: __Host-csrf={token}\r\nx-csrf-token: {token}\r\n\r\n"
+            )
+        ),
+        "HTTP/1.1 403 Forbidden"
+    );
+    assert_eq!(
+        status(
+            &server,
+            format!("PUT / HTTP/1.1\r\nx-token: {token}\r\n\r\n")
+        ),
+        "HTTP/1.1 403 Forbidden"
+    );
+}
+
+#[test]
+fn origin() {
+    let csrf = Csrf::new().trusted_origin("https://App.example.com");
+    let server =
+        TestServer::start(move |req: Request| csrf.handle(req, |req| app(&csrf, req))).unwrap();
+    let token = get_token(&server);
+    let post = |headers: &str| {
+        status(
+            &server,
+            format!(
+                "POST / HTTP/1.1\r\nhost: example.com\r\ncookie: __Host-csrf={token}\r\n\
+                x-csrf-token: {token}\r\n{headers}\r\n"
+            ),
+        )
+    };
+    for headers in [
+        "",
+        "origin: http://example.com\r\n",
+        "origin: http://EXAMPLE.com:80\r\n",
+        "origin: https://app.example.com\r\n",
+        "sec-fetch-site: same-origin\r\n",
+        "sec-fetch-site: same-site\r\n",
+    ] {
+        assert_eq!(post(headers), "HTTP/1.1 200 OK", "{headers:?}");
+    }
+    for headers in [
+        "origin: https://example.com\r\n",
+        "origin: http://example.com:8080\r\n",
+        "origin: http://evil.com\r\n",
+        "origin: null\r\n",
+        "sec-fetch-site: cross-site\r\n",
+    ] {
+        assert_eq!(post(headers), "HTTP/1.1 403 Forbidden", "{headers:?}");
+    }
+}
@@ -0,0 +1,219 @@
+use crate::test_util::TestServer;
+use servlin::{FastCgi, Request};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+mod test_util;
+
+fn read_len(bytes: &mut &[u8]) -> usize {
+    if bytes[0] < 128 {
+        let len = usize::from(bytes[0]);
+        *bytes = &bytes[1..];
+        len
+    } else {
+        let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) & 0x7fff_ffff;
+        *bytes = &bytes[4..];
+        usize::try_from(len).unwrap()
+    }
+}
+
+fn write_record(stream: &mut impl Write, record_type: u8, content: &[u8]) {
+    let [len1, len0] = u16::try_from(content.len()).unwrap().to_be_bytes();
+    // Responders may add padding.
+    stream
+        .write_all(&[1, record_type, 0, 1, len1, len0, 3, 0])
+        .unwrap();
+    stream.write_all(content).unwrap();
+    stream.write_all(&[0, 0, 0]).unwrap();
+}
+
+/// Reads a request and returns its params and stdin.
+fn read_request(stream: &mut impl Read) -> (BTreeMap<String, String>, Vec<u8>) {
+    let mut params = Vec::new();
+    let mut stdin = Vec::new();
+    loop {
+        let mut header = [0_u8; 8];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!([1, 0, 1], [header[0], header[2], header[3]]);
+        let len = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        let mut content = vec![0_u8; len + usize::from(header[6])];
+        stream.read_exact(&mut content).unwrap();
+        content.truncate(len);
+        match header[1] {
+            1 => assert_eq!(content, [0, 1, 0, 0, 0, 0, 0, 0]),
+            4 => params.extend(content),
+            5 if content.is_empty() => break,
+            5 => stdin.extend(content),
+            other => panic!("unexpected record type {other}"),
+        }
+    }
+    let mut map = BTreeMap::new();
+    let mut bytes = params.as_slice();
+    while !bytes.is_empty() {
+        let name_len = read_len(&mut bytes);
+        let value_len = read_len(&mut bytes);
+        let name = String::from_utf8(bytes[..name_len].to_vec()).unwrap();
+        let value = String::from_utf8(bytes[name_len..name_len + value_len].to_vec()).unwrap();
+        bytes = &bytes[name_len + value_len..];
+        map.insert(name, value);
+    }
+    (map, stdin)
+}
+
+/// Responds with the params and stdin of each request.
+fn echo(stream: &mut (impl Read + Write)) {
+    let (params, stdin) = read_request(stream);
+    let mut body = String::new();
+    for (name, value) in params {
+        if name == "SERVER_SOFTWARE" {
+            continue;
+        }
+        let line = if value.len() > 100 {
+            format!("{name}: ({} bytes)\n", value.len())
+        } else {
+            format!("{name}: {value}\n")
+        };
+        body.push_str(&line);
+    }
+    body.push_str(&String::from_utf8(stdin).unwrap());
+    write_record(stream, 6, b"Content-Type: text/plain\r\n");
+    write_record(stream, 7, b"a warning\n");
+    write_record(stream, 6, format!("\r\n{body}").as_bytes());
+    write_record(stream, 6, b"");
+    write_record(stream, 3, &[0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+fn start_responder(respond: fn(&mut std::net::TcpStream)) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            respond(&mut stream.unwrap());
+        }
+    });
+    addr
+}
+
+#[test]
+fn tcp() {
+    let fastcgi = FastCgi::tcp(start_responder(echo))
+        .script_name("/app")
+        .param("SCRIPT_FILENAME", "/var/www/app.php")
+        .param("SERVER_NAME", "example.com");
+    let server = TestServer::start(move |req: Request| fastcgi.handle(&req)).unwrap();
+    let long = "a".repeat(200);
+    let response = server
+        .exchange(format!(
+            "POST /app/x?y=z HTTP/1.1\r\nhost: www.example.com\r\nx-long: {long}\r\ncontent-length: 3\r\n\r\nabc"
+        ))
+        .unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert_eq!(
+        head,
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\ncontent-type: text/plain",
+            body.len()
+        )
+    );
+    assert_eq!(
+        body,
+        "CONTENT_LENGTH: 3\n\
+        GATEWAY_INTERFACE: CGI/1.1\n\
+        HTTP_HOST: www.example.com\n\
+        HTTP_X_LONG: (200 bytes)\n\
+        PATH_INFO: /x\n\
+        QUERY_STRING: y=z\n\
+        REMOTE_ADDR: 127.0.0.1\n\
+        REMOTE_HOST: 127.0.0.1\n\
+        REQUEST_METHOD: POST\n\
+        SCRIPT_FILENAME: /var/www/app.php\n\
+        SCRIPT_NAME: /app\n\
+        SERVER_NAME: example.com\n\
+        SERVER_PORT: 80\n\
+        SERVER_PROTOCOL: HTTP/1.1\n\
+        abc",
+    );
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 404 Not Found\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 9\r\n\r\nnot found",
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn unix() {
+    let dir = temp_dir::TempDir::new().unwrap();
+    let path = dir.child("fcgi.sock");
+    let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            echo(&mut stream.unwrap());
+        }
+    });
+    let fastcgi = FastCgi::unix(&path);
+    let server = TestServer::start(move |req: Request| fastcgi.handle(&req)).unwrap();
+    let response = server.exchange("GET /?q HTTP/1.1\r\n\r\n").unwrap();
+    assert!(
+        response.contains("\r\n\r\nGATEWAY_INTERFACE: CGI/1.1\n"),
+        "{response:?}"
+    );
+    assert!(response.contains("\nQUERY_STRING: q\n"), "{response:?}");
+}
+
+#[test]
+fn errors() {
+    let addr: SocketAddr = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let fastcgi = FastCgi::tcp(addr);
+    let server = TestServer::start(move |req: Request| fastcgi.handle(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 502 Bad Gateway\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+    for respond in [
+        // Unknown role
+        |stream: &mut std::net::TcpStream| {
+            read_request(stream);
+            write_record(stream, 3, &[0, 0, 0, 0, 3, 0, 0, 0]);
+        },
+        // Malformed output
+        |stream: &mut std::net::TcpStream| {
+            read_request(stream);
+            write_record(stream, 6, b"hello");
+            write_record(stream, 3, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        },
+        // Too much output
+        |stream: &mut std::net::TcpStream| {
+            read_request(stream);
+            write_record(stream, 6, b"Content-Type: text/plain\r\n\r\n");
+            write_record(stream, 6, &[b'a'; 200]);
+            write_record(stream, 3, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        },
+        // Closed early
+        |stream: &mut std::net::TcpStream| {
+            read_request(stream);
+            write_record(stream, 6, b"Content-Type: text/plain\r\n\r\n");
+        },
+    ] {
+        let fastcgi = FastCgi::tcp(start_responder(respond)).max_output_len(100);
+        let server = TestServer::start(move |req: Request| fastcgi.handle(&req)).unwrap();
+        assert_eq!(
+            server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+            "HTTP/1.1 502 Bad Gateway\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+        );
+    }
+    let fastcgi = FastCgi::tcp(start_responder(|stream| {
+        read_request(stream);
+        std::thread::sleep(Duration::from_millis(500));
+    }))
+    .timeout(Duration::from_millis(100));
+    let server = TestServer::start(move |req: Request| fastcgi.handle(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 504 Gateway Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+}
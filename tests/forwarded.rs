@@ -0,0 +1,340 @@
+use crate::test_util::TestServer;
+use servlin::internal::{
+    ForwardedElement, parse_forwarded_header, parse_forwarded_node, resolve_forwarded,
+};
+use servlin::{Forwarded, HeaderList, HttpServerBuilder, IpCidr, RemoteAddr, Request, Response};
+use std::net::IpAddr;
+
+mod test_util;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn headers(pairs: &[(&str, &str)]) -> HeaderList {
+    let mut list = HeaderList::new();
+    for (name, value) in pairs {
+        list.add(name, (*value).try_into().unwrap());
+    }
+    list
+}
+
+fn forwarded(client: Option<&str>, proto: Option<&str>, host: Option<&str>) -> Forwarded {
+    Forwarded {
+        client: client.map(ip),
+        proto: proto.map(ToString::to_string),
+        host: host.map(ToString::to_string),
+    }
+}
+
+#[test]
+fn parse_header() {
+    assert_eq!(
+        Some(vec![
+            ForwardedElement {
+                for_: Some("192.0.2.60".to_string()),
+                proto: Some("http".to_string()),
+                host: None,
+            },
+            ForwardedElement {
+                for_: Some("[2001:db8:cafe::17]:4711".to_string()),
+                proto: None,
+                host: Some("example.com".to_string()),
+            },
+        ]),
+        parse_forwarded_header([
+            "for=192.0.2.60;proto=http;by=203.0.113.43",
+            "For=\"[2001:db8:cafe::17]:4711\" ; host=example.com, ",
+        ])
+    );
+    assert_eq!(
+        Some(vec![ForwardedElement {
+            for_: Some("a\"b".to_string()),
+            proto: None,
+            host: None,
+        }]),
+        parse_forwarded_header(["for=\"a\\\"b\""])
+    );
+    // Empty list elements are ignored.
+    let one = ForwardedElement {
+        for_: Some("192.0.2.1".to_string()),
+        proto: None,
+        host: None,
+    };
+    for value in ["for=192.0.2.1,", ", for=192.0.2.1", " , ,for=192.0.2.1 , "] {
+        assert_eq!(
+            Some(vec![one.clone()]),
+            parse_forwarded_header([value]),
+            "{value:?}"
+        );
+    }
+    assert_eq!(Some(vec![]), parse_forwarded_header(["", " , "]));
+    assert_eq!(
+        Some(vec![ForwardedElement::default()]),
+        parse_forwarded_header([";"])
+    );
+    for malformed in [
+        "for",
+        "for=",
+        "=1.2.3.4",
+        "for=1.2.3.4 proto=http",
+        "for=\"1.2.3.4",
+        "for=\"1.2.3.4\\",
+        "for=1.2.3.4;for=5.6.7.8",
+        "for=[::1]",
+    ] {
+        assert_eq!(None, parse_forwarded_header([malformed]), "{malformed:?}");
+    }
+}
+
+#[test]
+fn parse_node() {
+    assert_eq!(Some(ip("1.2.3.4")), parse_forwarded_node("1.2.3.4"));
+    assert_eq!(Some(ip("1.2.3.4")), parse_forwarded_node(" 1.2.3.4:80 "));
+    assert_eq!(
+        Some(ip("2001:db8::1")),
+        parse_forwarded_node("[2001:db8::1]")
+    );
+    assert_eq!(
+        Some(ip("2001:db8::1")),
+        parse_forwarded_node("[2001:db8::1]:443")
+    );
+    assert_eq!(Some(ip("2001:db8::1")), parse_forwarded_node("2001:db8::1"));
+    for value in [
+        "",
+        "unknown",
+        "_hidden",
+        "1.2.3",
+        "[1.2.3.4]",
+        "[2001:db8::1",
+        "example.com:80",
+    ] {
+        assert_eq!(None, parse_forwarded_node(value), "{value:?}");
+    }
+}
+
+fn trusted_cidrs() -> [IpCidr; 1] {
+    ["10.0.0.0/8".parse::<IpCidr>().unwrap()]
+}
+
+fn proxy() -> RemoteAddr {
+    "10.0.0.1:1234"
+        .parse::<std::net::SocketAddr>()
+        .unwrap()
+        .into()
+}
+
+#[test]
+fn resolve() {
+    let (trusted, proxy) = (trusted_cidrs(), proxy());
+    let client: RemoteAddr = "1.2.3.4:1234"
+        .parse::<std::net::SocketAddr>()
+        .unwrap()
+        .into();
+    // Untrusted peer
+    assert_eq!(
+        None,
+        resolve_forwarded(&client, &headers(&[("forwarded", "for=5.6.7.8")]), &trusted)
+    );
+    // No headers
+    assert_eq!(
+        Some(forwarded(Some("10.0.0.1"), None, None)),
+        resolve_forwarded(&proxy, &headers(&[]), &trusted)
+    );
+    // Forwarded
+    assert_eq!(
+        Some(forwarded(
+            Some("5.6.7.8"),
+            Some("https"),
+            Some("example.com")
+        )),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=5.6.7.8;proto=HTTPS;host=example.com")]),
+            &trusted
+        )
+    );
+    // The client added a fake element, then two trusted proxies added theirs.
+    assert_eq!(
+        Some(forwarded(Some("5.6.7.8"), Some("https"), None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[
+                ("forwarded", "for=9.9.9.9;proto=http"),
+                ("forwarded", "for=5.6.7.8;proto=https, for=10.0.0.2"),
+            ]),
+            &trusted
+        )
+    );
+    assert_eq!(
+        Some(forwarded(None, Some("https"), None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=unknown;proto=https")]),
+            &trusted
+        )
+    );
+    assert_eq!(
+        Some(forwarded(None, None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=\"_hidden\";proto=gopher")]),
+            &trusted
+        )
+    );
+    // Every element is trusted
+    assert_eq!(
+        Some(forwarded(Some("10.0.0.3"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=10.0.0.3, for=10.0.0.2")]),
+            &trusted
+        )
+    );
+    // Malformed
+    assert_eq!(
+        Some(forwarded(Some("10.0.0.1"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=5.6.7.8;;x")]),
+            &trusted
+        )
+    );
+}
+
+#[test]
+fn resolve_x_forwarded() {
+    let (trusted, proxy) = (trusted_cidrs(), proxy());
+    // Forwarded takes precedence over X-Forwarded-*.
+    assert_eq!(
+        Some(forwarded(Some("5.6.7.8"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[
+                ("forwarded", "for=5.6.7.8"),
+                ("x-forwarded-for", "9.9.9.9"),
+                ("x-forwarded-proto", "https"),
+            ]),
+            &trusted
+        )
+    );
+    // X-Forwarded-*
+    assert_eq!(
+        Some(forwarded(
+            Some("5.6.7.8"),
+            Some("https"),
+            Some("example.com")
+        )),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[
+                ("x-forwarded-for", "9.9.9.9, 5.6.7.8"),
+                ("x-forwarded-for", "10.0.0.2"),
+                ("x-forwarded-proto", "http, https"),
+                ("x-forwarded-host", "example.com"),
+            ]),
+            &trusted
+        )
+    );
+    assert_eq!(
+        Some(forwarded(Some("2001:db8::1"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("x-forwarded-for", "2001:db8::1")]),
+            &trusted
+        )
+    );
+    assert_eq!(
+        Some(forwarded(None, None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("x-forwarded-for", "garbage, 10.0.0.2")]),
+            &trusted
+        )
+    );
+}
+
+#[test]
+fn resolve_empty_elements() {
+    let (trusted, proxy) = (trusted_cidrs(), proxy());
+    assert_eq!(
+        Some(forwarded(Some("192.0.2.1"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=192.0.2.1,")]),
+            &trusted
+        )
+    );
+    assert_eq!(
+        Some(forwarded(Some("192.0.2.1"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("forwarded", "for=192.0.2.1"), ("forwarded", "")]),
+            &trusted
+        )
+    );
+    assert_eq!(
+        Some(forwarded(Some("192.0.2.1"), None, None)),
+        resolve_forwarded(
+            &proxy,
+            &headers(&[("x-forwarded-for", "192.0.2.1, ,")]),
+            &trusted
+        )
+    );
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn echo_client(req: Request) -> Response {
+    Response::text(
+        200,
+        format!("{:?} {} {:?}", req.client_addr(), req.scheme(), req.host()),
+    )
+}
+
+fn trust_localhost(builder: HttpServerBuilder) -> HttpServerBuilder {
+    builder.trusted_proxies(["127.0.0.0/8".parse::<IpCidr>().unwrap()])
+}
+
+#[test]
+fn trusted() {
+    let server = TestServer::start_with(trust_localhost, echo_client).unwrap();
+    assert_eq!(
+        server
+            .exchange(
+                "GET / HTTP/1.1\r\nhost: internal\r\nforwarded: for=1.2.3.4;proto=https;host=example.com\r\n\r\n"
+            )
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 39\r\n\r\nSome(1.2.3.4) https Some(\"example.com\")",
+    );
+    assert_eq!(
+        server
+            .exchange("GET / HTTP/1.1\r\nhost: internal\r\nx-forwarded-for: 1.2.3.4\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 35\r\n\r\nSome(1.2.3.4) http Some(\"internal\")",
+    );
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 25\r\n\r\nSome(127.0.0.1) http None",
+    );
+}
+
+#[test]
+fn untrusted() {
+    for server in [
+        TestServer::start(echo_client).unwrap(),
+        TestServer::start_with(
+            |builder| builder.trusted_proxies(["10.0.0.0/8".parse::<IpCidr>().unwrap()]),
+            echo_client,
+        )
+        .unwrap(),
+    ] {
+        assert_eq!(
+            server
+                .exchange(
+                    "GET / HTTP/1.1\r\nhost: internal\r\nforwarded: for=1.2.3.4;proto=https;host=example.com\r\nx-forwarded-for: 1.2.3.4\r\n\r\n"
+                )
+                .unwrap(),
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 37\r\n\r\nSome(127.0.0.1) http Some(\"internal\")",
+        );
+    }
+}
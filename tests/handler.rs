@@ -300,6 +300,90 @@ fn error_reading_body_file() {
     );
 }
 
+#[test]
+fn stream_body() {
+    let server = TestServer::start(|req| {
+        if req.body.is_pending() {
+            Response::stream_body_and_reprocess(70_000)
+        } else {
+            let mut buf = Vec::new();
+            match req.body.reader().unwrap().read_to_end(&mut buf) {
+                Ok(len) => Response::text(200, format!("len={len}")),
+                Err(e) => Response::text(400, format!("{e}")),
+            }
+        }
+    })
+    .unwrap();
+    // With content-length
+    assert_ends_with(server.exchange(req_with_len(0)).unwrap(), "len=0");
+    assert_ends_with(server.exchange(req_with_len(1)).unwrap(), "len=1");
+    assert_ends_with(server.exchange(req_with_len(65_537)).unwrap(), "len=65537");
+    assert_ends_with(server.exchange(req_with_len(70_000)).unwrap(), "len=70000");
+    assert_eq!(
+        server.exchange(req_with_len(70_001)).unwrap(),
+        "HTTP/1.1 413 Payload Too Large\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 25\r\n\r\nUploaded data is too big.",
+    );
+
+    // Without content-length
+    assert_ends_with(server.exchange(req_without_len(0)).unwrap(), "len=0");
+    assert_ends_with(server.exchange(req_without_len(1)).unwrap(), "len=1");
+    assert_ends_with(
+        server.exchange(req_without_len(70_000)).unwrap(),
+        "len=70000",
+    );
+    assert_eq!(
+        server.exchange(req_without_len(70_001)).unwrap(),
+        "HTTP/1.1 413 Payload Too Large\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 25\r\n\r\nUploaded data is too big.",
+    );
+}
+
+#[test]
+fn stream_body_handler_reads_while_receiving() {
+    let server = TestServer::start(|req| {
+        if req.body.is_pending() {
+            Response::stream_body_and_reprocess(100_000)
+        } else {
+            let mut buf = [0_u8; 3];
+            req.body.reader().unwrap().read_exact(&mut buf).unwrap();
+            Response::text(200, String::from_utf8(buf.to_vec()).unwrap())
+        }
+    })
+    .unwrap();
+    // The client sends only part of the body.
+    let mut tcp_stream = server
+        .connect_and_send("M / HTTP/1.1\r\ncontent-length:100000\r\n\r\nabc")
+        .unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 3\r\n\r\nabc",
+    );
+}
+
+#[test]
+fn stream_body_truncated() {
+    let server = TestServer::start(|req| {
+        if req.body.is_pending() {
+            Response::stream_body_and_reprocess(100_000)
+        } else {
+            let mut buf = Vec::new();
+            let err = req
+                .body
+                .reader()
+                .unwrap()
+                .read_to_end(&mut buf)
+                .unwrap_err();
+            Response::text(200, format!("{:?}", err.kind()))
+        }
+    })
+    .unwrap();
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\ncontent-length:70000\r\n\r\nabc")
+            .unwrap(),
+        "HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 20\r\n\r\nHttpError::Truncated",
+    );
+}
+
 #[test]
 fn fast_reply() {
     let server = TestServer::start(|_req| Response::new(200)).unwrap();
@@ -335,14 +419,14 @@ fn expect_100_continue() {
         }
     })
     .unwrap();
-    // Small body
+    // Small body, the server calls the handler before sending `100 Continue`.
     let mut tcp_stream = server.connect().unwrap();
     let before = Instant::now();
     tcp_stream
         .write_all(b"M / HTTP/1.1\r\ncontent-length:100\r\nexpect: 100-continue\r\n\r\n")
         .unwrap();
     assert_eq!(
-        read_for(&mut tcp_stream, 100).unwrap(),
+        read_response(&mut tcp_stream).unwrap(),
         "HTTP/1.1 100 Continue\r\ncontent-length: 0\r\n\r\n"
     );
     check_elapsed(before, 100..200).unwrap();
@@ -364,6 +448,44 @@ fn expect_100_continue() {
     assert_ends_with(read_response(&mut tcp_stream).unwrap(), "len=66000");
 }
 
+#[test]
+fn expect_100_continue_rejected() {
+    let server = TestServer::start(|req| {
+        if req.headers.get_only("authorization").is_none() {
+            return Response::new(401);
+        }
+        if req.body.is_pending() {
+            return Response::get_body_and_reprocess(100);
+        }
+        Response::text(200, "ok")
+    })
+    .unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("M / HTTP/1.1\r\ncontent-length:3\r\nexpect: 100-continue\r\n\r\n")
+        .unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 401 Unauthorized\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+    );
+    // Without `Expect`, the server reads small bodies before calling the handler.
+    let mut tcp_stream = server
+        .connect_and_send("M / HTTP/1.1\r\nauthorization: x\r\ncontent-length:3\r\n\r\nabc")
+        .unwrap();
+    assert_ends_with(read_for(&mut tcp_stream, 100).unwrap(), "\r\n\r\nok");
+    // The server sends `100 Continue` when the handler asks for the body.
+    let mut tcp_stream = server
+        .connect_and_send(
+            "M / HTTP/1.1\r\nauthorization: x\r\ncontent-length:3\r\nexpect: 100-continue\r\n\r\n",
+        )
+        .unwrap();
+    assert_eq!(
+        read_response(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 100 Continue\r\ncontent-length: 0\r\n\r\n"
+    );
+    tcp_stream.write_all(b"abc").unwrap();
+    assert_ends_with(read_for(&mut tcp_stream, 100).unwrap(), "\r\n\r\nok");
+}
+
 #[test]
 fn client_incomplete_read() {
     let server = TestServer::start(|_req| {
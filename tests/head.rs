@@ -8,8 +8,8 @@ use futures_lite::AsyncWriteExt;
 use safina::async_test;
 use safina::sync::Receiver;
 use safina::timer::sleep_for;
-use servlin::internal::{Head, HeadError, HttpError, read_http_head};
-use servlin::{AsciiString, HeaderList, Response, Url};
+use servlin::internal::{Head, HeadError, HeadOptions, HttpError, read_http_head};
+use servlin::{AsciiString, HeaderList, HttpVersion, Response, Url};
 use std::time::Duration;
 use test_util::connected_streams;
 
@@ -25,6 +25,9 @@ fn request_line() {
         server.exchange("M /?q HTTP/1.1\r\n\r\n").unwrap().as_str(),
         "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n",
     );
+    let head = Head::try_read(&mut FixedBuf::from(*b"M /a%2Fb?q=%2F HTTP/1.1\r\n\r\n")).unwrap();
+    assert_eq!("/a%2Fb?q=%2F", head.target);
+    assert_eq!("/a/b", head.url.path);
     // asterisk-form
     assert_eq!(
         server.exchange("M * HTTP/1.1\r\n\r\n").unwrap().as_str(),
@@ -69,7 +72,9 @@ fn try_read_request_line() {
         (
             Ok(Head {
                 method: "M".to_string(),
+                target: "/".to_string(),
                 url: Url::parse_relative("/").unwrap(),
+                version: HttpVersion::Http11,
                 headers: HeaderList::default(),
             }),
             "M / HTTP/1.1\r\n\r\n",
@@ -100,7 +105,9 @@ fn try_read_url() {
         Head::try_read(&mut FixedBuf::from(*b"M / HTTP/1.1\r\n\r\n",)),
         Ok(Head {
             method: "M".to_string(),
+            target: "/".to_string(),
             url: Url::parse_relative("/").unwrap(),
+            version: HttpVersion::Http11,
             headers: HeaderList::default(),
         })
     );
@@ -108,7 +115,9 @@ fn try_read_url() {
         Head::try_read(&mut FixedBuf::from(*b"M /? HTTP/1.1\r\n\r\n",)),
         Ok(Head {
             method: "M".to_string(),
+            target: "/?".to_string(),
             url: Url::parse_relative("/?").unwrap(),
+            version: HttpVersion::Http11,
             headers: HeaderList::default(),
         })
     );
@@ -116,7 +125,9 @@ fn try_read_url() {
         Head::try_read(&mut FixedBuf::from(*b"M /?q HTTP/1.1\r\n\r\n",)),
         Ok(Head {
             method: "M".to_string(),
+            target: "/?q".to_string(),
             url: Url::parse_relative("/?q").unwrap(),
+            version: HttpVersion::Http11,
             headers: HeaderList::default(),
         })
     );
@@ -125,7 +136,9 @@ fn try_read_url() {
         Head::try_read(&mut FixedBuf::from(*b"M * HTTP/1.1\r\n\r\n",)),
         Ok(Head {
             method: "M".to_string(),
+            target: "*".to_string(),
             url: Url::parse_relative("*").unwrap(),
+            version: HttpVersion::Http11,
             headers: HeaderList::default(),
         })
     );
@@ -134,6 +147,49 @@ fn try_read_url() {
         Err(HeadError::MalformedPath),
         Head::try_read(&mut FixedBuf::from(*b"M http://h/ HTTP/1.1\r\n\r\n",))
     );
+    // authority-form
+    assert_eq!(
+        Head::try_read(&mut FixedBuf::from(
+            *b"CONNECT h.example:443 HTTP/1.1\r\n\r\n",
+        )),
+        Ok(Head {
+            method: "CONNECT".to_string(),
+            target: "h.example:443".to_string(),
+            url: Url {
+                host: "h.example".to_string(),
+                port: Some(443),
+                ..Url::parse_relative("").unwrap()
+            },
+            version: HttpVersion::Http11,
+            headers: HeaderList::default(),
+        })
+    );
+    assert_eq!(
+        Head::try_read(&mut FixedBuf::from(
+            *b"CONNECT 127.0.0.1:80 HTTP/1.1\r\n\r\n",
+        ))
+        .unwrap()
+        .url
+        .ip,
+        Some(std::net::IpAddr::from([127, 0, 0, 1]))
+    );
+    for req in [
+        "CONNECT / HTTP/1.1\r\n\r\n",
+        "CONNECT h.example HTTP/1.1\r\n\r\n",
+        "CONNECT h.example:443/ HTTP/1.1\r\n\r\n",
+        "CONNECT u@h.example:443 HTTP/1.1\r\n\r\n",
+        "CONNECT h.example:443? HTTP/1.1\r\n\r\n",
+        "CONNECT http://h.example:443 HTTP/1.1\r\n\r\n",
+        "M h.example:443 HTTP/1.1\r\n\r\n",
+    ] {
+        let mut buf: FixedBuf<200> = FixedBuf::new();
+        buf.write_bytes(req).unwrap();
+        assert_eq!(
+            Err(HeadError::MalformedPath),
+            Head::try_read(&mut buf),
+            "{req:?}"
+        );
+    }
     // Malformed
     assert_eq!(
         Err(HeadError::MalformedPath),
@@ -151,9 +207,20 @@ fn try_read_url() {
 
 #[test]
 fn try_read_proto() {
-    Head::try_read(&mut FixedBuf::from(*b"M / HTTP/1.1\r\n\r\n")).unwrap();
+    assert_eq!(
+        HttpVersion::Http11,
+        Head::try_read(&mut FixedBuf::from(*b"M / HTTP/1.1\r\n\r\n"))
+            .unwrap()
+            .version
+    );
+    assert_eq!(
+        HttpVersion::Http10,
+        Head::try_read(&mut FixedBuf::from(*b"M / HTTP/1.0\r\n\r\n"))
+            .unwrap()
+            .version
+    );
     for req in [
-        "M / HTTP/1.0\r\n\r\n",
+        "M / HTTP/0.9\r\n\r\n",
         "M / HTTP/1.2\r\n\r\n",
         "M / X\r\n\r\n",
     ] {
@@ -302,7 +369,12 @@ async fn read_http_head_task() -> (async_net::TcpStream, Receiver<Result<Head, H
     let (sender, receiver) = safina::sync::sync_channel(5);
     safina::executor::spawn(async move {
         loop {
-            let result = read_http_head(&mut <FixedBuf<1000>>::new(), &mut stream0).await;
+            let result = read_http_head(
+                &mut <FixedBuf<1000>>::new(),
+                &mut stream0,
+                &HeadOptions::default(),
+            )
+            .await;
             let result_is_err = result.is_err();
             if sender.send(result).is_err() || result_is_err {
                 break;
@@ -417,7 +489,50 @@ fn head_derive() {
     assert_ne!(head1, head2);
     // Debug
     assert_eq!(
-        "Head{method=\"A\", url=/1?q=x, headers={H1: \"V1\", h2: \"v2\"}}",
+        "Head{method=\"A\", url=/1?q=x, version=HTTP/1.1, headers={H1: \"V1\", h2: \"v2\"}}",
         format!("{head1:?}").as_str()
     );
 }
+
+#[test]
+fn try_read_with_limits() {
+    let options = HeadOptions {
+        max_head_len: 40,
+        max_header_count: 1,
+        max_header_len: 10,
+        max_url_len: 10,
+        strict: false,
+    };
+    let try_read = |req: &str| {
+        let mut buf: FixedBuf<200> = FixedBuf::new();
+        buf.write_bytes(req).unwrap();
+        Head::try_read_with(&mut buf, &options)
+    };
+    try_read("M /23456789 HTTP/1.1\r\na: 4567890\r\n\r\n").unwrap();
+    assert_eq!(
+        Err(HeadError::UriTooLong),
+        try_read("M /2345678901 HTTP/1.1\r\n\r\n")
+    );
+    assert_eq!(
+        Err(HeadError::HeaderTooLong),
+        try_read("M / HTTP/1.1\r\na: 45678901\r\n\r\n")
+    );
+    assert_eq!(
+        Err(HeadError::TooManyHeaders),
+        try_read("M / HTTP/1.1\r\na: 1\r\nb: 2\r\n\r\n")
+    );
+    assert_eq!(
+        Err(HeadError::TooLong),
+        try_read("M / HTTP/1.1\r\na: 1\r\nb: 2\r\nc: 3\r\nd: 4\r\ne: 5\r\n\r\n")
+    );
+    // The buffer does not yet contain the whole head.
+    assert_eq!(Err(HeadError::Truncated), try_read("M / HTTP/1.1\r\na: 1"));
+    assert_eq!(
+        Err(HeadError::UriTooLong),
+        try_read(&format!("M /{}", "a".repeat(50)))
+    );
+    assert_eq!(
+        Err(HeadError::TooLong),
+        try_read(&format!("M / HTTP/1.1\r\na: {}", "a".repeat(50)))
+    );
+}
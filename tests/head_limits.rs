@@ -0,0 +1,133 @@
+use crate::test_util::{TestServer, assert_starts_with, read_to_string};
+use servlin::{HttpServerBuilder, Request, Response};
+use std::io::Read;
+
+mod test_util;
+
+fn echo_lengths(mut req: Request) -> Response {
+    let cookie_len = req.headers.get_only("cookie").map_or(0, |s| s.len());
+    let mut body = String::new();
+    if let Ok(mut reader) = req.body.reader() {
+        reader.read_to_string(&mut body).unwrap();
+    }
+    req.body = servlin::RequestBody::empty();
+    Response::text(
+        200,
+        format!("url={} cookie={cookie_len} body={body}", req.url.path.len()),
+    )
+}
+
+fn big_cookie_request(cookie_len: usize) -> String {
+    format!(
+        "POST / HTTP/1.1\r\ncookie: c={}\r\ncontent-length: 3\r\n\r\nabc",
+        "a".repeat(cookie_len)
+    )
+}
+
+#[test]
+fn default_limits() {
+    let server = TestServer::start(echo_lengths).unwrap();
+    assert_eq!(
+        server.exchange(big_cookie_request(8_000)).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 26\r\n\r\nurl=1 cookie=8002 body=abc",
+    );
+    assert_eq!(
+        server.exchange(big_cookie_request(9_000)).unwrap(),
+        "HTTP/1.1 431 Request Header Fields Too Large\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 22\r\n\r\nHttpError::HeadTooLong",
+    );
+    let url = format!("/{}", "a".repeat(9_000));
+    assert_eq!(
+        server
+            .exchange(format!("GET {url} HTTP/1.1\r\n\r\n"))
+            .unwrap(),
+        "HTTP/1.1 414 URI Too Long\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 21\r\n\r\nHttpError::UriTooLong",
+    );
+}
+
+#[test]
+fn max_head_len() {
+    let server = TestServer::start_with(
+        |builder: HttpServerBuilder| builder.max_head_len(64 * 1024).max_header_len(64 * 1024),
+        echo_lengths,
+    )
+    .unwrap();
+    // The head does not fit in the connection's buffer.
+    assert_eq!(
+        server.exchange(big_cookie_request(50_000)).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 27\r\n\r\nurl=1 cookie=50002 body=abc",
+    );
+    // Pipelined requests after a large head.
+    let mut tcp_stream = server
+        .connect_and_send(
+            big_cookie_request(20_000) + "GET / HTTP/1.1\r\nconnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let responses = read_to_string(&mut tcp_stream).unwrap();
+    assert!(
+        responses.contains("\r\n\r\nurl=1 cookie=20002 body=abc"),
+        "{responses:?}"
+    );
+    assert!(
+        responses.ends_with("\r\n\r\nurl=1 cookie=0 body="),
+        "{responses:?}"
+    );
+    assert_starts_with(
+        server.exchange(big_cookie_request(70_000)).unwrap(),
+        "HTTP/1.1 431 Request Header Fields Too Large\r\n",
+    );
+}
+
+#[test]
+fn max_url_len() {
+    let server = TestServer::start_with(|builder| builder.max_url_len(100), echo_lengths).unwrap();
+    let url = format!("/{}", "a".repeat(99));
+    assert_starts_with(
+        server
+            .exchange(format!("GET {url} HTTP/1.1\r\n\r\n"))
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\n",
+    );
+    let url = format!("/{}", "a".repeat(100));
+    assert_starts_with(
+        server
+            .exchange(format!("GET {url} HTTP/1.1\r\n\r\n"))
+            .unwrap(),
+        "HTTP/1.1 414 URI Too Long\r\n",
+    );
+}
+
+#[test]
+fn max_header_count() {
+    let server =
+        TestServer::start_with(|builder| builder.max_header_count(2), echo_lengths).unwrap();
+    assert_starts_with(
+        server
+            .exchange("GET / HTTP/1.1\r\na: 1\r\nb: 2\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\n",
+    );
+    assert_eq!(
+        server
+            .exchange("GET / HTTP/1.1\r\na: 1\r\nb: 2\r\nc: 3\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 431 Request Header Fields Too Large\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 25\r\n\r\nHttpError::TooManyHeaders",
+    );
+}
+
+#[test]
+fn max_header_len() {
+    let server =
+        TestServer::start_with(|builder| builder.max_header_len(10), echo_lengths).unwrap();
+    assert_starts_with(
+        server
+            .exchange("GET / HTTP/1.1\r\na: 4567890\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\n",
+    );
+    assert_eq!(
+        server
+            .exchange("GET / HTTP/1.1\r\na: 45678901\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 431 Request Header Fields Too Large\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 24\r\n\r\nHttpError::HeaderTooLong",
+    );
+}
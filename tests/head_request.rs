@@ -0,0 +1,74 @@
+use crate::test_util::TestServer;
+use servlin::{HttpServerBuilder, Request, Response, ResponseBody};
+use std::path::PathBuf;
+
+mod test_util;
+
+#[allow(clippy::needless_pass_by_value)]
+fn get_only(req: Request) -> Response {
+    match req.method.as_str() {
+        "GET" => Response::text(200, "abc"),
+        _ => Response::method_not_allowed_405(&["GET"]),
+    }
+}
+
+#[test]
+fn head_omits_body() {
+    let server = TestServer::start(|req| Response::text(200, req.method)).unwrap();
+    assert_eq!(
+        server
+            .exchange("HEAD / HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 4\r\n\r\n\
+        HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 4\r\n\r\n",
+    );
+}
+
+#[test]
+fn head_file_not_opened() {
+    let server = TestServer::start(|_req| {
+        Response::new(200).with_body(ResponseBody::File(PathBuf::from("/nonexistent"), 5))
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("HEAD / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n",
+    );
+}
+
+#[test]
+fn head_unknown_length_body() {
+    let server = TestServer::start(|_req| {
+        Response::new(200).with_body(ResponseBody::from_reader(std::io::Cursor::new(b"abc")))
+    })
+    .unwrap();
+    assert_eq!(
+        server
+            .exchange("HEAD / HTTP/1.1\r\n\r\nHEAD / HTTP/1.0\r\nconnection: keep-alive\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+        HTTP/1.1 200 OK\r\nconnection: keep-alive\r\n\r\n",
+    );
+}
+
+#[test]
+fn head_as_get() {
+    let server = TestServer::start_with(HttpServerBuilder::head_as_get, get_only).unwrap();
+    assert_eq!(
+        server.exchange("HEAD / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 3\r\n\r\n",
+    );
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 3\r\n\r\nabc",
+    );
+}
+
+#[test]
+fn head_as_get_disabled_by_default() {
+    let server = TestServer::start(get_only).unwrap();
+    assert_eq!(
+        server.exchange("HEAD / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 405 Method Not Allowed\r\ncontent-length: 0\r\nallow: GET\r\n\r\n",
+    );
+}
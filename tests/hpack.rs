@@ -0,0 +1,277 @@
+use servlin::internal::{HpackDecoder, HpackError, hpack_encode, huffman_decode, huffman_encode};
+
+fn hex(s: &str) -> Vec<u8> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+fn decode(decoder: &mut HpackDecoder, block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+    Ok(decoder
+        .decode(block, 16 * 1024)?
+        .into_iter()
+        .map(|(name, value)| {
+            (
+                String::from_utf8(name).unwrap(),
+                String::from_utf8(value).unwrap(),
+            )
+        })
+        .collect())
+}
+
+fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+    list.iter()
+        .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+        .collect()
+}
+
+#[test]
+fn literal_fields() {
+    // https://datatracker.ietf.org/doc/html/rfc7541#appendix-C.2
+    let mut decoder = HpackDecoder::new(4096);
+    assert_eq!(
+        Ok(pairs(&[("custom-key", "custom-header")])),
+        decode(
+            &mut decoder,
+            &hex("400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572")
+        )
+    );
+    let mut decoder = HpackDecoder::new(4096);
+    assert_eq!(
+        Ok(pairs(&[(":path", "/sample/path")])),
+        decode(&mut decoder, &hex("040c 2f73 616d 706c 652f 7061 7468"))
+    );
+    assert_eq!(
+        Ok(pairs(&[("password", "secret")])),
+        decode(
+            &mut decoder,
+            &hex("1008 7061 7373 776f 7264 0673 6563 7265 74")
+        )
+    );
+    assert_eq!(
+        Ok(pairs(&[(":method", "GET")])),
+        decode(&mut decoder, &hex("82"))
+    );
+}
+
+#[test]
+fn requests() {
+    // https://datatracker.ietf.org/doc/html/rfc7541#appendix-C.3
+    let mut decoder = HpackDecoder::new(4096);
+    let first = pairs(&[
+        (":method", "GET"),
+        (":scheme", "http"),
+        (":path", "/"),
+        (":authority", "www.example.com"),
+    ]);
+    assert_eq!(
+        Ok(first.clone()),
+        decode(
+            &mut decoder,
+            &hex("8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d")
+        )
+    );
+    let mut second = first.clone();
+    second.push(("cache-control".to_string(), "no-cache".to_string()));
+    assert_eq!(
+        Ok(second.clone()),
+        decode(&mut decoder, &hex("8286 84be 5808 6e6f 2d63 6163 6865"))
+    );
+    let third = pairs(&[
+        (":method", "GET"),
+        (":scheme", "https"),
+        (":path", "/index.html"),
+        (":authority", "www.example.com"),
+        ("custom-key", "custom-value"),
+    ]);
+    assert_eq!(
+        Ok(third.clone()),
+        decode(
+            &mut decoder,
+            &hex("8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65")
+        )
+    );
+
+    // https://datatracker.ietf.org/doc/html/rfc7541#appendix-C.4
+    let mut decoder = HpackDecoder::new(4096);
+    assert_eq!(
+        Ok(first),
+        decode(
+            &mut decoder,
+            &hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff")
+        )
+    );
+    assert_eq!(
+        Ok(second),
+        decode(&mut decoder, &hex("8286 84be 5886 a8eb 1064 9cbf"))
+    );
+    assert_eq!(
+        Ok(third),
+        decode(
+            &mut decoder,
+            &hex("8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf")
+        )
+    );
+}
+
+#[test]
+fn responses_with_eviction() {
+    // https://datatracker.ietf.org/doc/html/rfc7541#appendix-C.6
+    let mut decoder = HpackDecoder::new(256);
+    assert_eq!(
+        Ok(pairs(&[
+            (":status", "302"),
+            ("cache-control", "private"),
+            ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+            ("location", "https://www.example.com"),
+        ])),
+        decode(
+            &mut decoder,
+            &hex(
+                "4882 6402 5885 aec3 771a 4b61 96d0 7abe 9410 54d4 44a8 2005 9504 0b81 66e0 82a6 \
+                 2d1b ff6e 919d 29ad 1718 63c7 8f0b 97c8 e9ae 82ae 43d3"
+            )
+        )
+    );
+    assert_eq!(
+        Ok(pairs(&[
+            (":status", "307"),
+            ("cache-control", "private"),
+            ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+            ("location", "https://www.example.com"),
+        ])),
+        decode(&mut decoder, &hex("4883 640e ffc1 c0bf"))
+    );
+    assert_eq!(
+        Ok(pairs(&[
+            (":status", "200"),
+            ("cache-control", "private"),
+            ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+            ("location", "https://www.example.com"),
+            ("content-encoding", "gzip"),
+            (
+                "set-cookie",
+                "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"
+            ),
+        ])),
+        decode(
+            &mut decoder,
+            &hex(
+                "88c1 6196 d07a be94 1054 d444 a820 0595 040b 8166 e084 a62d 1bff c05a 839b d9ab \
+                 77ad 94e7 821d d7f2 e6c7 b335 dfdf cd5b 3960 d5af 2708 7f36 72c1 ab27 0fb5 291f \
+                 9587 3160 65c0 03ed 4ee5 b106 3d50 07"
+            )
+        )
+    );
+}
+
+#[test]
+fn huffman() {
+    assert_eq!(
+        hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff"),
+        huffman_encode(b"www.example.com")
+    );
+    assert_eq!(
+        Ok(b"www.example.com".to_vec()),
+        huffman_decode(&hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff"))
+    );
+    let all_bytes: Vec<u8> = (0..=255).collect();
+    assert_eq!(
+        Ok(all_bytes.clone()),
+        huffman_decode(&huffman_encode(&all_bytes))
+    );
+    assert_eq!(Ok(Vec::new()), huffman_decode(&[]));
+    // Padding longer than 7 bits.
+    assert_eq!(Err(HpackError::Malformed), huffman_decode(&hex("ffff")));
+    // Padding that is not EOS bits.
+    assert_eq!(Err(HpackError::Malformed), huffman_decode(&hex("00")));
+    // EOS symbol.
+    assert_eq!(
+        Err(HpackError::Malformed),
+        huffman_decode(&hex("ffff fffc"))
+    );
+}
+
+#[test]
+fn encode_round_trip() {
+    let value = b"a value that is long enough to compress".as_slice();
+    let headers: Vec<(&str, &[u8])> = vec![
+        (":status", b"200"),
+        (":status", b"404"),
+        ("content-type", b"text/plain"),
+        ("x-custom", value),
+        ("x-binary", &[0x00, 0xFF]),
+    ];
+    let block = hpack_encode(headers.clone());
+    // The stateless encoder never adds to the dynamic table.
+    let mut decoder = HpackDecoder::new(0);
+    let fields = decoder.decode(&block, 16 * 1024).unwrap();
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = headers
+        .into_iter()
+        .map(|(name, value)| (name.as_bytes().to_vec(), value.to_vec()))
+        .collect();
+    assert_eq!(expected, fields);
+    assert_eq!(vec![0x88], hpack_encode([(":status", b"200".as_slice())]));
+}
+
+#[test]
+fn malformed() {
+    for block in [
+        // Index zero.
+        "80",
+        // Index past the end of the tables.
+        "bf",
+        // Truncated integer.
+        "ff",
+        // Missing value.
+        "41",
+        // String longer than block.
+        "4103 6162",
+        // Table size larger than the limit.
+        "3fe2 1f",
+        // Table size update after a field.
+        "8220",
+    ] {
+        let mut decoder = HpackDecoder::new(4096);
+        assert_eq!(
+            Err(HpackError::Malformed),
+            decoder.decode(&hex(block), 16 * 1024),
+            "{block}"
+        );
+    }
+}
+
+#[test]
+fn table_size_update() {
+    let mut decoder = HpackDecoder::new(4096);
+    // Add "custom-key: custom-header" to the dynamic table and then shrink the table to zero.
+    decode(
+        &mut decoder,
+        &hex("400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572"),
+    )
+    .unwrap();
+    assert_eq!(
+        Ok(pairs(&[("custom-key", "custom-header")])),
+        decode(&mut decoder, &hex("be"))
+    );
+    assert_eq!(Ok(Vec::new()), decode(&mut decoder, &hex("20")));
+    assert_eq!(Err(HpackError::Malformed), decode(&mut decoder, &hex("be")));
+}
+
+#[test]
+fn header_list_too_long() {
+    let mut decoder = HpackDecoder::new(4096);
+    let block = hex("400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572");
+    // The entry size is 10 + 13 + 32 = 55 bytes.
+    assert_eq!(
+        Err(HpackError::HeaderListTooLong),
+        decoder.decode(&block, 54)
+    );
+    // The decoder still added the entry to the dynamic table.
+    assert_eq!(
+        Ok(vec![(b"custom-key".to_vec(), b"custom-header".to_vec())]),
+        decoder.decode(&hex("be"), 55)
+    );
+}
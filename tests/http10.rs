@@ -0,0 +1,84 @@
+use crate::test_util::{TestServer, read_for, read_to_string};
+use servlin::{Response, ResponseBody};
+use std::io::Write;
+
+mod test_util;
+
+#[test]
+fn http10_closes_by_default() {
+    let server = TestServer::start(|_req| Response::text(200, "abc")).unwrap();
+    let mut tcp_stream = server.connect_and_send("M / HTTP/1.0\r\n\r\n").unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 3\r\n\r\nabc",
+    );
+}
+
+#[test]
+fn http10_keep_alive() {
+    let server = TestServer::start(|_req| Response::text(200, "abc")).unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("M / HTTP/1.0\r\nconnection: Keep-Alive\r\n\r\n")
+        .unwrap();
+    let expected = "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: keep-alive\r\ncontent-length: 3\r\n\r\nabc";
+    assert_eq!(read_for(&mut tcp_stream, 100).unwrap(), expected);
+    tcp_stream
+        .write_all(b"M / HTTP/1.0\r\nconnection: keep-alive\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_for(&mut tcp_stream, 100).unwrap(), expected);
+}
+
+#[test]
+fn http10_unknown_length_body() {
+    let server = TestServer::start(|_req| {
+        Response::new(200).with_body(ResponseBody::from_reader(std::io::Cursor::new(b"abc")))
+    })
+    .unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("M / HTTP/1.0\r\nconnection: keep-alive\r\n\r\n")
+        .unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nabc",
+    );
+}
+
+#[test]
+fn http10_no_continue() {
+    let server = TestServer::start(|req| {
+        assert!(!req.expect_continue);
+        Response::new(200)
+    })
+    .unwrap();
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.0\r\nexpect: 100-continue\r\ncontent-length: 3\r\n\r\nabc")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+}
+
+#[test]
+fn version() {
+    let server = TestServer::start(|req| Response::text(200, req.version.to_string())).unwrap();
+    assert_eq!(
+        server.exchange("M / HTTP/1.0\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 8\r\n\r\nHTTP/1.0",
+    );
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 8\r\n\r\nHTTP/1.1",
+    );
+}
+
+#[test]
+fn http11_connection_close() {
+    let server = TestServer::start(|_req| Response::text(200, "abc")).unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("M / HTTP/1.1\r\nconnection: foo, close\r\n\r\nM / HTTP/1.1\r\n\r\n")
+        .unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 3\r\n\r\nabc",
+    );
+}
@@ -0,0 +1,444 @@
+use crate::test_util::TestServer;
+use servlin::internal::{HTTP2_PREFACE, HpackDecoder, hpack_encode};
+use servlin::{Request, Response};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+mod test_util;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+
+#[derive(Debug, Eq, PartialEq)]
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct H2Response {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+struct Client {
+    tcp_stream: TcpStream,
+    decoder: HpackDecoder,
+}
+impl Client {
+    fn new(tcp_stream: TcpStream) -> Self {
+        tcp_stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Self {
+            tcp_stream,
+            decoder: HpackDecoder::new(4096),
+        }
+    }
+
+    fn connect(server: &TestServer, settings: &[(u16, u32)]) -> Self {
+        let mut client = Self::new(server.connect().unwrap());
+        client.tcp_stream.write_all(HTTP2_PREFACE).unwrap();
+        client.handshake(settings);
+        client
+    }
+
+    fn handshake(&mut self, settings: &[(u16, u32)]) {
+        let mut payload = Vec::new();
+        for (id, value) in settings {
+            payload.extend(id.to_be_bytes());
+            payload.extend(value.to_be_bytes());
+        }
+        self.send(SETTINGS, 0, 0, &payload);
+        let frame = self.read_frame();
+        assert_eq!((SETTINGS, 0, 0), (frame.kind, frame.flags, frame.stream_id));
+        self.send(SETTINGS, ACK, 0, &[]);
+        let frame = self.read_frame();
+        assert_eq!(
+            (SETTINGS, ACK, 0),
+            (frame.kind, frame.flags, frame.stream_id)
+        );
+    }
+
+    fn send(&mut self, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+        let mut bytes = u32::try_from(payload.len()).unwrap().to_be_bytes()[1..].to_vec();
+        bytes.push(kind);
+        bytes.push(flags);
+        bytes.extend(stream_id.to_be_bytes());
+        bytes.extend(payload);
+        self.tcp_stream.write_all(&bytes).unwrap();
+    }
+
+    fn send_headers(&mut self, stream_id: u32, headers: &[(&str, &str)], end_stream: bool) {
+        let block = hpack_encode(
+            headers
+                .iter()
+                .map(|(name, value)| (*name, value.as_bytes())),
+        );
+        let flags = END_HEADERS | if end_stream { END_STREAM } else { 0 };
+        self.send(HEADERS, flags, stream_id, &block);
+    }
+
+    fn read_frame(&mut self) -> Frame {
+        let mut head = [0_u8; 9];
+        self.tcp_stream.read_exact(&mut head).unwrap();
+        let len = u32::from_be_bytes([0, head[0], head[1], head[2]]);
+        let mut payload = vec![0_u8; usize::try_from(len).unwrap()];
+        self.tcp_stream.read_exact(&mut payload).unwrap();
+        Frame {
+            kind: head[3],
+            flags: head[4],
+            stream_id: u32::from_be_bytes([head[5], head[6], head[7], head[8]]),
+            payload,
+        }
+    }
+
+    /// Reads frames until the server ends `num_streams` streams.
+    fn read_responses(&mut self, num_streams: usize) -> HashMap<u32, H2Response> {
+        let mut responses: HashMap<u32, H2Response> = HashMap::new();
+        let mut ended = 0;
+        while ended < num_streams {
+            let frame = self.read_frame();
+            let response = responses.entry(frame.stream_id).or_default();
+            match frame.kind {
+                HEADERS => {
+                    for (name, value) in self.decoder.decode(&frame.payload, 64 * 1024).unwrap() {
+                        response.headers.push((
+                            String::from_utf8(name).unwrap(),
+                            String::from_utf8(value).unwrap(),
+                        ));
+                    }
+                }
+                DATA => response
+                    .body
+                    .push_str(std::str::from_utf8(&frame.payload).unwrap()),
+                SETTINGS | WINDOW_UPDATE => continue,
+                _ => panic!("unexpected frame {frame:?}"),
+            }
+            if frame.flags & END_STREAM != 0 {
+                ended += 1;
+            }
+        }
+        responses.retain(|_, response| !response.headers.is_empty());
+        responses
+    }
+
+    fn read_response(&mut self, stream_id: u32) -> H2Response {
+        self.read_responses(1).remove(&stream_id).unwrap()
+    }
+
+    fn assert_closed(&mut self) {
+        let mut rest = Vec::new();
+        match self.tcp_stream.read_to_end(&mut rest) {
+            Ok(..) => assert!(rest.is_empty(), "{rest:?}"),
+            Err(e) if e.kind() == ErrorKind::ConnectionReset => {}
+            Err(e) => panic!("{e:?}"),
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // Wait for the server to close the connection so its tasks end before the server stops.
+        let _ignored = self.tcp_stream.shutdown(Shutdown::Write);
+        let _ignored = self.tcp_stream.read_to_end(&mut Vec::new());
+    }
+}
+
+fn get(path: &str) -> Vec<(&str, &str)> {
+    vec![
+        (":method", "GET"),
+        (":scheme", "http"),
+        (":authority", "example.com"),
+        (":path", path),
+    ]
+}
+
+fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+    list.iter()
+        .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+        .collect()
+}
+
+fn echo_server() -> TestServer {
+    TestServer::start_with(
+        servlin::HttpServerBuilder::enable_h2c,
+        |mut req: Request| {
+            let mut body = String::new();
+            if let Ok(mut reader) = req.body.reader() {
+                reader.read_to_string(&mut body).unwrap();
+            }
+            let host = req.headers.get_only("host").unwrap().to_string();
+            let body = format!("{} {} {host} {body}", req.method, req.url.path);
+            req.body = servlin::RequestBody::empty();
+            Response::text(200, body)
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn get_with_prior_knowledge() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    client.send_headers(1, &get("/a"), true);
+    assert_eq!(
+        H2Response {
+            headers: pairs(&[
+                (":status", "200"),
+                ("content-type", "text/plain; charset=UTF-8"),
+                ("content-length", "19"),
+            ]),
+            body: "GET /a example.com ".to_string(),
+        },
+        client.read_response(1)
+    );
+}
+
+#[test]
+fn post_body() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    let mut headers = get("/b");
+    headers[0].1 = "POST";
+    headers.push(("content-length", "3"));
+    client.send_headers(1, &headers, false);
+    client.send(DATA, 0, 1, b"ab");
+    client.send(DATA, END_STREAM, 1, b"c");
+    assert_eq!("POST /b example.com abc", client.read_response(1).body);
+}
+
+#[test]
+fn head() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    let mut headers = get("/c");
+    headers[0].1 = "HEAD";
+    client.send_headers(1, &headers, true);
+    assert_eq!(
+        H2Response {
+            headers: pairs(&[
+                (":status", "200"),
+                ("content-type", "text/plain; charset=UTF-8"),
+                ("content-length", "20"),
+            ]),
+            body: String::new(),
+        },
+        client.read_response(1)
+    );
+}
+
+#[test]
+fn expect_continue_handler_decides() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    let mut headers = get("/e");
+    headers[0].1 = "POST";
+    headers.push(("content-length", "3"));
+    headers.push(("expect", "100-continue"));
+    client.send_headers(1, &headers, false);
+    // The handler answers without the body, so the server does not send `100 Continue`.
+    assert_eq!(
+        H2Response {
+            headers: pairs(&[
+                (":status", "200"),
+                ("content-type", "text/plain; charset=UTF-8"),
+                ("content-length", "20"),
+            ]),
+            body: "POST /e example.com ".to_string(),
+        },
+        client.read_response(1)
+    );
+}
+
+#[test]
+fn multiplexed_streams() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    client.send_headers(1, &get("/one"), true);
+    client.send_headers(3, &get("/two"), true);
+    let responses = client.read_responses(2);
+    assert_eq!("GET /one example.com ", responses[&1].body);
+    assert_eq!("GET /two example.com ", responses[&3].body);
+}
+
+#[test]
+fn flow_control() {
+    let server = TestServer::start_with(servlin::HttpServerBuilder::enable_h2c, |_req| {
+        Response::text(200, "a".repeat(25))
+    })
+    .unwrap();
+    // SETTINGS_INITIAL_WINDOW_SIZE
+    let mut client = Client::connect(&server, &[(0x4, 10)]);
+    client.send_headers(1, &get("/"), true);
+    assert_eq!(HEADERS, client.read_frame().kind);
+    let frame = client.read_frame();
+    assert_eq!(
+        (DATA, 0, 10),
+        (frame.kind, frame.flags, frame.payload.len())
+    );
+    client
+        .tcp_stream
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    assert_eq!(
+        ErrorKind::WouldBlock,
+        client.tcp_stream.read(&mut [0_u8; 1]).unwrap_err().kind()
+    );
+    client
+        .tcp_stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    client.send(WINDOW_UPDATE, 0, 1, &100_u32.to_be_bytes());
+    let frame = client.read_frame();
+    assert_eq!(
+        (DATA, END_STREAM, 15),
+        (frame.kind, frame.flags, frame.payload.len())
+    );
+}
+
+#[test]
+fn upgrade() {
+    let server = echo_server();
+    let mut client = Client::new(
+        server
+            .connect_and_send(
+                "GET /c HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n",
+            )
+            .unwrap(),
+    );
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0_u8; 1];
+        client.tcp_stream.read_exact(&mut byte).unwrap();
+        head.extend(byte);
+    }
+    assert_eq!(
+        "HTTP/1.1 101 Switching Protocols\r\nupgrade: h2c\r\nconnection: upgrade\r\n\r\n",
+        String::from_utf8(head).unwrap()
+    );
+    client.tcp_stream.write_all(HTTP2_PREFACE).unwrap();
+    client.send(SETTINGS, 0, 0, &[]);
+    // The response to the upgrade request may arrive before the SETTINGS ACK.
+    assert_eq!("GET /c example.com ", client.read_response(1).body);
+    client.send_headers(3, &get("/d"), true);
+    assert_eq!("GET /d example.com ", client.read_response(3).body);
+}
+
+#[test]
+fn upgrade_disabled_by_default() {
+    let server = TestServer::start(|_req| Response::text(200, "http/1.1")).unwrap();
+    let reply = server
+        .exchange(
+            "GET / HTTP/1.1\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n",
+        )
+        .unwrap();
+    assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"), "{reply:?}");
+    let reply = server.exchange(HTTP2_PREFACE).unwrap();
+    assert!(!reply.starts_with("HTTP/1.1 2"), "{reply:?}");
+}
+
+#[test]
+fn ping() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    client.send(PING, 0, 0, b"12345678");
+    assert_eq!(
+        Frame {
+            kind: PING,
+            flags: ACK,
+            stream_id: 0,
+            payload: b"12345678".to_vec()
+        },
+        client.read_frame()
+    );
+}
+
+#[test]
+fn malformed_request() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    let mut headers = get("/");
+    headers.push(("Upper-Case", "x"));
+    client.send_headers(1, &headers, true);
+    assert_eq!(
+        Frame {
+            kind: RST_STREAM,
+            flags: 0,
+            stream_id: 1,
+            // PROTOCOL_ERROR
+            payload: 1_u32.to_be_bytes().to_vec()
+        },
+        client.read_frame()
+    );
+    // The connection still works.
+    client.send_headers(3, &get("/"), true);
+    assert_eq!("GET / example.com ", client.read_response(3).body);
+}
+
+#[test]
+fn header_list_too_long() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    let long = "a".repeat(20_000);
+    let mut headers = get("/");
+    headers.push(("x-long", &long));
+    client.send_headers(1, &headers, true);
+    let response = client.read_response(1);
+    assert_eq!(
+        (":status".to_string(), "431".to_string()),
+        response.headers[0]
+    );
+}
+
+#[test]
+fn protocol_error() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    client.send_headers(1, &get("/"), true);
+    client.read_response(1);
+    // DATA on stream 0.
+    client.send(DATA, 0, 0, b"x");
+    let frame = client.read_frame();
+    assert_eq!(GOAWAY, frame.kind);
+    // Last stream ID 1, PROTOCOL_ERROR.
+    assert_eq!([0, 0, 0, 1, 0, 0, 0, 1], frame.payload[..8]);
+    client.assert_closed();
+}
+
+#[test]
+fn settings_flood() {
+    let server = echo_server();
+    let mut client = Client::connect(&server, &[]);
+    // Send SETTINGS frames without reading the acks, until the server stops reading.
+    let mut writer = client.tcp_stream.try_clone().unwrap();
+    writer
+        .set_write_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let batch: Vec<u8> = [0, 0, 0, SETTINGS, 0, 0, 0, 0, 0].repeat(1000);
+    while writer.write_all(&batch).is_ok() {}
+    loop {
+        let frame = client.read_frame();
+        if frame.kind == SETTINGS {
+            continue;
+        }
+        assert_eq!(GOAWAY, frame.kind);
+        // Last stream ID 0, ENHANCE_YOUR_CALM.
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 11], frame.payload[..8]);
+        break;
+    }
+    client.assert_closed();
+}
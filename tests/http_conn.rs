@@ -16,7 +16,7 @@ const MILLIS_100: Duration = Duration::from_millis(100);
 
 async fn handle_http_conn_task<F, Fut>(request_handler: F) -> async_net::TcpStream
 where
-    Fut: Future<Output = Response> + Send,
+    Fut: Future<Output = Response> + Send + 'static,
     F: FnOnce(Request) -> Fut + 'static + Send + Sync + Clone,
 {
     let (stream0, stream1) = connected_streams().await;
@@ -26,7 +26,7 @@ where
         handle_http_conn(
             Permit::new(),
             Token::new(),
-            HttpConn::new(addr, stream0),
+            HttpConn::new(addr.into(), stream0.into()),
             Some(temp_dir.path().to_path_buf()),
             64 * 1024,
             request_handler,
@@ -0,0 +1,74 @@
+use servlin::IpCidr;
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn cidr(s: &str) -> IpCidr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn parse() {
+    assert_eq!(IpCidr::new(ip("10.0.0.0"), 8).unwrap(), cidr("10.0.0.0/8"));
+    assert_eq!(IpCidr::new(ip("10.0.0.0"), 8).unwrap(), cidr("10.1.2.3/8"));
+    assert_eq!(IpCidr::single(ip("10.1.2.3")), cidr("10.1.2.3"));
+    assert_eq!(IpCidr::single(ip("10.1.2.3")), cidr("10.1.2.3/32"));
+    assert_eq!(IpCidr::new(ip("fd00::"), 8).unwrap(), cidr("fd12::1/8"));
+    assert_eq!(IpCidr::single(ip("::1")), cidr("::1"));
+    assert_eq!(0, cidr("0.0.0.0/0").prefix_len());
+    assert_eq!(ip("10.0.0.0"), cidr("10.1.2.3/8").addr());
+    assert_eq!("10.0.0.0/8", cidr("10.1.2.3/8").to_string());
+    assert_eq!("fd00::/8", cidr("fd12::1/8").to_string());
+    assert_eq!("::1/128", cidr("::1").to_string());
+    // IPv4-mapped addresses become IPv4.
+    assert_eq!(cidr("10.0.0.0/8"), cidr("::ffff:10.1.2.3/104"));
+    assert_eq!(cidr("10.1.2.3"), cidr("::ffff:10.1.2.3"));
+    assert_eq!(cidr("10.1.2.3"), IpCidr::single(ip("::ffff:10.1.2.3")));
+    assert_eq!(cidr("0.0.0.0/0"), cidr("::ffff:0.0.0.0/96"));
+    // Shorter prefixes stay IPv6.
+    assert_eq!("::fffe:0:0/95", cidr("::ffff:0.0.0.0/95").to_string());
+    for s in [
+        "",
+        "/8",
+        "10.0.0.0/",
+        "10.0.0.0/33",
+        "10.0.0.0/+8",
+        "10.0.0.0/ 8",
+        "10.0.0/8",
+        "::/129",
+        "::/1000",
+        "a/8",
+    ] {
+        s.parse::<IpCidr>().unwrap_err();
+    }
+    IpCidr::new(ip("10.0.0.0"), 33).unwrap_err();
+    IpCidr::new(ip("::"), 129).unwrap_err();
+}
+
+#[test]
+fn contains() {
+    let private = cidr("10.0.0.0/8");
+    assert!(private.contains(ip("10.0.0.0")));
+    assert!(private.contains(ip("10.255.255.255")));
+    assert!(!private.contains(ip("11.0.0.0")));
+    assert!(!private.contains(ip("9.255.255.255")));
+    assert!(private.contains(ip("::ffff:10.1.2.3")));
+    assert!(!private.contains(ip("::ffff:11.1.2.3")));
+    assert!(!private.contains(ip("a00::")));
+    let mapped = cidr("::ffff:10.0.0.0/104");
+    assert!(mapped.contains(ip("10.1.2.3")));
+    assert!(mapped.contains(ip("::ffff:10.1.2.3")));
+    assert!(!mapped.contains(ip("::ffff:11.1.2.3")));
+    assert!(cidr("::ffff:1.2.3.4").contains(ip("1.2.3.4")));
+    assert!(cidr("0.0.0.0/0").contains(ip("1.2.3.4")));
+    assert!(!cidr("0.0.0.0/0").contains(ip("2001:db8::1")));
+    assert!(cidr("::/0").contains(ip("2001:db8::1")));
+    assert!(cidr("1.2.3.4").contains(ip("1.2.3.4")));
+    assert!(!cidr("1.2.3.4").contains(ip("1.2.3.5")));
+    let ula = cidr("fc00::/7");
+    assert!(ula.contains(ip("fc00::1")));
+    assert!(ula.contains(ip("fdff::1")));
+    assert!(!ula.contains(ip("fe00::1")));
+}
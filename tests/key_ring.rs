@@ -0,0 +1,177 @@
+use crate::test_util::TestServer;
+use servlin::{Cookie, KeyRing, Request, Response};
+use std::time::{Duration, SystemTime};
+
+mod test_util;
+
+const SECRET1: [u8; 32] = [1; 32];
+const SECRET2: [u8; 32] = [2; 32];
+
+#[test]
+fn sign() {
+    let keys = KeyRing::new(SECRET1);
+    let signed = keys.sign("name1", "value 1 é", None);
+    assert!(signed.starts_with("dmFsdWUgMSDDqQ.0."), "{signed:?}");
+    assert_eq!(keys.verify("name1", &signed).as_deref(), Some("value 1 é"));
+    // Signatures are for one cookie name.
+    assert_eq!(keys.verify("name2", &signed), None);
+    let (payload, mac) = signed.rsplit_once('.').unwrap();
+    for bad in [
+        "",
+        "abc",
+        payload,
+        &format!("{payload}."),
+        &format!("dmFsdWUgMg.0.{mac}"),
+        &format!("{payload}.{}", &mac[1..]),
+        &format!("{payload}.{mac}A"),
+    ] {
+        assert_eq!(keys.verify("name1", bad), None, "{bad:?}");
+    }
+    assert_eq!(KeyRing::new(SECRET2).verify("name1", &signed), None);
+}
+
+#[test]
+fn encrypt() {
+    let keys = KeyRing::new(SECRET1);
+    let encrypted = keys.encrypt("name1", "value 1 é", None);
+    assert!(!encrypted.contains("dmFsdWU"), "{encrypted:?}");
+    // Each encryption uses a new nonce.
+    assert_ne!(encrypted, keys.encrypt("name1", "value 1 é", None));
+    assert_eq!(
+        keys.decrypt("name1", &encrypted).as_deref(),
+        Some("value 1 é")
+    );
+    assert_eq!(keys.decrypt("name2", &encrypted), None);
+    assert_eq!(KeyRing::new(SECRET2).decrypt("name1", &encrypted), None);
+    let mut tampered = encrypted.clone().into_bytes();
+    tampered[20] = if tampered[20] == b'A' { b'B' } else { b'A' };
+    for bad in [
+        "",
+        "abc",
+        &encrypted[1..],
+        &encrypted[..encrypted.len() - 1],
+        &String::from_utf8(tampered).unwrap(),
+    ] {
+        assert_eq!(keys.decrypt("name1", bad), None, "{bad:?}");
+    }
+    assert_eq!(
+        keys.decrypt("name1", &keys.encrypt("name1", "", None))
+            .as_deref(),
+        Some("")
+    );
+}
+
+#[test]
+fn rotation() {
+    let old_keys = KeyRing::new(SECRET1);
+    let old_signed = old_keys.sign("name1", "old", None);
+    let old_encrypted = old_keys.encrypt("name1", "old", None);
+    let keys = KeyRing::new(SECRET2).with_old_key(SECRET1);
+    assert_eq!(keys.verify("name1", &old_signed).as_deref(), Some("old"));
+    assert_eq!(
+        keys.decrypt("name1", &old_encrypted).as_deref(),
+        Some("old")
+    );
+    // The first key makes new values.
+    let signed = keys.sign("name1", "new", None);
+    assert_eq!(old_keys.verify("name1", &signed), None);
+    assert_eq!(
+        KeyRing::new(SECRET2).verify("name1", &signed).as_deref(),
+        Some("new")
+    );
+    let encrypted = keys.encrypt("name1", "new", None);
+    assert_eq!(old_keys.decrypt("name1", &encrypted), None);
+    assert_eq!(
+        KeyRing::new(SECRET2)
+            .decrypt("name1", &encrypted)
+            .as_deref(),
+        Some("new")
+    );
+}
+
+#[test]
+fn expiration() {
+    let keys = KeyRing::new(SECRET1);
+    let future = Some(SystemTime::now() + Duration::from_mins(1));
+    let past = Some(SystemTime::now() - Duration::from_secs(1));
+    assert!(keys.verify("a", &keys.sign("a", "v", future)).is_some());
+    assert!(keys.verify("a", &keys.sign("a", "v", past)).is_none());
+    assert!(keys.decrypt("a", &keys.encrypt("a", "v", future)).is_some());
+    assert!(keys.decrypt("a", &keys.encrypt("a", "v", past)).is_none());
+    // Clients cannot change the expiration time.
+    let signed = keys.sign("a", "v", past);
+    let (payload, mac) = signed.rsplit_once('.').unwrap();
+    let (value, _expires) = payload.split_once('.').unwrap();
+    assert!(keys.verify("a", &format!("{value}.0.{mac}")).is_none());
+}
+
+#[test]
+#[should_panic(expected = "KeyRing secret must have at least 32 bytes")]
+fn short_secret() {
+    let _keys = KeyRing::new([0_u8; 31]);
+}
+
+#[test]
+fn debug() {
+    let keys = KeyRing::new(SECRET1).with_old_key(SECRET2);
+    assert_eq!(format!("{keys:?}"), "KeyRing{2 keys}");
+    assert_ne!(KeyRing::generate_secret(), KeyRing::generate_secret());
+}
+
+#[test]
+fn cookies() {
+    let keys = KeyRing::new(SECRET1);
+    let cookie = Cookie::new("user", "alice".try_into().unwrap())
+        .with_max_age(Duration::from_mins(1))
+        .signed(&keys)
+        .to_string();
+    let (value, attributes) = cookie
+        .strip_prefix("user=")
+        .unwrap()
+        .split_once(';')
+        .unwrap();
+    assert_eq!(attributes, " HttpOnly; Max-Age=60; SameSite=Strict; Secure");
+    let expires: u64 = value.split('.').nth(1).unwrap().parse().unwrap();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!((now + 58..=now + 61).contains(&expires), "{expires} {now}");
+    let encrypted_cookie = Cookie::new("flash", "Saved.".try_into().unwrap())
+        .encrypted(&keys)
+        .to_string();
+    let encrypted = encrypted_cookie
+        .strip_prefix("flash=")
+        .unwrap()
+        .split_once(';')
+        .unwrap()
+        .0;
+
+    let server_keys = keys.clone();
+    let server = TestServer::start(move |req: Request| {
+        Response::text(
+            200,
+            format!(
+                "{:?} {:?}",
+                req.signed_cookie("user", &server_keys),
+                req.encrypted_cookie("flash", &server_keys)
+            ),
+        )
+    })
+    .unwrap();
+    let body = |cookie: &str| {
+        let response = server
+            .exchange(format!("GET / HTTP/1.1\r\ncookie: {cookie}\r\n\r\n"))
+            .unwrap();
+        response.split_once("\r\n\r\n").unwrap().1.to_string()
+    };
+    assert_eq!(
+        body(&format!("user={value}; flash={encrypted}")),
+        "Some(\"alice\") Some(\"Saved.\")"
+    );
+    assert_eq!(
+        body(&format!("user={encrypted}; flash={value}")),
+        "None None"
+    );
+    assert_eq!(body("user=alice; flash=Saved."), "None None");
+}
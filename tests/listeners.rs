@@ -0,0 +1,150 @@
+use crate::test_util::read_response;
+use permit::Permit;
+use safina::executor::Executor;
+use servlin::{
+    HttpServerBuilder, ListenAddr, Request, Response, socket_addr_127_0_0_1,
+    socket_addr_127_0_0_1_any_port,
+};
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+mod test_util;
+
+#[allow(clippy::needless_pass_by_value)]
+fn echo_listener(req: Request) -> Response {
+    Response::text(200, format!("{:?}", req.listener))
+}
+
+fn get(addr: SocketAddr) -> String {
+    let mut tcp_stream = std::net::TcpStream::connect(addr).unwrap();
+    tcp_stream
+        .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+        .unwrap();
+    read_response(&mut tcp_stream).unwrap()
+}
+
+#[test]
+fn tagged_listeners() {
+    safina::timer::start_timer_thread();
+    let permit = Permit::new();
+    let executor = Executor::new(1, 1).unwrap();
+    let (addrs, stopped_receiver) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .add_listen_addr(socket_addr_127_0_0_1_any_port())
+                .add_tagged_listen_addr(socket_addr_127_0_0_1_any_port(), "admin")
+                .permit(permit.new_sub())
+                .spawn_listeners(echo_listener),
+        )
+        .unwrap();
+    let addrs: Vec<SocketAddr> = addrs.iter().filter_map(ListenAddr::socket_addr).collect();
+    assert_eq!(2, addrs.len());
+    assert_ne!(addrs[0], addrs[1]);
+    assert_eq!(
+        get(addrs[0]),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 4\r\n\r\nNone",
+    );
+    assert_eq!(
+        get(addrs[1]),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 13\r\n\r\nSome(\"admin\")",
+    );
+    drop(permit);
+    stopped_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    std::net::TcpStream::connect(addrs[0]).unwrap_err();
+    std::net::TcpStream::connect(addrs[1]).unwrap_err();
+}
+
+#[test]
+fn listen_addr_replaces_others() {
+    safina::timer::start_timer_thread();
+    let executor = Executor::new(1, 1).unwrap();
+    let (addrs, _stopped_receiver) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .add_tagged_listen_addr(socket_addr_127_0_0_1_any_port(), "a")
+                .add_tagged_listen_addr(socket_addr_127_0_0_1_any_port(), "b")
+                .listen_addr(socket_addr_127_0_0_1_any_port())
+                .spawn_listeners(echo_listener),
+        )
+        .unwrap();
+    let addrs: Vec<SocketAddr> = addrs.iter().filter_map(ListenAddr::socket_addr).collect();
+    assert_eq!(1, addrs.len());
+    assert_eq!(
+        get(addrs[0]),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 4\r\n\r\nNone",
+    );
+}
+
+#[test]
+fn listeners_share_max_conns() {
+    safina::timer::start_timer_thread();
+    let executor = Executor::new(1, 2).unwrap();
+    let (addrs, _stopped_receiver) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .add_listen_addr(socket_addr_127_0_0_1_any_port())
+                .add_listen_addr(socket_addr_127_0_0_1_any_port())
+                .max_conns(1)
+                .spawn_listeners(|_req| Response::text(200, "ok")),
+        )
+        .unwrap();
+    let addrs: Vec<SocketAddr> = addrs.iter().filter_map(ListenAddr::socket_addr).collect();
+    let mut conn1 = std::net::TcpStream::connect(addrs[0]).unwrap();
+    conn1.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    read_response(&mut conn1).unwrap();
+    let mut conn2 = std::net::TcpStream::connect(addrs[1]).unwrap();
+    conn2.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    conn2
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let mut buf = [0_u8; 1];
+    let result = conn2.read(&mut buf);
+    assert!(result.is_err(), "{result:?}");
+    conn1.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(
+        read_response(&mut conn2).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\r\nok",
+    );
+}
+
+#[test]
+fn ipv4_and_ipv6_on_same_port() {
+    let ipv6_localhost = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0);
+    let Ok(probe) = std::net::TcpListener::bind(ipv6_localhost) else {
+        // This machine has no IPv6.
+        return;
+    };
+    drop(probe);
+    safina::timer::start_timer_thread();
+    let executor = Executor::new(1, 1).unwrap();
+    let port = std::net::TcpListener::bind(socket_addr_127_0_0_1_any_port())
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let (addrs, _stopped_receiver) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .add_tagged_listen_addr(socket_addr_127_0_0_1(port), "v4")
+                .add_tagged_listen_addr(
+                    SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port),
+                    "v6",
+                )
+                .spawn_listeners(echo_listener),
+        )
+        .unwrap();
+    let addrs: Vec<SocketAddr> = addrs.iter().filter_map(ListenAddr::socket_addr).collect();
+    assert_eq!(port, addrs[0].port());
+    assert_eq!(port, addrs[1].port());
+    assert_eq!(
+        get(socket_addr_127_0_0_1(port)),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 10\r\n\r\nSome(\"v4\")",
+    );
+    assert_eq!(
+        get(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), port)),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 10\r\n\r\nSome(\"v6\")",
+    );
+}
@@ -0,0 +1,222 @@
+mod test_util;
+
+use crate::test_util::{TestServer, assert_starts_with};
+use servlin::internal::parse_header_params;
+use servlin::{ContentType, MultipartParser, RequestBody, Response};
+use std::io::Read;
+use temp_dir::TempDir;
+
+const BODY: &str = "preamble\r\n--b1\r\ncontent-disposition: form-data; name=\"field1\"\r\n\r\nvalue1\r\n--b1\r\nContent-Disposition: form-data; name=\"file1\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nline1\r\nline2\r\n\r\n--b1--\r\nepilogue";
+
+fn req(body: &str) -> String {
+    format!(
+        "M / HTTP/1.1\r\ncontent-type: multipart/form-data; boundary=b1\r\ncontent-length: {}\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[test]
+fn header_params() {
+    assert_eq!(
+        vec![
+            ("name".to_string(), "a;b \"c\"".to_string()),
+            ("filename".to_string(), "x.txt".to_string()),
+            ("other".to_string(), String::new()),
+        ],
+        parse_header_params("form-data; Name=\"a;b \\\"c\\\"\" ;filename=x.txt; other")
+    );
+    assert!(parse_header_params("form-data").is_empty());
+}
+
+#[test]
+fn parse_reader() {
+    let cache_dir = TempDir::new().unwrap();
+    let form = MultipartParser::new(cache_dir.path())
+        .parse_reader(BODY.as_bytes(), b"b1")
+        .unwrap();
+    assert_eq!(2, form.parts.len());
+    let field1 = form.get("field1").unwrap();
+    assert_eq!(None, field1.filename);
+    assert_eq!(ContentType::None, field1.content_type);
+    assert_eq!(Some("value1"), form.text("field1"));
+    let file1 = form.get("file1").unwrap();
+    assert_eq!(Some("a.txt"), file1.filename.as_deref());
+    assert_eq!(ContentType::PlainText, file1.content_type);
+    assert_eq!(None, file1.text());
+    assert!(matches!(file1.body, RequestBody::TempFile(.., 14)));
+    let mut contents = String::new();
+    file1
+        .body
+        .reader()
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!("line1\r\nline2\r\n", contents);
+    assert_eq!(
+        vec!["file1"],
+        form.files().map(|p| p.name.as_str()).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn parse_reader_errors() {
+    let cache_dir = TempDir::new().unwrap();
+    let parser = MultipartParser::new(cache_dir.path());
+    for body in [
+        "",
+        "--b1",
+        "--b1\r\ncontent-disposition: form-data; name=\"a\"\r\n\r\nvalue",
+        "--b1\r\ncontent-disposition: form-data\r\n\r\nvalue\r\n--b1--",
+        "--b1\r\ncontent-type: text/plain\r\n\r\nvalue\r\n--b1--",
+        "--b1\r\nbad header\r\n\r\nvalue\r\n--b1--",
+        "--b1garbage\r\n\r\n--b1--",
+    ] {
+        assert_eq!(
+            400,
+            parser
+                .parse_reader(body.as_bytes(), b"b1")
+                .unwrap_err()
+                .code,
+            "{body:?}"
+        );
+    }
+    assert!(
+        parser
+            .parse_reader("--b1--".as_bytes(), b"b1")
+            .unwrap()
+            .parts
+            .is_empty()
+    );
+}
+
+#[test]
+fn part_without_headers() {
+    let cache_dir = TempDir::new().unwrap();
+    let response = MultipartParser::new(cache_dir.path())
+        .parse_reader("--b1\r\n\r\nvalue\r\n--b1--".as_bytes(), b"b1")
+        .unwrap_err();
+    assert_eq!(400, response.code);
+    let server = TestServer::start(|req| {
+        let cache_dir = TempDir::new().unwrap();
+        match req.multipart(cache_dir.path()) {
+            Ok(_form) => Response::ok_200(),
+            Err(response) => response,
+        }
+    })
+    .unwrap();
+    assert_starts_with(
+        server.exchange(req("--b1\r\n\r\nvalue\r\n--b1--")).unwrap(),
+        "HTTP/1.1 400 Bad Request\r\n",
+    );
+}
+
+#[test]
+fn limits() {
+    let cache_dir = TempDir::new().unwrap();
+    let parser = MultipartParser::new(cache_dir.path());
+    assert_eq!(
+        413,
+        parser
+            .clone()
+            .max_text_len(5)
+            .parse_reader(BODY.as_bytes(), b"b1")
+            .unwrap_err()
+            .code
+    );
+    parser
+        .clone()
+        .max_text_len(6)
+        .parse_reader(BODY.as_bytes(), b"b1")
+        .unwrap();
+    assert_eq!(
+        413,
+        parser
+            .clone()
+            .max_file_len(13)
+            .parse_reader(BODY.as_bytes(), b"b1")
+            .unwrap_err()
+            .code
+    );
+    assert_eq!(
+        413,
+        parser
+            .clone()
+            .max_total_len(19)
+            .parse_reader(BODY.as_bytes(), b"b1")
+            .unwrap_err()
+            .code
+    );
+    parser
+        .clone()
+        .max_total_len(20)
+        .parse_reader(BODY.as_bytes(), b"b1")
+        .unwrap();
+    assert_eq!(
+        413,
+        parser
+            .clone()
+            .max_parts(1)
+            .parse_reader(BODY.as_bytes(), b"b1")
+            .unwrap_err()
+            .code
+    );
+}
+
+#[test]
+fn request_multipart() {
+    let server = TestServer::start(|req| {
+        let cache_dir = TempDir::new().unwrap();
+        match req.multipart(cache_dir.path()) {
+            Ok(form) => Response::text(
+                200,
+                form.iter()
+                    .map(|part| format!("{}:{:?}", part.name, part.body.len()))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            Err(response) => response,
+        }
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange(req(BODY)).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 29\r\n\r\nfield1:Some(6),file1:Some(14)",
+    );
+    assert_starts_with(
+        server
+            .exchange("M / HTTP/1.1\r\ncontent-type: multipart/form-data\r\ncontent-length: 6\r\n\r\n--b1--")
+            .unwrap(),
+        "HTTP/1.1 400 Bad Request\r\n",
+    );
+    assert_starts_with(
+        server
+            .exchange("M / HTTP/1.1\r\ncontent-type: text/plain\r\ncontent-length: 6\r\n\r\n--b1--")
+            .unwrap(),
+        "HTTP/1.1 400 Bad Request\r\n",
+    );
+}
+
+#[cfg(feature = "urlencoded")]
+#[test]
+fn deserialize() {
+    #[derive(serde::Deserialize)]
+    struct Input {
+        num: usize,
+        msg: String,
+    }
+    let cache_dir = TempDir::new().unwrap();
+    let body = "--b1\r\ncontent-disposition: form-data; name=\"num\"\r\n\r\n123\r\n--b1\r\ncontent-disposition: form-data; name=\"msg\"\r\n\r\na&b=c\r\n--b1\r\ncontent-disposition: form-data; name=\"f\"; filename=\"f\"\r\n\r\nfile\r\n--b1--";
+    let form = MultipartParser::new(cache_dir.path())
+        .parse_reader(body.as_bytes(), b"b1")
+        .unwrap();
+    let input: Input = form.deserialize().unwrap();
+    assert_eq!(123, input.num);
+    assert_eq!("a&b=c", input.msg);
+    let form = MultipartParser::new(cache_dir.path())
+        .parse_reader(
+            "--b1\r\ncontent-disposition: form-data; name=\"num\"\r\n\r\nx\r\n--b1--".as_bytes(),
+            b"b1",
+        )
+        .unwrap();
+    assert_eq!(400, form.deserialize::<Input>().err().unwrap().code);
+}
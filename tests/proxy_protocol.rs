@@ -0,0 +1,297 @@
+use crate::test_util::{TestServer, assert_starts_with};
+use servlin::internal::{MAX_PROXY_HEADER_LEN, ProxyHeader, ProxyHeaderError};
+use servlin::{HttpServerBuilder, IpCidr, Request, Response};
+use std::net::SocketAddr;
+
+mod test_util;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+}
+
+fn v2(command: u8, family: u8, addrs: &[u8]) -> Vec<u8> {
+    let mut bytes = V2_SIGNATURE.to_vec();
+    bytes.push(0x20 | command);
+    bytes.push(family);
+    bytes.extend(u16::try_from(addrs.len()).unwrap().to_be_bytes());
+    bytes.extend(addrs);
+    bytes
+}
+
+fn v2_tcp4() -> Vec<u8> {
+    v2(0x1, 0x11, &[1, 2, 3, 4, 5, 6, 7, 8, 0x16, 0x2E, 0, 80])
+}
+
+#[test]
+fn parse_v1() {
+    assert_eq!(
+        Ok((
+            ProxyHeader {
+                source: Some(addr("1.2.3.4:5678")),
+                destination: Some(addr("5.6.7.8:80")),
+            },
+            36
+        )),
+        ProxyHeader::parse(b"PROXY TCP4 1.2.3.4 5.6.7.8 5678 80\r\nGET / HTTP/1.1\r\n")
+    );
+    assert_eq!(
+        Ok((
+            ProxyHeader {
+                source: Some(addr("[2001:db8::1]:5678")),
+                destination: Some(addr("[2001:db8::2]:443")),
+            },
+            45
+        )),
+        ProxyHeader::parse(b"PROXY TCP6 2001:db8::1 2001:db8::2 5678 443\r\n")
+    );
+    assert_eq!(
+        Ok((
+            ProxyHeader {
+                source: None,
+                destination: None,
+            },
+            15
+        )),
+        ProxyHeader::parse(b"PROXY UNKNOWN\r\n")
+    );
+    assert_eq!(
+        Ok((
+            ProxyHeader {
+                source: None,
+                destination: None,
+            },
+            47
+        )),
+        ProxyHeader::parse(b"PROXY UNKNOWN ffff:f...f:ffff ffff:f...f:ffff\r\n")
+    );
+    for truncated in [
+        b"".as_slice(),
+        b"P",
+        b"PROXY",
+        b"PROXY ",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 5678 80",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 5678 80\r",
+    ] {
+        assert_eq!(
+            Err(ProxyHeaderError::Truncated),
+            ProxyHeader::parse(truncated),
+            "{}",
+            truncated.escape_ascii()
+        );
+    }
+    for malformed in [
+        b"GET / HTTP/1.1\r\n".as_slice(),
+        b"proxy TCP4 1.2.3.4 5.6.7.8 5678 80\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 5678\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 5678 80 \r\n",
+        b"PROXY TCP4  1.2.3.4 5.6.7.8 5678 80\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 5678 65536\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 05678 80\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 +5678 80\r\n",
+        b"PROXY TCP4 2001:db8::1 5.6.7.8 5678 80\r\n",
+        b"PROXY TCP6 1.2.3.4 5.6.7.8 5678 80\r\n",
+        b"PROXY UDP4 1.2.3.4 5.6.7.8 5678 80\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 5678 80\n",
+    ] {
+        assert_eq!(
+            Err(ProxyHeaderError::Malformed),
+            ProxyHeader::parse(malformed),
+            "{}",
+            malformed.escape_ascii()
+        );
+    }
+    let too_long = format!("PROXY UNKNOWN {}\r\n", "a".repeat(100));
+    assert_eq!(
+        Err(ProxyHeaderError::TooLong),
+        ProxyHeader::parse(too_long.as_bytes())
+    );
+}
+
+#[test]
+fn parse_v2() {
+    let mut bytes = v2_tcp4();
+    bytes.extend(b"GET");
+    assert_eq!(
+        Ok((
+            ProxyHeader {
+                source: Some(addr("1.2.3.4:5678")),
+                destination: Some(addr("5.6.7.8:80")),
+            },
+            28
+        )),
+        ProxyHeader::parse(&bytes)
+    );
+    let mut addrs = Vec::new();
+    addrs.extend(
+        "2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    addrs.extend(
+        "2001:db8::2"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    addrs.extend([0x16, 0x2E, 0x01, 0xBB]);
+    // TLVs after the addresses
+    addrs.extend([0x04, 0x00, 0x01, 0x00]);
+    assert_eq!(
+        Ok((
+            ProxyHeader {
+                source: Some(addr("[2001:db8::1]:5678")),
+                destination: Some(addr("[2001:db8::2]:443")),
+            },
+            56
+        )),
+        ProxyHeader::parse(&v2(0x1, 0x21, &addrs))
+    );
+    let no_addrs = ProxyHeader {
+        source: None,
+        destination: None,
+    };
+    // LOCAL
+    assert_eq!(Ok((no_addrs, 16)), ProxyHeader::parse(&v2(0x0, 0x00, &[])));
+    assert_eq!(
+        Ok((no_addrs, 28)),
+        ProxyHeader::parse(&v2(0x0, 0x11, &[0; 12]))
+    );
+    // UNSPEC, UDP, and UNIX
+    assert_eq!(Ok((no_addrs, 16)), ProxyHeader::parse(&v2(0x1, 0x00, &[])));
+    assert_eq!(
+        Ok((no_addrs, 28)),
+        ProxyHeader::parse(&v2(0x1, 0x12, &[0; 12]))
+    );
+    assert_eq!(
+        Ok((no_addrs, 232)),
+        ProxyHeader::parse(&v2(0x1, 0x31, &[0; 216]))
+    );
+    let header = v2_tcp4();
+    for n in 0..header.len() {
+        assert_eq!(
+            Err(ProxyHeaderError::Truncated),
+            ProxyHeader::parse(&header[..n]),
+            "{n}"
+        );
+    }
+    // Version 1
+    let mut bytes = v2_tcp4();
+    bytes[12] = 0x11;
+    assert_eq!(Err(ProxyHeaderError::Malformed), ProxyHeader::parse(&bytes));
+    // Unknown command
+    assert_eq!(
+        Err(ProxyHeaderError::Malformed),
+        ProxyHeader::parse(&v2(0x2, 0x11, &[0; 12]))
+    );
+    // Addresses too short
+    assert_eq!(
+        Err(ProxyHeaderError::Malformed),
+        ProxyHeader::parse(&v2(0x1, 0x11, &[0; 11]))
+    );
+    assert_eq!(
+        Err(ProxyHeaderError::Malformed),
+        ProxyHeader::parse(&v2(0x1, 0x21, &[0; 35]))
+    );
+    assert_eq!(
+        Err(ProxyHeaderError::TooLong),
+        ProxyHeader::parse(&v2(0x1, 0x11, &vec![0; MAX_PROXY_HEADER_LEN]))
+    );
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn echo_addrs(req: Request) -> Response {
+    Response::text(
+        200,
+        format!("{} {:?}", req.remote_addr, req.local_addr.map(|a| a.port())),
+    )
+}
+
+fn trust_localhost(builder: HttpServerBuilder) -> HttpServerBuilder {
+    builder.proxy_protocol(["127.0.0.0/8".parse::<IpCidr>().unwrap()])
+}
+
+#[test]
+fn trusted_v1() {
+    let server = TestServer::start_with(trust_localhost, echo_addrs).unwrap();
+    assert_eq!(
+        server
+            .exchange(
+                "PROXY TCP4 1.2.3.4 5.6.7.8 5678 80\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"
+            )
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 21\r\n\r\n1.2.3.4:5678 Some(80)\
+        HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 21\r\n\r\n1.2.3.4:5678 Some(80)",
+    );
+}
+
+#[test]
+fn trusted_v2() {
+    let server = TestServer::start_with(trust_localhost, echo_addrs).unwrap();
+    let mut bytes = v2_tcp4();
+    bytes.extend(b"GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(
+        server.exchange(bytes).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 21\r\n\r\n1.2.3.4:5678 Some(80)",
+    );
+}
+
+#[test]
+fn trusted_local() {
+    let server = TestServer::start_with(trust_localhost, echo_addrs).unwrap();
+    let port = server.addr.port();
+    // Health checks use LOCAL and UNKNOWN, so the server uses the connection's addresses.
+    let mut bytes = v2(0x0, 0x00, &[]);
+    bytes.extend(b"GET / HTTP/1.1\r\n\r\n");
+    let response = server.exchange(bytes).unwrap();
+    assert!(
+        response.ends_with(&format!(" Some({port})")),
+        "{response:?}"
+    );
+    assert!(response.contains("\r\n\r\n127.0.0.1:"), "{response:?}");
+    let response = server
+        .exchange("PROXY UNKNOWN\r\nGET / HTTP/1.1\r\n\r\n")
+        .unwrap();
+    assert!(
+        response.ends_with(&format!(" Some({port})")),
+        "{response:?}"
+    );
+}
+
+#[test]
+fn trusted_without_header() {
+    let server = TestServer::start_with(trust_localhost, echo_addrs).unwrap();
+    assert_eq!(server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(), "");
+    assert_eq!(
+        server
+            .exchange("PROXY TCP4 1.2.3.4 5.6.7.8 5678\r\nGET / HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        ""
+    );
+    assert_eq!(server.exchange("PROXY TCP4 1.2.3.4").unwrap(), "");
+}
+
+#[test]
+fn untrusted() {
+    let server = TestServer::start_with(
+        |builder| builder.proxy_protocol(["10.0.0.0/8".parse::<IpCidr>().unwrap()]),
+        echo_addrs,
+    )
+    .unwrap();
+    let port = server.addr.port();
+    let response = server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap();
+    assert!(response.contains("\r\n\r\n127.0.0.1:"), "{response:?}");
+    assert!(
+        response.ends_with(&format!(" Some({port})")),
+        "{response:?}"
+    );
+    // An untrusted client cannot choose its address.
+    assert_starts_with(
+        server
+            .exchange("PROXY TCP4 1.2.3.4 5.6.7.8 5678 80\r\nGET / HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 400 Bad Request\r\n",
+    );
+}
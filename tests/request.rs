@@ -4,7 +4,7 @@ use fixed_buffer::FixedBuf;
 use futures_lite::AsyncWriteExt;
 use safina::async_test;
 use safina::sync::Receiver;
-use servlin::internal::{HttpError, read_http_request};
+use servlin::internal::{HeadOptions, HttpError, read_http_request};
 use servlin::{AsciiString, ContentType, Request, RequestBody};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::time::Duration;
@@ -17,7 +17,13 @@ fn addr1() -> SocketAddr {
 async fn call_read(b: impl AsRef<[u8]>) -> Result<Request, HttpError> {
     let mut buf: FixedBuf<1000> = FixedBuf::new();
     std::io::Write::write_all(&mut buf, b.as_ref()).unwrap();
-    read_http_request(addr1(), &mut buf, <FixedBuf<0>>::new()).await
+    read_http_request(
+        addr1().into(),
+        &mut buf,
+        <FixedBuf<0>>::new(),
+        &HeadOptions::default(),
+    )
+    .await
 }
 
 #[async_test]
@@ -25,7 +31,7 @@ async fn head() {
     let req = call_read("M /1 HTTP/1.1\r\nHeader1: Val1\r\n\r\n")
         .await
         .unwrap();
-    assert_eq!(addr1(), req.remote_addr);
+    assert_eq!(req.remote_addr, addr1());
     assert_eq!("M", req.method());
     assert_eq!("/1", req.url.path.as_str());
     assert_eq!(
@@ -198,6 +204,35 @@ async fn method() {
     assert_eq!(&RequestBody::PendingUnknown, &req.body);
 }
 
+#[async_test]
+async fn url_host() {
+    let req = call_read("M / HTTP/1.1\r\nhost: WWW.Example.com:8080\r\n\r\n")
+        .await
+        .unwrap();
+    assert_eq!("www.example.com", req.url.host);
+    assert_eq!(None, req.url.ip);
+    assert_eq!(Some(8080), req.url.port);
+    assert_eq!("//www.example.com:8080/", req.url.to_string());
+    let req = call_read("M / HTTP/1.1\r\nhost: [::1]\r\n\r\n")
+        .await
+        .unwrap();
+    assert_eq!("", req.url.host);
+    assert_eq!(
+        Some(std::net::IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])),
+        req.url.ip
+    );
+    assert_eq!(None, req.url.port);
+    for host in ["", "a@b", "a/b", "a:b", "a b"] {
+        let req = call_read(format!("M / HTTP/1.1\r\nhost: {host}\r\n\r\n"))
+            .await
+            .unwrap();
+        assert_eq!("", req.url.host, "{host:?}");
+        assert_eq!(None, req.url.port, "{host:?}");
+    }
+    let req = call_read("M / HTTP/1.0\r\n\r\n").await.unwrap();
+    assert_eq!("", req.url.host);
+}
+
 async fn read_http_request_task() -> (async_net::TcpStream, Receiver<Result<Request, HttpError>>) {
     let (mut stream0, stream1) = connected_streams().await;
     let addr = stream1.local_addr().unwrap();
@@ -205,7 +240,9 @@ async fn read_http_request_task() -> (async_net::TcpStream, Receiver<Result<Requ
     safina::executor::spawn(async move {
         let mut buf = <FixedBuf<1000>>::new();
         loop {
-            match read_http_request(addr, &mut buf, &mut stream0).await {
+            match read_http_request(addr.into(), &mut buf, &mut stream0, &HeadOptions::default())
+                .await
+            {
                 Err(HttpError::Disconnected) => break,
                 result => {
                     let _ignored = sender.send(result);
@@ -240,3 +277,19 @@ async fn read_http_request_multiple_writes() {
     drop(stream);
     assert_eq!("C", receiver.async_recv().await.unwrap().unwrap().method());
 }
+
+#[async_test]
+async fn accepts_trailers() {
+    let req = call_read("M / HTTP/1.1\r\n\r\n").await.unwrap();
+    assert!(!req.accepts_trailers());
+    let req = call_read("M / HTTP/1.1\r\nTE: trailers\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(req.accepts_trailers());
+    let req = call_read("M / HTTP/1.1\r\nte: gzip;q=0.5, Trailers\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(req.accepts_trailers());
+    let req = call_read("M / HTTP/1.1\r\nte: gzip\r\n\r\n").await.unwrap();
+    assert!(!req.accepts_trailers());
+}
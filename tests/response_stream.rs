@@ -0,0 +1,170 @@
+use crate::test_util::{TestServer, read_for, read_to_string};
+use servlin::{Response, ResponseBody, Trailers};
+use std::io::{ErrorKind, Read};
+use std::time::Duration;
+
+mod test_util;
+
+struct FailingReader;
+impl Read for FailingReader {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        Err(std::io::Error::other("err1"))
+    }
+}
+
+#[test]
+fn reader() {
+    let server = TestServer::start(|_req| {
+        Response::new(200).with_body(ResponseBody::from_reader(std::io::Cursor::new(b"abc")))
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+    );
+}
+
+#[test]
+fn reader_error() {
+    let server = TestServer::start(|_req| {
+        Response::new(200).with_body(ResponseBody::from_reader(FailingReader))
+    })
+    .unwrap();
+    let mut tcp_stream = server.connect_and_send("M / HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n",
+    );
+}
+
+#[test]
+fn async_reader() {
+    let server = TestServer::start(|_req| {
+        Response::new(200).with_body(ResponseBody::from_async_reader(
+            futures_lite::io::Cursor::new(b"abc"),
+        ))
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+    );
+}
+
+#[test]
+fn byte_stream() {
+    let server = TestServer::start(|_req| {
+        let (sender, response) = Response::byte_stream();
+        std::thread::spawn(move || {
+            sender.send_blocking(b"abc".to_vec()).unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            sender.send_blocking(b"de".to_vec()).unwrap();
+        });
+        response
+    })
+    .unwrap();
+    let mut tcp_stream = server.connect_and_send("M / HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(
+        read_for(&mut tcp_stream, 100).unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n",
+    );
+    assert_eq!(
+        read_for(&mut tcp_stream, 200).unwrap(),
+        "2\r\nde\r\n0\r\n\r\n"
+    );
+}
+
+#[test]
+fn byte_stream_error() {
+    let server = TestServer::start(|_req| {
+        let (sender, response) = Response::byte_stream();
+        std::thread::spawn(move || {
+            sender.send_blocking(b"abc".to_vec()).unwrap();
+            sender.send_error_blocking(ErrorKind::Other);
+        });
+        response
+    })
+    .unwrap();
+    let mut tcp_stream = server.connect_and_send("M / HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(
+        read_to_string(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n",
+    );
+}
+
+#[test]
+fn byte_stream_client_disconnects() {
+    let (result_sender, result_receiver) = std::sync::mpsc::sync_channel(1);
+    let server = TestServer::start(move |_req| {
+        let (sender, response) = Response::byte_stream();
+        let result_sender = result_sender.clone();
+        std::thread::spawn(move || {
+            while sender.send_blocking(vec![b'a'; 65536]).is_ok() {}
+            result_sender.send(()).unwrap();
+        });
+        response
+    })
+    .unwrap();
+    let tcp_stream = server.connect_and_send("M / HTTP/1.1\r\n\r\n").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    drop(tcp_stream);
+    result_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+}
+
+fn trailers_server() -> TestServer {
+    TestServer::start(|_req| {
+        let trailers = Trailers::new(["x-checksum"]);
+        let (sender, response) = Response::byte_stream();
+        let response = response.with_trailers(&trailers);
+        std::thread::spawn(move || {
+            sender.send_blocking(b"abc".to_vec()).unwrap();
+            trailers.set("x-checksum", "123".try_into().unwrap());
+        });
+        response
+    })
+    .unwrap()
+}
+
+#[test]
+fn trailers() {
+    let server = trailers_server();
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\nte: trailers\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\ntrailer: x-checksum\r\n\r\n3\r\nabc\r\n0\r\nx-checksum: 123\r\n\r\n",
+    );
+}
+
+#[test]
+fn trailers_not_accepted() {
+    let server = trailers_server();
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+    );
+}
+
+#[test]
+fn trailers_with_known_length() {
+    let server = TestServer::start(|_req| {
+        let trailers = Trailers::new(["x-checksum"]);
+        trailers.set("x-checksum", "123".try_into().unwrap());
+        Response::text(200, "abc").with_trailers(&trailers)
+    })
+    .unwrap();
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\nte: trailers\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 3\r\n\r\nabc",
+    );
+}
+
+#[test]
+#[should_panic(expected = "field \"content-length\" is not allowed in a trailer")]
+fn trailers_forbidden_name() {
+    let _ = Trailers::new(["Content-Length"]);
+}
@@ -0,0 +1,317 @@
+use crate::test_util::{TestServer, assert_starts_with, read_response};
+use servlin::{HttpServerBuilder, IpCidr, Request, Response, ReverseProxy};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+mod test_util;
+
+#[allow(clippy::needless_pass_by_value)]
+fn echo_head(req: Request) -> Response {
+    let mut headers: Vec<String> = req
+        .headers
+        .iter()
+        .map(|h| format!("{}: {}", h.name, h.value))
+        .collect();
+    headers.sort();
+    Response::text(
+        200,
+        format!(
+            "{} {}?{}\n{}",
+            req.method,
+            req.url.path,
+            req.url.query,
+            headers.join("\n")
+        ),
+    )
+    .with_header("x-upstream", "1".try_into().unwrap())
+}
+
+#[test]
+fn get() {
+    let upstream = TestServer::start(echo_head).unwrap();
+    let proxy = ReverseProxy::new(upstream.addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    let response = server
+        .exchange(
+            "GET /a%20b?c=d HTTP/1.1\r\nhost: example.com:80\r\nx-custom: 1\r\n\
+            connection: close, x-secret\r\nx-secret: 2\r\nkeep-alive: 5\r\nte: trailers\r\n\
+            upgrade: h2c\r\nforwarded: for=9.9.9.9\r\n\r\n",
+        )
+        .unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert_eq!(
+        head,
+        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 126\r\n\
+        content-type: text/plain; charset=UTF-8\r\nx-upstream: 1",
+    );
+    assert_eq!(
+        body,
+        "GET /a b?c=d\n\
+        forwarded: for=127.0.0.1;proto=http;host=\"example.com:80\"\n\
+        forwarded: for=9.9.9.9\n\
+        host: example.com:80\n\
+        x-custom: 1",
+    );
+    // The proxy forwards the scheme that a trusted proxy reported.
+    let server = TestServer::start_with(
+        |builder: HttpServerBuilder| {
+            builder.trusted_proxies(["127.0.0.1/32".parse::<IpCidr>().unwrap()])
+        },
+        move |req: Request| ReverseProxy::new(upstream.addr).forward(&req),
+    )
+    .unwrap();
+    let response = server
+        .exchange("GET / HTTP/1.1\r\nx-forwarded-proto: https\r\n\r\n")
+        .unwrap();
+    assert!(
+        response.contains("\nforwarded: for=127.0.0.1;proto=https\n"),
+        "{response:?}"
+    );
+}
+
+#[test]
+fn target_unchanged() {
+    let upstream = TestServer::start(|req: Request| Response::text(200, req.target)).unwrap();
+    let proxy = ReverseProxy::new(upstream.addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    for target in [
+        "/a%2Fb",
+        "/public/%2e%2e/admin",
+        "/%FF%2f?q=%2F&r",
+        "/a#frag",
+    ] {
+        let response = server
+            .exchange(format!("GET {target} HTTP/1.1\r\n\r\n"))
+            .unwrap();
+        let expected = target.split('#').next().unwrap();
+        assert!(
+            response.ends_with(&format!("\r\n\r\n{expected}")),
+            "{response:?}"
+        );
+    }
+}
+
+#[test]
+fn request_bodies() {
+    let upstream = TestServer::start(|req: Request| {
+        if req.body.is_pending() {
+            return Response::get_body_and_reprocess(1024 * 1024);
+        }
+        let body: Vec<u8> = req.body.try_into().unwrap();
+        let sum: u64 = body.iter().map(|b| u64::from(*b)).sum();
+        Response::text(200, format!("{} {sum}", body.len()))
+    })
+    .unwrap();
+    let proxy = ReverseProxy::new(upstream.addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    assert_eq!(
+        server
+            .exchange("POST / HTTP/1.1\r\ncontent-length: 3\r\n\r\nabc")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 5\r\ncontent-type: text/plain; charset=UTF-8\r\n\r\n3 294",
+    );
+    assert_eq!(
+        server
+            .exchange("POST / HTTP/1.1\r\ncontent-length: 0\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 3\r\ncontent-type: text/plain; charset=UTF-8\r\n\r\n0 0",
+    );
+    // The proxy streams large bodies.
+    let body = vec![b'a'; 200 * 1024];
+    let mut tcp_stream = server
+        .connect_and_send(format!(
+            "PUT / HTTP/1.1\r\ncontent-length: {}\r\n\r\n",
+            body.len()
+        ))
+        .unwrap();
+    tcp_stream.write_all(&body).unwrap();
+    assert_eq!(
+        read_response(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 15\r\ncontent-type: text/plain; charset=UTF-8\r\n\r\n204800 19865600",
+    );
+}
+
+#[test]
+fn unknown_length_body() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, requests) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut stream, _addr) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0_u8; 1024];
+        while !received.ends_with(b"0\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            assert_ne!(0, n);
+            received.extend(&buf[..n]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+            .unwrap();
+        sender.send(String::from_utf8(received).unwrap()).unwrap();
+    });
+    let proxy = ReverseProxy::new(addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    // The client sends the body until it closes its side of the connection.
+    assert_eq!(
+        server.exchange("POST / HTTP/1.1\r\n\r\nabc").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok",
+    );
+    let received = requests.recv().unwrap();
+    assert_starts_with(&received, "POST / HTTP/1.1\r\n");
+    assert!(
+        received.contains("\r\ntransfer-encoding: chunked\r\n\r\n"),
+        "{received:?}"
+    );
+    assert!(
+        received.ends_with("\r\n\r\n3\r\nabc\r\n0\r\n\r\n"),
+        "{received:?}"
+    );
+}
+
+#[test]
+fn streamed_response() {
+    let upstream = TestServer::start(|_req| {
+        let (sender, response) = Response::byte_stream();
+        std::thread::spawn(move || {
+            for chunk in ["abc", "def"] {
+                sender.send_blocking(chunk.as_bytes().to_vec()).unwrap();
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+        response
+    })
+    .unwrap();
+    let proxy = ReverseProxy::new(upstream.addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    let response = server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap();
+    assert_starts_with(
+        &response,
+        "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n",
+    );
+    let body: String = response
+        .split("\r\n")
+        .skip(4)
+        .step_by(2)
+        .take_while(|s| !s.is_empty())
+        .collect();
+    assert_eq!(body, "abcdef");
+    assert!(response.ends_with("0\r\n\r\n"), "{response:?}");
+}
+
+#[test]
+fn head() {
+    let upstream = TestServer::start(|_req| Response::text(200, "abc")).unwrap();
+    let proxy = ReverseProxy::new(upstream.addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    assert_eq!(
+        server.exchange("HEAD / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-length: 3\r\ncontent-type: text/plain; charset=UTF-8\r\n\r\n",
+    );
+}
+
+#[test]
+fn reuses_connections() {
+    let upstream =
+        TestServer::start(|req: Request| Response::text(200, req.remote_addr.to_string())).unwrap();
+    let proxy = ReverseProxy::new(upstream.addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let response1 = read_response(&mut tcp_stream).unwrap();
+    let response2 = read_response(&mut tcp_stream).unwrap();
+    assert_starts_with(&response1, "HTTP/1.1 200 OK\r\n");
+    assert_eq!(response1, response2);
+    // The proxy connects for each request when it keeps no idle connections.
+    let upstream = TestServer::start(|_req| Response::text(200, "new")).unwrap();
+    let proxy = ReverseProxy::new(upstream.addr).max_idle_conns(0);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    for _ in 0..2 {
+        assert_eq!(
+            server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+            "HTTP/1.1 200 OK\r\ncontent-length: 3\r\ncontent-type: text/plain; charset=UTF-8\r\n\r\nnew",
+        );
+    }
+}
+
+#[test]
+fn upstream_closed_idle_conn() {
+    // The upstream server closes each connection after responding,
+    // without sending `connection: close`.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buf = [0_u8; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .unwrap();
+        }
+    });
+    let proxy = ReverseProxy::new(addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    for _ in 0..3 {
+        assert_eq!(
+            server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+            "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok",
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn upstream_errors() {
+    let addr: SocketAddr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let proxy = ReverseProxy::new(addr);
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 502 Bad Gateway\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+    let upstream = TestServer::start(|_req| {
+        std::thread::sleep(Duration::from_millis(500));
+        Response::text(200, "slow")
+    })
+    .unwrap();
+    let proxy = ReverseProxy::new(upstream.addr).response_timeout(Duration::from_millis(100));
+    let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 504 Gateway Timeout\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+    );
+    for response in [
+        "HTTP/1.1 2000 OK\r\n\r\n".as_bytes(),
+        b"HTTP/2 200 OK\r\n\r\n",
+        b"HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nbad header\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nx-a: 1\rx-b: 2\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nx-a: 1\x00\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\ncontent-length: 2\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\ntransfer-encoding: gzip\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\n",
+    ] {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _addr) = listener.accept().unwrap();
+            let mut buf = [0_u8; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            stream.write_all(response).unwrap();
+        });
+        let proxy = ReverseProxy::new(addr);
+        let server = TestServer::start(move |req: Request| proxy.forward(&req)).unwrap();
+        assert_eq!(
+            server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+            "HTTP/1.1 502 Bad Gateway\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+            "{}",
+            response.escape_ascii()
+        );
+    }
+}
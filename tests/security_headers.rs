@@ -0,0 +1,173 @@
+use crate::test_util::TestServer;
+use servlin::{
+    CrossOriginEmbedderPolicy, CrossOriginResourcePolicy, HttpServerBuilder, IpCidr,
+    ReferrerPolicy, Request, Response, SecurityHeaders,
+};
+use std::time::Duration;
+
+mod test_util;
+
+#[allow(clippy::needless_pass_by_value)]
+fn app(req: Request, nonce: &str) -> Response {
+    match req.url.path.as_str() {
+        "/override" => Response::text(200, nonce.to_string())
+            .with_header("referrer-policy", "origin".try_into().unwrap())
+            .with_header(
+                "content-security-policy",
+                "default-src *".try_into().unwrap(),
+            ),
+        "/reprocess" if req.body.is_pending() => Response::get_body_and_reprocess(1024),
+        _ => Response::text(200, nonce.to_string()),
+    }
+}
+
+/// Returns the response's headers after `content-length`, and the nonce from its body.
+fn get(server: &TestServer, request: &str) -> (Vec<String>, String) {
+    let response = server.exchange(request).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let headers = head
+        .split("\r\n")
+        .skip_while(|line| !line.starts_with("content-length: "))
+        .skip(1)
+        .map(|line| line.replace(body, "NONCE"))
+        .collect();
+    (headers, body.to_string())
+}
+
+#[test]
+fn defaults() {
+    let security_headers = SecurityHeaders::new();
+    let server = TestServer::start(move |req: Request| security_headers.handle(req, app)).unwrap();
+    let (headers, nonce) = get(&server, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(
+        headers,
+        [
+            "content-security-policy: default-src 'self'; script-src 'self' 'nonce-NONCE'; style-src 'self' 'nonce-NONCE'; object-src 'none'; base-uri 'self'; form-action 'self'; frame-ancestors 'none'",
+            "permissions-policy: camera=(), geolocation=(), microphone=(), payment=(), usb=()",
+            "cross-origin-opener-policy: same-origin",
+            "cross-origin-resource-policy: same-origin",
+            "referrer-policy: no-referrer",
+            "x-content-type-options: nosniff",
+        ]
+    );
+    assert_eq!(nonce.len(), 22, "{nonce:?}");
+    // Each request gets a new nonce.
+    assert_ne!(get(&server, "GET / HTTP/1.1\r\n\r\n").1, nonce);
+    // Error responses get the headers, too.
+    let response = server.exchange("GET /x HTTP/1.1\r\n\r\n").unwrap();
+    assert!(
+        response.contains("\r\nx-content-type-options: nosniff\r\n"),
+        "{response:?}"
+    );
+}
+
+#[test]
+fn hsts() {
+    let security_headers = SecurityHeaders::new().without("content-security-policy");
+    let server = TestServer::start_with(
+        |builder: HttpServerBuilder| {
+            builder.trusted_proxies(["127.0.0.1/32".parse::<IpCidr>().unwrap()])
+        },
+        move |req: Request| security_headers.handle(req, app),
+    )
+    .unwrap();
+    let (headers, _nonce) = get(
+        &server,
+        "GET / HTTP/1.1\r\nforwarded: proto=https;host=example.com\r\n\r\n",
+    );
+    assert_eq!(
+        headers,
+        [
+            "permissions-policy: camera=(), geolocation=(), microphone=(), payment=(), usb=()",
+            "cross-origin-opener-policy: same-origin",
+            "cross-origin-resource-policy: same-origin",
+            "referrer-policy: no-referrer",
+            "strict-transport-security: max-age=63072000; includeSubDomains",
+            "x-content-type-options: nosniff",
+        ]
+    );
+    let security_headers = SecurityHeaders::new()
+        .hsts(Duration::from_mins(1), false)
+        .without("content-security-policy")
+        .without("permissions-policy")
+        .without("cross-origin-opener-policy")
+        .without("cross-origin-resource-policy")
+        .without("Referrer-Policy")
+        .without("x-content-type-options");
+    let server = TestServer::start_with(
+        |builder: HttpServerBuilder| {
+            builder.trusted_proxies(["127.0.0.1/32".parse::<IpCidr>().unwrap()])
+        },
+        move |req: Request| security_headers.handle(req, app),
+    )
+    .unwrap();
+    let (headers, _nonce) = get(
+        &server,
+        "GET / HTTP/1.1\r\nforwarded: proto=https;host=example.com\r\n\r\n",
+    );
+    assert_eq!(headers, ["strict-transport-security: max-age=60"]);
+    let (headers, _nonce) = get(&server, "GET / HTTP/1.1\r\n\r\n");
+    assert!(headers.is_empty(), "{headers:?}");
+}
+
+#[test]
+fn builder() {
+    let security_headers = SecurityHeaders::new()
+        .csp_directive("default-src", "'none'")
+        .csp_directive("script-src", "'nonce'  'strict-dynamic'")
+        .csp_directive("style-src", "'self'")
+        .csp_directive("upgrade-insecure-requests", "")
+        .csp_report_only()
+        .permission("geolocation", "(self)")
+        .permission("fullscreen", "*")
+        .cross_origin_embedder_policy(CrossOriginEmbedderPolicy::RequireCorp)
+        .cross_origin_resource_policy(CrossOriginResourcePolicy::CrossOrigin)
+        .referrer_policy(ReferrerPolicy::StrictOriginWhenCrossOrigin)
+        .header("x-frame-options", "DENY");
+    let server = TestServer::start(move |req: Request| security_headers.handle(req, app)).unwrap();
+    let (headers, _nonce) = get(&server, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(
+        headers,
+        [
+            "content-security-policy-report-only: default-src 'none'; script-src 'nonce-NONCE' 'strict-dynamic'; style-src 'self'; object-src 'none'; base-uri 'self'; form-action 'self'; frame-ancestors 'none'; upgrade-insecure-requests",
+            "permissions-policy: camera=(), geolocation=(self), microphone=(), payment=(), usb=(), fullscreen=*",
+            "cross-origin-opener-policy: same-origin",
+            "cross-origin-resource-policy: cross-origin",
+            "referrer-policy: strict-origin-when-cross-origin",
+            "x-content-type-options: nosniff",
+            "cross-origin-embedder-policy: require-corp",
+            "x-frame-options: DENY",
+        ]
+    );
+}
+
+#[test]
+fn overrides() {
+    let security_headers = SecurityHeaders::new();
+    let server = TestServer::start(move |req: Request| security_headers.handle(req, app)).unwrap();
+    let (headers, _nonce) = get(&server, "GET /override HTTP/1.1\r\n\r\n");
+    assert_eq!(
+        headers,
+        [
+            "referrer-policy: origin",
+            "content-security-policy: default-src *",
+            "permissions-policy: camera=(), geolocation=(), microphone=(), payment=(), usb=()",
+            "cross-origin-opener-policy: same-origin",
+            "cross-origin-resource-policy: same-origin",
+            "x-content-type-options: nosniff",
+        ]
+    );
+    // The handler gets called again with the body.
+    let (headers, nonce) = get(
+        &server,
+        "POST /reprocess HTTP/1.1\r\ncontent-length: 3\r\n\r\nabc",
+    );
+    assert_eq!(headers.len(), 6, "{headers:?}");
+    assert_eq!(nonce.len(), 22, "{nonce:?}");
+}
+
+#[test]
+#[should_panic(expected = "invalid CSP directive")]
+fn invalid_directive() {
+    let _security_headers = SecurityHeaders::new().csp_directive("script-src", "'self'; a");
+}
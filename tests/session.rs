@@ -0,0 +1,330 @@
+use crate::test_util::TestServer;
+use servlin::{
+    FileSessionStore, MemorySessionStore, Request, Response, Session, SessionRecord, SessionStore,
+    Sessions,
+};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use temp_dir::TempDir;
+
+mod test_util;
+
+const DELETE_COOKIE: &str =
+    "session=; Expires=1970-01-01T00:00:01Z; HttpOnly; Path=/; SameSite=Lax; Secure";
+
+#[allow(clippy::needless_pass_by_value)]
+fn app(req: Request, session: &mut Session) -> Response {
+    match req.url.path.as_str() {
+        "/login" => {
+            session.rotate_id();
+            session.set("user", "alice");
+            Response::text(200, "logged in")
+        }
+        "/logout" => {
+            session.destroy();
+            Response::text(200, "logged out")
+        }
+        "/count" => {
+            let n = session.parse::<u32>("n").unwrap_or_default() + 1;
+            session.set("n", n);
+            Response::text(200, format!("{n}"))
+        }
+        "/reprocess" if req.body.is_pending() => {
+            session.set("user", "mallory");
+            Response::get_body_and_reprocess(1024)
+        }
+        _ => Response::text(
+            200,
+            format!("{} {:?}", session.is_new(), session.get("user")),
+        ),
+    }
+}
+
+/// Returns the response body and the session cookie it set, if any.
+fn get(server: &TestServer, path: &str, opt_id: Option<&str>) -> (String, Option<String>) {
+    let cookie = opt_id
+        .map(|id| format!("cookie: session={id}\r\n"))
+        .unwrap_or_default();
+    let response = server
+        .exchange(format!("GET {path} HTTP/1.1\r\n{cookie}\r\n"))
+        .unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let opt_set_cookie = head
+        .split("\r\n")
+        .find_map(|line| line.strip_prefix("set-cookie: "))
+        .map(str::to_string);
+    (body.to_string(), opt_set_cookie)
+}
+
+fn new_id(set_cookie: Option<String>) -> String {
+    let set_cookie = set_cookie.unwrap();
+    let (id, attributes) = set_cookie
+        .strip_prefix("session=")
+        .unwrap()
+        .split_once(';')
+        .unwrap();
+    assert_eq!(
+        attributes,
+        " HttpOnly; Max-Age=86400; Path=/; SameSite=Lax; Secure"
+    );
+    assert_eq!(id.len(), 64, "{id:?}");
+    id.to_string()
+}
+
+#[test]
+fn login_and_logout() {
+    let store = Arc::new(MemorySessionStore::new());
+    let sessions = Sessions::new(store.clone());
+    let server = TestServer::start(move |req: Request| sessions.handle(req, app)).unwrap();
+    // Anonymous clients get no session.
+    assert_eq!(get(&server, "/", None), ("true None".to_string(), None));
+    assert!(store.is_empty());
+
+    let id = new_id(get(&server, "/login", None).1);
+    assert_eq!(store.len(), 1);
+    assert_eq!(
+        get(&server, "/", Some(&id)),
+        ("false Some(\"alice\")".to_string(), None)
+    );
+
+    // Logging in again rotates the id.
+    let (body, set_cookie) = get(&server, "/login", Some(&id));
+    assert_eq!(body, "logged in");
+    let id2 = new_id(set_cookie);
+    assert_ne!(id, id2);
+    assert_eq!(store.len(), 1);
+    // Clients with unknown session ids lose their cookies.
+    assert_eq!(
+        get(&server, "/", Some(&id)),
+        ("true None".to_string(), Some(DELETE_COOKIE.to_string()))
+    );
+    assert_eq!(
+        get(&server, "/", Some(&id2)),
+        ("false Some(\"alice\")".to_string(), None)
+    );
+
+    assert_eq!(
+        get(&server, "/logout", Some(&id2)),
+        ("logged out".to_string(), Some(DELETE_COOKIE.to_string()))
+    );
+    assert!(store.is_empty());
+    assert_eq!(
+        get(&server, "/", Some(&id2)),
+        ("true None".to_string(), Some(DELETE_COOKIE.to_string()))
+    );
+}
+
+#[test]
+fn values() {
+    let sessions = Sessions::new(MemorySessionStore::new());
+    let server = TestServer::start(move |req: Request| sessions.handle(req, app)).unwrap();
+    let (body, set_cookie) = get(&server, "/count", None);
+    assert_eq!(body, "1");
+    let id = new_id(set_cookie);
+    assert_eq!(get(&server, "/count", Some(&id)), ("2".to_string(), None));
+    assert_eq!(get(&server, "/count", Some(&id)), ("3".to_string(), None));
+    // Clients cannot choose session ids.
+    for bad_id in ["", "abc", &"0".repeat(64), &id.to_ascii_uppercase()] {
+        let (body, set_cookie) = get(&server, "/count", Some(bad_id));
+        assert_eq!(body, "1", "{bad_id:?}");
+        assert_ne!(new_id(set_cookie), bad_id);
+    }
+    // Changes are not saved with a response that is not normal.
+    let response = server
+        .exchange(format!(
+            "POST /reprocess HTTP/1.1\r\ncookie: session={id}\r\ncontent-length: 3\r\n\r\nabc"
+        ))
+        .unwrap();
+    test_util::assert_starts_with(response, "HTTP/1.1 200 OK\r\n");
+    assert_eq!(
+        get(&server, "/", Some(&id)),
+        ("false None".to_string(), None)
+    );
+}
+
+#[test]
+fn idle_timeout() {
+    let sessions =
+        Sessions::new(MemorySessionStore::new()).idle_timeout(Duration::from_millis(300));
+    let server = TestServer::start(move |req: Request| sessions.handle(req, app)).unwrap();
+    let id = new_id(get(&server, "/count", None).1);
+    for n in 2..5 {
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(get(&server, "/count", Some(&id)), (format!("{n}"), None));
+    }
+    std::thread::sleep(Duration::from_millis(400));
+    let (body, set_cookie) = get(&server, "/count", Some(&id));
+    assert_eq!(body, "1");
+    assert_ne!(new_id(set_cookie), id);
+}
+
+#[test]
+fn absolute_timeout() {
+    let store = Arc::new(MemorySessionStore::new());
+    let sessions = Sessions::new(store.clone()).absolute_timeout(Duration::from_millis(400));
+    let server = TestServer::start(move |req: Request| sessions.handle(req, app)).unwrap();
+    let response = server.exchange("GET /count HTTP/1.1\r\n\r\n").unwrap();
+    let (_, rest) = response.split_once("set-cookie: session=").unwrap();
+    let id = rest[..64].to_string();
+    assert!(
+        rest[64..].starts_with("; HttpOnly; Max-Age=1; Path=/; "),
+        "{response:?}"
+    );
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(get(&server, "/count", Some(&id)), ("2".to_string(), None));
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(get(&server, "/count", Some(&id)).0, "1");
+    assert_eq!(store.len(), 1);
+}
+
+#[test]
+fn cleanup_errors() {
+    struct FailingCleanup(MemorySessionStore);
+    impl SessionStore for FailingCleanup {
+        fn load(&self, key: &str) -> Result<Option<SessionRecord>, std::io::Error> {
+            self.0.load(key)
+        }
+        fn save(&self, key: &str, record: &SessionRecord) -> Result<(), std::io::Error> {
+            self.0.save(key, record)
+        }
+        fn remove(&self, key: &str) -> Result<(), std::io::Error> {
+            self.0.remove(key)
+        }
+        fn remove_expired(&self, _now: SystemTime) -> Result<(), std::io::Error> {
+            Err(ErrorKind::PermissionDenied.into())
+        }
+    }
+    // Cleanup is best-effort, so its errors do not fail requests.
+    let sessions = Sessions::new(FailingCleanup(MemorySessionStore::new()));
+    let server = TestServer::start(move |req: Request| sessions.handle(req, app)).unwrap();
+    let id = new_id(get(&server, "/count", None).1);
+    assert_eq!(get(&server, "/count", Some(&id)), ("2".to_string(), None));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_values() {
+    let server = TestServer::start(move |req: Request| {
+        let sessions = Sessions::new(MemorySessionStore::new());
+        sessions.handle(req, |_req, session| {
+            session.set_json("cart", &vec![1_u32, 2]).unwrap();
+            session.set("bad", "[1,");
+            Response::text(
+                200,
+                format!(
+                    "{:?} {:?} {:?}",
+                    session.get("cart"),
+                    session.get_json::<Vec<u32>>("cart"),
+                    session.get_json::<Vec<u32>>("bad")
+                ),
+            )
+        })
+    })
+    .unwrap();
+    let response = server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap();
+    test_util::assert_ends_with(response, "\r\n\r\nSome(\"[1,2]\") Some([1, 2]) None");
+}
+
+#[test]
+fn file_store_cleanup() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.child("sessions");
+    let store = FileSessionStore::new(&dir);
+    let key = "0123456789abcdef";
+    let now = SystemTime::now();
+    store
+        .save(
+            key,
+            &SessionRecord {
+                values: HashMap::new(),
+                created: now,
+                expires: now + Duration::from_hours(24),
+            },
+        )
+        .unwrap();
+    // A time past `SystemTime`'s range is malformed, not a panic.
+    std::fs::write(dir.join("aa"), format!("0 {}\n", u64::MAX)).unwrap();
+    assert_eq!(store.load("aa").unwrap_err().kind(), ErrorKind::InvalidData);
+    let temp_path = dir.join(format!("{key}.0011223344556677.tmp"));
+    std::fs::write(&temp_path, "0 0\n").unwrap();
+    let other_path = dir.join("notes.tmp");
+    std::fs::write(&other_path, "").unwrap();
+
+    // Cleanup keeps recent temporary files, since a save may be writing them.
+    store.remove_expired(now).unwrap();
+    assert!(!dir.join("aa").exists());
+    assert!(temp_path.exists());
+    store.remove_expired(now + Duration::from_hours(2)).unwrap();
+    assert!(!temp_path.exists());
+    assert!(other_path.exists());
+    assert!(store.load(key).unwrap().is_some());
+}
+
+#[test]
+fn file_store() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = FileSessionStore::new(temp_dir.child("sessions"));
+    let key = "0123456789abcdef";
+    assert_eq!(store.load(key).unwrap(), None);
+    store.remove(key).unwrap();
+    store.remove_expired(SystemTime::now()).unwrap();
+
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let record = SessionRecord {
+        values: HashMap::from([
+            ("user".to_string(), "alice é".to_string()),
+            (String::new(), "a b\nc".to_string()),
+            ("empty".to_string(), String::new()),
+        ]),
+        created: now,
+        expires: now + Duration::from_mins(1),
+    };
+    store.save(key, &record).unwrap();
+    assert_eq!(store.load(key).unwrap(), Some(record.clone()));
+    let expired_key = "ff";
+    store
+        .save(
+            expired_key,
+            &SessionRecord {
+                values: HashMap::new(),
+                created: now,
+                expires: now + Duration::from_secs(1),
+            },
+        )
+        .unwrap();
+    store.remove_expired(now + Duration::from_secs(30)).unwrap();
+    assert_eq!(store.load(expired_key).unwrap(), None);
+    assert_eq!(store.load(key).unwrap(), Some(record));
+    assert_eq!(
+        std::fs::read_dir(temp_dir.child("sessions"))
+            .unwrap()
+            .count(),
+        1
+    );
+
+    std::fs::write(temp_dir.child("sessions").join("aa"), "bad").unwrap();
+    assert_eq!(store.load("aa").unwrap_err().kind(), ErrorKind::InvalidData);
+    for bad_key in ["", "../aa", "aa/bb"] {
+        assert_eq!(
+            store.load(bad_key).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+
+    // Cleanup deletes malformed records and skips entries it cannot read.
+    std::fs::create_dir(temp_dir.child("sessions").join("bb")).unwrap();
+    store.remove_expired(now).unwrap_err();
+    assert!(!temp_dir.child("sessions").join("aa").exists());
+    assert!(temp_dir.child("sessions").join("bb").exists());
+    assert!(store.load(key).unwrap().is_some());
+
+    store.remove(key).unwrap();
+    assert_eq!(store.load(key).unwrap(), None);
+
+    let sessions = Sessions::new(store);
+    let server = TestServer::start(move |req: Request| sessions.handle(req, app)).unwrap();
+    let id = new_id(get(&server, "/count", None).1);
+    assert_eq!(get(&server, "/count", Some(&id)), ("2".to_string(), None));
+}
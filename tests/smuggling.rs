@@ -0,0 +1,210 @@
+use crate::test_util::{TestServer, assert_starts_with, read_to_string};
+use fixed_buffer::FixedBuf;
+use servlin::internal::{Head, HeadError, HeadOptions};
+use servlin::{HttpServerBuilder, Response};
+
+mod test_util;
+
+fn strict() -> HeadOptions {
+    HeadOptions {
+        strict: true,
+        ..HeadOptions::default()
+    }
+}
+
+/// Request heads that proxies and servers can disagree about.
+/// The server must reject them in strict mode.
+const CORPUS: &[(HeadError, &str)] = &[
+    // CL.TE and TE.CL
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n",
+    ),
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+    ),
+    // TE.TE
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: x\r\n\r\n",
+    ),
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n",
+    ),
+    // CL.CL
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n",
+    ),
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\n",
+    ),
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5, 6\r\n\r\n",
+    ),
+    (
+        HeadError::AmbiguousBodyLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5,5\r\n\r\n",
+    ),
+    (
+        HeadError::InvalidContentLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: +5\r\n\r\n",
+    ),
+    (
+        HeadError::InvalidContentLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 0x5\r\n\r\n",
+    ),
+    (
+        HeadError::InvalidContentLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5 5\r\n\r\n",
+    ),
+    (
+        HeadError::InvalidContentLength,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length:\r\n\r\n",
+    ),
+    // Whitespace before the colon
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding : chunked\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length\t: 5\r\n\r\n",
+    ),
+    // obs-fold
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding:\r\n chunked\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\r\nX: a\r\n\tContent-Length: 5\r\n\r\n",
+    ),
+    // Bare LF and bare CR
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\nContent-Length: 5\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\rContent-Length: 5\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedRequestLine,
+        "POST / HTTP/1.1\nHost: h\r\n\r\n",
+    ),
+    // Control characters in values
+    (
+        HeadError::MalformedHeader,
+        "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: \x0bchunked\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedHeader,
+        "GET / HTTP/1.1\r\nHost: h\x00\r\n\r\n",
+    ),
+    // Host
+    (HeadError::InvalidHost, "GET / HTTP/1.1\r\n\r\n"),
+    (
+        HeadError::InvalidHost,
+        "GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+    ),
+    // Request target
+    (
+        HeadError::MalformedPath,
+        "GET /a#b HTTP/1.1\r\nHost: h\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedPath,
+        "GET * HTTP/1.1\r\nHost: h\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedPath,
+        "GET /\x7f HTTP/1.1\r\nHost: h\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedPath,
+        "GET a HTTP/1.1\r\nHost: h\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedRequestLine,
+        "GET  / HTTP/1.1\r\nHost: h\r\n\r\n",
+    ),
+    (
+        HeadError::MalformedRequestLine,
+        "GET /\tHTTP/1.1\r\nHost: h\r\n\r\n",
+    ),
+];
+
+fn try_read(options: &HeadOptions, req: &str) -> Result<Head, HeadError> {
+    let mut buf: FixedBuf<1000> = FixedBuf::new();
+    buf.write_bytes(req).unwrap();
+    Head::try_read_with(&mut buf, options)
+}
+
+#[test]
+fn corpus_strict() {
+    for (expected, req) in CORPUS {
+        assert_eq!(Err(*expected), try_read(&strict(), req), "{req:?}");
+    }
+}
+
+#[test]
+fn valid_strict() {
+    for req in [
+        "GET / HTTP/1.1\r\nHost: h\r\n\r\n",
+        "GET / HTTP/1.0\r\n\r\n",
+        "OPTIONS * HTTP/1.1\r\nHost: h\r\n\r\n",
+        "CONNECT h:443 HTTP/1.1\r\nHost: h:443\r\n\r\n",
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\n",
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length:\t05 \r\n\r\n",
+        "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+        "GET /?a=%20 HTTP/1.1\r\nHost: h\r\nX: \"q\"\t(1)\r\n\r\n",
+    ] {
+        try_read(&strict(), req).unwrap();
+    }
+}
+
+#[test]
+fn lenient_by_default() {
+    for req in [
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n",
+        "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n",
+        "POST / HTTP/1.1\r\nHost: h\nContent-Length: 5\r\n\r\n",
+        "GET / HTTP/1.1\r\n\r\n",
+        "GET * HTTP/1.1\r\n\r\n",
+    ] {
+        try_read(&HeadOptions::default(), req).unwrap();
+    }
+}
+
+#[test]
+fn server_rejects_corpus() {
+    let server = TestServer::start_with(HttpServerBuilder::strict_parsing, |_req| {
+        Response::text(200, "ok")
+    })
+    .unwrap();
+    for (_expected, req) in CORPUS {
+        // The server closes the connection after the response.
+        let mut tcp_stream = server.connect_and_send(req).unwrap();
+        assert_starts_with(
+            read_to_string(&mut tcp_stream).unwrap(),
+            "HTTP/1.1 400 Bad Request\r\n",
+        );
+    }
+    assert_eq!(
+        server
+            .exchange("GET / HTTP/1.1\r\nHost: h\r\nContent-Length: 1\r\nContent-Length: 1\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 30\r\n\r\nHttpError::AmbiguousBodyLength",
+    );
+    assert_starts_with(
+        server
+            .exchange("GET / HTTP/1.1\r\nHost: h\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\n",
+    );
+}
@@ -0,0 +1,145 @@
+use crate::test_util::{TestServer, assert_starts_with};
+use servlin::Response;
+use std::path::PathBuf;
+
+mod test_util;
+
+fn static_files_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/static_files")
+}
+
+#[test]
+fn static_file() {
+    let server = TestServer::start(|req| {
+        Response::static_file(&req, static_files_dir().join("plain.txt"))
+            .unwrap_or_else(|e| *e.response.unwrap())
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 3\r\n\r\ntxt",
+    );
+}
+
+#[test]
+fn static_file_not_found() {
+    let server = TestServer::start(|req| {
+        Response::static_file(&req, static_files_dir().join("missing.txt"))
+            .unwrap_or_else(|e| *e.response.unwrap())
+    })
+    .unwrap();
+    assert_starts_with(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 404 Not Found\r\n",
+    );
+}
+
+#[test]
+fn static_file_precompressed() {
+    let server = TestServer::start(|req| {
+        Response::static_file(&req, static_files_dir().join("app.js"))
+            .unwrap_or_else(|e| *e.response.unwrap())
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 2\r\nvary: accept-encoding\r\n\r\njs",
+    );
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\naccept-encoding: gzip\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 5\r\ncontent-encoding: gzip\r\nvary: accept-encoding\r\n\r\njs-gz",
+    );
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\naccept-encoding: gzip, deflate, br\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 5\r\ncontent-encoding: br\r\nvary: accept-encoding\r\n\r\njs-br",
+    );
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\naccept-encoding: br;q=0, GZIP;q=0.5\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 5\r\ncontent-encoding: gzip\r\nvary: accept-encoding\r\n\r\njs-gz",
+    );
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\naccept-encoding: *;q=0\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 2\r\nvary: accept-encoding\r\n\r\njs",
+    );
+    assert_eq!(
+        server
+            .exchange("M / HTTP/1.1\r\naccept-encoding: *\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 5\r\ncontent-encoding: br\r\nvary: accept-encoding\r\n\r\njs-br",
+    );
+}
+
+#[test]
+fn static_dir() {
+    let server = TestServer::start(|req| {
+        Response::static_dir(&req, static_files_dir()).unwrap_or_else(|e| *e.response.unwrap())
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/html; charset=UTF-8\r\ncontent-length: 5\r\n\r\nindex",
+    );
+    assert_eq!(
+        server.exchange("M /sub HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 301 Moved Permanently\r\ncontent-length: 0\r\nlocation: /sub/\r\n\r\n",
+    );
+    assert_eq!(
+        server.exchange("M /sub/ HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/html; charset=UTF-8\r\ncontent-length: 9\r\n\r\nsub-index",
+    );
+    assert_eq!(
+        server
+            .exchange("M /app.js HTTP/1.1\r\naccept-encoding: gzip\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 5\r\ncontent-encoding: gzip\r\nvary: accept-encoding\r\n\r\njs-gz",
+    );
+    assert_starts_with(
+        server.exchange("M /missing HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 404 Not Found\r\n",
+    );
+    assert_starts_with(
+        server
+            .exchange("M /sub/../app.js HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 404 Not Found\r\n",
+    );
+    assert_starts_with(
+        server
+            .exchange("M /sub/%2e%2e/app.js HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 404 Not Found\r\n",
+    );
+}
+
+#[cfg(feature = "include_dir")]
+#[test]
+fn include_dir_precompressed() {
+    static DIR: include_dir::Dir =
+        include_dir::include_dir!("$CARGO_MANIFEST_DIR/tests/static_files");
+    let server = TestServer::start(|req| {
+        Response::include_dir(&req, &DIR).unwrap_or_else(|e| *e.response.unwrap())
+    })
+    .unwrap();
+    assert_eq!(
+        server.exchange("M /app.js HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 2\r\nvary: accept-encoding\r\n\r\njs",
+    );
+    assert_eq!(
+        server
+            .exchange("M /app.js HTTP/1.1\r\naccept-encoding: br\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/javascript; charset=UTF-8\r\ncontent-length: 5\r\ncontent-encoding: br\r\nvary: accept-encoding\r\n\r\njs-br",
+    );
+    assert_eq!(
+        server.exchange("M / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/html; charset=UTF-8\r\ncontent-length: 5\r\n\r\nindex",
+    );
+}
@@ -0,0 +1 @@
+js
\ No newline at end of file
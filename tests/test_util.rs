@@ -211,6 +211,18 @@ pub struct TestServer {
 impl TestServer {
     #[allow(clippy::missing_errors_doc)]
     pub fn start<F>(handler: F) -> Result<Self, std::io::Error>
+    where
+        F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
+    {
+        Self::start_with(|builder| builder, handler)
+    }
+
+    /// Starts a server with the default test settings changed by `configure`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn start_with<F>(
+        configure: impl FnOnce(HttpServerBuilder) -> HttpServerBuilder,
+        handler: F,
+    ) -> Result<Self, std::io::Error>
     where
         F: FnOnce(Request) -> Response + 'static + Clone + Send + Sync,
     {
@@ -219,7 +231,7 @@ impl TestServer {
         let executor = Executor::new(1, 1)?;
         let cache_dir = TempDir::new()?;
         let (addr, stopped_receiver): (SocketAddr, Receiver<()>) = executor.block_on(
-            HttpServerBuilder::new()
+            configure(HttpServerBuilder::new())
                 .listen_addr(socket_addr_127_0_0_1_any_port())
                 .max_conns(1000)
                 .small_body_len(64 * 1024)
@@ -264,7 +276,8 @@ impl TestServer {
         tcp_stream
             .write_all(send.as_ref())
             .map_err(ExchangeErr::write)?;
-        tcp_stream.shutdown(Shutdown::Write).unwrap();
+        // The server may have already closed the connection.
+        let _ignored = tcp_stream.shutdown(Shutdown::Write);
         let mut string = String::new();
         match tcp_stream.read_to_string(&mut string) {
             Ok(_) => Ok(string),
@@ -0,0 +1,196 @@
+#![cfg(unix)]
+use permit::Permit;
+use safina::executor::Executor;
+use servlin::{HttpServerBuilder, ListenAddr, Request, Response};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use temp_dir::TempDir;
+
+#[allow(clippy::needless_pass_by_value)]
+fn echo_peer(req: Request) -> Response {
+    let peer = req.remote_addr.unix().unwrap();
+    let credentials = peer.credentials.map(|c| (c.pid, c.uid));
+    Response::text(200, format!("{} {credentials:?}", req.remote_addr))
+}
+
+fn get(mut stream: UnixStream) -> String {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn body(response: &str) -> &str {
+    response.split_once("\r\n\r\n").unwrap().1
+}
+
+fn spawn(
+    executor: &Arc<Executor>,
+    builder: HttpServerBuilder,
+) -> Result<Vec<ListenAddr>, std::io::Error> {
+    safina::timer::start_timer_thread();
+    executor
+        .block_on(builder.spawn_listeners(echo_peer))
+        .map(|(addrs, _stopped_receiver)| addrs)
+}
+
+fn my_uid(dir: &Path) -> u32 {
+    std::fs::metadata(dir).unwrap().uid()
+}
+
+#[test]
+fn unix_path() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.child("http.sock");
+    safina::timer::start_timer_thread();
+    let permit = Permit::new();
+    let executor = Executor::new(1, 1).unwrap();
+    let (addrs, stopped_receiver) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .listen_addr(ListenAddr::unix_with_mode(&path, 0o600))
+                .permit(permit.new_sub())
+                .spawn_listeners(echo_peer),
+        )
+        .unwrap();
+    assert_eq!(vec![ListenAddr::unix_with_mode(&path, 0o600)], addrs);
+    assert_eq!(
+        0o600,
+        std::fs::metadata(&path).unwrap().permissions().mode() & 0o777
+    );
+    let response = get(UnixStream::connect(&path).unwrap());
+    if cfg!(any(target_os = "android", target_os = "linux")) {
+        let uid = my_uid(dir.path());
+        let pid = std::process::id();
+        assert!(
+            body(&response).starts_with(&format!("unix:(pid={pid} uid={uid} gid=")),
+            "{response:?}"
+        );
+        assert!(
+            body(&response).ends_with(&format!(" Some((Some({pid}), {uid}))")),
+            "{response:?}"
+        );
+    } else {
+        assert_eq!("unix: None", body(&response));
+    }
+    drop(permit);
+    stopped_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn tcp_and_unix() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.child("http.sock");
+    safina::timer::start_timer_thread();
+    let executor = Executor::new(1, 1).unwrap();
+    let (addr, _stopped_receiver) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .add_listen_addr(ListenAddr::unix(&path))
+                .add_tagged_listen_addr(servlin::socket_addr_127_0_0_1_any_port(), "tcp")
+                .spawn(|req: Request| Response::text(200, format!("{:?}", req.listener))),
+        )
+        .unwrap();
+    let mut tcp_stream = std::net::TcpStream::connect(addr).unwrap();
+    tcp_stream
+        .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    tcp_stream.read_to_string(&mut response).unwrap();
+    assert_eq!("Some(\"tcp\")", body(&response));
+    assert_eq!("None", body(&get(UnixStream::connect(&path).unwrap())));
+}
+
+#[test]
+fn spawn_needs_tcp() {
+    let dir = TempDir::new().unwrap();
+    safina::timer::start_timer_thread();
+    let executor = Executor::new(1, 1).unwrap();
+    let err = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .listen_addr(ListenAddr::unix(dir.child("http.sock")))
+                .spawn(echo_peer),
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    assert!(!dir.child("http.sock").exists());
+}
+
+#[test]
+fn stale_socket_file() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.child("http.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let executor = Executor::new(1, 1).unwrap();
+    spawn(
+        &executor,
+        HttpServerBuilder::new().listen_addr(ListenAddr::unix(&path)),
+    )
+    .unwrap();
+    assert_eq!(
+        "HTTP/1.1 200 OK\r\n",
+        &get(UnixStream::connect(&path).unwrap())[..17]
+    );
+}
+
+#[test]
+fn socket_in_use() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.child("http.sock");
+    let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+    let executor = Executor::new(1, 1).unwrap();
+    let err = spawn(
+        &executor,
+        HttpServerBuilder::new().listen_addr(ListenAddr::unix(&path)),
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::AddrInUse, err.kind());
+    assert!(path.exists());
+}
+
+#[test]
+fn path_is_not_socket() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.child("http.sock");
+    std::fs::write(&path, "data").unwrap();
+    let executor = Executor::new(1, 1).unwrap();
+    let err = spawn(
+        &executor,
+        HttpServerBuilder::new().listen_addr(ListenAddr::unix(&path)),
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::AlreadyExists, err.kind());
+    assert_eq!("data", std::fs::read_to_string(&path).unwrap());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn unix_abstract() {
+    use std::os::linux::net::SocketAddrExt;
+    let name = format!("servlin-test-{}", std::process::id());
+    let executor = Executor::new(1, 1).unwrap();
+    let addrs = spawn(
+        &executor,
+        HttpServerBuilder::new().listen_addr(ListenAddr::unix_abstract(name.as_bytes())),
+    )
+    .unwrap();
+    assert_eq!(vec![ListenAddr::unix_abstract(name.as_bytes())], addrs);
+    assert_eq!(format!("unix:@{name}"), addrs[0].to_string());
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+    let response = get(UnixStream::connect_addr(&addr).unwrap());
+    assert!(body(&response).starts_with("unix:(pid="), "{response:?}");
+}
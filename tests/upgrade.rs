@@ -0,0 +1,145 @@
+use crate::test_util::{TestServer, read_response};
+use permit::Permit;
+use safina::executor::Executor;
+use servlin::{HttpServerBuilder, Response, socket_addr_127_0_0_1_any_port};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+mod test_util;
+
+fn echo_upper(mut conn: servlin::UpgradedConn) {
+    let mut buf = [0_u8; 100];
+    loop {
+        match conn.read(&mut buf) {
+            Ok(0) | Err(..) => return,
+            Ok(n) => {
+                buf[..n].make_ascii_uppercase();
+                conn.write_all(&buf[..n]).unwrap();
+            }
+        }
+    }
+}
+
+fn read_n(tcp_stream: &mut std::net::TcpStream, n: usize) -> String {
+    tcp_stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut buf = vec![0_u8; n];
+    tcp_stream.read_exact(&mut buf).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+#[test]
+fn upgrade() {
+    let server = TestServer::start(|req| {
+        if req
+            .headers
+            .get_only("upgrade")
+            .map(servlin::AsciiString::as_str)
+            == Some("upper")
+        {
+            Response::upgrade("upper", echo_upper)
+        } else {
+            Response::text(426, "use upper")
+        }
+    })
+    .unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("GET / HTTP/1.1\r\nupgrade: upper\r\nconnection: upgrade\r\n\r\nabc")
+        .unwrap();
+    assert_eq!(
+        read_response(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 101 Switching Protocols\r\nupgrade: upper\r\nconnection: upgrade\r\n\r\n",
+    );
+    assert_eq!("ABC", read_n(&mut tcp_stream, 3));
+    tcp_stream.write_all(b"def").unwrap();
+    assert_eq!("DEF", read_n(&mut tcp_stream, 3));
+    tcp_stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut rest = String::new();
+    tcp_stream.read_to_string(&mut rest).unwrap();
+    assert_eq!("", rest);
+}
+
+#[test]
+fn upgrade_after_pending_body() {
+    let server = TestServer::start(|_req| Response::upgrade("upper", echo_upper)).unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("POST / HTTP/1.1\r\ncontent-length: 100000\r\n\r\nabc")
+        .unwrap();
+    assert_eq!(
+        read_response(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 101 Switching Protocols\r\nupgrade: upper\r\nconnection: upgrade\r\n\r\n",
+    );
+    assert_eq!("ABC", read_n(&mut tcp_stream, 3));
+}
+
+#[test]
+fn upgrade_with_wrong_status() {
+    let server = TestServer::start(|_req| Response::new(404).with_upgrade(echo_upper)).unwrap();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 500 Internal Server Error\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 21\r\n\r\nInternal server error",
+    );
+}
+
+#[test]
+fn connect_tunnel() {
+    let server = TestServer::start(|req| {
+        if req.method() == "CONNECT" {
+            assert_eq!("example.com", req.url().host);
+            assert_eq!(Some(443), req.url().port);
+            Response::connect_tunnel(echo_upper)
+        } else {
+            Response::method_not_allowed_405(&["CONNECT"])
+        }
+    })
+    .unwrap();
+    let mut tcp_stream = server
+        .connect_and_send("CONNECT example.com:443 HTTP/1.1\r\nhost: example.com:443\r\n\r\n")
+        .unwrap();
+    // A 2xx response to CONNECT has no body, so `read_response` would wait for EOF.
+    assert_eq!("HTTP/1.1 200 OK\r\n\r\n", read_n(&mut tcp_stream, 19));
+    tcp_stream.write_all(b"abc").unwrap();
+    assert_eq!("ABC", read_n(&mut tcp_stream, 3));
+}
+
+#[test]
+fn upgraded_conn_counts_against_max_conns() {
+    safina::timer::start_timer_thread();
+    let permit = Permit::new();
+    let executor = Executor::new(1, 2).unwrap();
+    let (addr, _stopped_receiver): (SocketAddr, _) = executor
+        .block_on(
+            HttpServerBuilder::new()
+                .listen_addr(socket_addr_127_0_0_1_any_port())
+                .max_conns(1)
+                .permit(permit.new_sub())
+                .spawn(|req| {
+                    if req.url().path == "/upgrade" {
+                        Response::upgrade("upper", echo_upper)
+                    } else {
+                        Response::text(200, "ok")
+                    }
+                }),
+        )
+        .unwrap();
+    let mut conn1 = std::net::TcpStream::connect(addr).unwrap();
+    conn1
+        .write_all(b"GET /upgrade HTTP/1.1\r\nupgrade: upper\r\n\r\n")
+        .unwrap();
+    read_response(&mut conn1).unwrap();
+    let mut conn2 = std::net::TcpStream::connect(addr).unwrap();
+    conn2.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    conn2
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let mut buf = [0_u8; 1];
+    let result = conn2.read(&mut buf);
+    assert!(result.is_err(), "{result:?}");
+    conn1.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(
+        read_response(&mut conn2).unwrap(),
+        "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 2\r\n\r\nok",
+    );
+}
@@ -376,4 +376,8 @@ fn display() {
     );
     assert_eq!(Url::parse_relative("?q1").unwrap().to_string(), "?q1");
     assert_eq!(Url::parse_relative("#f1").unwrap().to_string(), "#f1");
+    let mut url = Url::parse_relative("/d1?q1").unwrap();
+    url.host = "h1".to_string();
+    url.port = Some(2);
+    assert_eq!(url.to_string(), "//h1:2/d1?q1");
 }
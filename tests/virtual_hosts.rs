@@ -0,0 +1,85 @@
+use crate::test_util::TestServer;
+use servlin::{Request, Response, VirtualHosts};
+
+mod test_util;
+
+fn text(s: &'static str) -> impl FnOnce(Request) -> Response + Clone + Send + Sync + 'static {
+    move |_req| Response::text(200, s)
+}
+
+fn get(server: &TestServer, host: &str) -> String {
+    let response = server
+        .exchange(format!("GET / HTTP/1.1\r\nhost: {host}\r\n\r\n"))
+        .unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    if body.is_empty() {
+        head.lines().next().unwrap().to_string()
+    } else {
+        body.to_string()
+    }
+}
+
+#[test]
+fn dispatch() {
+    let hosts = VirtualHosts::new()
+        .host("example.com", text("main"))
+        .host("*.example.com", text("sub"))
+        .host("api.example.com", text("api"))
+        .host("*.eu.example.com", text("eu"))
+        .host("127.0.0.1", text("ipv4"))
+        .host("::1", text("ipv6"))
+        .default(|req: Request| Response::text(200, format!("default {}", req.url.host)));
+    let server = TestServer::start(move |req: Request| hosts.handle(req)).unwrap();
+    assert_eq!("main", get(&server, "example.com"));
+    assert_eq!("main", get(&server, "EXAMPLE.com:8080"));
+    assert_eq!("main", get(&server, "example.com."));
+    assert_eq!("api", get(&server, "api.example.com"));
+    assert_eq!("sub", get(&server, "www.example.com"));
+    assert_eq!("sub", get(&server, "a.b.example.com"));
+    assert_eq!("eu", get(&server, "www.eu.example.com"));
+    assert_eq!("sub", get(&server, "eu.example.com"));
+    assert_eq!("ipv4", get(&server, "127.0.0.1:80"));
+    assert_eq!("ipv6", get(&server, "[::1]:80"));
+    assert_eq!("default other.com", get(&server, "other.com"));
+    assert_eq!("default notexample.com", get(&server, "notexample.com"));
+    assert_eq!("default ", get(&server, "bad/host"));
+}
+
+#[test]
+fn misdirected() {
+    let hosts = VirtualHosts::new().host("example.com", text("main"));
+    let server = TestServer::start(move |req: Request| hosts.handle(req)).unwrap();
+    assert_eq!("main", get(&server, "example.com"));
+    assert_eq!(
+        server
+            .exchange("GET / HTTP/1.1\r\nhost: other.com\r\n\r\n")
+            .unwrap(),
+        "HTTP/1.1 421 Misdirected Request\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 20\r\n\r\nMisdirected request.",
+    );
+    assert_eq!(
+        server.exchange("GET / HTTP/1.0\r\n\r\n").unwrap(),
+        "HTTP/1.1 421 Misdirected Request\r\ncontent-type: text/plain; charset=UTF-8\r\nconnection: close\r\ncontent-length: 20\r\n\r\nMisdirected request.",
+    );
+}
+
+#[test]
+#[should_panic(expected = "invalid host pattern")]
+fn invalid_pattern() {
+    let _hosts = VirtualHosts::new().host("www.*.com", text("x"));
+}
+
+#[test]
+fn debug() {
+    let hosts = VirtualHosts::new()
+        .host("Example.com", text("main"))
+        .host("*.a.example.com", text("a"))
+        .host("*.example.com", text("sub"));
+    assert_eq!(
+        format!("{hosts:?}"),
+        "VirtualHosts{[\"example.com\", \"*.a.example.com\", \"*.example.com\"]}"
+    );
+    assert_eq!(
+        format!("{:?}", hosts.default(text("x"))),
+        "VirtualHosts{[\"example.com\", \"*.a.example.com\", \"*.example.com\"], default}"
+    );
+}
@@ -0,0 +1,302 @@
+use crate::test_util::{TestServer, read_response};
+use servlin::{Response, WebSocketAcceptor, WebSocketError, WebSocketMessage};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+mod test_util;
+
+const UPGRADE_REQ: &str = "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+
+fn masked_frame(first_byte: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [1_u8, 2, 3, 4];
+    let mut bytes = vec![first_byte];
+    match payload.len() {
+        len @ 0..=125 => bytes.push(0x80 | u8::try_from(len).unwrap()),
+        len @ 126..=0xFFFF => {
+            bytes.push(0x80 | 0x7E);
+            bytes.extend(u16::try_from(len).unwrap().to_be_bytes());
+        }
+        len => {
+            bytes.push(0x80 | 0x7F);
+            bytes.extend((len as u64).to_be_bytes());
+        }
+    }
+    bytes.extend(mask);
+    bytes.extend(payload.iter().enumerate().map(|(n, b)| b ^ mask[n % 4]));
+    bytes
+}
+
+fn read_frame(tcp_stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    tcp_stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut head = [0_u8; 2];
+    tcp_stream.read_exact(&mut head).unwrap();
+    assert_eq!(0, head[1] & 0x80, "server frame is masked");
+    let len = match head[1] {
+        126 => {
+            let mut bytes = [0_u8; 2];
+            tcp_stream.read_exact(&mut bytes).unwrap();
+            usize::from(u16::from_be_bytes(bytes))
+        }
+        127 => unimplemented!(),
+        n => usize::from(n),
+    };
+    let mut payload = vec![0_u8; len];
+    tcp_stream.read_exact(&mut payload).unwrap();
+    (head[0], payload)
+}
+
+fn echo_server() -> TestServer {
+    TestServer::start(|req| {
+        WebSocketAcceptor::new()
+            .max_message_len(1000)
+            .accept(&req, |socket| {
+                loop {
+                    match socket.recv() {
+                        Ok(WebSocketMessage::Close(..)) | Err(..) => return,
+                        Ok(message) => socket.send(message).unwrap(),
+                    }
+                }
+            })
+            .unwrap_or_else(|e| e)
+    })
+    .unwrap()
+}
+
+fn connect(server: &TestServer) -> TcpStream {
+    let mut tcp_stream = server.connect_and_send(UPGRADE_REQ).unwrap();
+    assert_eq!(
+        read_response(&mut tcp_stream).unwrap(),
+        "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\nconnection: upgrade\r\nsec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+    );
+    tcp_stream
+}
+
+fn assert_closed_with(tcp_stream: &mut TcpStream, code: u16) {
+    let (first_byte, payload) = read_frame(tcp_stream);
+    assert_eq!(0x88, first_byte);
+    assert_eq!(code.to_be_bytes(), payload[..2]);
+    let mut rest = Vec::new();
+    tcp_stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn not_upgrade() {
+    let server = echo_server();
+    assert_eq!(
+        server.exchange("GET / HTTP/1.1\r\n\r\n").unwrap(),
+        "HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: 32\r\n\r\nNot a WebSocket upgrade request.",
+    );
+}
+
+#[test]
+fn wrong_method() {
+    let server = echo_server();
+    let reply = server.exchange(UPGRADE_REQ.replace("GET", "POST")).unwrap();
+    assert!(
+        reply.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+        "{reply:?}"
+    );
+}
+
+#[test]
+fn unsupported_version() {
+    let server = echo_server();
+    let reply = server
+        .exchange(UPGRADE_REQ.replace("Version: 13", "Version: 8"))
+        .unwrap();
+    assert!(
+        reply.starts_with("HTTP/1.1 426 Upgrade Required\r\n"),
+        "{reply:?}"
+    );
+    assert!(
+        reply.contains("\r\nsec-websocket-version: 13\r\n"),
+        "{reply:?}"
+    );
+}
+
+#[test]
+fn malformed_key() {
+    let server = echo_server();
+    let reply = server
+        .exchange(UPGRADE_REQ.replace("dGhlIHNhbXBsZSBub25jZQ==", "abc"))
+        .unwrap();
+    assert!(
+        reply.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{reply:?}"
+    );
+}
+
+#[test]
+fn protocol() {
+    let server = TestServer::start(|req| {
+        WebSocketAcceptor::new()
+            .protocol("v2.chat")
+            .protocol("chat")
+            .accept(&req, |socket| {
+                let protocol = socket.protocol().unwrap().to_string();
+                socket.send(WebSocketMessage::Text(protocol)).unwrap();
+            })
+            .unwrap_or_else(|e| e)
+    })
+    .unwrap();
+    let mut tcp_stream = server
+        .connect_and_send(UPGRADE_REQ.replace(
+            "\r\n\r\n",
+            "\r\nSec-WebSocket-Protocol: chat, v2.chat\r\n\r\n",
+        ))
+        .unwrap();
+    let head = read_response(&mut tcp_stream).unwrap();
+    assert!(
+        head.contains("\r\nsec-websocket-protocol: v2.chat\r\n"),
+        "{head:?}"
+    );
+    assert_eq!((0x81, b"v2.chat".to_vec()), read_frame(&mut tcp_stream));
+}
+
+#[test]
+fn echo() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(&masked_frame(0x81, b"abc")).unwrap();
+    assert_eq!((0x81, b"abc".to_vec()), read_frame(&mut tcp_stream));
+    tcp_stream
+        .write_all(&masked_frame(0x82, &[0, 1, 2]))
+        .unwrap();
+    assert_eq!((0x82, vec![0, 1, 2]), read_frame(&mut tcp_stream));
+    let long = vec![b'a'; 300];
+    tcp_stream.write_all(&masked_frame(0x82, &long)).unwrap();
+    assert_eq!((0x82, long), read_frame(&mut tcp_stream));
+}
+
+#[test]
+fn prefix_bytes() {
+    let server = echo_server();
+    let mut bytes = UPGRADE_REQ.as_bytes().to_vec();
+    bytes.extend(masked_frame(0x81, b"abc"));
+    let mut tcp_stream = server.connect_and_send(bytes).unwrap();
+    read_response(&mut tcp_stream).unwrap();
+    assert_eq!((0x81, b"abc".to_vec()), read_frame(&mut tcp_stream));
+}
+
+#[test]
+fn fragmented() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(&masked_frame(0x01, b"ab")).unwrap();
+    // Ping between fragments.
+    tcp_stream.write_all(&masked_frame(0x89, b"p")).unwrap();
+    tcp_stream.write_all(&masked_frame(0x00, b"c")).unwrap();
+    tcp_stream.write_all(&masked_frame(0x80, b"d")).unwrap();
+    assert_eq!((0x8A, b"p".to_vec()), read_frame(&mut tcp_stream));
+    assert_eq!((0x81, b"abcd".to_vec()), read_frame(&mut tcp_stream));
+}
+
+#[test]
+fn close() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    let mut payload = 1000_u16.to_be_bytes().to_vec();
+    payload.extend(b"bye");
+    tcp_stream.write_all(&masked_frame(0x88, &payload)).unwrap();
+    assert_closed_with(&mut tcp_stream, 1000);
+}
+
+#[test]
+fn server_close() {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+    let server = TestServer::start(move |req| {
+        let sender = sender.clone();
+        Response::websocket(&req, move |socket| {
+            socket.close(4000, "done").unwrap();
+            sender
+                .send((
+                    socket.recv(),
+                    socket.send(WebSocketMessage::Text("a".to_string())),
+                ))
+                .unwrap();
+        })
+        .unwrap_or_else(|e| e)
+    })
+    .unwrap();
+    let mut tcp_stream = connect(&server);
+    let (first_byte, payload) = read_frame(&mut tcp_stream);
+    assert_eq!(0x88, first_byte);
+    assert_eq!(b"\x0f\xa0done".to_vec(), payload);
+    tcp_stream
+        .write_all(&masked_frame(0x88, &4000_u16.to_be_bytes()))
+        .unwrap();
+    assert_eq!(
+        (
+            Ok(WebSocketMessage::Close(4000, String::new())),
+            Err(WebSocketError::Closed)
+        ),
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    );
+}
+
+#[test]
+fn unmasked_frame() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(b"\x81\x03abc").unwrap();
+    assert_closed_with(&mut tcp_stream, 1002);
+}
+
+#[test]
+fn reserved_bits() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(&masked_frame(0xC1, b"abc")).unwrap();
+    assert_closed_with(&mut tcp_stream, 1002);
+}
+
+#[test]
+fn unexpected_continuation() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(&masked_frame(0x80, b"abc")).unwrap();
+    assert_closed_with(&mut tcp_stream, 1002);
+}
+
+#[test]
+fn fragmented_control_frame() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(&masked_frame(0x09, b"abc")).unwrap();
+    assert_closed_with(&mut tcp_stream, 1002);
+}
+
+#[test]
+fn invalid_close_code() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream
+        .write_all(&masked_frame(0x88, &1005_u16.to_be_bytes()))
+        .unwrap();
+    assert_closed_with(&mut tcp_stream, 1002);
+}
+
+#[test]
+fn not_utf8() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream.write_all(&masked_frame(0x81, &[0xFF])).unwrap();
+    assert_closed_with(&mut tcp_stream, 1007);
+}
+
+#[test]
+fn too_long() {
+    let server = echo_server();
+    let mut tcp_stream = connect(&server);
+    tcp_stream
+        .write_all(&masked_frame(0x02, &[b'a'; 600]))
+        .unwrap();
+    tcp_stream
+        .write_all(&masked_frame(0x80, &[b'a'; 600]))
+        .unwrap();
+    assert_closed_with(&mut tcp_stream, 1009);
+}